@@ -1,45 +1,390 @@
 use axum::{
     extract::Request,
-    http::{header, StatusCode},
+    http::header,
     middleware::Next,
     response::Response,
     extract::State,
+    Extension, Json,
 };
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
 use subtle::ConstantTimeEq;
 
-/// Wrapper type to store the API key in Axum state.
+use crate::error::AppError;
+
+/// Label of the key given with `API_KEY`.
+pub const DEFAULT_KEY_LABEL: &str = "default";
+
+/// Scopes reported for a write key: it may do everything.
+const FULL_ACCESS_SCOPES: &[&str] = &["read", "upload", "delete", "admin"];
+
+/// What an API key may do. `write` includes everything `read` allows.
+///
+/// | Scope | Routes |
+/// |-------|--------|
+/// | `read` | `GET` of `/api/drawings`, `/api/drawings/deleted`, `/api/drawings/{id}/meta`, `/api/drawings/{id}/hash`, `/api/lookup`, `/api/folders`, `/api/stats`, `/api/storage/history`, `/api/admin/verify`, `/api/auth/whoami`, `/api/collab/sessions`, `/api/ice-config`; `POST /api/drawings/meta-batch` |
+/// | `write` | `POST /api/upload`, `DELETE /api/drawings/{id}`, `POST /api/drawings/bulk-delete`, `POST /api/drawings/{id}/extend`, `POST /api/drawings/{id}/rollback/{version}`, `PUT /api/drawings/{id}/appstate`, `POST /api/import`, `GET /api/export`, `GET /api/admin/config`, `POST /api/collab/start`, `POST /api/collab/stop`, `POST /api/persistent-collab/enable`, `POST /api/persistent-collab/disable` |
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyScope {
+    Read,
+    Write,
+}
+
+impl KeyScope {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(KeyScope::Read),
+            "write" => Some(KeyScope::Write),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyScope::Read => "read",
+            KeyScope::Write => "write",
+        }
+    }
+
+    /// Whether a key with this scope may use a route requiring `required`.
+    pub fn allows(self, required: KeyScope) -> bool {
+        self >= required
+    }
+}
+
+/// An accepted API key, the label requests made with it are attributed to,
+/// and its scope.
+#[derive(Clone)]
+struct LabeledKey {
+    label: String,
+    key: String,
+    scope: KeyScope,
+}
+
+/// The accepted API keys, stored in Axum state. Each can be revoked on its
+/// own by dropping it from the configuration.
+#[derive(Clone, Default)]
+pub struct ApiKeys(Arc<Vec<LabeledKey>>);
+
+impl ApiKeys {
+    /// A single write key labelled [`DEFAULT_KEY_LABEL`].
+    pub fn single(key: impl Into<String>) -> Self {
+        Self(Arc::new(vec![LabeledKey { label: DEFAULT_KEY_LABEL.to_string(), key: key.into(), scope: KeyScope::Write }]))
+    }
+
+    /// Parse `label:key[:scope]` entries separated by commas or newlines, as
+    /// given to `--api-keys` or in a keys file. The scope is `read` or `write`
+    /// (the default). Blank entries and `#` comment lines are skipped; an entry
+    /// without a label is labelled with its [`KeyId`].
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut keys = Vec::new();
+        for entry in spec.lines().filter(|line| !line.trim_start().starts_with('#')).flat_map(|line| line.split(',')) {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (label, key) = match entry.split_once(':') {
+                Some((label, key)) => (label.trim().to_string(), key.trim()),
+                None => (KeyId::from_key(entry.as_bytes()).0, entry),
+            };
+            let (key, scope) = match key.rsplit_once(':').and_then(|(key, scope)| Some((key, KeyScope::parse(scope.trim())?))) {
+                Some((key, scope)) => (key.trim(), scope),
+                None => (key, KeyScope::Write),
+            };
+            if label.is_empty() || key.is_empty() {
+                return Err("API key entries must be `label:key[:scope]` with neither label nor key empty".to_string());
+            }
+            keys.push(LabeledKey { label, key: key.to_string(), scope });
+        }
+        Ok(Self(Arc::new(keys)))
+    }
+
+    /// All keys of `self` followed by those of `other`.
+    pub fn merge(self, other: ApiKeys) -> Self {
+        let mut keys = Arc::unwrap_or_clone(self.0);
+        keys.extend(Arc::unwrap_or_clone(other.0));
+        Self(Arc::new(keys))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|k| k.key == key)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|k| k.label.as_str())
+    }
+
+    /// Label and scope of the key `token` matches. Every key is compared (in
+    /// constant time), so the response time doesn't tell which one, if any, matched.
+    pub fn find(&self, token: &str) -> Option<(&str, KeyScope)> {
+        let mut matched = None;
+        for entry in self.0.iter() {
+            if key_matches(token, &entry.key) && matched.is_none() {
+                matched = Some((entry.label.as_str(), entry.scope));
+            }
+        }
+        matched
+    }
+
+    /// Whether `token` is a key with at least the `required` scope.
+    pub fn authorizes(&self, token: &str, required: KeyScope) -> bool {
+        self.find(token).is_some_and(|(_, scope)| scope.allows(required))
+    }
+}
+
+/// State of [`api_key_middleware`]: the accepted keys and the scope the
+/// routes behind it require.
 #[derive(Clone)]
-pub struct ApiKey(pub String);
+pub struct ScopedKeys {
+    pub keys: ApiKeys,
+    pub required: KeyScope,
+}
+
+/// Label of the API key that authenticated a request, inserted into request
+/// extensions by [`api_key_middleware`] next to its [`KeyId`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyLabel(pub String);
+
+/// Scope of the API key that authenticated a request (request extension).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrantedScope(pub KeyScope);
+
+/// Identifies the API key that authenticated a request, without keeping the
+/// key itself around. Inserted into request extensions by [`api_key_middleware`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyId(pub String);
+
+impl KeyId {
+    /// First 16 hex characters of the key's SHA-256.
+    pub fn from_key(key: &[u8]) -> Self {
+        let digest = Sha256::digest(key);
+        Self(digest.iter().take(8).map(|b| format!("{b:02x}")).collect())
+    }
+}
+
+/// Whether `token` is the configured API key, in time independent of where
+/// (or whether) they differ. Both sides are hashed first so keys of different
+/// lengths are compared as equal-length digests and the length doesn't leak.
+pub fn key_matches(token: &str, key: &str) -> bool {
+    Sha256::digest(token.as_bytes()).ct_eq(&Sha256::digest(key.as_bytes())).into()
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` value. The scheme is
+/// matched case-insensitively (RFC 7235) and extra whitespace around the token is ignored.
+pub fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.trim_start().split_once(char::is_whitespace)?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
 
 /// Middleware that validates the `Authorization: Bearer <key>` header
-/// against the configured API key using constant-time comparison
-/// to prevent timing attacks.
+/// against the configured API keys using constant-time comparison
+/// to prevent timing attacks. A valid key without the scope the route
+/// requires gets 403 rather than 401.
 pub async fn api_key_middleware(
-    State(api_key): State<ApiKey>,
-    request: Request,
+    State(ScopedKeys { keys, required }): State<ScopedKeys>,
+    mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let auth_header = request
+) -> Result<Response, AppError> {
+    let token = request
         .headers()
         .get(header::AUTHORIZATION)
-        .and_then(|v| v.to_str().ok());
-
-    match auth_header {
-        Some(value) if value.starts_with("Bearer ") => {
-            let token = value[7..].as_bytes();
-            let key = api_key.0.as_bytes();
-            // Constant-time comparison: prevents timing side-channel attacks.
-            // Length check leaks key length but not content (acceptable trade-off).
-            if token.len() == key.len() && token.ct_eq(key).into() {
+        .and_then(|v| v.to_str().ok())
+        .and_then(bearer_token);
+
+    match token {
+        Some(token) => {
+            if let Some((label, scope)) = keys.find(token) {
+                if !scope.allows(required) {
+                    tracing::warn!(key = %label, required = required.as_str(), "API key lacks the required scope");
+                    return Err(AppError::Forbidden(format!("this API key lacks the `{}` scope", required.as_str())));
+                }
+                let label = KeyLabel(label.to_string());
+                let key_id = KeyId::from_key(token.as_bytes());
+                request.extensions_mut().insert(label);
+                request.extensions_mut().insert(GrantedScope(scope));
+                request.extensions_mut().insert(key_id);
                 Ok(next.run(request).await)
             } else {
                 tracing::warn!("Invalid API key attempt");
-                Err(StatusCode::UNAUTHORIZED)
+                Err(AppError::Unauthorized)
             }
         }
-        _ => {
+        None => {
             tracing::warn!("Missing or malformed Authorization header");
-            Err(StatusCode::UNAUTHORIZED)
+            Err(AppError::Unauthorized)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, serde::Deserialize)]
+pub struct WhoamiResponse {
+    /// Non-secret identifier of the key (see [`KeyId`])
+    pub key_id: String,
+    pub label: String,
+    pub scopes: Vec<String>,
+}
+
+/// Report which key authenticated the request and what it may do (auth required).
+/// Lets clients offer a "test connection" check without attempting an upload.
+pub async fn whoami(
+    Extension(key_id): Extension<KeyId>,
+    Extension(label): Extension<KeyLabel>,
+    Extension(GrantedScope(scope)): Extension<GrantedScope>,
+) -> Json<WhoamiResponse> {
+    let scopes = match scope {
+        KeyScope::Write => FULL_ACCESS_SCOPES,
+        KeyScope::Read => &["read"],
+    };
+    Json(WhoamiResponse {
+        key_id: key_id.0,
+        label: label.0,
+        scopes: scopes.iter().map(|s| s.to_string()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn whoami_with(token: &str) -> (StatusCode, serde_json::Value) {
+        whoami_with_header(&format!("Bearer {token}")).await
+    }
+
+    async fn whoami_with_header(authorization: &str) -> (StatusCode, serde_json::Value) {
+        whoami_as(ApiKeys::single("secret-key"), authorization).await
+    }
+
+    async fn whoami_as(keys: ApiKeys, authorization: &str) -> (StatusCode, serde_json::Value) {
+        let app = Router::new()
+            .route("/api/auth/whoami", get(whoami))
+            .route_layer(middleware::from_fn_with_state(ScopedKeys { keys, required: KeyScope::Read }, api_key_middleware));
+        let request = Request::builder()
+            .uri("/api/auth/whoami")
+            .header(header::AUTHORIZATION, authorization)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_whoami_reports_label_and_scopes() {
+        let (status, body) = whoami_with("secret-key").await;
+        assert_eq!(status, StatusCode::OK);
+        let whoami: WhoamiResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(whoami.label, "default");
+        assert!(whoami.scopes.contains(&"upload".to_string()));
+        assert_eq!(whoami.key_id, KeyId::from_key(b"secret-key").0);
+        assert!(!whoami.key_id.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_whoami_rejects_invalid_key_with_json() {
+        let (status, body) = whoami_with("wrong-key").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body["error"].as_str().unwrap().contains("invalid or missing API key"));
+    }
+
+    #[test]
+    fn test_key_matches_only_the_exact_key() {
+        assert!(key_matches("secret-key", "secret-key"));
+        for token in ["secret-kez", "secret-ke", "secret-key-", "", "s", "Secret-key"] {
+            assert!(!key_matches(token, "secret-key"), "{token:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_near_miss_and_other_length_keys_rejected() {
+        for token in ["secret-kex", "secret-keyy", "secret", &"x".repeat(1024)] {
+            let (status, _) = whoami_with(token).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{token:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_any_configured_key_accepted_with_its_label() {
+        let keys = ApiKeys::parse("alice:alice-key, ci:ci-key\n# revoked:old-key\n").unwrap();
+        assert_eq!(keys.labels().collect::<Vec<_>>(), ["alice", "ci"]);
+        let keys = keys.merge(ApiKeys::single("legacy-key"));
+
+        for (token, label) in [("alice-key", "alice"), ("ci-key", "ci"), ("legacy-key", "default")] {
+            let (status, body) = whoami_as(keys.clone(), &format!("Bearer {token}")).await;
+            assert_eq!(status, StatusCode::OK, "{token}");
+            assert_eq!(body["label"], label);
+        }
+        for token in ["old-key", "alice", "alice:alice-key"] {
+            let (status, _) = whoami_as(keys.clone(), &format!("Bearer {token}")).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{token}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_key_forbidden_on_write_routes() {
+        let keys = ApiKeys::parse("dashboard:dash-key:read, ci:ci-key:write").unwrap();
+        let app = Router::new()
+            .route("/api/drawings", get(|| async { "listed" }))
+            .route_layer(middleware::from_fn_with_state(
+                ScopedKeys { keys: keys.clone(), required: KeyScope::Read },
+                api_key_middleware,
+            ))
+            .merge(Router::new().route("/api/upload", axum::routing::post(|| async { "stored" })).route_layer(
+                middleware::from_fn_with_state(ScopedKeys { keys, required: KeyScope::Write }, api_key_middleware),
+            ));
+        let status = |method: &str, uri: &str, token: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(status("GET", "/api/drawings", "dash-key").await, StatusCode::OK);
+        assert_eq!(status("POST", "/api/upload", "dash-key").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("GET", "/api/drawings", "ci-key").await, StatusCode::OK);
+        assert_eq!(status("POST", "/api/upload", "ci-key").await, StatusCode::OK);
+        assert_eq!(status("POST", "/api/upload", "wrong-key").await, StatusCode::UNAUTHORIZED);
+
+        let (_, body) = whoami_as(ApiKeys::parse("dashboard:dash-key:read").unwrap(), "Bearer dash-key").await;
+        assert_eq!(body["scopes"], serde_json::json!(["read"]));
+    }
+
+    #[test]
+    fn test_api_keys_parse() {
+        let keys = ApiKeys::parse("bare-key").unwrap();
+        let label = KeyId::from_key(b"bare-key").0;
+        assert_eq!(keys.find("bare-key"), Some((label.as_str(), KeyScope::Write)));
+        // Only the first colon separates the label, and a trailing scope is split off
+        assert_eq!(ApiKeys::parse("ops:a:b").unwrap().find("a:b"), Some(("ops", KeyScope::Write)));
+        assert_eq!(ApiKeys::parse("ops:a:b:read").unwrap().find("a:b"), Some(("ops", KeyScope::Read)));
+        assert!(ApiKeys::parse("ops:a:read").unwrap().authorizes("a", KeyScope::Read));
+        assert!(!ApiKeys::parse("ops:a:read").unwrap().authorizes("a", KeyScope::Write));
+        assert!(ApiKeys::parse(" , \n").unwrap().is_empty());
+        assert!(ApiKeys::parse("label:").is_err());
+        assert!(ApiKeys::parse(":key").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bearer_scheme_is_case_insensitive() {
+        for header in ["bearer secret-key", "Bearer secret-key", "BEARER secret-key", "Bearer   secret-key "] {
+            let (status, _) = whoami_with_header(header).await;
+            assert_eq!(status, StatusCode::OK, "{header:?}");
         }
+        let (status, _) = whoami_with_header("bearer wrong-key").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = whoami_with_header("Basic secret-key").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
     }
 }
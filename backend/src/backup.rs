@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::error::AppError;
+use crate::storage::{self, DrawingMeta, DrawingStorage};
+
+/// One drawing in a backup snapshot, pointing at its content-addressed blob.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub meta: DrawingMeta,
+    /// SHA-256 of the stored document; the blob is `blobs/<hash>.json.gz`
+    pub hash: String,
+}
+
+/// `snapshots/<timestamp>.manifest.json.gz`: the full catalog at backup time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub created_at: DateTime<Utc>,
+    pub drawings: Vec<ManifestEntry>,
+}
+
+/// Outcome of one backup run.
+#[derive(Debug, Default)]
+pub struct BackupSummary {
+    pub snapshot: PathBuf,
+    /// Drawings whose content changed (or are new) and were written as blobs
+    pub copied: usize,
+    /// Drawings unchanged since an earlier snapshot, only referenced
+    pub referenced: usize,
+}
+
+fn gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+fn gunzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+async fn write_new(path: &Path, bytes: &[u8]) -> Result<(), AppError> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes).await?;
+    fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+/// Read the most recent snapshot manifest in `backup_dir`, if any.
+pub async fn latest_manifest(backup_dir: &Path) -> Result<Option<BackupManifest>, AppError> {
+    let mut entries = match fs::read_dir(backup_dir.join("snapshots")).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut latest: Option<PathBuf> = None;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_manifest = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.ends_with(".manifest.json.gz"));
+        // Snapshot names are timestamps, so the greatest name is the newest
+        if is_manifest && latest.as_ref().is_none_or(|l| path > *l) {
+            latest = Some(path);
+        }
+    }
+    let Some(path) = latest else {
+        return Ok(None);
+    };
+    let manifest = serde_json::from_slice(&gunzip(&fs::read(&path).await?)?)?;
+    Ok(Some(manifest))
+}
+
+/// Back up every drawing into `backup_dir`. Drawings are stored once as gzipped,
+/// content-addressed blobs; each run writes only blobs for content that changed
+/// since the previous snapshot, plus a manifest referencing all current drawings.
+pub async fn run_backup(storage: &impl DrawingStorage, backup_dir: &Path) -> Result<BackupSummary, AppError> {
+    let blobs_dir = backup_dir.join("blobs");
+    let snapshots_dir = backup_dir.join("snapshots");
+    fs::create_dir_all(&blobs_dir).await?;
+    fs::create_dir_all(&snapshots_dir).await?;
+
+    let previous: HashMap<String, String> = latest_manifest(backup_dir)
+        .await?
+        .map(|m| m.drawings.into_iter().map(|e| (e.meta.id, e.hash)).collect())
+        .unwrap_or_default();
+
+    let mut summary = BackupSummary::default();
+    let mut drawings = Vec::new();
+    for meta in storage.list().await? {
+        let data = match storage.load(&meta.id).await {
+            Ok(data) => data,
+            // Deleted between listing and loading
+            Err(AppError::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+        let bytes = serde_json::to_vec(&data)?;
+        let hash = storage::content_hash(&bytes);
+        let blob_path = blobs_dir.join(format!("{hash}.json.gz"));
+
+        let unchanged = previous.get(&meta.id) == Some(&hash);
+        if unchanged && blob_path.exists() {
+            summary.referenced += 1;
+        } else {
+            if !blob_path.exists() {
+                write_new(&blob_path, &gzip(&bytes)?).await?;
+            }
+            summary.copied += 1;
+        }
+        drawings.push(ManifestEntry { meta, hash });
+    }
+
+    let created_at = Utc::now();
+    let manifest = BackupManifest { created_at, drawings };
+    let snapshot = snapshots_dir.join(format!(
+        "{}.manifest.json.gz",
+        created_at.format("%Y%m%dT%H%M%S%.6fZ")
+    ));
+    write_new(&snapshot, &gzip(&serde_json::to_vec(&manifest)?)?).await?;
+    summary.snapshot = snapshot;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileSystemStorage;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_incremental_backup_copies_only_changes() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(data_dir.path()).await.unwrap();
+        for id in ["a", "b", "c"] {
+            let doc = json!({"type": "excalidraw", "elements": [{"id": id}]});
+            storage.save(id, &doc, None, None).await.unwrap();
+        }
+
+        let first = run_backup(&storage, backup_dir.path()).await.unwrap();
+        assert_eq!((first.copied, first.referenced), (3, 0));
+
+        let changed = json!({"type": "excalidraw", "elements": [{"id": "b"}, {"id": "new"}]});
+        storage.save("b", &changed, None, None).await.unwrap();
+        let second = run_backup(&storage, backup_dir.path()).await.unwrap();
+        assert_eq!((second.copied, second.referenced), (1, 2));
+        assert_ne!(first.snapshot, second.snapshot);
+
+        // The latest manifest covers every drawing, and every blob it names exists
+        let manifest = latest_manifest(backup_dir.path()).await.unwrap().unwrap();
+        assert_eq!(manifest.drawings.len(), 3);
+        let blobs = std::fs::read_dir(backup_dir.path().join("blobs")).unwrap().count();
+        assert_eq!(blobs, 4);
+        for entry in &manifest.drawings {
+            let blob = std::fs::read(backup_dir.path().join(format!("blobs/{}.json.gz", entry.hash))).unwrap();
+            let doc: serde_json::Value = serde_json::from_slice(&gunzip(&blob).unwrap()).unwrap();
+            if entry.meta.id == "b" {
+                assert_eq!(doc["elements"], changed["elements"]);
+            }
+        }
+    }
+}
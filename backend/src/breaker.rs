@@ -0,0 +1,218 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::Storage;
+
+/// Circuit breaker over storage writes. After `threshold` consecutive write
+/// requests fail on storage I/O the server goes read-only: reads are still
+/// served, writes get a 503 until a background write probe succeeds again.
+#[derive(Clone)]
+pub struct WriteBreaker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// Consecutive failures that trip the breaker; 0 never trips
+    threshold: u32,
+    consecutive_failures: AtomicU32,
+    open: AtomicBool,
+    /// Sent as `Retry-After` while read-only
+    probe_interval: Duration,
+}
+
+/// Marks an error response as storage failing (an I/O or database error),
+/// the only server errors the breaker counts.
+#[derive(Debug, Clone, Copy)]
+pub struct StorageFailure;
+
+fn is_write(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+impl WriteBreaker {
+    pub fn new(threshold: u32, probe_interval: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                threshold,
+                consecutive_failures: AtomicU32::new(0),
+                open: AtomicBool::new(false),
+                probe_interval,
+            }),
+        }
+    }
+
+    /// Whether the server is currently refusing writes.
+    pub fn is_read_only(&self) -> bool {
+        self.inner.open.load(Ordering::Relaxed)
+    }
+
+    pub fn record_failure(&self) {
+        let failures = self.inner.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.inner.threshold > 0 && failures >= self.inner.threshold && !self.inner.open.swap(true, Ordering::Relaxed) {
+            tracing::error!(failures, "Storage writes failing; switching to read-only mode");
+        }
+    }
+
+    pub fn record_success(&self) {
+        self.inner.consecutive_failures.store(0, Ordering::Relaxed);
+        if self.inner.open.swap(false, Ordering::Relaxed) {
+            tracing::info!("Storage writes recovered; leaving read-only mode");
+        }
+    }
+
+    /// Try a write against storage and record the outcome. Called periodically
+    /// while read-only, since refused writes can't close the breaker themselves.
+    pub async fn probe(&self, storage: &Storage) {
+        match storage.probe_write().await {
+            Ok(()) => self.record_success(),
+            Err(e) => tracing::warn!(error = %e, "Storage write probe failed; staying read-only"),
+        }
+    }
+
+    /// Probe every `probe_interval` while read-only.
+    pub fn spawn_prober(&self, storage: Storage) {
+        let breaker = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(breaker.inner.probe_interval);
+            loop {
+                interval.tick().await;
+                if breaker.is_read_only() {
+                    breaker.probe(&storage).await;
+                }
+            }
+        });
+    }
+}
+
+/// Middleware refusing writes while the breaker is open and feeding it the
+/// outcome of every write request that gets through.
+pub async fn write_breaker_middleware(
+    State(breaker): State<WriteBreaker>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_write(request.method()) {
+        return next.run(request).await;
+    }
+
+    if breaker.is_read_only() {
+        let body = Json(serde_json::json!({
+            "error": "Storage is failing writes; the server is read-only until it recovers",
+        }));
+        let mut response = (StatusCode::SERVICE_UNAVAILABLE, body).into_response();
+        if let Ok(value) = HeaderValue::from_str(&breaker.inner.probe_interval.as_secs().max(1).to_string()) {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        return response;
+    }
+
+    let response = next.run(request).await;
+    // Other server errors (a bug, a full quota) don't say storage is failing
+    if response.extensions().get::<StorageFailure>().is_some() {
+        breaker.record_failure();
+    } else if response.status().is_success() {
+        breaker.record_success();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::storage::FileSystemStorage;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use std::sync::atomic::AtomicBool;
+    use tower::ServiceExt;
+
+    fn app(breaker: WriteBreaker, failing: Arc<AtomicBool>) -> Router {
+        let write = move || {
+            let failing = failing.clone();
+            async move {
+                if failing.load(Ordering::Relaxed) {
+                    Err(AppError::Storage(std::io::Error::other("disk full")))
+                } else {
+                    Ok(StatusCode::CREATED)
+                }
+            }
+        };
+        Router::new()
+            .route("/api/view/{id}", get(|| async { "drawing" }))
+            .route("/api/upload", axum::routing::post(write))
+            .route("/api/bug", axum::routing::post(|| async { Err::<(), _>(AppError::Internal("bug".into())) }))
+            .layer(middleware::from_fn_with_state(breaker, write_breaker_middleware))
+    }
+
+    async fn status(app: &Router, method: Method, uri: &str) -> StatusCode {
+        let request = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_consecutive_write_failures_trip_breaker() {
+        let breaker = WriteBreaker::new(3, Duration::from_secs(30));
+        let failing = Arc::new(AtomicBool::new(true));
+        let app = app(breaker.clone(), failing.clone());
+
+        for _ in 0..3 {
+            assert_eq!(status(&app, Method::POST, "/api/upload").await, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert!(breaker.is_read_only());
+
+        // Writes are refused without reaching storage; reads keep working
+        failing.store(false, Ordering::Relaxed);
+        assert_eq!(status(&app, Method::POST, "/api/upload").await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status(&app, Method::GET, "/api/view/abc").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_only_storage_failures_count() {
+        let breaker = WriteBreaker::new(2, Duration::from_secs(30));
+        let app = app(breaker.clone(), Arc::new(AtomicBool::new(false)));
+        for _ in 0..5 {
+            assert_eq!(status(&app, Method::POST, "/api/bug").await, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert!(!breaker.is_read_only());
+    }
+
+    #[tokio::test]
+    async fn test_success_resets_breaker() {
+        let breaker = WriteBreaker::new(2, Duration::from_secs(30));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(!breaker.is_read_only(), "failures must be consecutive");
+        breaker.record_failure();
+        assert!(breaker.is_read_only());
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage: Storage = FileSystemStorage::new(dir.path()).await.unwrap().into();
+        std::fs::remove_dir_all(dir.path()).unwrap();
+        breaker.probe(&storage).await;
+        assert!(breaker.is_read_only(), "a failed probe keeps the server read-only");
+
+        std::fs::create_dir_all(dir.path()).unwrap();
+        breaker.probe(&storage).await;
+        assert!(!breaker.is_read_only());
+
+        let app = app(breaker, Arc::new(AtomicBool::new(false)));
+        assert_eq!(status(&app, Method::POST, "/api/upload").await, StatusCode::CREATED);
+    }
+
+    #[test]
+    fn test_zero_threshold_never_trips() {
+        let breaker = WriteBreaker::new(0, Duration::from_secs(30));
+        for _ in 0..100 {
+            breaker.record_failure();
+        }
+        assert!(!breaker.is_read_only());
+    }
+}
@@ -0,0 +1,600 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+
+use crate::error::AppError;
+use crate::storage::{
+    page_of, DrawingAccess, DrawingFilter, DrawingHead, DrawingMeta, DrawingStorage, DrawingSummary, DrawingVersion, Page,
+    StorageStats, Tombstone, TrashedDrawing, VerifyReport,
+};
+
+/// Rough per-entry overhead of a cached listing row beyond its strings.
+const META_OVERHEAD_BYTES: usize = std::mem::size_of::<DrawingMeta>();
+
+/// Current cache memory usage, reported by `/api/stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheUsage {
+    pub used_bytes: usize,
+    pub budget_bytes: usize,
+    pub cached_drawings: usize,
+    /// Drawing loads served from the cache since startup
+    pub hits: u64,
+    /// Drawing loads that had to go to disk
+    pub misses: u64,
+}
+
+struct CachedDrawing {
+    data: serde_json::Value,
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    drawings: HashMap<String, CachedDrawing>,
+    /// `last_used` tick -> id, oldest first
+    recency: BTreeMap<u64, String>,
+    drawings_bytes: usize,
+    list: Option<(Vec<DrawingMeta>, usize)>,
+    tick: u64,
+    /// Bumped on every invalidation, so a read that raced with a write
+    /// doesn't cache what it read
+    generation: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheInner {
+    fn used_bytes(&self) -> usize {
+        self.drawings_bytes + self.list.as_ref().map_or(0, |(_, size)| *size)
+    }
+
+    fn remove_drawing(&mut self, id: &str) {
+        if let Some(entry) = self.drawings.remove(id) {
+            self.recency.remove(&entry.last_used);
+            self.drawings_bytes -= entry.size;
+        }
+    }
+
+    /// Evict least recently used drawings, then the listing, until within
+    /// budget and at most `max_drawings` (if non-zero) remain.
+    fn shrink_to(&mut self, budget: usize, max_drawings: usize) {
+        while max_drawings > 0 && self.drawings.len() > max_drawings {
+            let Some((_, id)) = self.recency.pop_first() else { break };
+            if let Some(entry) = self.drawings.remove(&id) {
+                self.drawings_bytes -= entry.size;
+            }
+        }
+        while self.used_bytes() > budget {
+            match self.recency.pop_first() {
+                Some((_, id)) => {
+                    if let Some(entry) = self.drawings.remove(&id) {
+                        self.drawings_bytes -= entry.size;
+                    }
+                }
+                None => {
+                    self.list = None;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// In-memory cache of loaded drawings (LRU) and of the drawing listing,
+/// bounded by a memory budget. Entries are sized by their serialized length,
+/// which is a reasonable proxy for their in-memory footprint.
+///
+/// Callers take a [`generation`](Self::generation) before reading from disk and
+/// pass it to `put_*`; the entry is dropped if anything changed in between.
+#[derive(Clone)]
+pub struct StorageCache {
+    budget_bytes: usize,
+    max_drawings: usize,
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+impl StorageCache {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            budget_bytes,
+            max_drawings: 0,
+            inner: Arc::new(Mutex::new(CacheInner::default())),
+        }
+    }
+
+    /// Also cap the number of cached drawings; 0 leaves only the memory budget.
+    pub fn with_max_drawings(mut self, max_drawings: usize) -> Self {
+        self.max_drawings = max_drawings;
+        self
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    pub fn get_drawing(&self, id: &str) -> Option<serde_json::Value> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        if !inner.drawings.contains_key(id) {
+            inner.misses += 1;
+            return None;
+        }
+        inner.hits += 1;
+        let entry = inner.drawings.get_mut(id)?;
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let data = entry.data.clone();
+        inner.recency.remove(&previous);
+        inner.recency.insert(tick, id.to_string());
+        Some(data)
+    }
+
+    pub fn put_drawing(&self, id: &str, data: &serde_json::Value, size: usize, generation: u64) {
+        // A drawing that can't fit even alone isn't worth evicting everything for
+        if size > self.budget_bytes {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return;
+        }
+        inner.remove_drawing(id);
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.drawings.insert(
+            id.to_string(),
+            CachedDrawing { data: data.clone(), size, last_used: tick },
+        );
+        inner.recency.insert(tick, id.to_string());
+        inner.drawings_bytes += size;
+        inner.shrink_to(self.budget_bytes, self.max_drawings);
+    }
+
+    pub fn get_list(&self) -> Option<Vec<DrawingMeta>> {
+        self.inner.lock().unwrap().list.as_ref().map(|(list, _)| list.clone())
+    }
+
+    pub fn put_list(&self, list: &[DrawingMeta], generation: u64) {
+        let size: usize = list
+            .iter()
+            .map(|d| {
+                META_OVERHEAD_BYTES
+                    + d.id.len()
+                    + d.source_path.as_ref().map_or(0, String::len)
+                    + d.tags.iter().map(String::len).sum::<usize>()
+                    + d.title.as_ref().map_or(0, String::len)
+                    + d.slug.as_ref().map_or(0, String::len)
+                    + d.user_agent.as_ref().map_or(0, String::len)
+                    + d.content_hash.as_ref().map_or(0, String::len)
+            })
+            .sum();
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return;
+        }
+        inner.list = Some((list.to_vec(), size));
+        inner.shrink_to(self.budget_bytes, self.max_drawings);
+    }
+
+    /// Drop everything cached about a drawing after it changed.
+    pub fn invalidate(&self, id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        inner.remove_drawing(id);
+        inner.list = None;
+    }
+
+    pub fn usage(&self) -> CacheUsage {
+        let inner = self.inner.lock().unwrap();
+        CacheUsage {
+            used_bytes: inner.used_bytes(),
+            budget_bytes: self.budget_bytes,
+            cached_drawings: inner.drawings.len(),
+            hits: inner.hits,
+            misses: inner.misses,
+        }
+    }
+}
+
+/// Any storage backend with a [`StorageCache`] of loaded drawings and the
+/// listing in front of it. Writes made through it drop what they change from
+/// the cache; writes it doesn't see (another process sharing the data
+/// directory, a follower's primary) leave it stale, so only cache while this
+/// process is the sole writer. Without a cache every call goes straight through.
+#[derive(Clone)]
+pub struct CachedStorage<S> {
+    inner: S,
+    cache: Option<StorageCache>,
+}
+
+impl<S: DrawingStorage> CachedStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner, cache: None }
+    }
+
+    /// Keep loaded drawings and the listing in memory, within `budget_bytes` and
+    /// (if non-zero) `max_drawings`. A budget of 0 disables caching.
+    pub fn with_cache(mut self, budget_bytes: usize, max_drawings: usize) -> Self {
+        self.cache = (budget_bytes > 0).then(|| StorageCache::new(budget_bytes).with_max_drawings(max_drawings));
+        self
+    }
+
+    /// The wrapped backend, for calls that bypass the cache.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Memory used by the cache, `None` when caching is disabled.
+    pub fn cache_usage(&self) -> Option<CacheUsage> {
+        self.cache.as_ref().map(StorageCache::usage)
+    }
+
+    fn invalidate(&self, id: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(id);
+        }
+    }
+
+    /// Run a write to `id`, then drop what the cache holds about it, whether or
+    /// not the write went through.
+    async fn write<T>(&self, id: &str, write: impl std::future::Future<Output = T>) -> T {
+        let result = write.await;
+        self.invalidate(id);
+        result
+    }
+}
+
+impl<S: DrawingStorage> DrawingStorage for CachedStorage<S> {
+    async fn save(&self, id: &str, data: &serde_json::Value, source_path: Option<&str>, password_hash: Option<&str>) -> Result<DrawingMeta, AppError> {
+        self.write(id, self.inner.save(id, data, source_path, password_hash)).await
+    }
+
+    async fn load(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        let Some(cache) = &self.cache else {
+            return self.inner.load(id).await;
+        };
+        if let Some(data) = cache.get_drawing(id) {
+            return Ok(data);
+        }
+        let generation = cache.generation();
+        let data = self.inner.load(id).await?;
+        cache.put_drawing(id, &data, serde_json::to_vec(&data)?.len(), generation);
+        Ok(data)
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), AppError> {
+        self.write(id, self.inner.delete(id)).await
+    }
+
+    async fn discard(&self, id: &str) -> Result<(), AppError> {
+        self.write(id, self.inner.discard(id)).await
+    }
+
+    async fn delete_many(&self, ids: &[String]) -> Vec<Result<(), AppError>> {
+        let results = self.inner.delete_many(ids).await;
+        for id in ids {
+            self.invalidate(id);
+        }
+        results
+    }
+
+    async fn list(&self) -> Result<Vec<DrawingMeta>, AppError> {
+        let Some(cache) = &self.cache else {
+            return self.inner.list().await;
+        };
+        if let Some(list) = cache.get_list() {
+            return Ok(list);
+        }
+        let generation = cache.generation();
+        let list = self.inner.list().await?;
+        cache.put_list(&list, generation);
+        Ok(list)
+    }
+
+    async fn exists(&self, id: &str) -> Result<bool, AppError> {
+        self.inner.exists(id).await
+    }
+
+    /// Served from the cached listing when caching, as the backend's own
+    /// paging would read from disk.
+    async fn list_page(&self, filter: &DrawingFilter, page: Page) -> Result<(Vec<DrawingMeta>, usize), AppError> {
+        if self.cache.is_none() {
+            return self.inner.list_page(filter, page).await;
+        }
+        let matching = self.list().await?.into_iter().filter(|d| filter.matches(d)).collect();
+        Ok(page_of(matching, page))
+    }
+
+    async fn get_persistent_collab_status(&self, id: &str) -> Result<bool, AppError> {
+        self.inner.get_persistent_collab_status(id).await
+    }
+
+    async fn save_persistent(&self, id: &str, data: &serde_json::Value, version: u64) -> Result<(), AppError> {
+        self.write(id, self.inner.save_persistent(id, data, version)).await
+    }
+
+    async fn list_persistent_collab_drawings(&self) -> Result<Vec<String>, AppError> {
+        self.inner.list_persistent_collab_drawings().await
+    }
+
+    async fn find_by_source_path(&self, source_path: &str) -> Result<Option<DrawingMeta>, AppError> {
+        self.inner.find_by_source_path(source_path).await
+    }
+
+    async fn search_text(&self, query: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        self.inner.search_text(query).await
+    }
+
+    async fn get_meta(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        self.inner.get_meta(id).await
+    }
+
+    async fn meta(&self, id: &str) -> Result<DrawingAccess, AppError> {
+        self.inner.meta(id).await
+    }
+
+    /// Hashed from the cached document when caching.
+    async fn head(&self, id: &str) -> Result<DrawingHead, AppError> {
+        if self.cache.is_none() {
+            return self.inner.head(id).await;
+        }
+        DrawingHead::of(self.load(id).await?)
+    }
+
+    /// Looked up in the cached listing when caching, as slug links are hot.
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<DrawingMeta>, AppError> {
+        if self.cache.is_none() {
+            return self.inner.find_by_slug(slug).await;
+        }
+        Ok(self.list().await?.into_iter().find(|d| d.slug.as_deref() == Some(slug)))
+    }
+
+    async fn stats(&self) -> Result<StorageStats, AppError> {
+        self.inner.stats().await
+    }
+
+    async fn summary(&self, id: &str) -> Result<DrawingSummary, AppError> {
+        self.inner.summary(id).await
+    }
+
+    async fn find_by_content_hash(&self, hash: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        self.inner.find_by_content_hash(hash).await
+    }
+
+    async fn record_view(&self, id: &str) -> Result<u64, AppError> {
+        self.write(id, self.inner.record_view(id)).await
+    }
+
+    async fn add_views(&self, id: &str, delta: u64) -> Result<u64, AppError> {
+        self.write(id, self.inner.add_views(id, delta)).await
+    }
+
+    async fn burn(&self, id: &str) -> Result<(), AppError> {
+        self.write(id, self.inner.burn(id)).await
+    }
+
+    async fn is_burned(&self, id: &str) -> Result<bool, AppError> {
+        self.inner.is_burned(id).await
+    }
+
+    async fn deleted_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Tombstone>, AppError> {
+        self.inner.deleted_since(since).await
+    }
+
+    /// The backend's report, plus the drawings the cached listing disagrees
+    /// with the backend's own listing about.
+    async fn verify(&self) -> Result<VerifyReport, AppError> {
+        let mut report = self.inner.verify().await?;
+        if let Some(cached) = self.cache.as_ref().and_then(StorageCache::get_list) {
+            let listed: HashMap<String, DrawingMeta> = self.inner.list().await?.into_iter().map(|d| (d.id.clone(), d)).collect();
+            for meta in &cached {
+                if listed.get(&meta.id).is_none_or(|d| d.content_hash != meta.content_hash) {
+                    report.stale_index_entries.push(meta.id.clone());
+                }
+            }
+            let cached_ids: std::collections::HashSet<&str> = cached.iter().map(|d| d.id.as_str()).collect();
+            report.stale_index_entries.extend(listed.into_keys().filter(|id| !cached_ids.contains(id.as_str())));
+            report.stale_index_entries.sort();
+            report.stale_index_entries.dedup();
+        }
+        Ok(report)
+    }
+
+    async fn history(&self, id: &str) -> Result<Vec<DrawingVersion>, AppError> {
+        self.inner.history(id).await
+    }
+
+    async fn load_version(&self, id: &str, version: u64) -> Result<serde_json::Value, AppError> {
+        self.inner.load_version(id, version).await
+    }
+
+    async fn trash(&self, id: &str) -> Result<(), AppError> {
+        self.write(id, self.inner.trash(id)).await
+    }
+
+    async fn list_trash(&self) -> Result<Vec<TrashedDrawing>, AppError> {
+        self.inner.list_trash().await
+    }
+
+    async fn restore(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        self.write(id, self.inner.restore(id)).await
+    }
+
+    async fn purge_trash(&self, before: DateTime<Utc>) -> Result<Vec<String>, AppError> {
+        self.inner.purge_trash(before).await
+    }
+}
+
+#[derive(Default)]
+struct RenderCacheInner {
+    /// key -> (rendered bytes, `last_used` tick)
+    entries: HashMap<String, (axum::body::Bytes, u64)>,
+    /// `last_used` tick -> key, oldest first
+    recency: BTreeMap<u64, String>,
+    used_bytes: usize,
+    tick: u64,
+}
+
+/// Rendered images (LRU) bounded by a memory budget. Keys include the content
+/// hash of what was rendered, so entries never go stale and are only evicted.
+#[derive(Clone)]
+pub struct RenderCache {
+    budget_bytes: usize,
+    inner: Arc<Mutex<RenderCacheInner>>,
+}
+
+impl RenderCache {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            budget_bytes,
+            inner: Arc::new(Mutex::new(RenderCacheInner::default())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<axum::body::Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let (bytes, last_used) = inner.entries.get_mut(key)?;
+        let previous = std::mem::replace(last_used, tick);
+        let bytes = bytes.clone();
+        inner.recency.remove(&previous);
+        inner.recency.insert(tick, key.to_string());
+        Some(bytes)
+    }
+
+    pub fn put(&self, key: &str, bytes: axum::body::Bytes) {
+        if bytes.len() > self.budget_bytes {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.used_bytes += bytes.len();
+        if let Some((old, last_used)) = inner.entries.insert(key.to_string(), (bytes, tick)) {
+            inner.used_bytes -= old.len();
+            inner.recency.remove(&last_used);
+        }
+        inner.recency.insert(tick, key.to_string());
+        while inner.used_bytes > self.budget_bytes {
+            let Some((_, oldest)) = inner.recency.pop_first() else { break };
+            if let Some((old, _)) = inner.entries.remove(&oldest) {
+                inner.used_bytes -= old.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_cache_evicts_least_recently_used() {
+        let cache = RenderCache::new(10);
+        cache.put("a", vec![0; 4].into());
+        cache.put("b", vec![0; 4].into());
+        assert!(cache.get("a").is_some());
+        cache.put("c", vec![0; 4].into());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some() && cache.get("c").is_some());
+        cache.put("huge", vec![0; 11].into());
+        assert!(cache.get("huge").is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_over_budget() {
+        let cache = StorageCache::new(250);
+        cache.put_drawing("a", &json!({"id": "a"}), 100, 0);
+        cache.put_drawing("b", &json!({"id": "b"}), 100, 0);
+        // Touch `a` so `b` is the oldest
+        assert!(cache.get_drawing("a").is_some());
+
+        cache.put_drawing("c", &json!({"id": "c"}), 100, 0);
+        assert!(cache.get_drawing("b").is_none());
+        assert!(cache.get_drawing("a").is_some());
+        assert!(cache.get_drawing("c").is_some());
+        assert_eq!(cache.usage().used_bytes, 200);
+        assert_eq!(cache.usage().cached_drawings, 2);
+    }
+
+    #[test]
+    fn test_max_drawings_and_hit_counts() {
+        let cache = StorageCache::new(1000).with_max_drawings(2);
+        cache.put_drawing("a", &json!({}), 10, 0);
+        cache.put_drawing("b", &json!({}), 10, 0);
+        cache.put_drawing("c", &json!({}), 10, 0);
+        assert!(cache.get_drawing("a").is_none());
+        assert!(cache.get_drawing("b").is_some());
+        assert!(cache.get_drawing("c").is_some());
+
+        let usage = cache.usage();
+        assert_eq!((usage.cached_drawings, usage.hits, usage.misses), (2, 2, 1));
+    }
+
+    #[test]
+    fn test_listing_counts_toward_budget() {
+        let cache = StorageCache::new(META_OVERHEAD_BYTES * 3);
+        cache.put_drawing("a", &json!({}), META_OVERHEAD_BYTES, 0);
+        let meta = DrawingMeta {
+            id: "a".to_string(),
+            created_at: chrono::Utc::now(),
+            modified_at: chrono::Utc::now(),
+            size_bytes: 2,
+            source_path: None,
+            password_protected: false,
+            persistent_collab: false,
+            tags: Vec::new(),
+            title: None,
+            slug: None,
+            user_agent: None,
+            expires_at: None,
+            views: 0,
+            content_hash: None,
+        };
+        cache.put_list(&[meta.clone(), meta], 0);
+
+        // The listing alone fills the budget, so the drawing is evicted
+        assert!(cache.get_drawing("a").is_none());
+        assert!(cache.get_list().is_some());
+        assert!(cache.usage().used_bytes <= cache.usage().budget_bytes);
+    }
+
+    #[tokio::test]
+    async fn test_cached_storage_drops_entries_on_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let sqlite = crate::storage::SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        let storage = CachedStorage::new(sqlite).with_cache(1024 * 1024, 0);
+        storage.save("a", &json!({"type": "excalidraw", "elements": []}), None, None).await.unwrap();
+
+        storage.load("a").await.unwrap();
+        storage.load("a").await.unwrap();
+        assert_eq!(storage.list().await.unwrap().len(), 1);
+        let usage = storage.cache_usage().unwrap();
+        assert_eq!((usage.cached_drawings, usage.hits, usage.misses), (1, 1, 1));
+
+        let updated = json!({"type": "excalidraw", "elements": [{"id": "x", "type": "text"}]});
+        storage.save("a", &updated, None, None).await.unwrap();
+        storage.save("b", &updated, None, None).await.unwrap();
+        assert_eq!(storage.load("a").await.unwrap()["elements"], updated["elements"]);
+        assert_eq!(storage.list().await.unwrap().len(), 2);
+
+        storage.delete("a").await.unwrap();
+        assert!(matches!(storage.load("a").await, Err(AppError::NotFound)));
+        assert_eq!(storage.list().await.unwrap().len(), 1);
+        assert!(storage.verify().await.unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_reads_racing_a_write_are_not_cached() {
+        let cache = StorageCache::new(1000);
+        let generation = cache.generation();
+        cache.invalidate("a");
+        cache.put_drawing("a", &json!({"stale": true}), 10, generation);
+        assert!(cache.get_drawing("a").is_none());
+    }
+}
@@ -365,6 +365,7 @@ impl SessionManager {
     }
 
     /// Check if a drawing has persistent collab registered.
+    #[allow(dead_code)]
     pub async fn is_persistent_drawing(&self, drawing_id: &str) -> bool {
         self.persistent_drawings.read().await.contains(drawing_id)
     }
@@ -510,6 +511,7 @@ impl SessionManager {
 
     /// Get session status for a drawing (public info).
     /// Returns (session_id, participant_count, password_required).
+    #[allow(dead_code)]
     pub async fn get_session_status(&self, drawing_id: &str) -> Option<(String, usize, bool)> {
         let drawing_sessions = self.drawing_sessions.read().await;
         if let Some(session_id) = drawing_sessions.get(drawing_id) {
@@ -932,6 +934,7 @@ impl SessionManager {
     }
 
     /// Broadcast a pointer update to all participants.
+    #[allow(clippy::too_many_arguments)]
     pub async fn broadcast_pointer(
         &self,
         session_id: &str,
@@ -1157,7 +1160,7 @@ impl SessionManager {
     /// Expired sessions are saved to storage before being removed to prevent data loss.
     /// Persistent sessions use idle-based cleanup (no participants + 30 min idle)
     /// instead of timeout-based cleanup.
-    pub async fn cleanup_expired(&self, storage: &crate::storage::FileSystemStorage) {
+    pub async fn cleanup_expired(&self, storage: &crate::storage::Storage) {
         use crate::storage::DrawingStorage;
 
         let now = Utc::now();
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// How long a delete confirm token stays valid after the prepare step.
+pub const CONFIRM_TOKEN_TTL_SECS: u64 = 120;
+
+/// Short-lived, single-use tokens guarding destructive operations.
+/// A token is bound to the drawing it was issued for.
+#[derive(Clone)]
+pub struct ConfirmTokens {
+    /// token -> (drawing_id, expiry)
+    tokens: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+    ttl: Duration,
+}
+
+impl ConfirmTokens {
+    pub fn new() -> Self {
+        Self::with_ttl(Duration::from_secs(CONFIRM_TOKEN_TTL_SECS))
+    }
+
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            tokens: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    /// Issue a new token for `drawing_id`. Expired tokens are pruned on the way.
+    pub async fn issue(&self, drawing_id: &str) -> String {
+        let token = Uuid::new_v4().to_string().replace('-', "");
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().await;
+        tokens.retain(|_, (_, expires)| *expires > now);
+        tokens.insert(token.clone(), (drawing_id.to_string(), now + self.ttl));
+        token
+    }
+
+    /// Consume a token. Returns true only if it exists, hasn't expired and was
+    /// issued for `drawing_id`. The token is removed either way.
+    pub async fn consume(&self, drawing_id: &str, token: &str) -> bool {
+        match self.tokens.lock().await.remove(token) {
+            Some((id, expires)) => id == drawing_id && expires > Instant::now(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_token_is_single_use_and_bound_to_drawing() {
+        let tokens = ConfirmTokens::new();
+        let token = tokens.issue("abc").await;
+        assert!(!tokens.consume("other", &token).await);
+
+        let token = tokens.issue("abc").await;
+        assert!(tokens.consume("abc", &token).await);
+        assert!(!tokens.consume("abc", &token).await);
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let tokens = ConfirmTokens::with_ttl(Duration::from_millis(0));
+        let token = tokens.issue("abc").await;
+        assert!(!tokens.consume("abc", &token).await);
+    }
+}
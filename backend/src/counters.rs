@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::storage::{DrawingStorage, Storage};
+
+/// View counts batched in memory so serving a drawing doesn't write to disk.
+/// Pending deltas are persisted by [`ViewCounter::flush`], which runs on an
+/// interval and once more on graceful shutdown.
+#[derive(Clone, Default)]
+pub struct ViewCounter {
+    /// drawing id -> views not yet persisted
+    pending: Arc<Mutex<HashMap<String, u64>>>,
+    /// Held for a whole flush, so the shutdown flush waits for an interval
+    /// flush still writing the deltas it took
+    flushing: Arc<Mutex<()>>,
+}
+
+impl ViewCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one view of `id`.
+    pub async fn increment(&self, id: &str) {
+        *self.pending.lock().await.entry(id.to_string()).or_default() += 1;
+    }
+
+    /// Views of `id` counted but not yet persisted.
+    pub async fn pending(&self, id: &str) -> u64 {
+        self.pending.lock().await.get(id).copied().unwrap_or(0)
+    }
+
+    /// Persist all pending deltas; returns how many drawings were updated.
+    /// Deltas for deleted drawings are dropped, and deltas that failed to write
+    /// are put back so the next flush retries them.
+    pub async fn flush(&self, storage: &Storage) -> usize {
+        let _flushing = self.flushing.lock().await;
+        let pending = std::mem::take(&mut *self.pending.lock().await);
+        let mut flushed = 0;
+        for (id, delta) in pending {
+            match storage.add_views(&id, delta).await {
+                Ok(_) => flushed += 1,
+                Err(AppError::NotFound) => {}
+                Err(e) => {
+                    tracing::warn!(id = %id, error = %e, "Failed to persist view count");
+                    *self.pending.lock().await.entry(id).or_default() += delta;
+                }
+            }
+        }
+        flushed
+    }
+
+    /// Flush every `interval` in the background.
+    pub fn spawn_flusher(&self, storage: Storage, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let counter = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                counter.flush(&storage).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileSystemStorage;
+    use serde_json::json;
+
+    async fn storage_with_drawing(dir: &std::path::Path) -> Storage {
+        let storage: Storage = FileSystemStorage::new(dir).await.unwrap().into();
+        storage
+            .save("counted", &json!({"type": "excalidraw", "elements": []}), None, None)
+            .await
+            .unwrap();
+        storage
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_counts_persisted_after_flush_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage_with_drawing(dir.path()).await;
+        let counter = ViewCounter::new();
+        let interval = Duration::from_secs(60);
+        let flusher = counter.spawn_flusher(storage.clone(), interval);
+
+        for _ in 0..3 {
+            counter.increment("counted").await;
+        }
+        // The clock is paused, so nothing is flushed until the interval has passed
+        tokio::time::sleep(interval - Duration::from_millis(1)).await;
+        assert_eq!(storage.get_meta("counted").await.unwrap().views, 0);
+        assert_eq!(counter.pending("counted").await, 3);
+
+        // Wake just after the tick, by when the flusher holds the flush lock;
+        // taking it waits for the deltas to be written
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        drop(counter.flushing.lock().await);
+        flusher.abort();
+        assert_eq!(storage.get_meta("counted").await.unwrap().views, 3);
+        assert_eq!(counter.pending("counted").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_counts_persisted_on_shutdown_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage_with_drawing(dir.path()).await;
+        let counter = ViewCounter::new();
+        counter.increment("counted").await;
+        counter.increment("counted").await;
+        counter.increment("deleted-meanwhile").await;
+
+        // What main does once the server has stopped
+        assert_eq!(counter.flush(&storage).await, 1);
+
+        let reopened = FileSystemStorage::new(dir.path()).await.unwrap();
+        assert_eq!(reopened.get_meta("counted").await.unwrap().views, 2);
+        assert_eq!(counter.pending("deleted-meanwhile").await, 0);
+    }
+}
@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// `frame-ancestors` sources allowed to embed drawing views, e.g. `'self' https://docs.example.com`.
+#[derive(Clone)]
+pub struct FrameAncestors(pub String);
+
+impl FrameAncestors {
+    /// Build from a comma- or whitespace-separated list of origins.
+    /// An empty list falls back to `'self'`.
+    pub fn from_list(origins: &str) -> Self {
+        let sources: Vec<&str> = origins
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if sources.is_empty() {
+            Self("'self'".to_string())
+        } else {
+            Self(sources.join(" "))
+        }
+    }
+}
+
+/// Drawing views (the `/d/{id}` viewer page and `/api/view/...` data) are what
+/// other sites embed; everything else may not be framed at all.
+fn is_embeddable(path: &str) -> bool {
+    path.starts_with("/d/") || path.starts_with("/api/view/")
+}
+
+/// Middleware that sets `Content-Security-Policy: frame-ancestors ...` to protect
+/// against clickjacking while letting configured sites iframe drawings.
+pub async fn frame_ancestors_middleware(
+    State(ancestors): State<FrameAncestors>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let embeddable = is_embeddable(request.uri().path());
+    let mut response = next.run(request).await;
+
+    let sources = if embeddable { ancestors.0.as_str() } else { "'none'" };
+    if let Ok(value) = HeaderValue::from_str(&format!("frame-ancestors {sources}")) {
+        response
+            .headers_mut()
+            .entry(header::CONTENT_SECURITY_POLICY)
+            .or_insert(value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn csp_for(ancestors: FrameAncestors, path: &str) -> String {
+        let app = Router::new()
+            .route("/d/{id}", get(|| async { "view" }))
+            .route("/admin", get(|| async { "admin" }))
+            .layer(middleware::from_fn_with_state(ancestors, frame_ancestors_middleware));
+        let response = app
+            .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.headers()[header::CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_frame_ancestors_reflects_configured_origins() {
+        let ancestors = FrameAncestors::from_list("https://a.example, https://b.example");
+        assert_eq!(
+            csp_for(ancestors.clone(), "/d/abc").await,
+            "frame-ancestors https://a.example https://b.example"
+        );
+        assert_eq!(csp_for(ancestors, "/admin").await, "frame-ancestors 'none'");
+    }
+
+    #[tokio::test]
+    async fn test_frame_ancestors_defaults_to_self() {
+        let ancestors = FrameAncestors::from_list("");
+        assert_eq!(csp_for(ancestors, "/d/abc").await, "frame-ancestors 'self'");
+    }
+}
@@ -7,18 +7,46 @@ pub enum AppError {
     #[error("Drawing not found")]
     NotFound,
 
+    #[error("Drawing is no longer available")]
+    Gone,
+
     #[error("Unauthorized: invalid or missing API key")]
     Unauthorized,
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Invalid input: {0}")]
     BadRequest(String),
 
     #[error("Payload too large")]
     PayloadTooLarge,
 
+    #[error("Response too large: {0}")]
+    ResponseTooLarge(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
+    /// Out of request budget; the client may retry after this many seconds
+    #[error("Rate limit exceeded, retry in {0}s")]
+    RateLimited(u64),
+
     #[error("Storage error: {0}")]
     Storage(#[from] std::io::Error),
 
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
@@ -44,12 +72,23 @@ pub enum AppError {
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
+    /// Id of the failed request, to find its log lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    fn new(error: String) -> Self {
+        Self { error, request_id: crate::request_id::current() }
+    }
 }
 
 #[derive(Serialize)]
 struct PasswordErrorResponse {
     error: String,
     password_protected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 impl axum::response::IntoResponse for AppError {
@@ -58,13 +97,29 @@ impl axum::response::IntoResponse for AppError {
 
         let (status, message) = match &self {
             AppError::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            AppError::Gone => (StatusCode::GONE, self.to_string()),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AppError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
             AppError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            AppError::ResponseTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            AppError::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::PreconditionFailed(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            AppError::QuotaExceeded(_) => (StatusCode::INSUFFICIENT_STORAGE, self.to_string()),
+            AppError::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AppError::RateLimited(retry_after_secs) => {
+                let body = axum::Json(ErrorResponse::new(self.to_string()));
+                let retry_after = [(axum::http::header::RETRY_AFTER, retry_after_secs.to_string())];
+                return (StatusCode::TOO_MANY_REQUESTS, retry_after, body).into_response();
+            }
             AppError::Storage(e) => {
                 tracing::error!("Storage error: {e}");
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
             }
+            AppError::Database(e) => {
+                tracing::error!("Database error: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
+            }
             AppError::Json(e) => {
                 tracing::error!("JSON error: {e}");
                 (StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}"))
@@ -80,12 +135,17 @@ impl axum::response::IntoResponse for AppError {
                 let body = PasswordErrorResponse {
                     error: self.to_string(),
                     password_protected: true,
+                    request_id: crate::request_id::current(),
                 };
                 return (StatusCode::FORBIDDEN, axum::Json(body)).into_response();
             }
         };
 
-        let body = axum::Json(ErrorResponse { error: message });
-        (status, body).into_response()
+        let body = axum::Json(ErrorResponse::new(message));
+        let mut response = (status, body).into_response();
+        if matches!(self, AppError::Storage(_) | AppError::Database(_)) {
+            response.extensions_mut().insert(crate::breaker::StorageFailure);
+        }
+        response
     }
 }
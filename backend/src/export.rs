@@ -0,0 +1,89 @@
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio_util::io::ReaderStream;
+
+use crate::error::AppError;
+use crate::storage::{DrawingMeta, DrawingStorage, Storage};
+
+/// Buffer between the zip writer task and the response body stream.
+const EXPORT_PIPE_BYTES: usize = 64 * 1024;
+
+/// `manifest.json` at the root of an export archive.
+#[derive(Serialize)]
+pub struct ExportManifest<'a> {
+    pub exported_at: DateTime<Utc>,
+    pub drawings: &'a [DrawingMeta],
+}
+
+/// The `_share` copy of a drawing's metadata added to exported documents with
+/// `--embed-metadata`, so a file that loses its other internal fields (e.g.
+/// edited by another tool) still imports with them.
+pub fn embedded_metadata(meta: &DrawingMeta) -> serde_json::Value {
+    serde_json::json!({
+        "created_at": meta.created_at.to_rfc3339(),
+        "source_path": meta.source_path,
+        "tags": meta.tags,
+        "title": meta.title,
+    })
+}
+
+/// Stream a ZIP archive of the given drawings (`<id>.json` each, as stored,
+/// plus `manifest.json`). With `embed_metadata` each document gets its
+/// [`embedded_metadata`]; without it, any `_share` a document was stored with
+/// is left out. Drawings are loaded one at a time on a background task writing
+/// into a bounded pipe, so memory stays flat however many there are.
+pub fn zip_stream(storage: Storage, drawings: Vec<DrawingMeta>, embed_metadata: bool) -> Body {
+    let (reader, writer) = tokio::io::duplex(EXPORT_PIPE_BYTES);
+
+    tokio::spawn(async move {
+        let result = async {
+            let mut zip = ZipFileWriter::with_tokio(writer);
+
+            for meta in &drawings {
+                let mut data = match storage.load(&meta.id).await {
+                    Ok(data) => data,
+                    // Deleted between listing and export
+                    Err(AppError::NotFound) => continue,
+                    Err(e) => return Err(e),
+                };
+                if let Some(obj) = data.as_object_mut() {
+                    if embed_metadata {
+                        obj.insert("_share".to_string(), embedded_metadata(meta));
+                    } else {
+                        obj.remove("_share");
+                    }
+                }
+                let bytes = serde_json::to_vec(&data)?;
+                let entry = ZipEntryBuilder::new(format!("{}.json", meta.id).into(), Compression::Deflate);
+                zip.write_entry_whole(entry, &bytes)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to write zip entry: {e}")))?;
+            }
+
+            let manifest = serde_json::to_vec_pretty(&ExportManifest {
+                exported_at: Utc::now(),
+                drawings: &drawings,
+            })?;
+            let entry = ZipEntryBuilder::new("manifest.json".to_string().into(), Compression::Deflate);
+            zip.write_entry_whole(entry, &manifest)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to write zip manifest: {e}")))?;
+            zip.close()
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to finish zip: {e}")))?;
+            Ok::<(), AppError>(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => tracing::info!(count = drawings.len(), "Drawing export completed"),
+            // The client sees a truncated archive; there is no way to signal mid-stream
+            Err(e) => tracing::error!(error = %e, "Drawing export failed"),
+        }
+    });
+
+    Body::from_stream(ReaderStream::new(reader))
+}
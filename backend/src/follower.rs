@@ -0,0 +1,83 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+/// Read-only follower configuration: where writes should go instead.
+#[derive(Clone)]
+pub struct Follower {
+    /// Base URL of the primary instance, if known
+    pub primary_url: Option<String>,
+}
+
+/// Middleware for follower mode: reads pass through, every request that could
+/// change state gets a 503 pointing at the primary (with a `Location` header
+/// for the same path there, when the primary is known).
+pub async fn reject_writes_middleware(
+    State(follower): State<Follower>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    let primary = follower.primary_url.as_deref().map(|u| u.trim_end_matches('/'));
+    let body = Json(serde_json::json!({
+        "error": "This instance is a read-only follower; send writes to the primary",
+        "primary": primary,
+    }));
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, body).into_response();
+    let location = primary.map(|p| format!("{p}{}", request.uri().path()));
+    if let Some(value) = location.and_then(|l| HeaderValue::from_str(&l).ok()) {
+        response.headers_mut().insert(header::LOCATION, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn app(primary_url: Option<&str>) -> Router {
+        Router::new()
+            .route("/api/view/{id}", get(|| async { "drawing" }))
+            .route("/api/upload", axum::routing::post(|| async { "stored" }))
+            .layer(middleware::from_fn_with_state(
+                Follower { primary_url: primary_url.map(String::from) },
+                reject_writes_middleware,
+            ))
+    }
+
+    fn request(method: Method, uri: &str) -> Request {
+        Request::builder().method(method).uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_follower_serves_reads() {
+        let response = app(None).oneshot(request(Method::GET, "/api/view/abc")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_follower_refuses_writes_with_pointer_to_primary() {
+        let response = app(Some("https://primary.example.com/"))
+            .oneshot(request(Method::POST, "/api/upload"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::LOCATION], "https://primary.example.com/api/upload");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["primary"], "https://primary.example.com");
+
+        let response = app(None).oneshot(request(Method::DELETE, "/api/upload")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!response.headers().contains_key(header::LOCATION));
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::storage::DrawingStorage;
+
+/// One point of the storage usage time series.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageSample {
+    pub timestamp: DateTime<Utc>,
+    pub drawing_count: usize,
+    pub total_bytes: u64,
+}
+
+/// Append-only storage usage history, one JSON sample per line.
+#[derive(Clone)]
+pub struct UsageHistory {
+    path: PathBuf,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl UsageHistory {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Record the current drawing count and total size.
+    pub async fn sample(&self, storage: &impl DrawingStorage) -> Result<UsageSample, AppError> {
+        let stats = storage.stats().await?;
+        let sample = UsageSample {
+            timestamp: Utc::now(),
+            drawing_count: stats.drawing_count,
+            total_bytes: stats.total_bytes,
+        };
+        self.append(&sample).await?;
+        Ok(sample)
+    }
+
+    async fn append(&self, sample: &UsageSample) -> Result<(), AppError> {
+        let mut line = serde_json::to_vec(sample)?;
+        line.push(b'\n');
+        let _guard = self.write_lock.lock().await;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// Samples within `[from, to]` (either bound optional), oldest first.
+    /// Unparseable lines (e.g. a write cut short by a crash) are skipped.
+    pub async fn range(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSample>, AppError> {
+        let contents = match fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str::<UsageSample>(line).ok())
+            .filter(|s| from.is_none_or(|from| s.timestamp >= from))
+            .filter(|s| to.is_none_or(|to| s.timestamp <= to))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileSystemStorage;
+
+    #[tokio::test]
+    async fn test_sampler_appends_and_range_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        let history = UsageHistory::new(dir.path().join(".usage-history"));
+
+        let first = history.sample(&storage).await.unwrap();
+        assert_eq!(first.drawing_count, 0);
+
+        let doc = serde_json::json!({"type": "excalidraw", "elements": []});
+        storage.save("a", &doc, None, None).await.unwrap();
+        let second = history.sample(&storage).await.unwrap();
+        assert_eq!(second.drawing_count, 1);
+        assert!(second.total_bytes > 0);
+
+        assert_eq!(history.range(None, None).await.unwrap(), vec![first.clone(), second.clone()]);
+        assert_eq!(history.range(Some(second.timestamp), None).await.unwrap(), vec![second]);
+        assert_eq!(history.range(None, Some(first.timestamp)).await.unwrap(), vec![first]);
+    }
+}
@@ -0,0 +1,752 @@
+use async_zip::base::read::mem::ZipFileReader;
+use futures::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::error::AppError;
+use crate::storage::{self, DrawingStorage};
+use crate::validation::{is_valid_id, validate_created_at, validate_excalidraw};
+
+/// Outcome of a directory import.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Files whose `source_path` is already published
+    pub skipped: usize,
+    /// (relative path, reason) for files that could not be imported
+    pub failed: Vec<(String, String)>,
+}
+
+/// Recursively collect `.excalidraw` files under `dir`, sorted for stable output.
+async fn collect_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = fs::read_dir(&current).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                pending.push(path);
+            } else if path.extension().and_then(|e| e.to_str()) == Some("excalidraw") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Publish every `.excalidraw` file under `dir`, using the path relative to
+/// `dir` (with `/` separators) as the drawing's `source_path`.
+pub async fn import_dir(storage: &impl DrawingStorage, dir: &Path) -> Result<ImportSummary, AppError> {
+    let mut summary = ImportSummary::default();
+
+    for path in collect_files(dir).await? {
+        let source_path = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let result = async {
+            let bytes = fs::read(&path).await?;
+            let data: serde_json::Value = serde_json::from_slice(&bytes)?;
+            validate_excalidraw(&data)?;
+
+            if storage.find_by_source_path(&source_path).await?.is_some() {
+                return Ok(false);
+            }
+            let id = storage::generate_id(storage).await?;
+            storage.save(&id, &data, Some(&source_path), None).await?;
+            Ok::<bool, AppError>(true)
+        }
+        .await;
+
+        match result {
+            Ok(true) => summary.imported += 1,
+            Ok(false) => summary.skipped += 1,
+            Err(e) => summary.failed.push((source_path, e.to_string())),
+        }
+    }
+
+    Ok(summary)
+}
+
+/// What to do when an archive entry's id already exists in this instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdConflict {
+    /// Keep the existing drawing and ignore the imported one
+    #[default]
+    Skip,
+    /// Replace the existing drawing with the imported one
+    Overwrite,
+    /// Store the imported drawing under a fresh id
+    Rename,
+}
+
+/// Outcome of an archive import, returned by `POST /api/import`.
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveImportSummary {
+    /// Ids imported as-is (including overwritten ones)
+    pub imported: Vec<String>,
+    /// Ids that already existed and were left untouched
+    pub skipped: Vec<String>,
+    /// Original id -> newly assigned id, for entries imported under `rename`
+    pub renamed: BTreeMap<String, String>,
+    /// Entries that could not be imported
+    pub failed: Vec<ImportFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportFailure {
+    pub entry: String,
+    pub error: String,
+}
+
+/// An archive entry that has been read and validated, to be stored under `id`.
+struct StagedDrawing {
+    entry: String,
+    original_id: String,
+    id: String,
+    data: serde_json::Value,
+}
+
+impl ArchiveImportSummary {
+    fn record(&mut self, drawing: StagedDrawing) {
+        if drawing.id == drawing.original_id {
+            self.imported.push(drawing.id);
+        } else {
+            self.renamed.insert(drawing.original_id, drawing.id);
+        }
+    }
+}
+
+/// Save an exported drawing, keeping its source path and password hash.
+async fn store(storage: &impl DrawingStorage, id: &str, data: &serde_json::Value) -> Result<(), AppError> {
+    let source_path = data.get("_source_path").and_then(|v| v.as_str());
+    let password_hash = data.get("_password_hash").and_then(|v| v.as_str());
+    storage.save(id, data, source_path, password_hash).await?;
+    Ok(())
+}
+
+/// Fill internal fields missing from `data` from its `_share` metadata (written
+/// with `--embed-metadata`), so a document that lost them still imports with
+/// its creation time, source path, tags and title.
+fn restore_embedded_metadata(data: &mut serde_json::Value) {
+    let Some(share) = data.get("_share").and_then(|s| s.as_object()).cloned() else {
+        return;
+    };
+    let Some(obj) = data.as_object_mut() else {
+        return;
+    };
+    for (field, key) in [
+        ("created_at", "_created_at"),
+        ("source_path", "_source_path"),
+        ("tags", "_tags"),
+        ("title", "_title"),
+    ] {
+        match share.get(field) {
+            Some(value) if !value.is_null() && !obj.contains_key(key) => {
+                obj.insert(key.to_string(), value.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Store all staged drawings or none of them. If a save fails, the drawings
+/// already written are rolled back and the failing entry is returned.
+async fn commit_all(storage: &impl DrawingStorage, staged: &[StagedDrawing]) -> Result<(), ImportFailure> {
+    let mut committed = Vec::new();
+    for drawing in staged {
+        let result = async {
+            let previous = match storage.load(&drawing.id).await {
+                Ok(data) => Some(data),
+                Err(AppError::NotFound) => None,
+                Err(e) => return Err(e),
+            };
+            store(storage, &drawing.id, &drawing.data).await?;
+            Ok(previous)
+        }
+        .await;
+
+        match result {
+            Ok(previous) => committed.push((drawing.id.as_str(), previous)),
+            Err(e) => {
+                rollback(storage, committed).await;
+                return Err(ImportFailure { entry: drawing.entry.clone(), error: e.to_string() });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Undo committed saves, newest first: overwritten drawings get their previous
+/// content back, drawings the import created are discarded without a tombstone.
+async fn rollback(storage: &impl DrawingStorage, committed: Vec<(&str, Option<serde_json::Value>)>) {
+    for (id, previous) in committed.into_iter().rev() {
+        let result = match previous {
+            Some(data) => store(storage, id, &data).await,
+            None => storage.discard(id).await,
+        };
+        if let Err(e) = result {
+            tracing::error!(id = %id, error = %e, "Failed to roll back atomic import");
+        }
+    }
+}
+
+/// Caps on what an archive import inflates, whatever sizes the archive
+/// declares: how many entries it may have, and how many uncompressed bytes
+/// each entry and all of them together may take.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportLimits {
+    pub max_entries: usize,
+    pub max_entry_bytes: u64,
+    pub max_total_bytes: u64,
+}
+
+/// An archive being imported, its entries read against the [`ImportLimits`].
+struct Archive {
+    zip: ZipFileReader,
+    limits: ImportLimits,
+    /// Uncompressed bytes the import may still read
+    remaining: u64,
+    /// Set once an entry ran into `max_total_bytes`
+    over_budget: bool,
+}
+
+impl Archive {
+    async fn open(archive: Vec<u8>, limits: ImportLimits) -> Result<Self, AppError> {
+        let zip = ZipFileReader::new(archive)
+            .await
+            .map_err(|e| AppError::BadRequest(format!("Invalid export archive: {e}")))?;
+        let entries = zip.file().entries().len();
+        if entries > limits.max_entries {
+            return Err(AppError::BadRequest(format!(
+                "Export archive has {entries} entries, more than the {} allowed",
+                limits.max_entries
+            )));
+        }
+        Ok(Self { zip, limits, remaining: limits.max_total_bytes, over_budget: false })
+    }
+
+    /// Entry `index`, uncompressed and checked against its CRC. Reading stops
+    /// one byte past the entry's share of the limits, so a small archive can't
+    /// inflate into more memory than they allow.
+    async fn read(&mut self, index: usize) -> Result<Vec<u8>, AppError> {
+        let unreadable = |e: &dyn std::fmt::Display| AppError::BadRequest(format!("Unreadable entry: {e}"));
+        let limit = self.limits.max_entry_bytes.min(self.remaining);
+        let mut reader = self.zip.reader_with_entry(index).await.map_err(|e| unreadable(&e))?;
+        let mut bytes = Vec::new();
+        (&mut reader).take(limit + 1).read_to_end(&mut bytes).await.map_err(|e| unreadable(&e))?;
+        self.remaining = self.remaining.saturating_sub(bytes.len() as u64);
+        if bytes.len() as u64 > limit {
+            self.over_budget |= limit < self.limits.max_entry_bytes;
+            return Err(AppError::BadRequest(format!("Entry expands past {limit} bytes")));
+        }
+        if reader.compute_hash() != reader.entry().crc32() {
+            return Err(unreadable(&"CRC32 check failed"));
+        }
+        Ok(bytes)
+    }
+}
+
+/// The fields of an export's `manifest.json` entries used on import.
+#[derive(Deserialize)]
+struct ManifestEntry {
+    id: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    source_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    drawings: Vec<ManifestEntry>,
+}
+
+/// The archive's `manifest.json` entries by id. An archive without a readable
+/// one isn't an export and is refused as a whole.
+async fn read_manifest(archive: &mut Archive) -> Result<BTreeMap<String, ManifestEntry>, AppError> {
+    let index = archive
+        .zip
+        .file()
+        .entries()
+        .iter()
+        .position(|e| e.filename().as_str().is_ok_and(|name| name == "manifest.json"))
+        .ok_or_else(|| AppError::BadRequest("Export archive has no manifest.json".into()))?;
+    let contents = archive
+        .read(index)
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Unreadable manifest.json: {e}")))?;
+    let manifest: Manifest = serde_json::from_slice(&contents)
+        .map_err(|e| AppError::BadRequest(format!("Invalid manifest.json: {e}")))?;
+    Ok(manifest.drawings.into_iter().map(|d| (d.id.clone(), d)).collect())
+}
+
+/// The first file in a `multipart/form-data` body, for clients sending the
+/// archive as a form upload (`curl -F archive=@export.zip`).
+pub fn multipart_file(content_type: &str, body: &[u8]) -> Result<Vec<u8>, AppError> {
+    let boundary = content_type
+        .split(';')
+        .skip(1)
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|b| b.trim_matches('"'))
+        .filter(|b| !b.is_empty())
+        .ok_or_else(|| AppError::BadRequest("Multipart body without a boundary".into()))?;
+    let delimiter = format!("\r\n--{boundary}");
+    // The first delimiter may open the body without a preceding line break
+    let mut rest = match body.strip_prefix(&delimiter.as_bytes()[2..]) {
+        Some(rest) => rest,
+        None => &body[find(body, delimiter.as_bytes()).map_or(body.len(), |i| i + delimiter.len())..],
+    };
+    while let Some(end) = find(rest, delimiter.as_bytes()) {
+        let part = rest[..end].strip_prefix(b"\r\n").unwrap_or(&rest[..end]);
+        rest = &rest[end + delimiter.len()..];
+        let Some(headers_end) = find(part, b"\r\n\r\n") else { continue };
+        let headers = String::from_utf8_lossy(&part[..headers_end]).to_lowercase();
+        if headers.contains("filename=") {
+            return Ok(part[headers_end + 4..].to_vec());
+        }
+    }
+    Err(AppError::BadRequest("Multipart body has no file".into()))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Restore drawings from a ZIP produced by `GET /api/export`. Each `<id>.json`
+/// entry is stored under its original id (with its source path and password
+/// hash), resolving collisions with existing drawings according to `policy`.
+/// Drawings keep their original `created_at`, from the document's own
+/// `_created_at` or else the manifest; a future date fails the entry. The
+/// manifest's `source_path` likewise fills in for a missing `_source_path`, and
+/// archives without a manifest are rejected.
+/// Metadata embedded under `_share` fills in any internal fields that are missing.
+///
+/// With `atomic`, every entry is read and validated before anything is stored,
+/// and the import either stores all of them or none: any failure leaves the
+/// instance as it was and is reported in `failed`.
+///
+/// An entry inflating past `limits.max_entry_bytes` fails; an archive with
+/// more than `limits.max_entries` entries, or inflating past
+/// `limits.max_total_bytes` in all, is refused, the latter as soon as it gets there.
+pub async fn import_archive(
+    storage: &impl DrawingStorage,
+    archive: Vec<u8>,
+    policy: IdConflict,
+    atomic: bool,
+    limits: ImportLimits,
+) -> Result<ArchiveImportSummary, AppError> {
+    let mut archive = Archive::open(archive, limits).await?;
+    let manifest = read_manifest(&mut archive).await?;
+    let mut summary = ArchiveImportSummary::default();
+    let mut staged = Vec::new();
+
+    for index in 0..archive.zip.file().entries().len() {
+        let entry_name = match archive.zip.file().entries()[index].filename().as_str() {
+            Ok(name) => name.to_string(),
+            Err(_) => continue,
+        };
+        let Some(original_id) = entry_name.strip_suffix(".json") else {
+            continue;
+        };
+        if entry_name == "manifest.json" {
+            continue;
+        }
+        let original_id = original_id.to_string();
+
+        let result = async {
+            if !is_valid_id(&original_id) {
+                return Err(AppError::BadRequest("Invalid drawing id".into()));
+            }
+            let contents = archive.read(index).await?;
+            let mut data: serde_json::Value = serde_json::from_slice(&contents)?;
+            validate_excalidraw(&data)?;
+            restore_embedded_metadata(&mut data);
+            let listed = manifest.get(&original_id);
+            if let (Some(obj), Some(source_path)) = (data.as_object_mut(), listed.and_then(|m| m.source_path.as_ref())) {
+                obj.entry("_source_path").or_insert_with(|| serde_json::json!(source_path));
+            }
+            let created_at = storage::created_at(&data).or_else(|| listed.map(|m| m.created_at));
+            if let Some(created_at) = created_at {
+                validate_created_at(created_at)?;
+                if let Some(obj) = data.as_object_mut() {
+                    obj.insert("_created_at".to_string(), serde_json::json!(created_at.to_rfc3339()));
+                }
+            }
+
+            let id = if storage.exists(&original_id).await? {
+                match policy {
+                    IdConflict::Skip => return Ok(None),
+                    IdConflict::Overwrite => original_id.clone(),
+                    IdConflict::Rename => storage::generate_id(storage).await?,
+                }
+            } else {
+                original_id.clone()
+            };
+            Ok::<_, AppError>(Some(StagedDrawing {
+                entry: entry_name.clone(),
+                original_id: original_id.clone(),
+                id,
+                data,
+            }))
+        }
+        .await;
+
+        if archive.over_budget {
+            return Err(AppError::BadRequest(format!(
+                "Export archive inflates past the {} bytes an import may read",
+                limits.max_total_bytes
+            )));
+        }
+        match result {
+            Ok(Some(drawing)) if atomic => staged.push(drawing),
+            Ok(Some(drawing)) => match store(storage, &drawing.id, &drawing.data).await {
+                Ok(()) => summary.record(drawing),
+                Err(e) => summary.failed.push(ImportFailure { entry: entry_name, error: e.to_string() }),
+            },
+            Ok(None) => summary.skipped.push(original_id),
+            Err(e) => summary.failed.push(ImportFailure { entry: entry_name, error: e.to_string() }),
+        }
+    }
+
+    if atomic && summary.failed.is_empty() {
+        match commit_all(storage, &staged).await {
+            Ok(()) => staged.into_iter().for_each(|drawing| summary.record(drawing)),
+            Err(failure) => summary.failed.push(failure),
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{FileSystemStorage, SqliteStorage};
+    use serde_json::json;
+
+    const LIMITS: ImportLimits = ImportLimits { max_entries: 100, max_entry_bytes: 1 << 20, max_total_bytes: 1 << 24 };
+
+    #[tokio::test]
+    async fn test_import_dir_summary() {
+        let source = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        let valid = r#"{"type":"excalidraw","version":2,"elements":[]}"#;
+
+        std::fs::create_dir_all(source.path().join("Projects/Nested")).unwrap();
+        std::fs::write(source.path().join("top.excalidraw"), valid).unwrap();
+        std::fs::write(source.path().join("Projects/Nested/deep.excalidraw"), valid).unwrap();
+        std::fs::write(source.path().join("broken.excalidraw"), "{not json").unwrap();
+        std::fs::write(source.path().join("wrong.excalidraw"), r#"{"type":"other"}"#).unwrap();
+        std::fs::write(source.path().join("notes.md"), "# ignored").unwrap();
+
+        let storage = FileSystemStorage::new(data.path()).await.unwrap();
+        let summary = import_dir(&storage, source.path()).await.unwrap();
+        assert_eq!(summary.imported, 2);
+        assert_eq!(summary.skipped, 0);
+        assert_eq!(summary.failed.len(), 2);
+
+        let stored = storage
+            .find_by_source_path("Projects/Nested/deep.excalidraw")
+            .await
+            .unwrap();
+        assert!(stored.is_some());
+        assert_eq!(storage.list().await.unwrap().len(), 2);
+
+        // Re-running skips what's already published
+        let summary = import_dir(&storage, source.path()).await.unwrap();
+        assert_eq!(summary.imported, 0);
+        assert_eq!(summary.skipped, 2);
+    }
+
+    /// Export archive holding `shared` and `fresh`, plus a target instance
+    /// that already has its own `shared`.
+    async fn archive_and_target(target_dir: &Path) -> (Vec<u8>, FileSystemStorage) {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = FileSystemStorage::new(source_dir.path()).await.unwrap();
+        let imported = json!({"type": "excalidraw", "elements": [{"id": "imported", "type": "text", "x": 0, "y": 0}]});
+        source.save("shared", &imported, Some("Shared.excalidraw"), None).await.unwrap();
+        source.save("fresh", &imported, None, None).await.unwrap();
+        let body = crate::export::zip_stream(source.clone().into(), source.list().await.unwrap(), false);
+        let archive = axum::body::to_bytes(body, usize::MAX).await.unwrap().to_vec();
+
+        let target = FileSystemStorage::new(target_dir).await.unwrap();
+        let existing = json!({"type": "excalidraw", "elements": [{"id": "existing", "type": "text", "x": 0, "y": 0}]});
+        target.save("shared", &existing, None, None).await.unwrap();
+        (archive, target)
+    }
+
+    async fn zip_of(entries: &[(&str, serde_json::Value)]) -> Vec<u8> {
+        use async_zip::{base::write::ZipFileWriter, Compression, ZipEntryBuilder};
+        let mut zip = ZipFileWriter::new(Vec::new());
+        for (name, value) in entries {
+            let entry = ZipEntryBuilder::new(name.to_string().into(), Compression::Stored);
+            zip.write_entry_whole(entry, &serde_json::to_vec(value).unwrap()).await.unwrap();
+        }
+        zip.close().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_keeps_original_created_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = FileSystemStorage::new(dir.path()).await.unwrap();
+        let drawing = json!({"type": "excalidraw", "elements": []});
+        let mut own_date = drawing.clone();
+        own_date["_created_at"] = json!("2020-01-02T03:04:05Z");
+        let mut future = drawing.clone();
+        future["_created_at"] = json!("2999-01-01T00:00:00Z");
+        let archive = zip_of(&[
+            ("legacy.json", drawing.clone()),
+            ("dated.json", own_date),
+            ("future.json", future),
+            ("manifest.json", json!({"exported_at": "2024-01-01T00:00:00Z", "drawings": [
+                {"id": "legacy", "created_at": "2019-05-06T07:08:09Z"}
+            ]})),
+        ])
+        .await;
+
+        let summary = import_archive(&target, archive, IdConflict::Skip, false, LIMITS).await.unwrap();
+        assert_eq!(summary.imported, vec!["legacy", "dated"]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].entry, "future.json");
+        assert!(summary.failed[0].error.contains("created_at"));
+        assert!(!target.exists("future").await.unwrap());
+
+        let legacy = target.get_meta("legacy").await.unwrap();
+        assert_eq!(legacy.created_at.to_rfc3339(), "2019-05-06T07:08:09+00:00");
+        let dated = target.get_meta("dated").await.unwrap();
+        assert_eq!(dated.created_at.to_rfc3339(), "2020-01-02T03:04:05+00:00");
+        // Listing order follows the original dates
+        let ids: Vec<_> = target.list().await.unwrap().into_iter().map(|d| d.id).collect();
+        assert_eq!(ids, ["dated", "legacy"]);
+    }
+
+    #[tokio::test]
+    async fn test_import_recovers_embedded_metadata() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = FileSystemStorage::new(source_dir.path()).await.unwrap();
+        let mut plan = json!({
+            "type": "excalidraw",
+            "elements": [],
+            "_created_at": "2020-01-02T03:04:05Z",
+            "_tags": ["work"],
+            "_title": "Draft",
+        });
+        source.save("plan", &plan, Some("Notes/Plan.excalidraw"), None).await.unwrap();
+        plan["_title"] = json!("Plan");
+        source.save("plan", &plan, Some("Notes/Plan.excalidraw"), None).await.unwrap();
+        let body = crate::export::zip_stream(source.clone().into(), source.list().await.unwrap(), true);
+        let archive = axum::body::to_bytes(body, usize::MAX).await.unwrap().to_vec();
+
+        // The export carries the metadata as it is now
+        let zip = ZipFileReader::new(archive).await.unwrap();
+        let index = zip.file().entries().iter().position(|e| e.filename().as_str().unwrap() == "plan.json").unwrap();
+        let mut contents = String::new();
+        zip.reader_with_entry(index).await.unwrap().read_to_string_checked(&mut contents).await.unwrap();
+        let mut exported: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(
+            exported["_share"],
+            json!({
+                "created_at": "2020-01-02T03:04:05+00:00",
+                "source_path": "Notes/Plan.excalidraw",
+                "tags": ["work"],
+                "title": "Plan",
+            })
+        );
+
+        // Only `_share` survives another tool editing the file
+        let share = exported["_share"].take();
+        storage::strip_internal_fields(&mut exported);
+        exported["_share"] = share;
+        let archive = zip_of(&[("plan.json", exported), ("manifest.json", json!({"drawings": []}))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let target = FileSystemStorage::new(dir.path()).await.unwrap();
+        let summary = import_archive(&target, archive, IdConflict::Skip, false, LIMITS).await.unwrap();
+        assert_eq!(summary.imported, vec!["plan"]);
+        let meta = target.get_meta("plan").await.unwrap();
+        assert_eq!(meta.created_at.to_rfc3339(), "2020-01-02T03:04:05+00:00");
+        assert_eq!(meta.source_path.as_deref(), Some("Notes/Plan.excalidraw"));
+        assert_eq!(meta.tags, vec!["work"]);
+        assert_eq!(meta.title.as_deref(), Some("Plan"));
+    }
+
+    #[tokio::test]
+    async fn test_import_caps_what_archives_inflate_to() {
+        use async_zip::{base::write::ZipFileWriter, Compression, ZipEntryBuilder};
+        let dir = tempfile::tempdir().unwrap();
+        let target = FileSystemStorage::new(dir.path()).await.unwrap();
+        // Deflates to a few hundred bytes, inflates to a megabyte
+        let padded = |bytes: usize| {
+            json!({"type": "excalidraw", "elements": [], "padding": " ".repeat(bytes)})
+        };
+        let archive = |entries: Vec<(String, serde_json::Value)>| async move {
+            let mut zip = ZipFileWriter::new(Vec::new());
+            for (name, value) in entries {
+                let entry = ZipEntryBuilder::new(name.into(), Compression::Deflate);
+                zip.write_entry_whole(entry, &serde_json::to_vec(&value).unwrap()).await.unwrap();
+            }
+            zip.close().await.unwrap()
+        };
+        let manifest = || ("manifest.json".to_string(), json!({"drawings": []}));
+        let limits = ImportLimits { max_entries: 4, max_entry_bytes: 1000, max_total_bytes: 2500 };
+
+        // An entry too big for its own limit fails alone
+        let bomb = archive(vec![manifest(), ("big.json".into(), padded(1 << 20)), ("small.json".into(), padded(10))]).await;
+        assert!(bomb.len() < 10_000);
+        let summary = import_archive(&target, bomb, IdConflict::Skip, false, limits).await.unwrap();
+        assert_eq!(summary.imported, vec!["small"]);
+        assert_eq!(summary.failed[0].entry, "big.json");
+        assert!(summary.failed[0].error.contains("1000 bytes"), "{}", summary.failed[0].error);
+
+        // Entries that together inflate past the total refuse the archive
+        let many = (0..3).map(|i| (format!("d{i}.json"), padded(900)));
+        let many = archive(std::iter::once(manifest()).chain(many).collect()).await;
+        let err = import_archive(&target, many, IdConflict::Skip, false, limits).await.unwrap_err();
+        assert!(err.to_string().contains("2500 bytes"), "{err}");
+
+        let crowded = (0..4).map(|i| (format!("e{i}.json"), padded(0)));
+        let crowded = archive(std::iter::once(manifest()).chain(crowded).collect()).await;
+        let err = import_archive(&target, crowded, IdConflict::Skip, false, limits).await.unwrap_err();
+        assert!(err.to_string().contains("5 entries"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_import_requires_manifest_and_uses_its_source_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = FileSystemStorage::new(dir.path()).await.unwrap();
+        let drawing = json!({"type": "excalidraw", "elements": []});
+
+        let archive = zip_of(&[("lone.json", drawing.clone())]).await;
+        let result = import_archive(&target, archive, IdConflict::Skip, false, LIMITS).await;
+        assert!(matches!(result, Err(AppError::BadRequest(e)) if e.contains("manifest")));
+        let archive = zip_of(&[("lone.json", drawing.clone()), ("manifest.json", json!({"nope": 1}))]).await;
+        assert!(import_archive(&target, archive, IdConflict::Skip, false, LIMITS).await.is_err());
+        assert!(target.list().await.unwrap().is_empty());
+
+        let archive = zip_of(&[
+            ("lone.json", drawing),
+            ("manifest.json", json!({"drawings": [
+                {"id": "lone", "created_at": "2021-01-01T00:00:00Z", "source_path": "Folder/Lone.excalidraw"}
+            ]})),
+        ])
+        .await;
+        import_archive(&target, archive, IdConflict::Skip, false, LIMITS).await.unwrap();
+        let meta = target.get_meta("lone").await.unwrap();
+        assert_eq!(meta.source_path.as_deref(), Some("Folder/Lone.excalidraw"));
+    }
+
+    #[test]
+    fn test_multipart_file() {
+        let body = b"--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"archive\"; filename=\"export.zip\"\r\n\
+            Content-Type: application/zip\r\n\r\nPK\x03\x04\r\nbinary\r\n--XyZ--\r\n";
+        let file = multipart_file("multipart/form-data; boundary=XyZ", body).unwrap();
+        assert_eq!(file, b"PK\x03\x04\r\nbinary");
+        assert!(multipart_file("multipart/form-data", body).is_err());
+        assert!(multipart_file("multipart/form-data; boundary=\"other\"", body).is_err());
+    }
+
+    fn first_element(data: &serde_json::Value) -> &str {
+        data["elements"][0]["id"].as_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_archive_skip() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, target) = archive_and_target(dir.path()).await;
+
+        let summary = import_archive(&target, archive, IdConflict::Skip, false, LIMITS).await.unwrap();
+        assert_eq!(summary.imported, vec!["fresh"]);
+        assert_eq!(summary.skipped, vec!["shared"]);
+        assert_eq!(first_element(&target.load("shared").await.unwrap()), "existing");
+    }
+
+    #[tokio::test]
+    async fn test_import_archive_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, target) = archive_and_target(dir.path()).await;
+
+        let summary = import_archive(&target, archive, IdConflict::Overwrite, false, LIMITS).await.unwrap();
+        let mut imported = summary.imported.clone();
+        imported.sort();
+        assert_eq!(imported, vec!["fresh", "shared"]);
+        let shared = target.load("shared").await.unwrap();
+        assert_eq!(first_element(&shared), "imported");
+        assert_eq!(shared["_source_path"], "Shared.excalidraw");
+    }
+
+    #[tokio::test]
+    async fn test_import_archive_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, target) = archive_and_target(dir.path()).await;
+
+        let summary = import_archive(&target, archive, IdConflict::Rename, false, LIMITS).await.unwrap();
+        assert_eq!(summary.imported, vec!["fresh"]);
+        let new_id = &summary.renamed["shared"];
+        assert_ne!(new_id, "shared");
+        assert_eq!(first_element(&target.load("shared").await.unwrap()), "existing");
+        assert_eq!(first_element(&target.load(new_id).await.unwrap()), "imported");
+    }
+
+    #[tokio::test]
+    async fn test_atomic_import_with_bad_entry_imports_nothing() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = FileSystemStorage::new(source_dir.path()).await.unwrap();
+        let imported = json!({"type": "excalidraw", "elements": [{"id": "imported", "type": "text", "x": 0, "y": 0}]});
+        source.save("good", &imported, None, None).await.unwrap();
+        source.save("shared", &imported, None, None).await.unwrap();
+        // Storage doesn't validate, so the export carries an entry import rejects
+        source.save("bad", &json!({"type": "not-excalidraw"}), None, None).await.unwrap();
+        let body = crate::export::zip_stream(source.clone().into(), source.list().await.unwrap(), false);
+        let archive = axum::body::to_bytes(body, usize::MAX).await.unwrap().to_vec();
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = FileSystemStorage::new(dir.path()).await.unwrap();
+        let existing = json!({"type": "excalidraw", "elements": [{"id": "existing", "type": "text", "x": 0, "y": 0}]});
+        target.save("shared", &existing, None, None).await.unwrap();
+
+        let summary = import_archive(&target, archive.clone(), IdConflict::Overwrite, true, LIMITS).await.unwrap();
+        assert!(summary.imported.is_empty());
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].entry, "bad.json");
+        assert!(!target.exists("good").await.unwrap());
+        assert_eq!(first_element(&target.load("shared").await.unwrap()), "existing");
+
+        // Without atomic, the valid entries go through
+        let summary = import_archive(&target, archive, IdConflict::Overwrite, false, LIMITS).await.unwrap();
+        assert_eq!(summary.imported.len(), 2);
+        assert_eq!(summary.failed.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_restores_overwritten_and_removes_new() {
+        let dir = tempfile::tempdir().unwrap();
+        check_rollback(FileSystemStorage::new(dir.path().join("fs")).await.unwrap()).await;
+        check_rollback(SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap()).await;
+    }
+
+    async fn check_rollback(storage: impl DrawingStorage) {
+        let before = json!({"type": "excalidraw", "elements": [{"id": "before"}]});
+        let after = json!({"type": "excalidraw", "elements": [{"id": "after"}]});
+        storage.save("kept", &before, Some("Kept.excalidraw"), None).await.unwrap();
+        let previous = storage.load("kept").await.unwrap();
+        storage.save("kept", &after, None, None).await.unwrap();
+        storage.save("created", &after, None, None).await.unwrap();
+
+        rollback(&storage, vec![("kept", Some(previous)), ("created", None)]).await;
+        let kept = storage.load("kept").await.unwrap();
+        assert_eq!(first_element(&kept), "before");
+        assert_eq!(kept["_source_path"], "Kept.excalidraw");
+        assert!(!storage.exists("created").await.unwrap());
+        // Nothing the rollback undid reads as deleted to sync clients
+        assert!(storage.deleted_since(None).await.unwrap().is_empty());
+    }
+}
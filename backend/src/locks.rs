@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// A set of async locks keyed by string, created on demand.
+/// Used to serialize work on the same key (e.g. uploads of identical content)
+/// while unrelated keys proceed in parallel.
+#[derive(Clone, Default)]
+pub struct KeyedLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+}
+
+/// Holds the lock for one key; the key's entry is dropped once nobody uses it.
+pub struct KeyedGuard {
+    key: String,
+    locks: KeyedLocks,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl KeyedLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for and take the lock for `key`.
+    pub async fn lock(&self, key: &str) -> KeyedGuard {
+        let lock = self
+            .locks
+            .lock()
+            .await
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let guard = lock.lock_owned().await;
+        KeyedGuard {
+            key: key.to_string(),
+            locks: self.clone(),
+            guard: Some(guard),
+        }
+    }
+
+    #[cfg(test)]
+    async fn len(&self) -> usize {
+        self.locks.lock().await.len()
+    }
+
+    /// Whether someone is waiting on the held lock for `key`.
+    #[cfg(test)]
+    pub(crate) async fn is_contended(&self, key: &str) -> bool {
+        self.locks.lock().await.get(key).is_some_and(|l| Arc::strong_count(l) > 2)
+    }
+}
+
+impl Drop for KeyedGuard {
+    fn drop(&mut self) {
+        // Release the key's lock first, then prune its entry if no one else holds
+        // or waits on it. Pruning is best-effort: if the map is busy we skip it.
+        self.guard.take();
+        if let Ok(mut locks) = self.locks.locks.try_lock() {
+            if locks.get(&self.key).is_some_and(|l| Arc::strong_count(l) == 1) {
+                locks.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_same_key_is_serialized() {
+        let locks = KeyedLocks::new();
+        let guard = locks.lock("a").await;
+
+        // A different key is not blocked
+        let other = tokio::time::timeout(Duration::from_millis(50), locks.lock("b")).await;
+        assert!(other.is_ok());
+        drop(other);
+
+        // The same key waits until the first guard is released
+        let same = tokio::time::timeout(Duration::from_millis(50), locks.lock("a")).await;
+        assert!(same.is_err());
+        drop(guard);
+        assert!(locks.lock("a").await.guard.is_some());
+        assert_eq!(locks.len().await, 0);
+    }
+}
@@ -0,0 +1,190 @@
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{FormatEvent, FormatFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// How log lines are written: the human-readable default, or one JSON object
+/// per line for log collectors.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+/// Collects recorded fields into a JSON object, keeping numbers and booleans
+/// as such and everything else as strings.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+/// Stores span fields as a JSON object so [`JsonFormat`] can merge them into
+/// each event inside the span.
+pub struct JsonFields;
+
+impl<'w> FormatFields<'w> for JsonFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut map = Map::new();
+        fields.record(&mut JsonVisitor(&mut map));
+        write!(writer, "{}", Value::Object(map))
+    }
+
+    fn add_fields(&self, current: &'w mut FormattedFields<Self>, fields: &tracing::span::Record<'_>) -> fmt::Result {
+        let mut map = match serde_json::from_str(&current.fields) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+        fields.record(&mut JsonVisitor(&mut map));
+        current.fields = Value::Object(map).to_string();
+        Ok(())
+    }
+}
+
+/// Writes each event as one JSON object: timestamp, level, target, the fields
+/// of its enclosing spans (outermost first) and then its own.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true).into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    if let Ok(Value::Object(fields)) = serde_json::from_str(&fields.fields) {
+                        line.extend(fields);
+                    }
+                }
+            }
+        }
+        event.record(&mut JsonVisitor(&mut line));
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Install the global subscriber, filtered by `RUST_LOG` (info for this crate
+/// and tower-http by default).
+pub fn init(format: LogFormat) {
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    let (pretty, json) = match format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(tracing_subscriber::fmt::layer().event_format(JsonFormat).fmt_fields(JsonFields)),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "excalishare=info,tower_http=info".into()),
+        )
+        .with(pretty)
+        .with(json)
+        .init();
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Log sink shared with a test subscriber.
+    #[derive(Clone, Default)]
+    pub struct CapturedLogs(pub Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CapturedLogs {
+        /// The captured lines, each parsed as JSON.
+        pub fn json_lines(&self) -> Vec<Value> {
+            let bytes = self.0.lock().unwrap().clone();
+            String::from_utf8(bytes)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{e}: {line}")))
+                .collect()
+        }
+    }
+
+    /// A subscriber writing [`JsonFormat`] lines into `logs`.
+    pub fn json_subscriber(logs: &CapturedLogs) -> impl Subscriber + Send + Sync {
+        let writer = logs.clone();
+        tracing_subscriber::fmt()
+            .event_format(JsonFormat)
+            .fmt_fields(JsonFields)
+            .with_writer(move || writer.clone())
+            .finish()
+    }
+
+    #[test]
+    fn test_json_lines_merge_span_fields() {
+        let logs = CapturedLogs::default();
+        tracing::subscriber::with_default(json_subscriber(&logs), || {
+            let span = tracing::info_span!("request", request_id = "abc", path = tracing::field::Empty);
+            span.record("path", "/api/health");
+            let _entered = span.enter();
+            tracing::info!(status = 200u16, ok = true, detail = ?Some("x"), "Finished \"request\"");
+        });
+
+        let lines = logs.json_lines();
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], module_path!());
+        assert_eq!(line["request_id"], "abc");
+        assert_eq!(line["path"], "/api/health");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["ok"], true);
+        assert_eq!(line["detail"], "Some(\"x\")");
+        assert_eq!(line["message"], "Finished \"request\"");
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+}
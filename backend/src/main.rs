@@ -1,37 +1,88 @@
 mod auth;
+mod backup;
+mod breaker;
+mod cache;
 mod collab;
+mod confirm;
+mod counters;
+mod csp;
 mod error;
+mod export;
+mod follower;
+mod history;
+mod import;
+mod locks;
+mod logging;
+mod metrics;
+mod normalize;
+mod openapi;
 mod password;
+mod quota;
+mod raster;
+mod rate_limit;
+mod render;
+mod request_id;
 mod routes;
+mod shutdown;
+mod slug;
+mod spa;
 mod storage;
+mod timing;
+mod tls;
+mod upload_limit;
+mod validation;
+mod webhook;
 mod ws;
 
 use axum::{
-    http::{header, Method},
+    handler::Handler,
+    http::{header, HeaderValue, Method},
     middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tower_governor::{governor::GovernorConfigBuilder, GovernorLayer};
+use governor::middleware::NoOpMiddleware;
+use tower_governor::{governor::GovernorConfigBuilder, key_extractor::PeerIpKeyExtractor, GovernorLayer};
 use tower_http::{
     compression::CompressionLayer,
-    cors::CorsLayer,
+    cors::{AllowOrigin, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    sensitive_headers::SetSensitiveRequestHeadersLayer,
     services::{ServeDir, ServeFile},
     trace::TraceLayer,
 };
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use auth::ApiKey;
+use auth::{ApiKeys, KeyScope, ScopedKeys};
+use breaker::WriteBreaker;
+use cache::{CachedStorage, RenderCache};
 use collab::SessionManager;
+use history::UsageHistory;
+use confirm::ConfirmTokens;
+use counters::ViewCounter;
+use csp::FrameAncestors;
+use follower::Follower;
+use locks::KeyedLocks;
+use logging::LogFormat;
+use metrics::Metrics;
+use quota::{ByteQuota, DrawingQuota};
+use rate_limit::KeyRateLimiter;
 use routes::AppState;
-use storage::{DrawingStorage, FileSystemStorage};
+use spa::SpaState;
+use storage::{DrawingStorage, FileSystemStorage, SqliteStorage, Storage};
+use timing::SlowRequestThreshold;
+use upload_limit::UploadLimiter;
+use webhook::{WebhookEvent, WebhookNotifier};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, serde::Serialize)]
 #[command(name = "excalishare", about = "Self-hosted Excalidraw sharing server")]
 struct Config {
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+
     /// Address to listen on
     #[arg(long, env = "LISTEN_ADDR", default_value = "127.0.0.1:8184")]
     listen_addr: String,
@@ -40,9 +91,36 @@ struct Config {
     #[arg(long, env = "DATA_DIR", default_value = "./data/drawings")]
     data_dir: PathBuf,
 
-    /// API key for upload/delete operations
+    /// Where drawings are kept: `fs` (JSON files in DATA_DIR) or `sqlite`
+    /// (one database file, see SQLITE_PATH)
+    #[arg(long, env = "STORAGE_BACKEND", value_enum, default_value_t = StorageBackend::Fs)]
+    storage_backend: StorageBackend,
+
+    /// Database file for the SQLite backend (default: `drawings.sqlite` in DATA_DIR)
+    #[arg(long, env = "SQLITE_PATH")]
+    sqlite_path: Option<PathBuf>,
+
+    /// Earlier versions kept per drawing when an update replaces its content,
+    /// for `/api/drawings/{id}/rollback/{version}`. 0 keeps no history
+    #[arg(long, env = "HISTORY_DEPTH", default_value = "10")]
+    history_depth: usize,
+
+    /// API key for upload/delete operations, labelled `default`. At least one
+    /// key (here, in `--api-keys` or in `--api-keys-file`) is required to run the server
     #[arg(long, env = "API_KEY")]
-    api_key: String,
+    #[serde(serialize_with = "redact")]
+    api_key: Option<String>,
+
+    /// Further accepted API keys as comma-separated `label:key[:scope]` entries,
+    /// scope `read` or `write` (default). The label is logged with uploads and
+    /// reported by `/api/auth/whoami`
+    #[arg(long, env = "API_KEYS")]
+    #[serde(serialize_with = "redact")]
+    api_keys: Option<String>,
+
+    /// File of `label:key[:scope]` entries, one per line (`#` starts a comment)
+    #[arg(long, env = "API_KEYS_FILE")]
+    api_keys_file: Option<PathBuf>,
 
     /// Public base URL (used to construct share links)
     #[arg(long, env = "BASE_URL", default_value = "http://localhost:8184")]
@@ -67,106 +145,332 @@ struct Config {
     /// TURN HMAC secret for generating time-limited credentials.
     /// If set, the /api/ice-config endpoint generates HMAC credentials valid for 1 hour.
     #[arg(long, env = "TURN_SECRET")]
+    #[serde(serialize_with = "redact")]
     turn_secret: Option<String>,
-}
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "excalidraw_share=info,tower_http=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    /// Cache-Control max-age (seconds) for public drawing responses
+    #[arg(long, env = "CACHE_MAX_AGE_SECS", default_value = "60")]
+    cache_max_age_secs: u64,
 
-    let config = Config::parse();
+    /// Cache-Control stale-while-revalidate window (seconds) for public drawing responses
+    #[arg(long, env = "CACHE_SWR_SECS", default_value = "300")]
+    cache_swr_secs: u64,
 
-    // Warn about insecure default API key
-    if config.api_key == "change-me-in-production" {
-        tracing::warn!("⚠️  Using default API key 'change-me-in-production' — set API_KEY for production!");
-    }
+    /// Maximum number of tags per drawing
+    #[arg(long, env = "MAX_TAGS_PER_DRAWING", default_value = "20")]
+    max_tags_per_drawing: usize,
 
-    tracing::info!(
-        listen = %config.listen_addr,
-        data_dir = %config.data_dir.display(),
-        base_url = %config.base_url,
-        max_upload_mb = config.max_upload_mb,
-        "Starting excalishare server"
-    );
+    /// Maximum length of a single tag (characters)
+    #[arg(long, env = "MAX_TAG_LENGTH", default_value = "32")]
+    max_tag_length: usize,
 
-    let storage = FileSystemStorage::new(&config.data_dir).await?;
-    let session_manager = SessionManager::new();
+    /// Require a two-phase delete: `?phase=prepare` issues a one-time token
+    /// that must be passed as `?token=` to actually delete
+    #[arg(long, env = "REQUIRE_DELETE_CONFIRMATION")]
+    require_delete_confirmation: bool,
 
-    // Scan for persistent collab drawings and register them
-    let persistent_ids = storage.list_persistent_collab_drawings().await
-        .unwrap_or_else(|e| {
-            tracing::warn!(error = %e, "Failed to scan for persistent collab drawings");
-            vec![]
-        });
-    if !persistent_ids.is_empty() {
-        tracing::info!(count = persistent_ids.len(), "Registering persistent collab drawings");
-        for id in persistent_ids {
-            session_manager.register_persistent_drawing(id).await;
-        }
-    }
+    /// URL to POST a JSON notification to after every upload and delete
+    #[arg(long, env = "WEBHOOK_URL")]
+    #[serde(serialize_with = "redact")]
+    webhook_url: Option<String>,
 
-    let app_state = AppState {
-        storage: storage.clone(),
-        base_url: config.base_url.clone(),
-        session_manager: session_manager.clone(),
-        api_key: config.api_key.clone(),
-        stun_url: config.stun_url.clone(),
-        turn_url: config.turn_url.clone(),
-        turn_secret: config.turn_secret.clone(),
-    };
+    /// Sign webhook payloads with HMAC-SHA256 under this secret, sent as
+    /// `X-Excalishare-Signature: sha256=<hex>`
+    #[arg(long, env = "WEBHOOK_SECRET")]
+    #[serde(serialize_with = "redact")]
+    webhook_secret: Option<String>,
 
-    let api_key = ApiKey(config.api_key.clone());
-    let body_limit = config.max_upload_mb * 1024 * 1024;
+    /// How long (seconds) failed webhook deliveries are retried before being dropped
+    #[arg(long, env = "WEBHOOK_MAX_AGE_SECS", default_value = "86400")]
+    webhook_max_age_secs: u64,
 
-    let index_file = config.frontend_dir.join("index.html");
-    let frontend_service = ServeDir::new(&config.frontend_dir)
-        .not_found_service(ServeFile::new(&index_file));
+    /// Store embedded images (`files`) once in content-addressed asset storage
+    /// instead of inline in every drawing
+    #[arg(long, env = "EXTRACT_ASSETS")]
+    extract_assets: bool,
 
-    // Rate limiting: 120 req/sec per IP for public, 30 req/sec per IP for protected
-    let public_rate_limit = GovernorLayer {
-        config: Arc::new(
-            GovernorConfigBuilder::default()
-                .per_second(1)
-                .burst_size(120)
-                .finish()
-                .expect("Failed to build public rate limiter"),
-        ),
-    };
-    let protected_rate_limit = GovernorLayer {
-        config: Arc::new(
-            GovernorConfigBuilder::default()
-                .per_second(1)
-                .burst_size(30)
-                .finish()
-                .expect("Failed to build protected rate limiter"),
-        ),
-    };
-    // Strict rate limit for password verification (brute-force protection): 5 req/sec per IP
-    let password_rate_limit = GovernorLayer {
-        config: Arc::new(
-            GovernorConfigBuilder::default()
-                .per_second(1)
-                .burst_size(5)
-                .finish()
-                .expect("Failed to build password rate limiter"),
-        ),
-    };
-    // Rate limit for WebSocket connections: 10 connections/sec per IP
-    let ws_rate_limit = GovernorLayer {
-        config: Arc::new(
-            GovernorConfigBuilder::default()
-                .per_second(1)
-                .burst_size(10)
-                .finish()
-                .expect("Failed to build WebSocket rate limiter"),
-        ),
-    };
+    /// Serve extracted images as `<url>/api/view/{id}/assets/{hash}` links (e.g. a
+    /// CDN in front of this server) instead of inlining them into drawing
+    /// responses. Drawings behind a password or view limit stay inline
+    #[arg(long, env = "ASSET_BASE_URL")]
+    asset_base_url: Option<String>,
+
+    /// HTML page served with a 404 status for unknown non-API paths and missing
+    /// drawings, instead of always falling back to index.html
+    #[arg(long, env = "NOT_FOUND_PAGE")]
+    not_found_page: Option<PathBuf>,
+
+    /// Serve only the API: no frontend files, and non-API paths get a JSON 404
+    #[arg(long, env = "API_ONLY")]
+    api_only: bool,
+
+    /// Run as a read-only follower of another instance serving a replicated
+    /// data dir: write requests get a 503 pointing at `PRIMARY_URL`, and the
+    /// drawing cache and usage sampler are disabled since files change underneath.
+    /// Nothing is written to the data dir: no views are counted (view-limited
+    /// drawings get a 403), no collab sessions are hosted and no webhooks sent.
+    #[arg(long, env = "FOLLOWER")]
+    follower: bool,
+
+    /// Base URL of the primary instance that followers point writes at
+    #[arg(long, env = "PRIMARY_URL")]
+    primary_url: Option<String>,
+
+    /// Origins allowed to make cross-origin API requests (comma-separated), with
+    /// credentials. `*` allows any origin, without credentials. Unset allows
+    /// BASE_URL and the Obsidian app (`app://obsidian.md`)
+    #[arg(long, env = "CORS_ORIGINS")]
+    cors_origins: Option<String>,
+
+    /// Origins allowed to embed drawing views in an iframe (comma-separated
+    /// CSP `frame-ancestors` sources). Other pages can't be framed at all.
+    #[arg(long, env = "ALLOWED_FRAME_ANCESTORS", default_value = "'self'")]
+    allowed_frame_ancestors: String,
+
+    /// Uploads without an `id` that are identical to an existing drawing (same
+    /// content and source_path) return the existing drawing instead of a copy
+    #[arg(long, env = "DEDUP_UPLOADS")]
+    dedup_uploads: bool,
+
+    /// Store the content of identical drawings once, shared and reference
+    /// counted (SQLite backend only)
+    #[arg(long, env = "DEDUP_STORAGE")]
+    dedup_storage: bool,
+
+    /// Give drawings uploaded with a `title` a URL-safe slug, viewable at `/s/{slug}`
+    #[arg(long, env = "AUTO_SLUG")]
+    auto_slug: bool,
+
+    /// Reject uploads in which two elements share the same `id`
+    #[arg(long, env = "REJECT_DUPLICATE_ELEMENT_IDS")]
+    reject_duplicate_element_ids: bool,
+
+    /// Warn in the upload response when a drawing has no visible elements
+    #[arg(long, env = "WARN_EMPTY_DRAWINGS")]
+    warn_empty_drawings: bool,
+
+    /// Reject uploads of drawings with no visible elements (strict mode)
+    #[arg(long, env = "REJECT_EMPTY_DRAWINGS")]
+    reject_empty_drawings: bool,
+
+    /// Add each drawing's current metadata (created_at, source_path, tags,
+    /// title) under `_share` to its exported document, so the file carries it
+    #[arg(long, env = "EMBED_METADATA")]
+    embed_metadata: bool,
+
+    /// Reject uploads embedding more than this many files (entries in `files`).
+    /// Unset means unlimited.
+    #[arg(long, env = "MAX_EMBEDDED_FILES")]
+    max_embedded_files: Option<usize>,
+
+    /// Reject uploads whose embedded files (`dataURL`s in `files`) add up to
+    /// more than this many MB. Unset means unlimited.
+    #[arg(long, env = "MAX_EMBEDDED_MB")]
+    max_embedded_mb: Option<usize>,
+
+    /// Remove the `files` map (embedded images) from uploads before storing
+    /// them; image elements stay, shown as missing
+    #[arg(long, env = "STRIP_FILES")]
+    strip_files: bool,
+
+    /// Refuse new drawings once the server holds this many (updates to existing
+    /// drawings still go through). Unset means unlimited.
+    #[arg(long, env = "MAX_DRAWINGS")]
+    max_drawings: Option<usize>,
+
+    /// Refuse writes that would take the total size of stored drawings past
+    /// this many MB; updates only count by how much they grow, unless the old
+    /// content is kept as history, which counts too. Drawings in the trash
+    /// count until purged; extracted assets and previews don't. Unset means unlimited.
+    #[arg(long = "max-total-mb", env = "MAX_TOTAL_MB")]
+    max_total_mb: Option<u64>,
+
+    /// Round element coordinates (`x`, `y`, `width`, `height`, `points`) of uploads
+    /// to this many decimal places, so cosmetically identical saves hash the same
+    #[arg(long, env = "ROUND_COORDINATES")]
+    round_coordinates: Option<u32>,
+
+    /// Reject uploads with an element coordinate beyond this magnitude
+    /// (e.g. `1000000`); unset means unbounded
+    #[arg(long, env = "MAX_COORDINATE")]
+    max_coordinate: Option<f64>,
+
+    /// With MAX_COORDINATE, clamp out-of-bounds coordinates instead of rejecting
+    #[arg(long, env = "CLAMP_COORDINATES")]
+    clamp_coordinates: bool,
+
+    /// Reject uploads whose elements link to or embed (`link`, `url`, `src`)
+    /// absolute URLs outside BASE_URL and ALLOWED_LINK_ORIGINS
+    #[arg(long, env = "BLOCK_EXTERNAL_LINKS")]
+    block_external_links: bool,
+
+    /// Extra origins (comma-separated, e.g. `https://example.com`) drawings may
+    /// link to when BLOCK_EXTERNAL_LINKS is set
+    #[arg(long, env = "ALLOWED_LINK_ORIGINS", default_value = "")]
+    allowed_link_origins: String,
+
+    /// JSON file of `appState` defaults (theme, grid, ...) merged into each
+    /// upload for keys it doesn't set itself
+    #[arg(long = "default-appstate", env = "DEFAULT_APPSTATE")]
+    default_appstate: Option<PathBuf>,
+
+    /// Longest TTL (seconds from now) a drawing can have; requested TTLs and
+    /// extensions are clamped to it
+    #[arg(long, env = "MAX_TTL_SECS", default_value = "7776000")]
+    max_ttl_secs: u64,
+
+    /// Seconds `POST /api/drawings/{id}/extend` adds when no amount is requested
+    #[arg(long, env = "TTL_EXTENSION_SECS", default_value = "604800")]
+    ttl_extension_secs: u64,
+
+    /// How often view counts batched in memory are written to disk (seconds).
+    /// Pending counts are also written on graceful shutdown.
+    #[arg(long, env = "VIEW_FLUSH_INTERVAL_SECS", default_value = "30")]
+    view_flush_interval_secs: u64,
+
+    /// Consecutive write requests failing on storage I/O after which the server
+    /// goes read-only until a storage write probe succeeds. 0 (the default) disables.
+    #[arg(long, env = "WRITE_FAILURE_THRESHOLD", default_value = "0")]
+    write_failure_threshold: u32,
+
+    /// How often storage is probed while read-only (seconds)
+    #[arg(long, env = "WRITE_PROBE_INTERVAL_SECS", default_value = "30")]
+    write_probe_interval_secs: u64,
+
+    /// Largest client-rendered preview image accepted with an upload (KB)
+    #[arg(long, env = "MAX_PREVIEW_KB", default_value = "1024")]
+    max_preview_kb: usize,
+
+    /// Emit weak `ETag`s (`W/"..."`) on drawing, SVG and index responses, for
+    /// proxies that drop or mangle strong ones on compressed bodies
+    #[arg(long, env = "WEAK_ETAGS")]
+    weak_etags: bool,
+
+    /// Largest serialized response of the list endpoints (bytes). Pages that
+    /// would exceed it get 413 and have to be fetched with a smaller `limit`.
+    #[arg(long, env = "MAX_LIST_RESPONSE_BYTES", default_value = "16777216")]
+    max_list_response_bytes: usize,
+
+    /// Most entries an archive given to `/api/import` may have
+    #[arg(long, env = "MAX_IMPORT_ENTRIES", default_value = "10000")]
+    max_import_entries: usize,
+
+    /// Most an archive given to `/api/import` may inflate to in all (MB);
+    /// each entry may inflate to `--max-upload-mb`
+    #[arg(long, env = "MAX_IMPORT_MB", default_value = "1024")]
+    max_import_mb: u64,
+
+    /// Rewrite `source_path` separators to `/` on upload and lookup (Windows
+    /// clients send backslashes), so folder grouping works across platforms
+    #[arg(long, env = "NORMALIZE_SOURCE_PATHS", default_value_t = true, action = clap::ArgAction::Set)]
+    normalize_source_paths: bool,
+
+    /// Log a warning (path, drawing id, duration) for requests slower than this
+    /// many milliseconds. Unset or 0 disables the slow-request log.
+    #[arg(long, env = "SLOW_REQUEST_MS")]
+    slow_request_ms: Option<u64>,
+
+    /// Log output: `pretty` (human-readable) or `json` (one object per line,
+    /// with request id, method, path, status and latency on access log lines)
+    #[arg(long, env = "LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Memory budget (MB) for caching loaded drawings and the drawing list.
+    /// Least recently used entries are evicted beyond it; 0 disables the cache.
+    /// Leave disabled if other processes write to the data directory.
+    #[arg(long, env = "CACHE_MEMORY_MB", default_value = "0")]
+    cache_memory_mb: usize,
+
+    /// Most drawings to keep in the cache, on top of the memory budget;
+    /// 0 leaves only `--cache-memory-mb` in charge. Needs that budget set.
+    #[arg(long, env = "CACHE_CAPACITY", default_value = "0")]
+    cache_capacity: usize,
+
+    /// Certificate chain (PEM) to serve HTTPS with; needs `--tls-key`
+    #[arg(long, env = "TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Private key (PEM) for `--tls-cert`
+    #[arg(long, env = "TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Once shutdown starts, how long (seconds) to wait for in-flight requests
+    /// before cutting them off. Unset waits for as long as they take.
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECS")]
+    shutdown_timeout_secs: Option<u64>,
+
+    /// Serve Prometheus metrics at `/metrics`
+    #[arg(long, env = "METRICS")]
+    metrics: bool,
+
+    /// Memory budget (MB) for PNGs rendered by `/api/view/{id}/preview.png`
+    #[arg(long, env = "RENDER_CACHE_MB", default_value = "16")]
+    render_cache_mb: usize,
+
+    /// How many `/api/view/{id}/preview.png` renders may run at once; further
+    /// requests wait for a free slot
+    #[arg(long, env = "RENDER_CONCURRENCY", default_value = "2", value_parser = clap::value_parser!(u32).range(1..))]
+    render_concurrency: u32,
+
+    /// How often (seconds) to record drawing count and total size for
+    /// `/api/storage/history`; 0 disables sampling
+    #[arg(long, env = "USAGE_SAMPLE_INTERVAL_SECS", default_value = "3600")]
+    usage_sample_interval_secs: u64,
+
+    /// How often (seconds) to delete drawings whose expiry has passed; 0
+    /// leaves them in storage, still refused to viewers
+    #[arg(long = "cleanup-interval", env = "CLEANUP_INTERVAL_SECS", default_value = "300")]
+    cleanup_interval_secs: u64,
+
+    /// Move deleted drawings to a trash they can be restored from (until
+    /// purged) instead of removing them
+    #[arg(long, env = "SOFT_DELETE")]
+    soft_delete: bool,
+
+    /// Days a drawing stays in the trash before it is purged for good; 0
+    /// keeps trashed drawings until restored
+    #[arg(long, env = "TRASH_RETENTION_DAYS", default_value = "30", value_parser = clap::value_parser!(u64).range(..=36500))]
+    trash_retention_days: u64,
+
+    /// Per-IP request burst allowed on public read endpoints (views, search,
+    /// previews); one more request is allowed every second
+    #[arg(long, env = "RATE_LIMIT_READ", default_value = "120", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit_read: u32,
+
+    /// Per-IP request burst allowed on authenticated endpoints (uploads,
+    /// deletes, imports, ...); one more request is allowed every second
+    #[arg(long, env = "RATE_LIMIT_WRITE", default_value = "30", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit_write: u32,
+
+    /// Requests per minute allowed per API key on authenticated endpoints, and
+    /// per client IP on public ones, on top of the bursts above; unset disables it
+    #[arg(long, env = "RATE_LIMIT_RPM", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit_rpm: Option<u32>,
+
+    /// How many requests' worth of `RATE_LIMIT_RPM` a write (anything but
+    /// `GET`/`HEAD`) counts as
+    #[arg(long, env = "RATE_LIMIT_WRITE_COST", default_value = "5", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit_write_cost: u32,
+
+    /// Maximum number of uploads a single API key may have in flight at once;
+    /// further concurrent uploads from that key get 429. Unset means unlimited.
+    #[arg(long, env = "MAX_CONCURRENT_UPLOADS_PER_KEY")]
+    max_concurrent_uploads_per_key: Option<usize>,
+}
+
+/// Every API route, with its rate limits and auth. The OpenAPI description in
+/// `openapi.rs` is checked against this router.
+fn api_router(config: &Config, app_state: AppState) -> Router {
+    let body_limit = config.max_upload_mb * 1024 * 1024;
+
+    // Rate limiting: separate per-IP limits for public reads and authenticated
+    // writes, so heavy viewing never eats into the upload allowance (or vice versa)
+    let public_rate_limit = rate_limit(config.rate_limit_read, "read");
+    let protected_rate_limit = rate_limit(config.rate_limit_write, "write");
+    // Strict rate limit for password verification (brute-force protection): bursts of 5 per IP
+    let password_rate_limit = rate_limit(5, "password");
+    // Rate limit for WebSocket connections: bursts of 10 per IP
+    let ws_rate_limit = rate_limit(10, "WebSocket");
 
     // Password verification route (stricter rate limit for brute-force protection)
     let password_api = Router::new()
@@ -179,8 +483,23 @@ async fn main() -> anyhow::Result<()> {
     // Public API routes (no auth required)
     let public_api = Router::new()
         .route("/api/health", get(routes::health))
+        .route("/api/health/ready", get(routes::ready))
+        .route("/api/openapi.json", get(openapi::openapi_json))
         .route("/api/public/drawings", get(routes::list_drawings_public))
-        .route("/api/view/{id}", get(routes::get_drawing))
+        .route("/api/view/{id}", get(routes::get_drawing).head(routes::head_drawing))
+        .route("/api/view/{id}/summary", get(routes::get_drawing_summary))
+        .route("/api/view/{id}/meta", get(routes::get_drawing_meta_public))
+        .route("/api/view/{id}/svg-json", get(routes::get_drawing_svg_json))
+        .route("/api/view/{id}/preview.png", get(routes::get_drawing_png))
+        .route("/api/view/{id}/drawing.svg", get(routes::get_drawing_svg))
+        .route("/api/view/{id}/download", get(routes::download_drawing))
+        .route("/api/view/{id}/history", get(routes::drawing_history))
+        .route("/api/view/bulk.ndjson", get(routes::bulk_view))
+        .route("/api/index.json", get(routes::drawing_index))
+        .route("/api/search", get(routes::search_drawings))
+        .route("/s/{slug}", get(routes::view_by_slug))
+        .route("/api/view/{id}/assets/{hash}", get(routes::get_asset))
+        .route("/api/preview/{id}", get(routes::get_preview))
         .route(
             "/api/collab/status/{drawing_id}",
             get(routes::collab_status),
@@ -188,18 +507,69 @@ async fn main() -> anyhow::Result<()> {
         .route(
             "/api/persistent-collab/activate/{drawing_id}",
             post(routes::activate_persistent_collab),
+        );
+    let public_api = if config.metrics {
+        public_api.route("/metrics", get(routes::get_metrics))
+    } else {
+        public_api
+    };
+    // Per-key (or per-IP) budget, shared by all routes; runs after the auth middleware
+    let key_limiter = config.rate_limit_rpm.map(|rpm| KeyRateLimiter::new(rpm, config.rate_limit_write_cost));
+    let key_limit = || {
+        tower::util::option_layer(
+            key_limiter.clone().map(|limiter| middleware::from_fn_with_state(limiter, rate_limit::key_rate_limit_middleware)),
         )
-        .layer(public_rate_limit);
+    };
+    let public_api = public_api.layer(key_limit()).layer(public_rate_limit);
 
     // Protected API routes (auth required)
-    let protected_api = Router::new()
-        .route("/api/upload", post(routes::upload_drawing))
-        .route("/api/drawings/{id}", delete(routes::delete_drawing))
+    // Per-key concurrency cap on uploads (runs inside the auth middleware, which identifies the key)
+    let upload_route = match config.max_concurrent_uploads_per_key.filter(|&n| n > 0) {
+        Some(max) => post(routes::upload_drawing).layer(middleware::from_fn_with_state(
+            UploadLimiter::new(max),
+            upload_limit::upload_limit_middleware,
+        )),
+        None => post(routes::upload_drawing),
+    };
+
+    // Routes any key may use; see `auth::KeyScope` for the scope mapping
+    let read_api = Router::new()
+        .route("/api/drawings/{id}/meta", get(routes::get_drawing_meta))
+        .route("/api/drawings/meta-batch", post(routes::get_drawing_meta_batch))
+        .route("/api/drawings/{id}/hash", get(routes::get_drawing_hash))
         .route("/api/drawings", get(routes::list_drawings))
+        .route("/api/drawings/deleted", get(routes::list_deleted))
+        .route("/api/trash", get(routes::list_trash))
         .route("/api/lookup", get(routes::lookup_by_source_path))
+        .route("/api/stats", get(routes::stats))
+        .route("/api/storage/history", get(routes::storage_history))
+        .route("/api/admin/verify", get(routes::verify_storage))
+        .route("/api/auth/whoami", get(auth::whoami))
+        .route("/api/folders", get(routes::list_folders))
+        .route("/api/collab/sessions", get(routes::list_collab_sessions))
+        .route("/api/ice-config", get(routes::ice_config_handler))
+        .layer(axum::extract::DefaultBodyLimit::max(body_limit))
+        .layer(protected_rate_limit.clone())
+        .layer(key_limit())
+        .route_layer(middleware::from_fn_with_state(
+            ScopedKeys { keys: app_state.api_keys.clone(), required: KeyScope::Read },
+            auth::api_key_middleware,
+        ));
+
+    // Routes that change drawings or sessions, or reveal the server's setup, need a write key
+    let write_api = Router::new()
+        .route("/api/upload", upload_route)
+        .route("/api/drawings/{id}", delete(routes::delete_drawing))
+        .route("/api/drawings/bulk-delete", post(routes::bulk_delete_drawings))
+        .route("/api/drawings/{id}/extend", post(routes::extend_drawing))
+        .route("/api/drawings/{id}/rollback/{version}", post(routes::rollback_drawing))
+        .route("/api/drawings/{id}/restore", post(routes::restore_drawing))
+        .route("/api/drawings/{id}/appstate", put(routes::update_app_state))
+        .route("/api/import", post(routes::import_drawings))
+        .route("/api/export", get(routes::export_drawings))
+        .route("/api/admin/config", get(routes::get_config))
         .route("/api/collab/start", post(routes::start_collab))
         .route("/api/collab/stop", post(routes::stop_collab))
-        .route("/api/collab/sessions", get(routes::list_collab_sessions))
         .route(
             "/api/persistent-collab/enable",
             post(routes::enable_persistent_collab),
@@ -208,18 +578,19 @@ async fn main() -> anyhow::Result<()> {
             "/api/persistent-collab/disable",
             post(routes::disable_persistent_collab),
         )
-        .route("/api/ice-config", get(routes::ice_config_handler))
         .layer(axum::extract::DefaultBodyLimit::max(body_limit))
         .layer(protected_rate_limit)
+        .layer(key_limit())
         .route_layer(middleware::from_fn_with_state(
-            api_key.clone(),
+            ScopedKeys { keys: app_state.api_keys.clone(), required: KeyScope::Write },
             auth::api_key_middleware,
         ));
+    let protected_api = read_api.merge(write_api);
 
     // WebSocket route (rate limited, no auth but session must exist)
     let ws_state = ws::WsState {
-        session_manager: session_manager.clone(),
-        api_key: config.api_key.clone(),
+        session_manager: app_state.session_manager.clone(),
+        api_keys: app_state.api_keys.clone(),
     };
     let ws_routes = Router::new()
         .route(
@@ -229,86 +600,848 @@ async fn main() -> anyhow::Result<()> {
         .layer(ws_rate_limit)
         .with_state(ws_state);
 
-    // Restrict CORS to the configured BASE_URL origin and Obsidian's app origin.
-    // This prevents arbitrary websites from making cross-origin requests while
-    // allowing the frontend (same-origin) and the Obsidian plugin to work.
-    let allowed_origins = [
-        config
-            .base_url
-            .parse()
-            .expect("BASE_URL must be a valid header value for CORS origin"),
-        "app://obsidian.md"
-            .parse()
-            .expect("Obsidian origin must be valid"),
-    ];
-    let cors = CorsLayer::new()
-        .allow_origin(allowed_origins)
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]);
-
     let app = Router::new()
         .merge(password_api)
         .merge(public_api)
         .merge(protected_api)
-        .with_state(app_state)
-        .merge(ws_routes)
-        .fallback_service(frontend_service)
+        .with_state(app_state);
+    // Collab sessions save to storage, so followers don't host them
+    if config.follower {
+        app
+    } else {
+        app.merge(ws_routes)
+    }
+}
+
+/// Per-IP rate limiter allowing bursts of `burst` requests, refilled at one
+/// request per second. Every call creates an independent set of buckets.
+fn rate_limit(burst: u32, name: &str) -> GovernorLayer<PeerIpKeyExtractor, NoOpMiddleware> {
+    GovernorLayer {
+        config: Arc::new(
+            GovernorConfigBuilder::default()
+                .per_second(1)
+                .burst_size(burst)
+                .finish()
+                .unwrap_or_else(|| panic!("Failed to build {name} rate limiter")),
+        ),
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum StorageBackend {
+    Fs,
+    Sqlite,
+}
+
+/// Open the configured storage backend behind the in-memory cache. Asset
+/// extraction only applies to the filesystem backend, content dedup to SQLite.
+async fn open_storage(config: &Config, cache_bytes: usize, byte_quota: ByteQuota) -> anyhow::Result<Storage> {
+    if config.follower && (config.cache_memory_mb > 0 || config.cache_capacity > 0) {
+        tracing::warn!("CACHE_MEMORY_MB and CACHE_CAPACITY are ignored by followers, which don't cache drawings");
+    } else if cache_bytes == 0 && config.cache_capacity > 0 {
+        tracing::warn!("CACHE_CAPACITY is ignored without a CACHE_MEMORY_MB budget");
+    }
+    Ok(match config.storage_backend {
+        StorageBackend::Fs => {
+            if config.dedup_storage {
+                tracing::warn!("DEDUP_STORAGE is ignored by the filesystem storage backend");
+            }
+            // Followers leave the primary's temp files and sidecars alone
+            let storage = if config.follower {
+                FileSystemStorage::open_read_only(&config.data_dir).await?
+            } else {
+                FileSystemStorage::new(&config.data_dir).await?
+            };
+            let storage = storage
+                .with_asset_extraction(config.extract_assets)
+                .with_history_depth(config.history_depth)
+                .with_byte_quota(byte_quota);
+            CachedStorage::new(storage).with_cache(cache_bytes, config.cache_capacity).into()
+        }
+        StorageBackend::Sqlite => {
+            if config.extract_assets {
+                tracing::warn!("EXTRACT_ASSETS is ignored by the SQLite storage backend");
+            }
+            let path = config
+                .sqlite_path
+                .clone()
+                .unwrap_or_else(|| config.data_dir.join("drawings.sqlite"));
+            let storage = SqliteStorage::new(path)
+                .await?
+                .with_history_depth(config.history_depth)
+                .with_content_dedup(config.dedup_storage)
+                .with_byte_quota(byte_quota);
+            CachedStorage::new(storage).with_cache(cache_bytes, config.cache_capacity).into()
+        }
+    })
+}
+
+/// Stands in for secrets in `/api/admin/config`.
+const REDACTED: &str = "[redacted]";
+
+/// Serialize a secret setting as [`REDACTED`], or `null` when it isn't set.
+fn redact<S: serde::Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => serializer.serialize_str(REDACTED),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// The configuration served by `/api/admin/config`: every setting, with secrets
+/// redacted, plus the labels of the accepted API keys.
+fn effective_config(config: &Config, api_keys: &ApiKeys) -> anyhow::Result<serde_json::Value> {
+    let mut value = serde_json::to_value(config)?;
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("api_key_labels".to_string(), serde_json::json!(api_keys.labels().collect::<Vec<_>>()));
+    }
+    Ok(value)
+}
+
+/// Collect the accepted API keys from `API_KEY`, `API_KEYS` and `API_KEYS_FILE`.
+fn load_api_keys(config: &Config) -> anyhow::Result<ApiKeys> {
+    let mut keys = ApiKeys::default();
+    if let Some(key) = config.api_key.as_deref().filter(|k| !k.is_empty()) {
+        keys = keys.merge(ApiKeys::single(key));
+    }
+    if let Some(spec) = &config.api_keys {
+        keys = keys.merge(ApiKeys::parse(spec).map_err(|e| anyhow::anyhow!("API_KEYS: {e}"))?);
+    }
+    if let Some(path) = &config.api_keys_file {
+        let spec = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read API_KEYS_FILE {}: {e}", path.display()))?;
+        keys = keys.merge(ApiKeys::parse(&spec).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?);
+    }
+    Ok(keys)
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Publish every .excalidraw file in a directory (recursively) without
+    /// starting the server. Relative paths are kept as `source_path`.
+    ImportDir {
+        /// Directory to import from
+        dir: PathBuf,
+    },
+    /// Write an incremental backup snapshot to a directory. Only drawings that
+    /// changed since the previous snapshot there are copied.
+    Backup {
+        /// Backup directory (holds `blobs/` and `snapshots/`)
+        dest: PathBuf,
+    },
+}
+
+/// Run `import-dir` against the configured data dir and print a summary.
+async fn run_import_dir(config: &Config, dir: &std::path::Path) -> anyhow::Result<()> {
+    let storage = open_storage(config, 0, ByteQuota::default()).await?;
+    let summary = import::import_dir(&storage, dir).await?;
+
+    for (path, reason) in &summary.failed {
+        println!("failed   {path}: {reason}");
+    }
+    println!(
+        "Imported {}, skipped {} (already published), failed {}",
+        summary.imported,
+        summary.skipped,
+        summary.failed.len()
+    );
+    Ok(())
+}
+
+/// Run `backup` against the configured data dir and print a summary.
+async fn run_backup(config: &Config, dest: &std::path::Path) -> anyhow::Result<()> {
+    let storage = open_storage(config, 0, ByteQuota::default()).await?;
+    let summary = backup::run_backup(&storage, dest).await?;
+    println!(
+        "Backed up to {}: copied {}, unchanged {}",
+        summary.snapshot.display(),
+        summary.copied,
+        summary.referenced
+    );
+    Ok(())
+}
+
+/// Install the fallback for non-API paths: the static frontend, where unmatched
+/// paths get index.html (or the custom not-found page, with a 404, for paths
+/// that aren't SPA routes) — or a JSON 404 in API-only mode.
+fn with_frontend(app: Router, config: &Config, storage: &Storage) -> Router {
+    if config.api_only {
+        return app.fallback(spa::api_only_fallback);
+    }
+
+    let index_file = config.frontend_dir.join("index.html");
+    match &config.not_found_page {
+        Some(not_found_page) => {
+            let spa_state = SpaState {
+                storage: storage.clone(),
+                index_file,
+                not_found_page: not_found_page.clone(),
+            };
+            app.fallback_service(
+                ServeDir::new(&config.frontend_dir)
+                    .fallback(spa::spa_fallback.with_state(spa_state)),
+            )
+        }
+        None => app.fallback_service(
+            ServeDir::new(&config.frontend_dir).not_found_service(ServeFile::new(&index_file)),
+        ),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::parse();
+    logging::init(config.log_format);
+
+    match &config.command {
+        Some(Command::ImportDir { dir }) => return run_import_dir(&config, dir).await,
+        Some(Command::Backup { dest }) => return run_backup(&config, dest).await,
+        None => {}
+    }
+
+    let api_keys = load_api_keys(&config)?;
+    if api_keys.is_empty() {
+        anyhow::bail!("An API key is required to run the server (set API_KEY, API_KEYS or API_KEYS_FILE)");
+    }
+
+    // Warn about insecure default API key
+    if api_keys.contains_key("change-me-in-production") {
+        tracing::warn!("⚠️  Using default API key 'change-me-in-production' — set API_KEY for production!");
+    }
+
+    tracing::info!(
+        listen = %config.listen_addr,
+        data_dir = %config.data_dir.display(),
+        storage_backend = ?config.storage_backend,
+        base_url = %config.base_url,
+        max_upload_mb = config.max_upload_mb,
+        api_keys = ?api_keys.labels().collect::<Vec<_>>(),
+        "Starting excalishare server"
+    );
+
+    let byte_quota = ByteQuota::new(config.max_total_mb.map(|mb| mb * 1024 * 1024));
+    let cache_bytes = if config.follower { 0 } else { config.cache_memory_mb * 1024 * 1024 };
+    let storage = open_storage(&config, cache_bytes, byte_quota.clone()).await?;
+    let session_manager = SessionManager::new();
+
+    // Scan for persistent collab drawings and register them
+    let persistent_ids = storage.list_persistent_collab_drawings().await
+        .unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Failed to scan for persistent collab drawings");
+            vec![]
+        });
+    if !persistent_ids.is_empty() {
+        tracing::info!(count = persistent_ids.len(), "Registering persistent collab drawings");
+        for id in persistent_ids {
+            session_manager.register_persistent_drawing(id).await;
+        }
+    }
+
+    // Failed webhook deliveries are queued in the data dir so they survive restarts.
+    // Followers make no changes to notify about, and the queue is the primary's.
+    let webhooks = match config.webhook_url.as_ref().filter(|_| !config.follower) {
+        Some(url) => {
+            tracing::info!(url = %url, "Webhook notifications enabled");
+            Some(
+                WebhookNotifier::new(
+                    url.clone(),
+                    config.data_dir.join(".webhook-queue"),
+                    config.webhook_max_age_secs,
+                )
+                .await?
+                .with_secret(config.webhook_secret.clone()),
+            )
+        }
+        None => None,
+    };
+    let usage_history = UsageHistory::new(config.data_dir.join(".usage-history"));
+    let view_counter = ViewCounter::new();
+    let metrics = Metrics::new();
+    let drawing_quota = DrawingQuota::load(config.max_drawings, &storage).await?;
+    if let Some(max) = config.max_drawings {
+        tracing::info!(max, current = drawing_quota.count(), "Drawing count capped");
+    }
+    byte_quota.recount(&storage).await?;
+    if let Some(max_bytes) = byte_quota.usage().max_bytes {
+        tracing::info!(max_bytes, used_bytes = byte_quota.usage().used_bytes, "Total drawing size capped");
+    }
+    let write_breaker = WriteBreaker::new(
+        config.write_failure_threshold,
+        std::time::Duration::from_secs(config.write_probe_interval_secs.max(1)),
+    );
+
+    let default_app_state = match &config.default_appstate {
+        Some(path) => {
+            let raw = tokio::fs::read(path).await?;
+            match serde_json::from_slice(&raw)? {
+                serde_json::Value::Object(defaults) => Some(Arc::new(defaults)),
+                _ => anyhow::bail!("DEFAULT_APPSTATE must contain a JSON object ({})", path.display()),
+            }
+        }
+        None => None,
+    };
+
+    // Shared with the expiry sweep, which takes the same per-drawing update locks
+    let upload_locks = KeyedLocks::new();
+    let app_state = AppState {
+        storage: storage.clone(),
+        base_url: config.base_url.clone(),
+        session_manager: session_manager.clone(),
+        api_keys: api_keys.clone(),
+        stun_url: config.stun_url.clone(),
+        turn_url: config.turn_url.clone(),
+        turn_secret: config.turn_secret.clone(),
+        cache_max_age_secs: config.cache_max_age_secs,
+        cache_swr_secs: config.cache_swr_secs,
+        max_tags_per_drawing: config.max_tags_per_drawing,
+        max_tag_length: config.max_tag_length,
+        require_delete_confirmation: config.require_delete_confirmation,
+        confirm_tokens: ConfirmTokens::new(),
+        webhooks: webhooks.clone(),
+        dedup_uploads: config.dedup_uploads,
+        upload_locks: upload_locks.clone(),
+        view_locks: KeyedLocks::new(),
+        asset_base_url: config.asset_base_url.clone(),
+        auto_slug: config.auto_slug,
+        reject_duplicate_element_ids: config.reject_duplicate_element_ids,
+        warn_empty_drawings: config.warn_empty_drawings,
+        reject_empty_drawings: config.reject_empty_drawings,
+        embed_metadata: config.embed_metadata,
+        max_embedded_files: config.max_embedded_files,
+        max_embedded_bytes: config.max_embedded_mb.map(|mb| mb * 1024 * 1024),
+        strip_files: config.strip_files,
+        drawing_quota: drawing_quota.clone(),
+        byte_quota,
+        render_cache: RenderCache::new(config.render_cache_mb * 1024 * 1024),
+        render_permits: Arc::new(tokio::sync::Semaphore::new(config.render_concurrency as usize)),
+        effective_config: Arc::new(effective_config(&config, &api_keys)?),
+        metrics: metrics.clone(),
+        round_coordinates: config.round_coordinates,
+        max_coordinate: config.max_coordinate,
+        clamp_coordinates: config.clamp_coordinates,
+        allowed_link_origins: config.block_external_links.then(|| {
+            let extra = config.allowed_link_origins.split(',').map(str::trim).filter(|o| !o.is_empty());
+            Arc::new(std::iter::once(config.base_url.as_str()).chain(extra).map(String::from).collect())
+        }),
+        default_app_state,
+        max_ttl_secs: config.max_ttl_secs,
+        ttl_extension_secs: config.ttl_extension_secs,
+        view_counter: view_counter.clone(),
+        write_breaker: write_breaker.clone(),
+        max_preview_bytes: config.max_preview_kb * 1024,
+        normalize_source_paths: config.normalize_source_paths,
+        usage_history: usage_history.clone(),
+        max_list_response_bytes: config.max_list_response_bytes,
+        import_limits: import::ImportLimits {
+            max_entries: config.max_import_entries,
+            max_entry_bytes: config.max_upload_mb as u64 * 1024 * 1024,
+            max_total_bytes: config.max_import_mb * 1024 * 1024,
+        },
+        weak_etags: config.weak_etags,
+        follower: config.follower,
+        soft_delete: config.soft_delete,
+    };
+
+    let cors = cors_layer(&config)?;
+    let app = api_router(&config, app_state);
+
+    let app = with_frontend(app, &config, &storage);
+
+    // Followers refuse every write themselves, so the breaker (whose probe
+    // writes) only guards the primary
+    let app = if config.follower {
+        tracing::info!(primary = ?config.primary_url, "Running as a read-only follower");
+        app.layer(middleware::from_fn_with_state(
+            Follower { primary_url: config.primary_url.clone() },
+            follower::reject_writes_middleware,
+        ))
+    } else {
+        app.layer(middleware::from_fn_with_state(
+            write_breaker.clone(),
+            breaker::write_breaker_middleware,
+        ))
+    };
+
+    let app = if config.metrics {
+        app.layer(middleware::from_fn_with_state(metrics.clone(), metrics::metrics_middleware))
+    } else {
+        app
+    };
+
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            FrameAncestors::from_list(&config.allowed_frame_ancestors),
+            csp::frame_ancestors_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            SlowRequestThreshold::from_millis(config.slow_request_ms),
+            timing::request_timing_middleware,
+        ))
         .layer(CompressionLayer::new())
-        .layer(cors)
-        .layer(TraceLayer::new_for_http());
+        .layer(cors);
+    let app = with_access_log(app);
 
     // Spawn background task for session cleanup (every 60 seconds).
     // Expired sessions are saved to storage before being removed.
-    let cleanup_manager = session_manager.clone();
-    let cleanup_storage = storage.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
-        loop {
-            interval.tick().await;
-            cleanup_manager.cleanup_expired(&cleanup_storage).await;
-        }
-    });
+    if !config.follower {
+        let cleanup_manager = session_manager.clone();
+        let cleanup_storage = storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                cleanup_manager.cleanup_expired(&cleanup_storage).await;
+            }
+        });
+    }
 
     // Spawn background task for persistent collab auto-save (every 2 seconds).
     // Saves dirty persistent sessions to disk without interrupting active collaboration.
-    let autosave_manager = session_manager.clone();
-    let autosave_storage = storage.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
-        loop {
-            interval.tick().await;
-            // Get all active persistent session IDs
-            let session_ids: Vec<String> = autosave_manager.list_sessions().await
-                .into_iter()
-                .filter(|s| s.persistent)
-                .map(|s| s.session_id)
-                .collect();
-
-            for session_id in session_ids {
-                if let Some((drawing_id, data, version)) =
-                    autosave_manager.get_persistent_save_data(&session_id).await
-                {
-                    if let Err(e) = autosave_storage.save_persistent(&drawing_id, &data, version).await {
-                        tracing::error!(
-                            drawing_id = %drawing_id,
-                            error = %e,
-                            "Failed to auto-save persistent collab session"
-                        );
+    if !config.follower {
+        let autosave_manager = session_manager.clone();
+        let autosave_storage = storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
+            loop {
+                interval.tick().await;
+                // Get all active persistent session IDs
+                let session_ids: Vec<String> = autosave_manager.list_sessions().await
+                    .into_iter()
+                    .filter(|s| s.persistent)
+                    .map(|s| s.session_id)
+                    .collect();
+
+                for session_id in session_ids {
+                    if let Some((drawing_id, data, version)) =
+                        autosave_manager.get_persistent_save_data(&session_id).await
+                    {
+                        if let Err(e) = autosave_storage.save_persistent(&drawing_id, &data, version).await {
+                            tracing::error!(
+                                drawing_id = %drawing_id,
+                                error = %e,
+                                "Failed to auto-save persistent collab session"
+                            );
+                        }
                     }
                 }
             }
-        }
-    });
+        });
+    }
 
+    // Spawn background task deleting expired drawings.
+    if config.cleanup_interval_secs > 0 && !config.follower {
+        let cleanup_storage = storage.clone();
+        let cleanup_webhooks = webhooks.clone();
+        let cleanup_locks = upload_locks.clone();
+        let interval_secs = config.cleanup_interval_secs;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                match storage::delete_expired(&cleanup_storage, &cleanup_locks).await {
+                    Ok(deleted) if !deleted.is_empty() => {
+                        tracing::info!(count = deleted.len(), "Deleted expired drawings");
+                        for drawing in deleted {
+                            drawing_quota.remove();
+                            if let Some(webhooks) = &cleanup_webhooks {
+                                webhooks.notify(WebhookEvent::new("delete", &drawing.id, drawing.source_path.as_deref()));
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!(error = %e, "Failed to delete expired drawings"),
+                }
+            }
+        });
+    }
+
+    // Spawn background task purging drawings kept in the trash past retention (hourly).
+    if config.trash_retention_days > 0 && !config.follower {
+        let purge_storage = storage.clone();
+        let retention = chrono::Duration::days(config.trash_retention_days as i64);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                match purge_storage.purge_trash(chrono::Utc::now() - retention).await {
+                    Ok(purged) if !purged.is_empty() => tracing::info!(count = purged.len(), "Purged drawings from trash"),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!(error = %e, "Failed to purge trash"),
+                }
+            }
+        });
+    }
+
+    // Spawn background task retrying queued webhook deliveries (every 5 seconds).
+    if let Some(retry_webhooks) = webhooks {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+            loop {
+                interval.tick().await;
+                retry_webhooks.retry_due().await;
+            }
+        });
+    }
+
+    // Spawn background task refreshing the drawing gauges served at /metrics.
+    if config.metrics {
+        let metrics_storage = storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(metrics::REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = metrics.refresh(&metrics_storage).await {
+                    tracing::warn!(error = %e, "Failed to refresh metrics gauges");
+                }
+            }
+        });
+    }
+
+    // Spawn background task sampling storage usage for capacity trending.
+    if config.usage_sample_interval_secs > 0 && !config.follower {
+        let sample_storage = storage.clone();
+        let interval_secs = config.usage_sample_interval_secs;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                if let Err(e) = usage_history.sample(&sample_storage).await {
+                    tracing::warn!(error = %e, "Failed to record storage usage sample");
+                }
+            }
+        });
+    }
+
+    // Spawn background tasks probing storage while failing writes keep the server
+    // read-only, and persisting batched view counts. Followers count no views.
+    if !config.follower {
+        write_breaker.spawn_prober(storage.clone());
+        view_counter.spawn_flusher(
+            storage.clone(),
+            std::time::Duration::from_secs(config.view_flush_interval_secs.max(1)),
+        );
+    }
+
+    // Load the certificate before binding, so a bad one fails startup cleanly
+    let tls_config = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::server_config(cert, key)?),
+        _ => None,
+    };
     let listener = tokio::net::TcpListener::bind(&config.listen_addr).await?;
-    tracing::info!("Listening on {}", config.listen_addr);
+    tracing::info!(tls = tls_config.is_some(), "Listening on {}", config.listen_addr);
 
-    // Use into_make_service_with_connect_info so tower_governor can extract peer IP
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
-    )
-    .await?;
+    // Served with connect info so tower_governor can extract the peer IP
+    let timeout = config.shutdown_timeout_secs.map(std::time::Duration::from_secs);
+    match tls_config {
+        Some(tls_config) => {
+            let listener = tls::TlsListener::new(listener, tls_config)?;
+            shutdown::serve(listener, app, shutdown_signal(), timeout).await?;
+        }
+        None => {
+            shutdown::serve(listener, app, shutdown_signal(), timeout).await?;
+        }
+    }
+
+    if !config.follower {
+        let flushed = view_counter.flush(&storage).await;
+        tracing::info!(flushed, "Shut down; pending view counts persisted");
+    }
 
     Ok(())
 }
+
+/// CORS for the API. By default only the frontend (BASE_URL) and the Obsidian
+/// plugin may make cross-origin requests; `CORS_ORIGINS` replaces that list, or
+/// opens the API to any origin when it is `*`.
+fn cors_layer(config: &Config) -> anyhow::Result<CorsLayer> {
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::IF_MATCH])
+        .expose_headers([header::ETAG]);
+
+    let configured = config.cors_origins.as_deref().map(str::trim);
+    if configured == Some("*") {
+        tracing::warn!("CORS allows any origin");
+        return Ok(cors.allow_origin(AllowOrigin::any()));
+    }
+    let origins: Vec<String> = match configured {
+        Some(list) => list.split(',').map(str::trim).filter(|o| !o.is_empty()).map(String::from).collect(),
+        None => vec![config.base_url.trim_end_matches('/').to_string(), "app://obsidian.md".to_string()],
+    };
+    if origins.iter().any(|o| o == "*") {
+        anyhow::bail!("CORS_ORIGINS can't mix `*` with a list of origins; use `*` alone to allow any origin");
+    }
+    let values = origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin).map_err(|_| anyhow::anyhow!("Invalid CORS origin: {origin:?}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let credentials = configured.is_some();
+    tracing::info!(origins = ?origins, credentials, "CORS restricted to listed origins");
+    Ok(cors.allow_origin(AllowOrigin::list(values)).allow_credentials(credentials))
+}
+
+/// Resolves on Ctrl+C or SIGTERM, letting in-flight requests finish.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Failed to listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Trace every request in a span carrying its id (the client's `X-Request-Id`,
+/// or a fresh UUID echoed back), method and path, and log its status and
+/// latency once answered. The id is also quoted in error bodies. Credentials
+/// are marked sensitive so trace output never shows them.
+fn with_access_log(app: Router) -> Router {
+    let trace = TraceLayer::new_for_http()
+        .make_span_with(|request: &axum::extract::Request| {
+            let request_id = request.headers().get("x-request-id").and_then(|v| v.to_str().ok()).unwrap_or("-");
+            tracing::info_span!(
+                "request",
+                request_id = %request_id,
+                method = %request.method(),
+                path = %request.uri().path(),
+            )
+        })
+        .on_response(|response: &axum::response::Response, latency: std::time::Duration, _span: &tracing::Span| {
+            tracing::info!(
+                status = response.status().as_u16(),
+                latency_ms = latency.as_millis() as u64,
+                "Request finished"
+            );
+        });
+    app.layer(middleware::from_fn(request_id::request_id_middleware))
+        .layer(trace)
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetSensitiveRequestHeadersLayer::new([header::AUTHORIZATION, header::COOKIE]))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Request, StatusCode}};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_api_only_mode_returns_json_404() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage: Storage = FileSystemStorage::new(dir.path()).await.unwrap().into();
+        let config = Config::parse_from(["excalishare", "--api-only"]);
+        let app = with_frontend(Router::new().route("/api/health", get(|| async { "ok" })), &config, &storage);
+
+        let get = |path: &str| Request::builder().uri(path).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(get("/api/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for path in ["/", "/admin", "/d/abc", "/favicon.ico"] {
+            let response = app.clone().oneshot(get(path)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+            assert_eq!(response.headers()[axum::http::header::CONTENT_TYPE], "application/json");
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body["error"], "Not found");
+        }
+    }
+
+    async fn cors_preflight(config: &Config, origin: &str) -> axum::http::Response<Body> {
+        let app = Router::new().route("/api/health", get(|| async { "ok" })).layer(cors_layer(config).unwrap());
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/health")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_origins_allowlist() {
+        let allowed = |response: &axum::http::Response<Body>| {
+            response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).map(|v| v.to_str().unwrap().to_string())
+        };
+
+        let config = Config::parse_from(["excalishare", "--base-url", "https://share.example.com/"]);
+        assert_eq!(allowed(&cors_preflight(&config, "https://share.example.com").await).as_deref(), Some("https://share.example.com"));
+        assert_eq!(allowed(&cors_preflight(&config, "app://obsidian.md").await).as_deref(), Some("app://obsidian.md"));
+        assert_eq!(allowed(&cors_preflight(&config, "https://evil.example").await), None);
+
+        let config = Config::parse_from(["excalishare", "--cors-origins", "https://a.example, https://b.example"]);
+        let response = cors_preflight(&config, "https://b.example").await;
+        assert_eq!(allowed(&response).as_deref(), Some("https://b.example"));
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(allowed(&cors_preflight(&config, "app://obsidian.md").await), None);
+
+        let config = Config::parse_from(["excalishare", "--cors-origins", "*"]);
+        let response = cors_preflight(&config, "https://evil.example").await;
+        assert_eq!(allowed(&response).as_deref(), Some("*"));
+        assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+
+        let config = Config::parse_from(["excalishare", "--cors-origins", "https://bad\norigin"]);
+        assert!(cors_layer(&config).is_err());
+        let config = Config::parse_from(["excalishare", "--cors-origins", "https://a.example,*"]);
+        assert!(cors_layer(&config).unwrap_err().to_string().contains("`*` alone"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_allows_put() {
+        let config = Config::parse_from(["excalishare", "--cors-origins", "https://a.example"]);
+        let app = Router::new().route("/api/health", get(|| async { "ok" })).layer(cors_layer(&config).unwrap());
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/health")
+            .header(header::ORIGIN, "https://a.example")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let methods = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().to_string();
+        assert!(methods.split(',').any(|m| m.trim() == "PUT"), "{methods}");
+    }
+
+    #[tokio::test]
+    async fn test_access_log_json_lines() {
+        let logs = logging::tests::CapturedLogs::default();
+        let _guard = tracing::subscriber::set_default(logging::tests::json_subscriber(&logs));
+        let app = with_access_log(Router::new().route(
+            "/api/drawings",
+            get(|headers: axum::http::HeaderMap| async move {
+                tracing::info!(?headers, "Handling");
+                "ok"
+            }),
+        ));
+
+        let request = Request::builder()
+            .uri("/api/drawings?limit=1")
+            .header(header::AUTHORIZATION, "Bearer supersecretkey")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let request_id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+        assert!(uuid::Uuid::parse_str(&request_id).is_ok(), "{request_id}");
+
+        let request = Request::builder().uri("/api/drawings").header("x-request-id", "client-id").body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().headers()["x-request-id"], "client-id");
+
+        let lines = logs.json_lines();
+        assert!(!serde_json::to_string(&lines).unwrap().contains("supersecretkey"));
+        let finished: Vec<_> = lines.iter().filter(|l| l["message"] == "Request finished").collect();
+        assert_eq!(finished.len(), 2);
+        assert_eq!(finished[0]["request_id"], request_id.as_str());
+        assert_eq!(finished[0]["method"], "GET");
+        assert_eq!(finished[0]["path"], "/api/drawings");
+        assert_eq!(finished[0]["status"], 200);
+        assert!(finished[0]["latency_ms"].is_u64());
+        assert_eq!(finished[1]["request_id"], "client-id");
+        // The handler saw the header, but only as a redacted value
+        assert!(lines[0]["headers"].as_str().unwrap().contains("authorization\": Sensitive"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn test_effective_config_redacts_secrets() {
+        let config = Config::parse_from([
+            "excalishare",
+            "--api-key", "supersecretkey",
+            "--api-keys", "ci:anothersecret:read",
+            "--turn-secret", "turnsecret",
+            "--webhook-secret", "webhooksecret",
+            "--max-upload-mb", "7",
+            "--storage-backend", "sqlite",
+            "--dedup-uploads",
+        ]);
+        let api_keys = load_api_keys(&config).unwrap();
+        let value = effective_config(&config, &api_keys).unwrap();
+
+        let serialized = value.to_string();
+        for secret in ["supersecretkey", "anothersecret", "turnsecret", "webhooksecret"] {
+            assert!(!serialized.contains(secret), "{secret} leaked");
+        }
+        assert_eq!(value["api_key"], REDACTED);
+        assert_eq!(value["turn_secret"], REDACTED);
+        assert_eq!(value["webhook_secret"], REDACTED);
+        assert!(value["webhook_url"].is_null());
+        assert_eq!(value["api_key_labels"], serde_json::json!(["default", "ci"]));
+
+        // Overridden values are reported, defaults alongside them
+        assert_eq!(value["max_upload_mb"], 7);
+        assert_eq!(value["storage_backend"], "sqlite");
+        assert_eq!(value["dedup_uploads"], true);
+        assert_eq!(value["listen_addr"], "127.0.0.1:8184");
+        assert!(value.get("command").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_admin_config_needs_a_write_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = routes::tests::test_state(dir.path()).await;
+        state.api_keys = ApiKeys::parse("dashboard:dash-key:read, ci:ci-key:write").unwrap();
+        let app = api_router(&Config::parse_from(["excalishare"]), state);
+        let get_config = |token: &str| {
+            let peer = std::net::SocketAddr::from(([203, 0, 113, 7], 40000));
+            let request = Request::builder()
+                .uri("/api/admin/config")
+                .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                .extension(axum::extract::ConnectInfo(peer))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        assert_eq!(get_config("dash-key").await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(get_config("ci-key").await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_write_rate_limit_trips_independently_of_reads() {
+        let config = Config::parse_from(["excalishare", "--rate-limit-read", "5", "--rate-limit-write", "2"]);
+        let reads = Router::new()
+            .route("/api/view/{id}", get(|| async { "drawing" }))
+            .layer(rate_limit(config.rate_limit_read, "read"));
+        let writes = Router::new()
+            .route("/api/upload", post(|| async { "stored" }))
+            .layer(rate_limit(config.rate_limit_write, "write"));
+        let app = Router::new().merge(reads).merge(writes);
+
+        let send = |method: Method, path: &str| {
+            let mut request = Request::builder().method(method).uri(path).body(Body::empty()).unwrap();
+            let peer = std::net::SocketAddr::from(([203, 0, 113, 7], 40000));
+            request.extensions_mut().insert(axum::extract::ConnectInfo(peer));
+            app.clone().oneshot(request)
+        };
+
+        for _ in 0..2 {
+            assert_eq!(send(Method::POST, "/api/upload").await.unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(send(Method::POST, "/api/upload").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // The exhausted write limit leaves the read allowance untouched
+        for _ in 0..5 {
+            assert_eq!(send(Method::GET, "/api/view/abc").await.unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(send(Method::GET, "/api/view/abc").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
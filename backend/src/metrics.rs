@@ -0,0 +1,217 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cache::CacheUsage;
+use crate::error::AppError;
+use crate::quota::ByteQuotaUsage;
+use crate::storage::DrawingStorage;
+
+/// How often the drawing count and stored bytes gauges are re-read from storage.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Upper bounds (seconds) of the request latency buckets, Prometheus' defaults.
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations in each bucket (not cumulative)
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Default)]
+struct Registry {
+    uploads: AtomicU64,
+    deletes: AtomicU64,
+    views: AtomicU64,
+    drawings: AtomicU64,
+    stored_bytes: AtomicU64,
+    /// Keyed by method and route template
+    latencies: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+/// Counters and gauges served in the Prometheus text format at `/metrics`.
+/// Cheap enough to update on every request, enabled or not; `--metrics` only
+/// decides whether they are exposed.
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Registry>);
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_upload(&self) {
+        self.0.uploads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_delete(&self) {
+        self.0.deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_view(&self) {
+        self.0.views.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Add one request, labelled by its route template (e.g. `/api/view/{id}`)
+    /// so drawing ids don't each become a series.
+    pub fn observe_request(&self, method: &str, route: &str, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut latencies = self.0.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = latencies.entry((method.to_string(), route.to_string())).or_default();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Re-read the drawing count and stored bytes. Runs every [`REFRESH_INTERVAL`]
+    /// rather than per scrape, as it lists all drawings.
+    pub async fn refresh(&self, storage: &impl DrawingStorage) -> Result<(), AppError> {
+        let drawings = storage.list().await?;
+        let bytes = drawings.iter().map(|d| d.size_bytes).sum();
+        self.0.drawings.store(drawings.len() as u64, Ordering::Relaxed);
+        self.0.stored_bytes.store(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Everything in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let scalars = [
+            ("excalishare_uploads_total", "counter", "Successful uploads, new drawings and updates", &self.0.uploads),
+            ("excalishare_deletes_total", "counter", "Drawings deleted through the API", &self.0.deletes),
+            ("excalishare_views_total", "counter", "Drawings served to viewers", &self.0.views),
+            ("excalishare_drawings", "gauge", "Stored drawings", &self.0.drawings),
+            ("excalishare_stored_bytes", "gauge", "Total size of the stored drawings", &self.0.stored_bytes),
+        ];
+        for (name, kind, help, value) in scalars {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        let name = "excalishare_request_duration_seconds";
+        let _ = writeln!(out, "# HELP {name} Time to handle a request\n# TYPE {name} histogram");
+        let latencies = self.0.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        for ((method, route), histogram) in latencies.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape_label(method), escape_label(route));
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}");
+            }
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", histogram.count);
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {}", histogram.sum);
+            let _ = writeln!(out, "{name}_count{{{labels}}} {}", histogram.count);
+        }
+        out
+    }
+}
+
+/// The drawing cache's hit and miss counters and memory use, appended to
+/// [`Metrics::render`] when caching is enabled. Hit ratio is
+/// `hits / (hits + misses)`.
+pub fn render_cache(out: &mut String, usage: CacheUsage) {
+    let series = [
+        ("excalishare_cache_hits_total", "counter", "Drawing loads served from the cache", usage.hits),
+        ("excalishare_cache_misses_total", "counter", "Drawing loads that missed the cache", usage.misses),
+        ("excalishare_cache_bytes", "gauge", "Memory used by the drawing cache", usage.used_bytes as u64),
+    ];
+    for (name, kind, help, value) in series {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}");
+    }
+}
+
+/// Bytes counted against `MAX_TOTAL_MB`, and the cap when there is one. Kept
+/// current on every write, unlike `excalishare_stored_bytes`.
+pub fn render_quota(out: &mut String, usage: ByteQuotaUsage) {
+    let name = "excalishare_quota_used_bytes";
+    let _ = writeln!(out, "# HELP {name} Total size of stored drawings counted against the quota\n# TYPE {name} gauge\n{name} {}", usage.used_bytes);
+    if let Some(max) = usage.max_bytes {
+        let name = "excalishare_quota_max_bytes";
+        let _ = writeln!(out, "# HELP {name} Most bytes of drawings the server stores\n# TYPE {name} gauge\n{name} {max}");
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Middleware timing every request into [`Metrics`]. Requests that matched no
+/// route (frontend pages, 404s) share the `unmatched` label.
+pub async fn metrics_middleware(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics.observe_request(&method, &route, started.elapsed());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileSystemStorage;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_render_counters_and_gauges() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        let drawing = serde_json::json!({"type": "excalidraw", "elements": []});
+        storage.save("a", &drawing, None, None).await.unwrap();
+        storage.save("b", &drawing, None, None).await.unwrap();
+
+        let metrics = Metrics::new();
+        metrics.record_upload();
+        metrics.record_upload();
+        metrics.record_view();
+        metrics.refresh(&storage).await.unwrap();
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE excalishare_uploads_total counter\nexcalishare_uploads_total 2\n"));
+        assert!(text.contains("excalishare_views_total 1\n"));
+        assert!(text.contains("excalishare_deletes_total 0\n"));
+        assert!(text.contains("excalishare_drawings 2\n"));
+        assert!(!text.contains("excalishare_stored_bytes 0\n"));
+    }
+
+    #[test]
+    fn test_render_cache_counters() {
+        let mut text = String::new();
+        render_cache(&mut text, CacheUsage { hits: 3, misses: 1, ..Default::default() });
+        assert!(text.contains("# TYPE excalishare_cache_hits_total counter\nexcalishare_cache_hits_total 3\n"));
+        assert!(text.contains("excalishare_cache_misses_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_timed_by_route_template() {
+        let metrics = Metrics::new();
+        let app = Router::new()
+            .route("/api/view/{id}", get(|| async { "drawing" }))
+            .layer(middleware::from_fn_with_state(metrics.clone(), metrics_middleware));
+        for path in ["/api/view/one", "/api/view/two", "/missing"] {
+            let request = axum::http::Request::get(path).body(Body::empty()).unwrap();
+            let _ = app.clone().oneshot(request).await.unwrap();
+        }
+
+        let text = metrics.render();
+        let series = "excalishare_request_duration_seconds";
+        assert!(text.contains(&format!("{series}_count{{method=\"GET\",route=\"/api/view/{{id}}\"}} 2\n")));
+        assert!(text.contains(&format!("{series}_bucket{{method=\"GET\",route=\"/api/view/{{id}}\",le=\"+Inf\"}} 2\n")));
+        assert!(text.contains(&format!("{series}_count{{method=\"GET\",route=\"unmatched\"}} 1\n")));
+    }
+}
@@ -0,0 +1,155 @@
+use serde_json::Value;
+
+/// Element fields holding coordinates. Everything else (`seed`, `version`,
+/// `strokeWidth`, `opacity`, ...) is left exactly as uploaded.
+pub const COORDINATE_FIELDS: &[&str] = &["x", "y", "width", "height"];
+
+fn round(value: &mut Value, decimals: i32) {
+    // Integers are already canonical
+    let Some(n) = value.as_f64().filter(|_| value.is_f64()) else {
+        return;
+    };
+    let factor = 10f64.powi(decimals);
+    if let Some(rounded) = serde_json::Number::from_f64((n * factor).round() / factor) {
+        *value = Value::Number(rounded);
+    }
+}
+
+/// Apply `f` to every element coordinate: `x`, `y`, `width`, `height` and
+/// each component of every `points` entry.
+fn for_each_coordinate(data: &mut Value, mut f: impl FnMut(&mut Value)) {
+    let Some(elements) = data.get_mut("elements").and_then(|v| v.as_array_mut()) else {
+        return;
+    };
+    for element in elements.iter_mut().filter_map(|e| e.as_object_mut()) {
+        for field in COORDINATE_FIELDS {
+            if let Some(value) = element.get_mut(*field) {
+                f(value);
+            }
+        }
+        if let Some(points) = element.get_mut("points").and_then(|v| v.as_array_mut()) {
+            for point in points.iter_mut().filter_map(|p| p.as_array_mut()) {
+                point.iter_mut().for_each(&mut f);
+            }
+        }
+    }
+}
+
+/// Round element coordinates to `decimals` places, so saves differing only in
+/// floating-point noise store identical content and hash the same.
+pub fn round_coordinates(data: &mut Value, decimals: u32) {
+    let decimals = decimals.min(15) as i32;
+    for_each_coordinate(data, |value| round(value, decimals));
+}
+
+/// Clamp element coordinates into `[-max, max]`.
+pub fn clamp_coordinates(data: &mut Value, max: f64) {
+    for_each_coordinate(data, |value| {
+        if let Some(n) = value.as_f64().filter(|n| n.abs() > max) {
+            if let Some(clamped) = serde_json::Number::from_f64(n.clamp(-max, max)) {
+                *value = Value::Number(clamped);
+            }
+        }
+    });
+}
+
+/// Fill in `appState` keys missing from an uploaded document from the
+/// operator's defaults. Keys the uploader set are never overridden.
+pub fn apply_default_app_state(data: &mut Value, defaults: &serde_json::Map<String, Value>) {
+    let Some(obj) = data.as_object_mut() else {
+        return;
+    };
+    let app_state = obj.entry("appState").or_insert_with(|| Value::Object(Default::default()));
+    if !app_state.is_object() {
+        *app_state = Value::Object(Default::default());
+    }
+    if let Some(app_state) = app_state.as_object_mut() {
+        for (key, value) in defaults {
+            app_state.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// Use `/` as the separator in a vault-relative `source_path` (Windows clients
+/// send `\\`), dropping leading, trailing and doubled separators.
+pub fn source_path(path: &str) -> String {
+    path.split(['/', '\\'])
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn drawing(x: f64, point: f64) -> Value {
+        json!({
+            "type": "excalidraw",
+            "elements": [{
+                "id": "a", "type": "line", "x": x, "y": 10, "width": 5.5, "height": 2.0,
+                "points": [[0, 0], [point, 1.25]],
+                "strokeWidth": 1.123456, "seed": 123456789
+            }]
+        })
+    }
+
+    #[test]
+    fn test_saves_differing_beyond_precision_canonicalize_identically() {
+        let mut first = drawing(100.123_000_1, 42.718_281_8);
+        let mut second = drawing(100.122_999_8, 42.724_9);
+        round_coordinates(&mut first, 2);
+        round_coordinates(&mut second, 2);
+        assert_eq!(first, second);
+        assert_eq!(crate::storage::document_hash(&first), crate::storage::document_hash(&second));
+        assert_eq!(first["elements"][0]["x"], json!(100.12));
+        assert_eq!(first["elements"][0]["points"][1][0], json!(42.72));
+    }
+
+    #[test]
+    fn test_non_coordinate_numbers_preserved() {
+        let mut data = drawing(1.0, 1.0);
+        round_coordinates(&mut data, 1);
+        let element = &data["elements"][0];
+        assert_eq!(element["strokeWidth"], json!(1.123456));
+        assert_eq!(element["seed"], json!(123456789));
+        assert_eq!(element["y"], json!(10));
+    }
+
+    #[test]
+    fn test_default_app_state_fills_missing_keys_only() {
+        let defaults = json!({"theme": "dark", "gridSize": 20, "viewBackgroundColor": "#1e1e1e"});
+        let defaults = defaults.as_object().unwrap();
+
+        let mut data = json!({"type": "excalidraw", "elements": [], "appState": {"theme": "light", "gridSize": null}});
+        apply_default_app_state(&mut data, defaults);
+        assert_eq!(data["appState"]["theme"], "light");
+        assert!(data["appState"]["gridSize"].is_null());
+        assert_eq!(data["appState"]["viewBackgroundColor"], "#1e1e1e");
+
+        let mut data = json!({"type": "excalidraw", "elements": []});
+        apply_default_app_state(&mut data, defaults);
+        assert_eq!(data["appState"], json!(defaults));
+    }
+
+    #[test]
+    fn test_source_path_separators_normalized() {
+        assert_eq!(source_path("Projects\\Work\\plan.excalidraw"), "Projects/Work/plan.excalidraw");
+        assert_eq!(source_path("/Projects//Work/plan.excalidraw/"), "Projects/Work/plan.excalidraw");
+        assert_eq!(source_path("plan.excalidraw"), "plan.excalidraw");
+    }
+
+    #[test]
+    fn test_clamp_coordinates() {
+        let mut data = json!({"type": "excalidraw", "elements": [
+            {"id": "a", "x": 1e18, "y": -1e18, "width": 10, "points": [[0, 0], [5e17, 3]]}
+        ]});
+        clamp_coordinates(&mut data, 1e6);
+        let element = &data["elements"][0];
+        assert_eq!(element["x"], json!(1e6));
+        assert_eq!(element["y"], json!(-1e6));
+        assert_eq!(element["width"], json!(10));
+        assert_eq!(element["points"][1], json!([1e6, 3]));
+    }
+}
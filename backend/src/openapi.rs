@@ -0,0 +1,564 @@
+use axum::{extract::State, http::Method, Json};
+use serde_json::{json, Map, Value};
+
+use crate::routes::AppState;
+
+/// Who may call an operation; see `auth::KeyScope`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Access {
+    Public,
+    Read,
+    Write,
+}
+
+/// What a response (or request) carries.
+#[derive(Clone, Copy)]
+enum Body {
+    Empty,
+    /// Raw bytes of this media type
+    Media(&'static str),
+    /// JSON matching a component schema, or any JSON object when `None`
+    Json(Option<&'static str>),
+    /// `{"error": "...", "request_id": "..."}`, what [`crate::error::AppError`] responds with
+    Error,
+    /// `403` of password-protected drawings, `{"error", "password_protected"}`
+    PasswordError,
+}
+
+struct Operation {
+    method: Method,
+    path: &'static str,
+    summary: &'static str,
+    access: Access,
+    request: Body,
+    responses: &'static [(u16, Body)],
+}
+
+const fn op(
+    method: Method,
+    path: &'static str,
+    access: Access,
+    summary: &'static str,
+    request: Body,
+    responses: &'static [(u16, Body)],
+) -> Operation {
+    Operation { method, path, summary, access, request, responses }
+}
+
+use Access::{Public, Read, Write};
+use Body::{Empty, Error, Json as J, Media, PasswordError};
+
+/// Responses of the routes serving a drawing to viewers: gone once expired or
+/// burned, behind its password unless an API key is sent.
+const VIEWED: [(u16, Body); 3] = [(403, PasswordError), (404, Error), (410, Error)];
+
+/// Every routed operation. Tests check this against the router `main.rs`
+/// builds, paths, methods and statuses, so a route can't be added without
+/// describing it here.
+const OPERATIONS: &[Operation] = &[
+    op(Method::GET, "/api/health", Public, "Liveness probe", Empty, &[(200, Media("text/plain"))]),
+    op(Method::GET, "/api/health/ready", Public, "Readiness probe: storage accepts writes and reads", Empty,
+        &[(200, J(Some("ReadyResponse"))), (503, J(Some("ReadyResponse")))]),
+    op(Method::GET, "/api/openapi.json", Public, "This OpenAPI description", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/public/drawings", Public, "List drawings (public fields only)", Empty,
+        &[(200, J(Some("PublicListResponse"))), (400, Error), (413, Error)]),
+    op(Method::GET, "/api/view/{id}", Public, "Get a drawing", Empty,
+        &[(200, J(None)), (304, Empty), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::HEAD, "/api/view/{id}", Public, "Check a drawing without downloading or counting a view", Empty,
+        &[(200, Empty), (304, Empty), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/summary", Public, "Structural summary of a drawing", Empty,
+        &[(200, J(None)), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/meta", Public, "Metadata of a drawing, without its elements", Empty,
+        &[(200, J(Some("PublicDrawingMeta"))), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/svg-json", Public, "Drawing prepared for SVG export", Empty,
+        &[(200, J(None)), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/preview.png", Public, "Server-rendered PNG of a drawing", Empty,
+        &[(200, Media("image/png")), (304, Empty), (400, Error), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/drawing.svg", Public, "Server-rendered SVG of a drawing", Empty,
+        &[(200, Media("image/svg+xml")), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/download", Public, "Download a drawing as an .excalidraw file", Empty,
+        &[(200, Media("application/json")), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/{id}/history", Public, "Earlier versions of a drawing", Empty,
+        &[(200, J(None)), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/view/bulk.ndjson", Public, "Stream matching drawings as NDJSON", Empty,
+        &[(200, Media("application/x-ndjson"))]),
+    op(Method::GET, "/api/index.json", Public, "Compact index of all drawings", Empty, &[(200, J(None)), (304, Empty)]),
+    op(Method::GET, "/api/search", Public, "Full-text search over drawing text", Empty, &[(200, J(None)), (400, Error)]),
+    op(Method::GET, "/s/{slug}", Public, "Redirect a slug to its drawing's viewer page", Empty,
+        &[(307, Empty), (404, Error)]),
+    op(Method::GET, "/api/view/{id}/assets/{hash}", Public, "Extracted embedded file of a drawing", Empty,
+        &[(200, Media("application/octet-stream")), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/preview/{id}", Public, "Client-uploaded PNG preview", Empty,
+        &[(200, Media("image/png")), VIEWED[0], VIEWED[1], VIEWED[2]]),
+    op(Method::GET, "/api/collab/status/{drawing_id}", Public, "Whether a drawing has a live collab session", Empty,
+        &[(200, J(None))]),
+    op(Method::POST, "/api/persistent-collab/activate/{drawing_id}", Public, "Join a drawing's persistent collab session",
+        J(None), &[(200, J(None)), (400, Error), (403, PasswordError), (404, Error), (409, Error)]),
+    op(Method::POST, "/api/collab/verify-password", Public, "Check a collab session password", J(None),
+        &[(200, J(None)), (400, Error), (403, PasswordError), (404, Error)]),
+    op(Method::GET, "/metrics", Public, "Prometheus metrics (only routed with --metrics)", Empty,
+        &[(200, Media("text/plain"))]),
+    op(Method::GET, "/ws/collab/{session_id}", Public, "WebSocket of a collab session", Empty,
+        &[(101, Empty), (400, Error), (404, Error)]),
+    op(Method::GET, "/api/drawings/{id}/meta", Read, "Metadata of a drawing", Empty,
+        &[(200, J(Some("DrawingMeta"))), (404, Error)]),
+    op(Method::POST, "/api/drawings/meta-batch", Read, "Metadata of several drawings", J(None),
+        &[(200, J(None)), (400, Error)]),
+    op(Method::GET, "/api/drawings/{id}/hash", Read, "Content hash of a drawing", Empty, &[(200, J(None)), (404, Error)]),
+    op(Method::GET, "/api/drawings", Read, "List drawings", Empty,
+        &[(200, J(Some("ListResponse"))), (304, Empty), (400, Error), (413, Error)]),
+    op(Method::GET, "/api/drawings/deleted", Read, "Drawings deleted since a time", Empty,
+        &[(200, J(None)), (400, Error), (413, Error)]),
+    op(Method::GET, "/api/trash", Read, "Soft-deleted drawings awaiting restore or purge", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/lookup", Read, "Find a drawing by source path", Empty,
+        &[(200, J(None)), (400, Error), (404, Error)]),
+    op(Method::GET, "/api/stats", Read, "Storage, cache and quota statistics", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/storage/history", Read, "Sampled storage usage over time", Empty, &[(200, J(None)), (400, Error)]),
+    op(Method::GET, "/api/admin/verify", Read, "Check the metadata index against the drawings", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/auth/whoami", Read, "The calling key's label and scopes", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/folders", Read, "Drawing counts per source folder", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/collab/sessions", Read, "Live collab sessions", Empty, &[(200, J(None))]),
+    op(Method::GET, "/api/ice-config", Read, "STUN/TURN servers for collab", Empty, &[(200, J(None))]),
+    op(Method::POST, "/api/upload", Write, "Upload a new drawing or update one", J(Some("UploadRequest")),
+        &[
+            (200, J(Some("UploadResponse"))),
+            (201, J(Some("UploadResponse"))),
+            (400, Error),
+            (404, Error),
+            (410, Error),
+            (412, Error),
+            (413, Error),
+            (507, Error),
+        ]),
+    op(Method::DELETE, "/api/drawings/{id}", Write, "Delete a drawing (or prepare a confirm token)", Empty,
+        &[(200, J(None)), (204, Empty), (400, Error), (404, Error)]),
+    op(Method::POST, "/api/drawings/bulk-delete", Write, "Delete several drawings", J(None),
+        &[(200, J(None)), (400, Error)]),
+    op(Method::POST, "/api/drawings/{id}/extend", Write, "Push back a drawing's expiry", J(None),
+        &[(200, J(None)), (400, Error), (404, Error)]),
+    op(Method::POST, "/api/drawings/{id}/rollback/{version}", Write, "Restore an earlier version", Empty,
+        &[(200, J(None)), (400, Error), (404, Error), (507, Error)]),
+    op(Method::POST, "/api/drawings/{id}/restore", Write, "Bring a drawing back from the trash", Empty,
+        &[(200, J(Some("DrawingMeta"))), (404, Error), (409, Error), (507, Error)]),
+    op(Method::PUT, "/api/drawings/{id}/appstate", Write, "Replace a drawing's appState", J(None),
+        &[(204, Empty), (400, Error), (404, Error), (507, Error)]),
+    op(Method::POST, "/api/import", Write, "Restore drawings from an export archive", Media("application/zip"),
+        &[(200, J(None)), (400, Error), (413, Error)]),
+    op(Method::GET, "/api/export", Write, "Export drawings as a ZIP archive", Empty,
+        &[(200, Media("application/zip")), (400, Error)]),
+    op(Method::GET, "/api/admin/config", Write, "Effective configuration, secrets redacted", Empty, &[(200, J(None))]),
+    op(Method::POST, "/api/collab/start", Write, "Start a collab session", J(None),
+        &[(201, J(None)), (400, Error), (404, Error), (409, Error)]),
+    op(Method::POST, "/api/collab/stop", Write, "Stop a collab session", J(None),
+        &[(200, J(None)), (400, Error), (404, Error)]),
+    op(Method::POST, "/api/persistent-collab/enable", Write, "Enable persistent collab on a drawing", J(None),
+        &[(200, J(None)), (400, Error), (404, Error)]),
+    op(Method::POST, "/api/persistent-collab/disable", Write, "Disable persistent collab on a drawing", J(None),
+        &[(200, J(None)), (400, Error), (404, Error)]),
+];
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+fn content(body: Body) -> Option<Value> {
+    let (media, schema) = match body {
+        Body::Empty => return None,
+        Body::Media(media) => (media, json!({ "type": "string", "format": "binary" })),
+        Body::Json(Some(name)) => ("application/json", schema_ref(name)),
+        Body::Json(None) => ("application/json", json!({ "type": "object" })),
+        Body::Error => ("application/json", schema_ref("Error")),
+        Body::PasswordError => ("application/json", schema_ref("PasswordError")),
+    };
+    Some(json!({ media: { "schema": schema } }))
+}
+
+fn description(status: u16) -> &'static str {
+    axum::http::StatusCode::from_u16(status).ok().and_then(|s| s.canonical_reason()).unwrap_or("Response")
+}
+
+/// The responses of `op`, with the ones shared by whole route groups added:
+/// `401`/`403` from the auth middleware, `429` from rate limiting, `503`
+/// for writes from the write breaker (and followers), and `422` (plain text)
+/// when a JSON request body doesn't fit the handler's type.
+fn responses(op: &Operation) -> Map<String, Value> {
+    let mut all: Vec<(u16, Body)> = op.responses.to_vec();
+    if matches!(op.request, Body::Json(_)) {
+        all.push((422, Body::Media("text/plain")));
+    }
+    if op.access != Access::Public {
+        all.push((401, Body::Error));
+    }
+    if op.access == Access::Write {
+        all.push((403, Body::Error));
+    }
+    all.push((429, Body::Error));
+    if !matches!(op.method, Method::GET | Method::HEAD) {
+        all.push((503, Body::Error));
+    }
+    all.sort_by_key(|(status, _)| *status);
+    all.dedup_by_key(|(status, _)| *status);
+
+    all.into_iter()
+        .map(|(status, body)| {
+            let mut response = json!({ "description": description(status) });
+            if let Some(content) = content(body) {
+                response["content"] = content;
+            }
+            (status.to_string(), response)
+        })
+        .collect()
+}
+
+/// Names of `{placeholder}` segments in a route template.
+fn path_params(path: &str) -> Vec<&str> {
+    path.split('/').filter_map(|s| s.strip_prefix('{')?.strip_suffix('}')).collect()
+}
+
+fn operation(op: &Operation) -> Value {
+    let mut operation = json!({
+        "summary": op.summary,
+        "responses": responses(op),
+    });
+    let params: Vec<Value> = path_params(op.path)
+        .into_iter()
+        .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }))
+        .collect();
+    if !params.is_empty() {
+        operation["parameters"] = Value::Array(params);
+    }
+    if let Some(content) = content(op.request) {
+        operation["requestBody"] = json!({ "required": true, "content": content });
+    }
+    if op.access != Access::Public {
+        operation["security"] = json!([{ "apiKey": [] }]);
+        operation["x-required-scope"] = json!(if op.access == Access::Write { "write" } else { "read" });
+    }
+    operation
+}
+
+fn nullable(schema: Value) -> Value {
+    let mut schema = schema;
+    let kind = schema["type"].clone();
+    schema["type"] = json!([kind, "null"]);
+    schema
+}
+
+fn object(properties: &[(&str, Value)], required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// Component schemas of the main request and response types. Each is checked
+/// against what serde actually produces for the type in the tests below.
+fn schemas() -> Value {
+    let string = json!({ "type": "string" });
+    let time = json!({ "type": "string", "format": "date-time" });
+    let int = json!({ "type": "integer", "minimum": 0 });
+    let boolean = json!({ "type": "boolean" });
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let array_of = |name: &str| json!({ "type": "array", "items": schema_ref(name) });
+
+    json!({
+        "Error": object(&[("error", string.clone()), ("request_id", string.clone())], &["error"]),
+        "PasswordError": object(
+            &[("error", string.clone()), ("password_protected", boolean.clone()), ("request_id", string.clone())],
+            &["error", "password_protected"],
+        ),
+        "UploadRequest": {
+            "description": "An Excalidraw document (`type`, `elements`, `appState`, `files`, ...) with these extra fields",
+            "type": "object",
+            "properties": object(&[
+                ("id", string.clone()),
+                ("source_path", string.clone()),
+                ("password", string.clone()),
+                ("tags", strings.clone()),
+                ("max_views", int.clone()),
+                ("title", string.clone()),
+                ("expires_in_secs", int.clone()),
+                ("created_at", time.clone()),
+                ("preview", string.clone()),
+            ], &[])["properties"],
+            "additionalProperties": true,
+        },
+        "UploadResponse": object(&[
+            ("id", string.clone()),
+            ("url", string.clone()),
+            ("password_protected", boolean.clone()),
+            ("warnings", strings.clone()),
+        ], &["id", "url", "password_protected"]),
+        "DrawingMeta": object(&[
+            ("id", string.clone()),
+            ("created_at", time.clone()),
+            ("modified_at", time.clone()),
+            ("size_bytes", int.clone()),
+            ("source_path", nullable(string.clone())),
+            ("password_protected", boolean.clone()),
+            ("persistent_collab", boolean.clone()),
+            ("tags", strings.clone()),
+            ("title", string.clone()),
+            ("slug", string.clone()),
+            ("user_agent", string.clone()),
+            ("expires_at", time.clone()),
+            ("views", int.clone()),
+            ("content_hash", string.clone()),
+        ], &["id", "created_at", "modified_at", "size_bytes", "source_path", "password_protected", "persistent_collab", "tags", "views"]),
+        "ListResponse": object(&[
+            ("drawings", array_of("DrawingMeta")),
+            ("total", int.clone()),
+            ("next_offset", nullable(int.clone())),
+        ], &["drawings", "total", "next_offset"]),
+        "PublicDrawingMeta": object(&[
+            ("id", string.clone()),
+            ("created_at", time.clone()),
+            ("source_path", nullable(string.clone())),
+            ("password_protected", boolean.clone()),
+            ("persistent_collab", boolean.clone()),
+            ("tags", strings.clone()),
+            ("size_bytes", int.clone()),
+            ("content_hash", string.clone()),
+        ], &["id", "created_at", "source_path", "password_protected", "persistent_collab", "tags"]),
+        "PublicListResponse": object(&[
+            ("drawings", array_of("PublicDrawingMeta")),
+            ("total", int.clone()),
+            ("next_offset", nullable(int.clone())),
+        ], &["drawings", "total", "next_offset"]),
+        "ReadyResponse": object(&[
+            ("status", json!({ "type": "string", "enum": ["ready", "unavailable"] })),
+            ("backend", json!({ "type": "string", "enum": ["fs", "sqlite"] })),
+            ("storage_ok", boolean),
+            ("drawing_count", int),
+            ("error", string),
+        ], &["status", "backend", "storage_ok"]),
+    })
+}
+
+/// The OpenAPI 3.1 description of every route.
+pub fn spec(base_url: &str) -> Value {
+    let mut paths = Map::new();
+    for op in OPERATIONS {
+        let item = paths.entry(op.path).or_insert_with(|| json!({}));
+        item[op.method.as_str().to_lowercase()] = operation(op);
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "ExcaliShare",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": base_url }],
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "apiKey": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "An API key from `API_KEY` or `API_KEYS`, sent as `Authorization: Bearer <key>`. Read keys may only use `x-required-scope: read` operations.",
+                },
+            },
+        },
+    })
+}
+
+/// Serve the OpenAPI description (public).
+pub async fn openapi_json(State(state): State<AppState>) -> Json<Value> {
+    Json(spec(&state.base_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::{ListResponse, PublicDrawingMeta, PublicListResponse, ReadyResponse, UploadRequest, UploadResponse};
+    use crate::storage::DrawingMeta;
+    use axum::extract::ConnectInfo;
+    use axum::http::{header, Request, StatusCode};
+    use clap::Parser;
+    use std::collections::BTreeSet;
+    use tower::ServiceExt;
+
+    /// Paths registered on `router`. Axum has no API to list them, so they are
+    /// read from its `Debug` output, which prints each `RouteId(n): "path"`
+    /// (the fallback router, printed after the routes, holds only catch-alls).
+    fn router_paths(router: &axum::Router) -> BTreeSet<String> {
+        let debug = format!("{router:?}");
+        let routes = debug.split("fallback_router").next().unwrap();
+        routes
+            .split("RouteId(")
+            .filter_map(|entry| entry.trim_start_matches(|c: char| c.is_ascii_digit()).strip_prefix("): \""))
+            .filter_map(|rest| rest.split('"').next())
+            .filter(|path| path.starts_with('/') && !path.contains("__private__"))
+            .map(String::from)
+            .collect()
+    }
+
+    /// `path` with its parameters filled in.
+    fn concrete(path: &str) -> String {
+        let segment = |s: &str| match s {
+            "{version}" => "1".to_string(),
+            s if s.starts_with('{') => "probe".to_string(),
+            s => s.to_string(),
+        };
+        path.split('/').map(segment).collect::<Vec<_>>().join("/")
+    }
+
+    /// Send `method path` with an API key and a minimal body of the operation's
+    /// request type. Returns the status, and whether a route handled it rather
+    /// than the router refusing it (`405`, or its untyped `404`; handlers'
+    /// errors are JSON).
+    async fn probe(router: &axum::Router, method: &Method, path: &str, request: Body, n: u32) -> (StatusCode, bool) {
+        let (content_type, body) = match request {
+            Body::Json(_) => ("application/json", "{}"),
+            Body::Media(media) => (media, ""),
+            _ => ("text/plain", ""),
+        };
+        let peer = std::net::SocketAddr::from(([10, 0, (n >> 8) as u8, n as u8], 4000));
+        let request = Request::builder()
+            .method(method.clone())
+            .uri(concrete(path))
+            .header(header::AUTHORIZATION, "Bearer test-key")
+            .header(header::CONTENT_TYPE, content_type)
+            .extension(ConnectInfo(peer))
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let typed = response.headers().contains_key(header::CONTENT_TYPE);
+        (status, status != StatusCode::METHOD_NOT_ALLOWED && (status != StatusCode::NOT_FOUND || typed))
+    }
+
+    #[tokio::test]
+    async fn test_spec_matches_router() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = crate::Config::parse_from(["excalishare", "--metrics"]);
+        let router = crate::api_router(&config, crate::routes::tests::test_state(dir.path()).await);
+
+        let routed = router_paths(&router);
+        assert!(routed.len() > 40, "router paths not found: {routed:?}");
+        let described: BTreeSet<String> = OPERATIONS.iter().map(|op| op.path.to_string()).collect();
+        assert_eq!(routed.difference(&described).collect::<Vec<_>>(), Vec::<&String>::new(), "routes missing from the spec");
+        assert_eq!(described.difference(&routed).collect::<Vec<_>>(), Vec::<&String>::new(), "spec describes unrouted paths");
+
+        // Each path answers the methods described for it, with documented statuses
+        let mut n = 0;
+        for path in &routed {
+            for method in [Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+                n += 1;
+                let op = OPERATIONS.iter().find(|op| op.path == path && op.method == method);
+                let (status, handled) = probe(&router, &method, path, op.map_or(Empty, |op| op.request), n).await;
+                // Every GET route answers HEAD as well
+                if method != Method::HEAD || op.is_some() {
+                    assert_eq!(handled, op.is_some(), "{method} {path} answered {status}");
+                }
+                if let Some(op) = op {
+                    assert!(responses(op).contains_key(status.as_str()), "{method} {path}: undocumented {status}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_operations_document_auth_and_errors() {
+        let spec = spec("http://localhost:8184");
+        let upload = &spec["paths"]["/api/upload"]["post"];
+        for status in ["200", "201", "400", "401", "403", "404", "413", "429", "503", "507"] {
+            assert!(upload["responses"][status].is_object(), "upload lacks {status}");
+        }
+        assert_eq!(upload["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/UploadRequest");
+        assert_eq!(upload["security"][0]["apiKey"], json!([]));
+
+        let view = &spec["paths"]["/api/view/{id}"];
+        assert!(view["get"]["security"].is_null());
+        assert_eq!(view["get"]["parameters"][0]["name"], "id");
+        assert!(view["get"]["responses"]["401"].is_null());
+        assert!(view["head"]["responses"]["304"].is_object());
+        assert_eq!(spec["paths"]["/api/drawings"]["get"]["x-required-scope"], "read");
+    }
+
+    /// Property names of a component schema.
+    fn properties(name: &str) -> BTreeSet<String> {
+        schemas()[name]["properties"].as_object().unwrap().keys().cloned().collect()
+    }
+
+    fn keys(value: &Value) -> BTreeSet<String> {
+        value.as_object().unwrap().keys().cloned().collect()
+    }
+
+    /// The schema lists exactly the fields of a fully populated value, and
+    /// requires only those that are always serialized.
+    fn check_schema(name: &str, full: Value, minimal: Value) {
+        assert_eq!(keys(&full), properties(name), "{name} properties");
+        let required: BTreeSet<String> =
+            schemas()[name]["required"].as_array().unwrap().iter().map(|v| v.as_str().unwrap().to_string()).collect();
+        assert_eq!(keys(&minimal), required, "{name} required");
+    }
+
+    fn meta(full: bool) -> DrawingMeta {
+        let some = |s: &str| full.then(|| s.to_string());
+        DrawingMeta {
+            id: "a".into(),
+            created_at: chrono::Utc::now(),
+            modified_at: chrono::Utc::now(),
+            size_bytes: 1,
+            source_path: None,
+            password_protected: false,
+            persistent_collab: false,
+            tags: Vec::new(),
+            title: some("t"),
+            slug: some("s"),
+            user_agent: some("ua"),
+            expires_at: full.then(chrono::Utc::now),
+            views: 0,
+            content_hash: some("h"),
+        }
+    }
+
+    fn to_json(value: &impl serde::Serialize) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_schemas_match_serialized_types() {
+        let upload = |warnings: Vec<String>| UploadResponse { id: "a".into(), url: "u".into(), password_protected: false, warnings };
+        check_schema("UploadResponse", to_json(&upload(vec!["w".into()])), to_json(&upload(Vec::new())));
+        check_schema("DrawingMeta", to_json(&meta(true)), to_json(&meta(false)));
+        let list = ListResponse { drawings: vec![meta(true)], total: 1, next_offset: None };
+        check_schema("ListResponse", to_json(&list), to_json(&list));
+
+        // Fully populated for `/meta`; the least is a protected drawing's list entry
+        let public = |full: bool| {
+            if full {
+                PublicDrawingMeta::from(meta(true))
+            } else {
+                PublicDrawingMeta::listed(DrawingMeta { password_protected: true, ..meta(false) })
+            }
+        };
+        check_schema("PublicDrawingMeta", to_json(&public(true)), to_json(&public(false)));
+        let public_list = PublicListResponse { drawings: vec![public(true)], total: 1, next_offset: Some(1) };
+        check_schema("PublicListResponse", to_json(&public_list), to_json(&public_list));
+
+        let ready = |full: bool| ReadyResponse {
+            status: "ready".into(),
+            backend: "fs".into(),
+            storage_ok: true,
+            drawing_count: full.then_some(1),
+            error: full.then(|| "e".into()),
+        };
+        check_schema("ReadyResponse", to_json(&ready(true)), to_json(&ready(false)));
+    }
+
+    #[test]
+    fn test_upload_request_schema_names_real_fields() {
+        // Every documented field is picked out of the document rather than
+        // left in `data`, so the names match the struct's serde names
+        let mut body = json!({ "type": "excalidraw", "elements": [] });
+        let samples = json!({
+            "id": "a", "source_path": "a.excalidraw", "password": "p", "tags": ["t"], "max_views": 1,
+            "title": "t", "expires_in_secs": 60, "created_at": "2026-01-01T00:00:00Z", "preview": "p",
+        });
+        for name in properties("UploadRequest") {
+            body[&name] = samples[&name].clone();
+        }
+        let request: UploadRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(keys(&request.data), BTreeSet::from(["type".to_string(), "elements".to_string()]));
+    }}
@@ -0,0 +1,196 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::storage::{DrawingStorage, Storage};
+
+/// Running count of stored drawings, checked against an optional cap when a
+/// drawing is created. Seeded from storage at startup and then kept up to date
+/// by the handlers that create and delete drawings, so enforcing the cap never
+/// needs a listing.
+#[derive(Clone, Default)]
+pub struct DrawingQuota {
+    max: Option<usize>,
+    count: Arc<AtomicUsize>,
+}
+
+impl DrawingQuota {
+    pub fn new(max: Option<usize>, count: usize) -> Self {
+        Self { max, count: Arc::new(AtomicUsize::new(count)) }
+    }
+
+    /// Seed the count from the drawings currently in `storage`.
+    pub async fn load(max: Option<usize>, storage: &Storage) -> Result<Self, AppError> {
+        Ok(Self::new(max, storage.list().await?.len()))
+    }
+
+    /// Drawings currently counted.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Count one new drawing, unless that would go over the cap. The count is
+    /// given back when the returned slot drops, unless
+    /// [`DrawingSlot::commit`] is called once the drawing is stored.
+    pub fn add(&self) -> Result<DrawingSlot<'_>, AppError> {
+        let max = self.max.unwrap_or(usize::MAX);
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < max).then_some(n + 1))
+            .map(|_| DrawingSlot { quota: self, committed: false })
+            .map_err(|n| AppError::QuotaExceeded(format!("the server holds its maximum of {n} drawings")))
+    }
+
+    /// Stop counting a deleted drawing.
+    pub fn remove(&self) {
+        let _ = self.count.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    }
+
+    /// Re-derive the count from storage, after bulk changes such as an import.
+    pub async fn recount(&self, storage: &Storage) -> Result<(), AppError> {
+        self.count.store(storage.list().await?.len(), Ordering::Release);
+        Ok(())
+    }
+}
+
+/// A drawing counted by [`DrawingQuota::add`] that isn't stored yet. Dropping
+/// it uncounts the drawing, so an early return can't leak a slot.
+#[must_use = "the drawing is uncounted again as soon as the slot drops"]
+pub struct DrawingSlot<'a> {
+    quota: &'a DrawingQuota,
+    committed: bool,
+}
+
+impl DrawingSlot<'_> {
+    /// Keep the drawing counted.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for DrawingSlot<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.quota.remove();
+        }
+    }
+}
+
+/// Running total of stored drawing bytes, checked against an optional cap
+/// whenever a drawing is written. The storage backends keep it up to date as
+/// they write and remove drawings; it is seeded once from a listing at startup.
+/// Drawing documents and their history count, trashed drawings too until
+/// purged; extracted assets and previews don't.
+#[derive(Clone, Default)]
+pub struct ByteQuota {
+    max: Option<u64>,
+    used: Arc<AtomicU64>,
+}
+
+/// Stored bytes against the cap, reported by `/api/stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ByteQuotaUsage {
+    pub used_bytes: u64,
+    pub max_bytes: Option<u64>,
+}
+
+impl ByteQuota {
+    pub fn new(max: Option<u64>) -> Self {
+        Self { max, used: Arc::default() }
+    }
+
+    pub fn usage(&self) -> ByteQuotaUsage {
+        ByteQuotaUsage { used_bytes: self.used.load(Ordering::Acquire), max_bytes: self.max }
+    }
+
+    /// Count a drawing growing from `old` to `new` bytes (0 when it is new),
+    /// unless that takes the total over the cap. Shrinking always succeeds.
+    /// Undo with [`ByteQuota::adjust`]`(new, old)` if the write then fails.
+    pub fn reserve(&self, old: u64, new: u64) -> Result<(), AppError> {
+        let max = self.max.unwrap_or(u64::MAX);
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                let after = used.saturating_sub(old).saturating_add(new);
+                (new <= old || after <= max).then_some(after)
+            })
+            .map(|_| ())
+            .map_err(|used| {
+                AppError::QuotaExceeded(format!(
+                    "storing {} more bytes would take the server past its {max} byte limit ({used} used)",
+                    new - old
+                ))
+            })
+    }
+
+    /// Count a drawing going from `old` to `new` bytes whatever the cap, e.g.
+    /// `new` = 0 once it is deleted.
+    pub fn adjust(&self, old: u64, new: u64) {
+        let _ = self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            Some(used.saturating_sub(old).saturating_add(new))
+        });
+    }
+
+    /// Re-derive the total from the drawings in `storage`, history and trash included.
+    pub async fn recount(&self, storage: &impl DrawingStorage) -> Result<(), AppError> {
+        let mut stored = 0;
+        for drawing in storage.list().await? {
+            let history: u64 = storage.history(&drawing.id).await?.iter().map(|v| v.size_bytes).sum();
+            stored += drawing.size_bytes + history;
+        }
+        let trashed: u64 = storage.list_trash().await?.iter().map(|d| d.size_bytes).sum();
+        let total = stored + trashed;
+        self.used.store(total, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_stops_at_the_cap() {
+        let quota = DrawingQuota::new(Some(2), 1);
+        quota.add().unwrap().commit();
+        assert!(matches!(quota.add(), Err(AppError::QuotaExceeded(_))));
+        assert_eq!(quota.count(), 2);
+
+        quota.remove();
+        assert!(quota.add().is_ok());
+    }
+
+    #[test]
+    fn test_uncommitted_slot_is_given_back() {
+        let quota = DrawingQuota::new(Some(1), 0);
+        let slot = quota.add().unwrap();
+        assert_eq!(quota.count(), 1);
+        drop(slot);
+        assert_eq!(quota.count(), 0);
+    }
+
+    #[test]
+    fn test_uncapped_quota_still_counts() {
+        let quota = DrawingQuota::new(None, 0);
+        quota.remove();
+        assert_eq!(quota.count(), 0);
+        for _ in 0..3 {
+            quota.add().unwrap().commit();
+        }
+        assert_eq!(quota.count(), 3);
+    }
+
+    #[test]
+    fn test_byte_quota_counts_only_the_growth() {
+        let quota = ByteQuota::new(Some(100));
+        quota.reserve(0, 60).unwrap();
+        // Growing an existing drawing by 40 fits, by 41 doesn't
+        assert!(matches!(quota.reserve(60, 101), Err(AppError::QuotaExceeded(_))));
+        quota.reserve(60, 100).unwrap();
+        assert_eq!(quota.usage().used_bytes, 100);
+
+        // Shrinking is always allowed, even with nothing to spare
+        quota.reserve(100, 90).unwrap();
+        quota.adjust(90, 0);
+        assert_eq!(quota.usage(), ByteQuotaUsage { used_bytes: 0, max_bytes: Some(100) });
+    }
+}
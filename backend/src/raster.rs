@@ -0,0 +1,420 @@
+use std::io::Write as _;
+
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+
+/// Sub-pixel samples per axis used to anti-alias filled shapes.
+const SAMPLES: usize = 4;
+
+/// An 8-bit color with straight (not premultiplied) alpha.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: f32,
+}
+
+impl Rgba {
+    /// Parse `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa` or `transparent`.
+    pub fn parse(color: &str) -> Option<Self> {
+        if color == "transparent" {
+            return Some(Rgba { r: 0, g: 0, b: 0, a: 0.0 });
+        }
+        let hex = color.strip_prefix('#')?;
+        let hex = match hex.len() {
+            3 | 4 => hex.chars().flat_map(|c| [c, c]).collect(),
+            6 | 8 => hex.to_string(),
+            _ => return None,
+        };
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        let a = if hex.len() == 8 { channel(6)? as f32 / 255.0 } else { 1.0 };
+        Some(Rgba { r: channel(0)?, g: channel(2)?, b: channel(4)?, a })
+    }
+
+    /// The same color with its alpha multiplied by `opacity` (0-1).
+    pub fn with_opacity(self, opacity: f32) -> Self {
+        Rgba { a: self.a * opacity.clamp(0.0, 1.0), ..self }
+    }
+}
+
+/// A software RGBA canvas that filled polygons and stroked polylines are
+/// composited onto, encoded as PNG at the end. Just enough for previews: no
+/// curves, clipping or text shaping.
+///
+/// Drawing costs roughly one unit per pixel a shape is rasterized over. Once
+/// the canvas's work budget is spent, further shapes are skipped, so a hostile
+/// drawing can't keep a render going indefinitely.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+    work_left: u64,
+}
+
+/// Pixel rectangle `[x0, x1) × [y0, y1)` of a canvas.
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Area {
+    fn width(&self) -> usize {
+        (self.x1 - self.x0) as usize
+    }
+
+    fn pixels(&self) -> u64 {
+        self.width() as u64 * (self.y1 - self.y0) as u64
+    }
+}
+
+/// How much of each pixel in an area one shape covers (0-1).
+struct Coverage {
+    area: Area,
+    values: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(area: Area) -> Self {
+        Coverage { area, values: vec![0.0; area.pixels() as usize] }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y - self.area.y0) as usize * self.area.width() + (x - self.area.x0) as usize
+    }
+}
+
+impl Canvas {
+    /// A transparent canvas with an unlimited work budget.
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas { width, height, pixels: vec![[0.0; 4]; width as usize * height as usize], work_left: u64::MAX }
+    }
+
+    /// Limit the total work spent drawing shapes (see [`Canvas`]).
+    pub fn with_work_budget(mut self, work: u64) -> Self {
+        self.work_left = work;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[cfg(test)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Paint the whole canvas with `color`.
+    pub fn clear(&mut self, color: Rgba) {
+        let value = [color.r as f32, color.g as f32, color.b as f32, color.a];
+        self.pixels.fill(value);
+    }
+
+    /// The pixel at `(x, y)` as 8-bit RGBA.
+    #[cfg(test)]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let [r, g, b, a] = self.pixels[(y * self.width + x) as usize];
+        [r.round() as u8, g.round() as u8, b.round() as u8, (a * 255.0).round() as u8]
+    }
+
+    /// Fill the polygon through `points` (nonzero winding), scanning each row
+    /// of samples for the spans between edge crossings.
+    pub fn fill_polygon(&mut self, points: &[(f64, f64)], color: Rgba) {
+        if points.len() < 3 || color.a <= 0.0 {
+            return;
+        }
+        let Some(area) = self.area(points, 0.0) else { return };
+        let rows = (area.y1 - area.y0) as u64 * SAMPLES as u64;
+        if !self.spend(area.pixels() + rows * points.len() as u64) {
+            return;
+        }
+
+        let mut coverage = Coverage::new(area);
+        let weight = 1.0 / (SAMPLES * SAMPLES) as f32;
+        let mut crossings: Vec<(f64, i32)> = Vec::new();
+        for row in area.y0..area.y1 {
+            for sy in 0..SAMPLES {
+                let y = row as f64 + (sy as f64 + 0.5) / SAMPLES as f64;
+                crossings.clear();
+                for (i, &(ax, ay)) in points.iter().enumerate() {
+                    let (bx, by) = points[(i + 1) % points.len()];
+                    let direction = if ay <= y && by > y {
+                        1
+                    } else if ay > y && by <= y {
+                        -1
+                    } else {
+                        continue;
+                    };
+                    crossings.push((ax + (y - ay) * (bx - ax) / (by - ay), direction));
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                // Samples left of a crossing are wound by its edge
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding == 0 {
+                        continue;
+                    }
+                    let first = ((pair[0].0 * SAMPLES as f64 - 0.5).ceil()).max((area.x0 as usize * SAMPLES) as f64);
+                    let end = ((pair[1].0 * SAMPLES as f64 - 0.5).ceil()).min((area.x1 as usize * SAMPLES) as f64);
+                    for sample in first as usize..end.max(first) as usize {
+                        let i = coverage.index((sample / SAMPLES) as u32, row);
+                        coverage.values[i] += weight;
+                    }
+                }
+            }
+        }
+        self.composite(&coverage, color);
+    }
+
+    /// Stroke the polyline through `points` with round joins and caps. Each
+    /// segment is rasterized over just the pixels near it.
+    pub fn stroke_polyline(&mut self, points: &[(f64, f64)], width: f64, color: Rgba) {
+        if points.is_empty() || color.a <= 0.0 {
+            return;
+        }
+        let half = (width / 2.0).max(0.5);
+        let segments: Vec<_> = match points {
+            [only] => vec![(*only, *only)],
+            _ => points.windows(2).map(|w| (w[0], w[1])).collect(),
+        };
+        let Some(area) = self.area(points, half + 1.0) else { return };
+        if !self.spend(area.pixels()) {
+            return;
+        }
+
+        let mut coverage = Coverage::new(area);
+        let reach = half + 0.5;
+        for &(a, b) in &segments {
+            let Some(bounds) = self.area(&[a, b], half + 1.0) else { continue };
+            for y in bounds.y0..bounds.y1 {
+                let Some((from, to)) = capsule_span(a, b, reach, y as f64 + 0.5) else { continue };
+                let x0 = (from - 0.5).ceil().clamp(bounds.x0 as f64, bounds.x1 as f64) as u32;
+                let x1 = ((to - 0.5).floor() + 1.0).clamp(x0 as f64, bounds.x1 as f64) as u32;
+                if !self.spend((x1 - x0) as u64) {
+                    return;
+                }
+                for x in x0..x1 {
+                    let distance = distance_to_segment((x as f64 + 0.5, y as f64 + 0.5), a, b);
+                    let i = coverage.index(x, y);
+                    coverage.values[i] = coverage.values[i].max((reach - distance).clamp(0.0, 1.0) as f32);
+                }
+            }
+        }
+        self.composite(&coverage, color);
+    }
+
+    /// Pixels of the bounding box of `points` grown by `margin`, clipped to the
+    /// canvas. `None` for non-finite points.
+    fn area(&self, points: &[(f64, f64)], margin: f64) -> Option<Area> {
+        let (mut x0, mut y0, mut x1, mut y1) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &(x, y) in points {
+            (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+        }
+        if !(x0.is_finite() && y0.is_finite() && x1.is_finite() && y1.is_finite()) {
+            return None;
+        }
+        let clamp_x = |v: f64| v.clamp(0.0, self.width as f64) as u32;
+        let clamp_y = |v: f64| v.clamp(0.0, self.height as f64) as u32;
+        let (x0, x1) = (clamp_x((x0 - margin).floor()), clamp_x((x1 + margin).ceil() + 1.0));
+        let (y0, y1) = (clamp_y((y0 - margin).floor()), clamp_y((y1 + margin).ceil() + 1.0));
+        Some(Area { x0, y0, x1, y1 })
+    }
+
+    /// Take `work` from the budget; false (leaving the rest) when it doesn't cover it.
+    fn spend(&mut self, work: u64) -> bool {
+        match self.work_left.checked_sub(work) {
+            Some(left) => {
+                self.work_left = left;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Blend `color` over the covered pixels, weighted by their coverage. Each
+    /// pixel is blended once, so overlapping parts of one shape don't darken
+    /// translucent colors.
+    fn composite(&mut self, coverage: &Coverage, color: Rgba) {
+        let area = coverage.area;
+        for y in area.y0..area.y1 {
+            for x in area.x0..area.x1 {
+                let alpha = color.a * coverage.values[coverage.index(x, y)].min(1.0);
+                if alpha > 0.0 {
+                    let pixel = &mut self.pixels[(y * self.width + x) as usize];
+                    blend(pixel, color, alpha);
+                }
+            }
+        }
+    }
+
+    /// Encode as an 8-bit RGBA PNG.
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((self.width as usize * 4 + 1) * self.height as usize);
+        for row in self.pixels.chunks(self.width.max(1) as usize) {
+            raw.push(0); // filter: none
+            for &[r, g, b, a] in row {
+                raw.extend([r.round() as u8, g.round() as u8, b.round() as u8, (a * 255.0).round() as u8]);
+            }
+        }
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        let _ = zlib.write_all(&raw);
+        let idat = zlib.finish().unwrap_or_default();
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend(self.width.to_be_bytes());
+        ihdr.extend(self.height.to_be_bytes());
+        ihdr.extend([8, 6, 0, 0, 0]); // 8-bit RGBA, deflate, no interlace
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in [(b"IHDR", &ihdr), (b"IDAT", &idat), (b"IEND", &Vec::new())] {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(data);
+            let mut crc = Crc::new();
+            crc.update(kind);
+            crc.update(data);
+            png.extend(crc.sum().to_be_bytes());
+        }
+        png
+    }
+}
+
+/// "Source over" compositing of `color` at `alpha` onto `pixel`.
+fn blend(pixel: &mut [f32; 4], color: Rgba, alpha: f32) {
+    let [r, g, b, a] = *pixel;
+    let out_a = alpha + a * (1.0 - alpha);
+    if out_a <= 0.0 {
+        return;
+    }
+    let mix = |src: u8, dst: f32| (src as f32 * alpha + dst * a * (1.0 - alpha)) / out_a;
+    *pixel = [mix(color.r, r), mix(color.g, g), mix(color.b, b), out_a];
+}
+
+/// Range of `x` on the horizontal line through `y` within `reach` of the
+/// segment from `a` to `b`, if the line passes that close.
+fn capsule_span(a: (f64, f64), b: (f64, f64), reach: f64, y: f64) -> Option<(f64, f64)> {
+    let mut span: Option<(f64, f64)> = None;
+    let mut include = |from: f64, to: f64| {
+        if from <= to {
+            span = Some(span.map_or((from, to), |(lo, hi)| (lo.min(from), hi.max(to))));
+        }
+    };
+
+    // The round caps
+    for (cx, cy) in [a, b] {
+        let dy = y - cy;
+        if dy.abs() <= reach {
+            let dx = (reach * reach - dy * dy).sqrt();
+            include(cx - dx, cx + dx);
+        }
+    }
+
+    // The band along the segment: 0 <= along <= length and |across| <= reach,
+    // both linear in x on this line
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length > 0.0 {
+        let (ux, uy) = (dx / length, dy / length);
+        let along = linear_range(ux, (y - a.1) * uy - a.0 * ux, 0.0, length);
+        let across = linear_range(uy, -(y - a.1) * ux - a.0 * uy, -reach, reach);
+        include(along.0.max(across.0), along.1.min(across.1));
+    }
+    span
+}
+
+/// Range of `x` with `lo <= k * x + d <= hi` (empty when `lo > hi` is returned).
+fn linear_range(k: f64, d: f64, lo: f64, hi: f64) -> (f64, f64) {
+    if k.abs() < 1e-12 {
+        return if (lo..=hi).contains(&d) { (f64::NEG_INFINITY, f64::INFINITY) } else { (f64::INFINITY, f64::NEG_INFINITY) };
+    }
+    let (p, q) = ((lo - d) / k, (hi - d) / k);
+    (p.min(q), p.max(q))
+}
+
+fn distance_to_segment((px, py): (f64, f64), (ax, ay): (f64, f64), (bx, by): (f64, f64)) -> f64 {
+    let (dx, dy) = (bx - ax, by - ay);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq == 0.0 { 0.0 } else { (((px - ax) * dx + (py - ay) * dy) / length_sq).clamp(0.0, 1.0) };
+    let (cx, cy) = (ax + t * dx, ay + t * dy);
+    ((px - cx).powi(2) + (py - cy).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba = Rgba { r: 255, g: 0, b: 0, a: 1.0 };
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!(Rgba::parse("#f00"), Some(RED));
+        assert_eq!(Rgba::parse("#ff000080").map(|c| (c.a * 255.0).round()), Some(128.0));
+        assert_eq!(Rgba::parse("transparent").map(|c| c.a), Some(0.0));
+        assert_eq!(Rgba::parse("red"), None);
+        assert_eq!(Rgba::parse("#12345"), None);
+    }
+
+    #[test]
+    fn test_fill_and_stroke() {
+        let mut canvas = Canvas::new(20, 20);
+        canvas.clear(Rgba::parse("#ffffff").unwrap());
+        canvas.fill_polygon(&[(2.0, 2.0), (10.0, 2.0), (10.0, 10.0), (2.0, 10.0)], RED);
+        assert_eq!(canvas.pixel(5, 5), [255, 0, 0, 255]);
+        assert_eq!(canvas.pixel(15, 15), [255, 255, 255, 255]);
+
+        canvas.stroke_polyline(&[(0.0, 15.5), (20.0, 15.5)], 2.0, Rgba::parse("#000").unwrap());
+        assert_eq!(canvas.pixel(10, 15), [0, 0, 0, 255]);
+        assert_eq!(canvas.pixel(10, 18), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_translucent_fill_blends_once() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.clear(Rgba::parse("#ffffff").unwrap());
+        canvas.stroke_polyline(&[(0.0, 2.0), (4.0, 2.0), (0.0, 2.0)], 4.0, RED.with_opacity(0.5));
+        assert_eq!(canvas.pixel(2, 2), [255, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_long_strokes_render_like_short_ones() {
+        // A diagonal stroke and a fill spanning the canvas
+        let mut canvas = Canvas::new(200, 200);
+        canvas.stroke_polyline(&[(0.0, 0.0), (200.0, 200.0)], 2.0, RED);
+        assert_eq!(canvas.pixel(100, 100), [255, 0, 0, 255]);
+        assert_eq!(canvas.pixel(100, 110)[3], 0);
+        assert_eq!(canvas.pixel(199, 199), [255, 0, 0, 255]);
+
+        let mut canvas = Canvas::new(20, 20);
+        canvas.fill_polygon(&[(10.0, 0.0), (20.0, 20.0), (0.0, 20.0)], RED);
+        assert_eq!(canvas.pixel(10, 15), [255, 0, 0, 255]);
+        assert_eq!(canvas.pixel(1, 1)[3], 0);
+        // Partly covered along the edge
+        assert!((1..255).contains(&canvas.pixel(4, 10)[3]), "{:?}", canvas.pixel(4, 10));
+    }
+
+    #[test]
+    fn test_work_budget_skips_shapes() {
+        let mut canvas = Canvas::new(10, 10).with_work_budget(300);
+        canvas.fill_polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)], RED);
+        assert_eq!(canvas.pixel(5, 5), [255, 0, 0, 255]);
+        // Not enough left for a second fill
+        canvas.fill_polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)], Rgba::parse("#00f").unwrap());
+        assert_eq!(canvas.pixel(5, 5), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_encode_png_header() {
+        let png = Canvas::new(3, 2).encode_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], [0, 0, 0, 3, 0, 0, 0, 2]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+}
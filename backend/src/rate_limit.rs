@@ -0,0 +1,132 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::KeyLabel;
+use crate::error::AppError;
+
+/// Buckets tracked before full (idle) ones are dropped.
+const MAX_IDLE_BUCKETS: usize = 4096;
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token buckets holding a per-minute request budget for each API key label
+/// (or client IP on public routes). Writes can take more than one token, so a
+/// client stuck uploading in a loop runs dry long before one that only reads.
+#[derive(Clone)]
+pub struct KeyRateLimiter {
+    per_minute: u32,
+    write_cost: u32,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl KeyRateLimiter {
+    pub fn new(per_minute: u32, write_cost: u32) -> Self {
+        Self { per_minute, write_cost, buckets: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Take `cost` tokens from `key`'s bucket, or say how long until enough
+    /// have refilled.
+    fn take(&self, key: &str, cost: u32, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let per_sec = capacity / 60.0;
+        let cost = f64::from(cost).min(capacity);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.refilled_at).as_secs_f64() * per_sec < capacity);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, refilled_at: now });
+        let refilled = now.duration_since(bucket.refilled_at).as_secs_f64() * per_sec;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.refilled_at = now;
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((cost - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+/// Middleware charging each request to its API key's bucket, or its client
+/// IP's when unauthenticated, with `429` and `Retry-After` once it runs dry.
+/// On protected routes it must run after [`crate::auth::api_key_middleware`].
+pub async fn key_rate_limit_middleware(
+    State(limiter): State<KeyRateLimiter>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let key = match request.extensions().get::<KeyLabel>() {
+        Some(label) => format!("key:{}", label.0),
+        None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(peer)) => format!("ip:{}", peer.ip()),
+            None => return Ok(next.run(request).await),
+        },
+    };
+    let cost = match *request.method() {
+        Method::GET | Method::HEAD | Method::OPTIONS => 1,
+        _ => limiter.write_cost,
+    };
+    if let Err(wait) = limiter.take(&key, cost, Instant::now()) {
+        let retry_after_secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+        tracing::warn!(key = %key, retry_after_secs, "Request rate limit reached");
+        return Err(AppError::RateLimited(retry_after_secs));
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let limiter = KeyRateLimiter::new(60, 5);
+        let start = Instant::now();
+        for _ in 0..12 {
+            limiter.take("alice", 5, start).unwrap();
+        }
+        // Empty: one write needs five seconds of refill
+        assert_eq!(limiter.take("alice", 5, start), Err(Duration::from_secs(5)));
+        // Other keys have their own budget
+        limiter.take("bob", 1, start).unwrap();
+        limiter.take("alice", 1, start + Duration::from_secs(1)).unwrap();
+        assert!(limiter.take("alice", 1, start + Duration::from_secs(1)).is_err());
+    }
+
+    fn request(method: &str, label: &str) -> Request {
+        let mut request = Request::builder().method(method).uri("/api").body(Body::empty()).unwrap();
+        request.extensions_mut().insert(KeyLabel(label.to_string()));
+        request
+    }
+
+    #[tokio::test]
+    async fn test_writes_cost_more_and_get_retry_after() {
+        let app = Router::new()
+            .route("/api", get(|| async { "read" }).post(|| async { "write" }))
+            .layer(middleware::from_fn_with_state(KeyRateLimiter::new(10, 4), key_rate_limit_middleware));
+
+        for _ in 0..2 {
+            assert_eq!(app.clone().oneshot(request("POST", "sync")).await.unwrap().status(), StatusCode::OK);
+        }
+        let limited = app.clone().oneshot(request("POST", "sync")).await.unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()["retry-after"], "12");
+        // Two tokens are left for reads
+        assert_eq!(app.clone().oneshot(request("GET", "sync")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(app.oneshot(request("GET", "reader")).await.unwrap().status(), StatusCode::OK);
+    }
+}
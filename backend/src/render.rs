@@ -0,0 +1,670 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+
+use crate::raster::{Canvas, Rgba};
+
+/// Blank space around the drawn content, in drawing units.
+const PADDING: f64 = 10.0;
+
+/// Excalidraw's default font size and line height for text elements.
+const DEFAULT_FONT_SIZE: f64 = 20.0;
+const LINE_HEIGHT: f64 = 1.25;
+
+/// Canvas colors used when a theme is requested for a drawing without a
+/// `viewBackgroundColor`, matching Excalidraw's own defaults.
+const LIGHT_BACKGROUND: &str = "#ffffff";
+const DARK_BACKGROUND: &str = "#121212";
+
+/// Default stroke color of elements, as in Excalidraw.
+const DEFAULT_STROKE: &str = "#1e1e1e";
+
+/// Largest width or height of a rendered PNG, in pixels.
+pub const MAX_PNG_SIDE: u32 = 2048;
+
+/// Work budget of one PNG render (see [`Canvas`]): enough to cover the
+/// largest canvas many times over, but bounded for hostile drawings.
+const MAX_RENDER_WORK: u64 = 64 * MAX_PNG_SIDE as u64 * MAX_PNG_SIDE as u64;
+
+/// Color scheme to render a drawing in (`?theme=dark|light`).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// The theme a drawing was saved in (`appState.theme`, light when unset).
+    fn of(data: &Value) -> Self {
+        match data.pointer("/appState/theme").and_then(|v| v.as_str()) {
+            Some("dark") => Theme::Dark,
+            _ => Theme::Light,
+        }
+    }
+}
+
+/// Maps stored colors to the rendered ones. When rendering in the opposite
+/// theme, hex colors keep their hue and saturation but get their lightness
+/// flipped, so dark strokes on a light canvas become light strokes on a dark
+/// one. Named colors and `transparent` pass through unchanged.
+struct Palette {
+    invert: bool,
+}
+
+impl Palette {
+    fn color(&self, color: &str) -> String {
+        escape(&self.resolve(color))
+    }
+
+    fn resolve(&self, color: &str) -> String {
+        if self.invert { invert_lightness(color).unwrap_or_else(|| color.to_string()) } else { color.to_string() }
+    }
+
+    fn rgba(&self, color: &str) -> Option<Rgba> {
+        Rgba::parse(&self.resolve(color))
+    }
+}
+
+/// `#rgb` / `#rrggbb` (optionally with alpha) with its HSL lightness flipped.
+fn invert_lightness(color: &str) -> Option<String> {
+    let hex = color.strip_prefix('#')?;
+    // Byte slicing below needs ASCII; other characters aren't hex digits anyway
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let (rgb, alpha) = match hex.len() {
+        3 | 4 => (hex[..3].chars().flat_map(|c| [c, c]).collect::<String>(), hex[3..].repeat(2)),
+        6 | 8 => (hex[..6].to_string(), hex[6..].to_string()),
+        _ => return None,
+    };
+    let channel = |i: usize| u8::from_str_radix(&rgb[i..i + 2], 16).ok().map(|c| c as f64 / 255.0);
+    let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    let (hue, saturation) = if delta == 0.0 {
+        (0.0, 0.0)
+    } else {
+        let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+        let hue = if max == r {
+            ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / delta + 2.0
+        } else {
+            (r - g) / delta + 4.0
+        };
+        (hue * 60.0, saturation)
+    };
+
+    // Back to RGB at the flipped lightness
+    let lightness = 1.0 - lightness;
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let to_hex = |c: f64| format!("{:02x}", ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8);
+    Some(format!("#{}{}{}{alpha}", to_hex(r), to_hex(g), to_hex(b)))
+}
+
+fn num(element: &Value, key: &str) -> f64 {
+    element.get(key).and_then(|v| v.as_f64()).unwrap_or(0.0)
+}
+
+fn text<'a>(element: &'a Value, key: &str) -> Option<&'a str> {
+    element.get(key).and_then(|v| v.as_str())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Absolute coordinates of a linear element's `points`.
+fn points(element: &Value) -> Vec<(f64, f64)> {
+    let (x, y) = (num(element, "x"), num(element, "y"));
+    element
+        .get("points")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|p| Some((x + p.get(0)?.as_f64()?, y + p.get(1)?.as_f64()?)))
+        .collect()
+}
+
+/// Bounding box `(min_x, min_y, max_x, max_y)` of the given elements.
+fn bounds(elements: &[&Value]) -> Option<(f64, f64, f64, f64)> {
+    let mut corners = Vec::new();
+    for element in elements {
+        let (x, y) = (num(element, "x"), num(element, "y"));
+        corners.push((x, y));
+        corners.push((x + num(element, "width"), y + num(element, "height")));
+        corners.extend(points(element));
+    }
+    corners.into_iter().fold(None, |acc, (x, y)| {
+        Some(match acc {
+            None => (x, y, x, y),
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        })
+    })
+}
+
+/// What an element draws, in drawing units and before its rotation. Both the
+/// SVG and the PNG renderer work from this, so the two outputs match.
+enum Shape<'a> {
+    Rectangle { x: f64, y: f64, w: f64, h: f64, radius: f64 },
+    Ellipse { cx: f64, cy: f64, rx: f64, ry: f64 },
+    Polygon(Vec<(f64, f64)>),
+    /// An open path (line, arrow, freedraw), with the barbs of an arrowhead
+    Polyline { points: Vec<(f64, f64)>, head: Option<Vec<(f64, f64)>> },
+    Text(TextBlock<'a>),
+    /// An element type neither renderer draws, e.g. `image` or `frame`
+    Unsupported(&'a str),
+}
+
+/// Lines of a text element, anchored at `x` according to `align`.
+struct TextBlock<'a> {
+    font_size: f64,
+    align: &'a str,
+    x: f64,
+    /// (baseline y, text) of each line
+    lines: Vec<(f64, &'a str)>,
+}
+
+impl Shape<'_> {
+    /// Outline of a closed shape as a polygon.
+    fn outline(&self) -> Option<Vec<(f64, f64)>> {
+        match *self {
+            Shape::Rectangle { x, y, w, h, radius } => Some(rectangle_outline(x, y, w, h, radius)),
+            Shape::Ellipse { cx, cy, rx, ry } => Some(ellipse_outline(cx - rx, cy - ry, 2.0 * rx, 2.0 * ry)),
+            Shape::Polygon(ref points) => Some(points.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Stroke, fill, opacity and rotation of an element, with colors as stored.
+struct Style<'a> {
+    stroke: &'a str,
+    /// Only for closed shapes with a background
+    fill: Option<&'a str>,
+    stroke_width: f64,
+    opacity: f64,
+    angle: f64,
+    center: (f64, f64),
+}
+
+fn style_of(element: &Value) -> Style<'_> {
+    Style {
+        stroke: text(element, "strokeColor").unwrap_or(DEFAULT_STROKE),
+        fill: text(element, "backgroundColor").filter(|c| *c != "transparent"),
+        stroke_width: element.get("strokeWidth").and_then(|v| v.as_f64()).unwrap_or(1.0),
+        opacity: element.get("opacity").and_then(|v| v.as_f64()).unwrap_or(100.0) / 100.0,
+        angle: num(element, "angle"),
+        center: (num(element, "x") + num(element, "width") / 2.0, num(element, "y") + num(element, "height") / 2.0),
+    }
+}
+
+fn shape_of(element: &Value) -> Shape<'_> {
+    let (x, y) = (num(element, "x"), num(element, "y"));
+    let (w, h) = (num(element, "width"), num(element, "height"));
+    match text(element, "type").unwrap_or("") {
+        "rectangle" => {
+            let radius = if element.get("roundness").is_some_and(|r| !r.is_null()) { w.min(h) * 0.1 } else { 0.0 };
+            Shape::Rectangle { x, y, w, h, radius }
+        }
+        "ellipse" => Shape::Ellipse { cx: x + w / 2.0, cy: y + h / 2.0, rx: w / 2.0, ry: h / 2.0 },
+        "diamond" => Shape::Polygon(vec![(x + w / 2.0, y), (x + w, y + h / 2.0), (x + w / 2.0, y + h), (x, y + h / 2.0)]),
+        kind @ ("line" | "arrow" | "freedraw") => {
+            let points = points(element);
+            let head = (kind == "arrow").then(|| arrowhead(&points, style_of(element).stroke_width)).flatten();
+            Shape::Polyline { points, head }
+        }
+        "text" => {
+            let font_size = element.get("fontSize").and_then(|v| v.as_f64()).unwrap_or(DEFAULT_FONT_SIZE);
+            let align = text(element, "textAlign").unwrap_or("left");
+            let anchor_x = match align {
+                "center" => x + w / 2.0,
+                "right" => x + w,
+                _ => x,
+            };
+            let lines = text(element, "text")
+                .unwrap_or("")
+                .lines()
+                .enumerate()
+                .map(|(i, line)| (y + font_size * (LINE_HEIGHT * i as f64 + 1.0), line))
+                .collect();
+            Shape::Text(TextBlock { font_size, align, x: anchor_x, lines })
+        }
+        other => Shape::Unsupported(other),
+    }
+}
+
+/// Stroke, fill, opacity and rotation attributes of an SVG shape.
+fn svg_attrs(style: &Style, fill: bool, palette: &Palette) -> String {
+    let fill = match style.fill {
+        Some(color) if fill => palette.color(color),
+        _ => "none".to_string(),
+    };
+    let mut attrs = format!(
+        r#" stroke="{}" fill="{fill}" stroke-width="{}""#,
+        palette.color(style.stroke),
+        style.stroke_width
+    );
+    if style.opacity < 1.0 {
+        let _ = write!(attrs, r#" opacity="{}""#, style.opacity);
+    }
+    if style.angle != 0.0 {
+        let (cx, cy) = style.center;
+        let _ = write!(attrs, r#" transform="rotate({} {cx} {cy})""#, style.angle.to_degrees());
+    }
+    attrs
+}
+
+fn svg_points(points: &[(f64, f64)]) -> String {
+    points.iter().map(|(x, y)| format!("{x},{y}")).collect::<Vec<_>>().join(" ")
+}
+
+fn render_svg_shape(out: &mut String, shape: &Shape, style: &Style, palette: &Palette) {
+    match shape {
+        Shape::Rectangle { x, y, w, h, radius } => {
+            let _ = write!(
+                out,
+                r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="{radius}"{}/>"#,
+                svg_attrs(style, true, palette)
+            );
+        }
+        Shape::Ellipse { cx, cy, rx, ry } => {
+            let _ = write!(out, r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}"{}/>"#, svg_attrs(style, true, palette));
+        }
+        Shape::Polygon(points) => {
+            let _ = write!(out, r#"<polygon points="{}"{}/>"#, svg_points(points), svg_attrs(style, true, palette));
+        }
+        Shape::Polyline { points, head } => {
+            for line in std::iter::once(points).chain(head) {
+                let _ = write!(
+                    out,
+                    r#"<polyline points="{}" stroke-linecap="round" stroke-linejoin="round"{}/>"#,
+                    svg_points(line),
+                    svg_attrs(style, false, palette)
+                );
+            }
+        }
+        Shape::Text(block) => {
+            let anchor = match block.align {
+                "center" => "middle",
+                "right" => "end",
+                _ => "start",
+            };
+            let _ = write!(
+                out,
+                r#"<text font-size="{}" font-family="Virgil, Segoe UI Emoji" fill="{}" text-anchor="{anchor}""#,
+                block.font_size,
+                palette.color(style.stroke)
+            );
+            if style.opacity < 1.0 {
+                let _ = write!(out, r#" opacity="{}""#, style.opacity);
+            }
+            if style.angle != 0.0 {
+                let (cx, cy) = style.center;
+                let _ = write!(out, r#" transform="rotate({} {cx} {cy})""#, style.angle.to_degrees());
+            }
+            out.push('>');
+            for (baseline, line) in &block.lines {
+                let _ = write!(out, r#"<tspan x="{}" y="{baseline}">{}</tspan>"#, block.x, escape(line));
+            }
+            out.push_str("</text>");
+        }
+        Shape::Unsupported(kind) => {
+            // `--` may not appear inside a comment
+            let kind: String = kind.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+            let _ = write!(out, "<!-- {kind} element not rendered -->");
+        }
+    }
+}
+
+/// Render a drawing's elements to a standalone SVG document. Covers the basic
+/// shapes, lines, arrows, freedraw strokes and text; other elements (such as
+/// embedded images) are left as a comment. With a `theme`, the drawing is shown
+/// in it regardless of its stored `appState.theme`.
+pub fn to_svg(data: &Value, theme: Option<Theme>) -> String {
+    let palette = Palette { invert: theme.is_some_and(|theme| theme != Theme::of(data)) };
+    let elements = visible_elements(data);
+
+    let (min_x, min_y, max_x, max_y) = bounds(&elements).unwrap_or_default();
+    let (x, y) = (min_x - PADDING, min_y - PADDING);
+    let (width, height) = (max_x - min_x + 2.0 * PADDING, max_y - min_y + 2.0 * PADDING);
+
+    let mut out = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{x} {y} {width} {height}" width="{width}" height="{height}">"#
+    );
+    if let Some(background) = background(data, theme) {
+        let _ = write!(
+            out,
+            r#"<rect x="{x}" y="{y}" width="{width}" height="{height}" fill="{}"/>"#,
+            palette.color(background)
+        );
+    }
+    for element in elements {
+        render_svg_shape(&mut out, &shape_of(element), &style_of(element), &palette);
+    }
+
+    out.push_str("</svg>");
+    out
+}
+
+/// Elements that aren't deleted, in stacking order.
+fn visible_elements(data: &Value) -> Vec<&Value> {
+    data.get("elements")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|e| !e.get("isDeleted").and_then(|v| v.as_bool()).unwrap_or(false))
+        .collect()
+}
+
+/// Canvas color to paint behind the drawing, before theming. Drawings without a
+/// `viewBackgroundColor` get a transparent canvas unless a theme is requested.
+fn background(data: &Value, theme: Option<Theme>) -> Option<&str> {
+    data.pointer("/appState/viewBackgroundColor").and_then(|v| v.as_str()).or(match theme {
+        Some(_) if Theme::of(data) == Theme::Dark => Some(DARK_BACKGROUND),
+        Some(_) => Some(LIGHT_BACKGROUND),
+        None => None,
+    })
+}
+
+/// Rotate `points` by the element's angle around the center of its box.
+fn rotated(style: &Style, points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    if style.angle == 0.0 {
+        return points.to_vec();
+    }
+    let (cx, cy) = style.center;
+    let (sin, cos) = style.angle.sin_cos();
+    points
+        .iter()
+        .map(|&(x, y)| (cx + (x - cx) * cos - (y - cy) * sin, cy + (x - cx) * sin + (y - cy) * cos))
+        .collect()
+}
+
+/// Outline of an ellipse inscribed in the box, as a polygon.
+fn ellipse_outline(x: f64, y: f64, w: f64, h: f64) -> Vec<(f64, f64)> {
+    let steps = 72;
+    (0..steps)
+        .map(|i| {
+            let t = 2.0 * PI * i as f64 / steps as f64;
+            (x + w / 2.0 * (1.0 + t.cos()), y + h / 2.0 * (1.0 + t.sin()))
+        })
+        .collect()
+}
+
+/// Outline of a rectangle with corners of radius `r`, as a polygon.
+fn rectangle_outline(x: f64, y: f64, w: f64, h: f64, r: f64) -> Vec<(f64, f64)> {
+    if r <= 0.0 {
+        return vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)];
+    }
+    let corners = [(x + w - r, y + r, -0.5), (x + w - r, y + h - r, 0.0), (x + r, y + h - r, 0.5), (x + r, y + r, 1.0)];
+    corners
+        .iter()
+        .flat_map(|&(cx, cy, start)| {
+            (0..=8).map(move |i| {
+                let t = PI * (start + 0.5 * i as f64 / 8.0);
+                (cx + r * t.cos(), cy + r * t.sin())
+            })
+        })
+        .collect()
+}
+
+/// The two barbs of an arrowhead at the end of `points`, as a polyline through the tip.
+fn arrowhead(points: &[(f64, f64)], stroke_width: f64) -> Option<Vec<(f64, f64)>> {
+    let (&tip, rest) = points.split_last()?;
+    let &from = rest.iter().rev().find(|&&p| p != tip)?;
+    let direction = (tip.1 - from.1).atan2(tip.0 - from.0);
+    let length = 12.0 + 2.0 * stroke_width;
+    let barb = |offset: f64| {
+        let t = direction + PI - offset;
+        (tip.0 + length * t.cos(), tip.1 + length * t.sin())
+    };
+    Some(vec![barb(0.45), tip, barb(-0.45)])
+}
+
+/// Render a drawing's elements to a PNG `width` pixels wide (its own size by
+/// default), from the same shapes and theming as [`to_svg`]. No fonts are
+/// bundled, so each line of text is drawn as a bar of its approximate extent.
+/// Elements that can't be drawn are skipped.
+pub fn to_png(data: &Value, width: Option<u32>, theme: Option<Theme>) -> Vec<u8> {
+    to_canvas(data, width, theme).encode_png()
+}
+
+/// The width a requested `?width=` is rendered at.
+pub fn clamp_width(width: u32) -> u32 {
+    width.clamp(1, MAX_PNG_SIDE)
+}
+
+fn to_canvas(data: &Value, width: Option<u32>, theme: Option<Theme>) -> Canvas {
+    let palette = Palette { invert: theme.is_some_and(|theme| theme != Theme::of(data)) };
+    let elements = visible_elements(data);
+
+    let (min_x, min_y, max_x, max_y) = bounds(&elements).unwrap_or_default();
+    let (origin_x, origin_y) = (min_x - PADDING, min_y - PADDING);
+    let (natural_w, natural_h) = (max_x - min_x + 2.0 * PADDING, max_y - min_y + 2.0 * PADDING);
+    let scale = width.map_or(1.0, |w| w as f64 / natural_w);
+    let max_side = MAX_PNG_SIDE as f64;
+    let scale = scale.min(max_side / natural_w).min(max_side / natural_h);
+    let size = |natural: f64| ((natural * scale).round() as u32).clamp(1, MAX_PNG_SIDE);
+    let mut canvas = Canvas::new(width.map_or_else(|| size(natural_w), clamp_width), size(natural_h))
+        .with_work_budget(MAX_RENDER_WORK);
+
+    if let Some(background) = background(data, theme).and_then(|c| palette.rgba(c)) {
+        canvas.clear(background);
+    }
+
+    for element in elements {
+        let style = style_of(element);
+        let to_pixels = |points: &[(f64, f64)]| -> Vec<(f64, f64)> {
+            rotated(&style, points).into_iter().map(|(x, y)| ((x - origin_x) * scale, (y - origin_y) * scale)).collect()
+        };
+        let color = |color: &str| palette.rgba(color).map(|c| c.with_opacity(style.opacity as f32));
+        let (stroke, fill) = (color(style.stroke), style.fill.and_then(color));
+        let stroke_width = style.stroke_width * scale;
+
+        let shape = shape_of(element);
+        match &shape {
+            Shape::Polyline { points, head } => {
+                if let Some(stroke) = stroke {
+                    for line in std::iter::once(points).chain(head) {
+                        canvas.stroke_polyline(&to_pixels(line), stroke_width, stroke);
+                    }
+                }
+            }
+            Shape::Text(block) => {
+                let Some(stroke) = stroke else { continue };
+                let box_width = num(element, "width");
+                for (baseline, line) in &block.lines {
+                    let mut extent = line.trim_end().chars().count() as f64 * block.font_size * 0.55;
+                    if box_width > 0.0 {
+                        extent = extent.min(box_width);
+                    }
+                    let start = match block.align {
+                        "center" => block.x - extent / 2.0,
+                        "right" => block.x - extent,
+                        _ => block.x,
+                    };
+                    let middle = baseline - block.font_size * 0.4;
+                    if extent > 0.0 {
+                        let bar = to_pixels(&[(start, middle), (start + extent, middle)]);
+                        canvas.stroke_polyline(&bar, block.font_size * 0.3 * scale, stroke);
+                    }
+                }
+            }
+            Shape::Unsupported(_) => {}
+            closed => {
+                let Some(outline) = closed.outline() else { continue };
+                let outline = to_pixels(&outline);
+                if let Some(fill) = fill {
+                    canvas.fill_polygon(&outline, fill);
+                }
+                if let (Some(stroke), Some(&first)) = (stroke, outline.first()) {
+                    let closed: Vec<_> = outline.iter().copied().chain([first]).collect();
+                    canvas.stroke_polyline(&closed, stroke_width, stroke);
+                }
+            }
+        }
+    }
+
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_renders_shapes_and_escapes_text() {
+        let data = json!({
+            "type": "excalidraw",
+            "elements": [
+                {"type": "rectangle", "x": 0, "y": 0, "width": 100, "height": 50, "backgroundColor": "#ffc9c9"},
+                {"type": "arrow", "x": 100, "y": 25, "points": [[0, 0], [80, 0]]},
+                {"type": "text", "x": 10, "y": 10, "text": "a < b\nsecond", "fontSize": 16},
+                {"type": "ellipse", "x": 0, "y": 0, "width": 10, "height": 10, "isDeleted": true}
+            ],
+            "appState": {"viewBackgroundColor": "#ffffff"}
+        });
+        let svg = to_svg(&data, None);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-10 -10 200 70""#));
+        assert!(svg.ends_with("</svg>"));
+        assert!(svg.contains(r##"<rect x="0" y="0" width="100" height="50" rx="0" stroke="#1e1e1e" fill="#ffc9c9""##));
+        assert!(svg.contains(r#"<polyline points="100,25 180,25""#));
+        assert!(svg.contains("a &lt; b</tspan>"));
+        assert!(!svg.contains("<ellipse"));
+    }
+
+    #[test]
+    fn test_svg_and_png_share_geometry() {
+        let data = json!({
+            "type": "excalidraw",
+            "elements": [
+                {"type": "arrow", "x": 0, "y": 0, "points": [[0, 0], [100, 0]], "strokeWidth": 4},
+                {"type": "frame", "x": 0, "y": 0, "width": 10, "height": 10},
+                {"type": "weird--type", "x": 0, "y": 0}
+            ]
+        });
+        let svg = to_svg(&data, None);
+        // The arrowhead barbs are drawn as their own polyline ending at the tip
+        let Shape::Polyline { head: Some(head), .. } = shape_of(&data["elements"][0]) else { panic!() };
+        assert_eq!(head[1], (100.0, 0.0));
+        assert!(svg.contains(&format!(r#"<polyline points="{}""#, svg_points(&head))));
+        assert!(svg.contains("<!-- frame element not rendered -->"));
+        assert!(svg.contains("<!-- weirdtype element not rendered -->"));
+
+        // The PNG draws the same barb: 20 units back from the tip, at 0.45 rad
+        let canvas = to_canvas(&data, None, None);
+        let (bx, by) = head[0];
+        assert!(canvas.pixel((bx + 10.0) as u32, (by + 10.0) as u32)[3] > 0);
+    }
+
+    #[test]
+    fn test_empty_drawing_renders_blank_canvas() {
+        let svg = to_svg(&json!({"type": "excalidraw", "elements": []}), None);
+        assert!(svg.contains(r#"viewBox="-10 -10 20 20""#));
+    }
+
+    fn background(svg: &str) -> &str {
+        let start = svg.find(r#"<rect x="-10""#).unwrap();
+        let fill = svg[start..].find(r#"fill=""#).unwrap() + start + 6;
+        &svg[fill..fill + 7]
+    }
+
+    #[test]
+    fn test_theme_overrides_stored_theme() {
+        let data = json!({
+            "type": "excalidraw",
+            "elements": [{"type": "rectangle", "x": 0, "y": 0, "width": 10, "height": 10, "strokeColor": "#1e1e1e", "backgroundColor": "#ffc9c9"}],
+            "appState": {"theme": "light", "viewBackgroundColor": "#ffffff"}
+        });
+
+        let dark = to_svg(&data, Some(Theme::Dark));
+        assert_eq!(background(&dark), "#000000");
+        assert!(dark.contains(r##"stroke="#e1e1e1""##));
+        // Pastel fills become deep colors of the same hue
+        assert!(dark.contains(r##"fill="#360000""##));
+
+        let light = to_svg(&data, Some(Theme::Light));
+        assert_eq!(background(&light), "#ffffff");
+        assert_eq!(light, to_svg(&data, None));
+    }
+
+    #[test]
+    fn test_theme_without_stored_background_uses_default_canvas() {
+        let data = json!({"type": "excalidraw", "elements": [], "appState": {"theme": "dark"}});
+        assert_eq!(background(&to_svg(&data, Some(Theme::Dark))), DARK_BACKGROUND);
+        assert_eq!(background(&to_svg(&data, Some(Theme::Light))), "#ededed");
+        assert!(!to_svg(&data, None).contains("<rect"));
+    }
+
+    #[test]
+    fn test_png_draws_shapes_at_requested_width() {
+        let data = json!({
+            "type": "excalidraw",
+            "elements": [
+                {"type": "rectangle", "x": 0, "y": 0, "width": 100, "height": 50, "strokeColor": "#1e1e1e", "backgroundColor": "#ff0000"},
+                {"type": "arrow", "x": 100, "y": 25, "points": [[0, 0], [80, 0]], "strokeWidth": 2},
+                {"type": "text", "x": 0, "y": 60, "width": 50, "height": 25, "text": "hello", "fontSize": 20},
+                {"type": "image", "x": 0, "y": 0, "width": 10, "height": 10, "fileId": "missing"},
+                {"type": "rectangle", "x": 0, "y": 0, "width": 10, "height": 10, "strokeColor": "not a color"}
+            ],
+            "appState": {"viewBackgroundColor": "#ffffff"}
+        });
+        let canvas = to_canvas(&data, Some(400), None);
+        // Content spans 180x85 units plus padding, scaled to 400px wide
+        assert_eq!((canvas.width(), canvas.height()), (400, 210));
+        let at = |x: f64, y: f64| canvas.pixel(((x + 10.0) * 2.0) as u32, ((y + 10.0) * 2.0) as u32);
+        assert_eq!(at(50.0, 25.0), [255, 0, 0, 255]);
+        assert_eq!(at(150.0, 25.0), [30, 30, 30, 255]);
+        assert_eq!(at(150.0, 40.0), [255, 255, 255, 255]);
+        assert_eq!(at(10.0, 72.0), [30, 30, 30, 255]);
+
+        let png = to_png(&data, None, None);
+        assert_eq!(&png[16..24], [0, 0, 0, 200, 0, 0, 0, 105]);
+    }
+
+    #[test]
+    fn test_png_dark_theme_and_size_limit() {
+        let data = json!({
+            "type": "excalidraw",
+            "elements": [{"type": "ellipse", "x": 0, "y": 0, "width": 100000, "height": 1000}],
+            "appState": {"theme": "dark"}
+        });
+        let canvas = to_canvas(&data, Some(100_000), Some(Theme::Dark));
+        assert_eq!(canvas.width(), MAX_PNG_SIDE);
+        assert_eq!(canvas.pixel(0, 0), [0x12, 0x12, 0x12, 255]);
+        // Without a theme or stored background the canvas stays transparent
+        let blank = json!({"type": "excalidraw", "elements": []});
+        assert_eq!(to_canvas(&blank, Some(100), None).pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn test_invert_lightness() {
+        assert_eq!(invert_lightness("#000").as_deref(), Some("#ffffff"));
+        assert_eq!(invert_lightness("#1971c2cc").as_deref(), Some("#3d95e6cc"));
+        assert_eq!(invert_lightness("transparent"), None);
+        assert_eq!(invert_lightness("#12345"), None);
+        // Multibyte characters are rejected, not sliced through
+        assert_eq!(invert_lightness("#ééé"), None);
+        assert_eq!(invert_lightness("#aé"), None);
+        assert_eq!(invert_lightness("#12g"), None);
+    }
+}
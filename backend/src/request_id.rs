@@ -0,0 +1,64 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use tower_http::request_id::RequestId;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if called while serving one.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Middleware making the id assigned by `SetRequestIdLayer` available to
+/// [`current`] for the rest of the request, so error bodies can quote it.
+pub async fn request_id_middleware(request: Request, next: Next) -> Response {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(str::to_string);
+    match id {
+        Some(id) => REQUEST_ID.scope(id, next.run(request)).await,
+        None => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+    use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+
+    async fn error_body(app: Router, request: Request) -> (String, serde_json::Value) {
+        let response = app.oneshot(request).await.unwrap();
+        let id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (id, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_error_bodies_quote_request_id() {
+        let app = Router::new()
+            .route("/missing", get(|| async { Err::<(), _>(AppError::NotFound) }))
+            .route("/locked", get(|| async { Err::<(), _>(AppError::PasswordRequired) }))
+            .layer(middleware::from_fn(request_id_middleware))
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
+
+        let request = Request::builder().uri("/missing").body(Body::empty()).unwrap();
+        let (id, body) = error_body(app.clone(), request).await;
+        assert!(uuid::Uuid::parse_str(&id).is_ok(), "{id}");
+        assert_eq!(body, serde_json::json!({ "error": "Drawing not found", "request_id": id }));
+
+        let request = Request::builder().uri("/locked").header("x-request-id", "plugin-42").body(Body::empty()).unwrap();
+        let (_, body) = error_body(app, request).await;
+        assert_eq!(body["request_id"], "plugin-42");
+        assert_eq!(body["password_protected"], true);
+
+        // Outside a request there is no id to quote
+        assert_eq!(current(), None);
+    }
+}
@@ -1,50 +1,148 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::{header, HeaderMap, StatusCode},
-    response::IntoResponse,
-    Json,
+    body::{Body, Bytes},
+    extract::{FromRequest, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Extension, Json,
 };
 use base64::{engine::general_purpose, Engine as _};
+use futures::StreamExt;
 use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha1::Sha1;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use subtle::ConstantTimeEq;
-use uuid::Uuid;
 
+use crate::auth::{bearer_token, ApiKeys, KeyLabel, KeyScope};
+use crate::breaker::WriteBreaker;
+use crate::cache::{CacheUsage, RenderCache};
 use crate::collab::{SessionInfo, SessionManager};
+use crate::confirm::{ConfirmTokens, CONFIRM_TOKEN_TTL_SECS};
+use crate::counters::ViewCounter;
 use crate::error::AppError;
+use crate::export;
+use crate::history::{UsageHistory, UsageSample};
+use crate::import::{self, ArchiveImportSummary, IdConflict};
+use crate::locks::KeyedLocks;
+use crate::metrics::Metrics;
+use crate::quota::{ByteQuota, ByteQuotaUsage, DrawingQuota};
+use crate::normalize;
 use crate::password;
-use crate::storage::{DrawingMeta, DrawingStorage, FileSystemStorage};
+use crate::render;
+use crate::slug;
+use crate::storage::{
+    self, DrawingFilter, DrawingMeta, DrawingStorage, DrawingSummary, DrawingVersion, Storage,
+    StorageStats, Tombstone, TrashedDrawing, VerifyReport,
+};
+use crate::validation::{
+    is_blank, is_valid_id, reject_duplicate_element_ids, reject_external_links, reject_out_of_bounds_coordinates,
+    strip_embedded_files, validate_created_at, validate_embedded_file_count, validate_embedded_file_size,
+    validate_excalidraw,
+};
+use crate::webhook::{WebhookEvent, WebhookNotifier};
 
 #[derive(Clone)]
 pub struct AppState {
-    pub storage: FileSystemStorage,
+    pub storage: Storage,
     pub base_url: String,
     pub session_manager: SessionManager,
-    pub api_key: String,
+    /// Keys accepted for authenticated requests (and the admin bypass on views)
+    pub api_keys: ApiKeys,
     pub stun_url: Option<String>,
     pub turn_url: Option<String>,
     pub turn_secret: Option<String>,
+    /// `max-age` for public drawing responses (seconds)
+    pub cache_max_age_secs: u64,
+    /// `stale-while-revalidate` window for public drawing responses (seconds)
+    pub cache_swr_secs: u64,
+    pub max_tags_per_drawing: usize,
+    pub max_tag_length: usize,
+    /// When set, deletes need a token from a prior `?phase=prepare` call
+    pub require_delete_confirmation: bool,
+    pub confirm_tokens: ConfirmTokens,
+    /// Upload/delete notifications, when `--webhook-url` is set
+    pub webhooks: Option<WebhookNotifier>,
+    /// Return the existing drawing for new uploads identical to one already stored
+    pub dedup_uploads: bool,
+    /// Serializes deduplicated uploads per content hash
+    pub upload_locks: KeyedLocks,
+    /// Serializes view counting per drawing for `max_views` drawings
+    pub view_locks: KeyedLocks,
+    /// When set, extracted files are returned as links to `<base>/api/view/{id}/assets/{hash}`
+    pub asset_base_url: Option<String>,
+    /// Generate a slug from the title of uploaded drawings
+    pub auto_slug: bool,
+    /// Reject uploads in which two elements share an `id`
+    pub reject_duplicate_element_ids: bool,
+    /// Add a warning to the upload response for drawings with no visible elements
+    pub warn_empty_drawings: bool,
+    /// Refuse uploads of drawings with no visible elements
+    pub reject_empty_drawings: bool,
+    /// Add each drawing's metadata under `_share` to exported documents
+    pub embed_metadata: bool,
+    /// Most entries allowed in a drawing's `files` map; `None` means unlimited
+    pub max_embedded_files: Option<usize>,
+    /// Largest total size of a drawing's embedded `dataURL`s; `None` means unlimited
+    pub max_embedded_bytes: Option<usize>,
+    /// Drop the `files` map of uploaded drawings instead of storing it
+    pub strip_files: bool,
+    /// Number of stored drawings, capped by `MAX_DRAWINGS`
+    pub drawing_quota: DrawingQuota,
+    /// Total size of stored drawings, capped by `MAX_TOTAL_MB`; shared with storage
+    pub byte_quota: ByteQuota,
+    /// PNGs rendered by `get_drawing_png`, keyed by content hash and options
+    pub render_cache: RenderCache,
+    /// Limits how many PNG renders run at once (`RENDER_CONCURRENCY`)
+    pub render_permits: Arc<tokio::sync::Semaphore>,
+    /// Served by `/api/admin/config`; secrets are already redacted
+    pub effective_config: Arc<serde_json::Value>,
+    /// Counters exposed at `/metrics` when `--metrics` is set
+    pub metrics: Metrics,
+    /// Round element coordinates to this many decimal places before storing
+    pub round_coordinates: Option<u32>,
+    /// Largest allowed element coordinate magnitude; `None` means unbounded
+    pub max_coordinate: Option<f64>,
+    /// Clamp out-of-bounds coordinates to `max_coordinate` instead of rejecting the upload
+    pub clamp_coordinates: bool,
+    /// Origins elements may link to; `None` allows any link
+    pub allowed_link_origins: Option<Arc<Vec<String>>>,
+    /// `appState` keys filled into uploads that don't set them
+    pub default_app_state: Option<Arc<serde_json::Map<String, serde_json::Value>>>,
+    /// Longest TTL a drawing can have, from now; requested TTLs and extensions are clamped to it
+    pub max_ttl_secs: u64,
+    /// How far `POST /api/drawings/{id}/extend` pushes the expiry when no amount is given
+    pub ttl_extension_secs: u64,
+    /// Views of drawings without a `max_views` limit, batched before hitting disk
+    pub view_counter: ViewCounter,
+    /// Trips into read-only mode when storage writes keep failing
+    pub write_breaker: WriteBreaker,
+    /// Largest accepted preview image, in bytes after base64 decoding
+    pub max_preview_bytes: usize,
+    /// Rewrite `source_path` separators to `/` (see [`normalize::source_path`])
+    pub normalize_source_paths: bool,
+    /// Periodic drawing count / size samples
+    pub usage_history: UsageHistory,
+    /// Largest serialized list response; bigger pages are refused with 413
+    pub max_list_response_bytes: usize,
+    /// How much an archive import may inflate
+    pub import_limits: import::ImportLimits,
+    /// Emit weak (`W/"..."`) rather than strong `ETag`s
+    pub weak_etags: bool,
+    /// Serving a replicated data dir read-only, so readiness doesn't probe writes
+    pub follower: bool,
+    /// Deletes move drawings to the trash instead of removing them
+    pub soft_delete: bool,
 }
 
 
 /// Check if the request carries a valid API key via `Authorization: Bearer <key>`.
 /// Uses constant-time comparison to prevent timing attacks.
-fn is_valid_api_key(headers: &HeaderMap, api_key: &str) -> bool {
+fn is_valid_api_key(headers: &HeaderMap, api_keys: &ApiKeys) -> bool {
     headers
         .get(header::AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
-        .map(|value| {
-            if let Some(token) = value.strip_prefix("Bearer ") {
-                let token_bytes = token.as_bytes();
-                let key_bytes = api_key.as_bytes();
-                token_bytes.len() == key_bytes.len() && token_bytes.ct_eq(key_bytes).into()
-            } else {
-                false
-            }
-        })
-        .unwrap_or(false)
+        .and_then(bearer_token)
+        .is_some_and(|token| api_keys.authorizes(token, KeyScope::Read))
 }
 
 // ──────────────────────────────────────────────
@@ -56,16 +154,83 @@ pub struct UploadResponse {
     pub id: String,
     pub url: String,
     pub password_protected: bool,
+    /// Problems worth telling the user about that didn't stop the upload
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct ListResponse {
     pub drawings: Vec<DrawingMeta>,
+    /// Drawings matching the filters across all pages
+    pub total: usize,
+    /// `offset` of the next page, `None` on the last one
+    pub next_offset: Option<usize>,
+}
+
+/// Longest accepted drawing title, in characters.
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// A client-sent `source_path` as stored and looked up.
+fn clean_source_path(state: &AppState, path: &str) -> String {
+    if state.normalize_source_paths {
+        normalize::source_path(path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Every PNG file starts with these bytes.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Decode and check an uploaded preview: base64, a PNG, and at most `max_bytes`.
+fn decode_preview(preview: &str, max_bytes: usize) -> Result<Vec<u8>, AppError> {
+    let encoded = preview.strip_prefix("data:image/png;base64,").unwrap_or(preview);
+    let png = general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| AppError::BadRequest("preview must be base64-encoded".into()))?;
+    if png.len() > max_bytes {
+        return Err(AppError::BadRequest(format!("preview must be at most {max_bytes} bytes")));
+    }
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err(AppError::BadRequest("preview must be a PNG image".into()));
+    }
+    Ok(png)
+}
+
+/// Stored `User-Agent` values are truncated to this many characters.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// TTLs are cut to a century whatever `MAX_TTL_SECS` says, keeping expiry
+/// timestamps well inside what chrono and RFC 3339 can represent.
+const MAX_EXPIRY_SECS: u64 = 100 * 365 * 24 * 60 * 60;
+
+/// The moment `secs` from `now`, with `secs` cut to [`MAX_EXPIRY_SECS`].
+fn expiry_after(now: chrono::DateTime<chrono::Utc>, secs: u64) -> chrono::DateTime<chrono::Utc> {
+    now + chrono::Duration::seconds(secs.min(MAX_EXPIRY_SECS) as i64)
+}
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub storage: StorageStats,
+    /// Pending webhook retries (absent when webhooks are disabled)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_queue_depth: Option<usize>,
+    /// Drawing/list cache memory usage (absent when caching is disabled)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheUsage>,
+    /// Stored drawing bytes against `MAX_TOTAL_MB`
+    pub quota: ByteQuotaUsage,
+    /// Whether failing storage writes have put the server into read-only mode
+    pub read_only: bool,
 }
 
 #[derive(Serialize)]
 pub struct PublicListResponse {
     pub drawings: Vec<PublicDrawingMeta>,
+    pub total: usize,
+    pub next_offset: Option<usize>,
 }
 
 #[derive(Serialize)]
@@ -75,6 +240,40 @@ pub struct PublicDrawingMeta {
     pub source_path: Option<String>,
     pub password_protected: bool,
     pub persistent_collab: bool,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+impl From<DrawingMeta> for PublicDrawingMeta {
+    fn from(d: DrawingMeta) -> Self {
+        Self {
+            id: d.id,
+            created_at: d.created_at,
+            source_path: d.source_path,
+            password_protected: d.password_protected,
+            persistent_collab: d.persistent_collab,
+            tags: d.tags,
+            size_bytes: Some(d.size_bytes),
+            content_hash: d.content_hash,
+        }
+    }
+}
+
+impl PublicDrawingMeta {
+    /// The entry for the public list, which anyone can read: no content
+    /// hashes, which would let visitors confirm guesses about a drawing's
+    /// content, and no sizes of password-protected drawings.
+    pub fn listed(d: DrawingMeta) -> Self {
+        let protected = d.password_protected;
+        Self {
+            size_bytes: Some(d.size_bytes).filter(|_| !protected),
+            content_hash: None,
+            ..d.into()
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -88,6 +287,54 @@ pub struct UploadRequest {
     /// Optional password for the drawing. Empty string removes password.
     #[serde(default)]
     pub password: Option<String>,
+    /// Optional tags. Omitted keeps existing tags on update; an empty list clears them.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// Delete the drawing after this many views (burn after reading).
+    #[serde(default)]
+    pub max_views: Option<u64>,
+    /// Human-readable title. Omitted keeps the existing title on update.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Stop serving the drawing this many seconds from now (capped at the server maximum).
+    /// Omitted keeps the existing expiry on update.
+    #[serde(default, alias = "expires_in_seconds")]
+    pub expires_in_secs: Option<u64>,
+    /// Original creation time, for drawings migrated from elsewhere. Must not
+    /// be in the future. Omitted keeps the existing one on update.
+    #[serde(default)]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Client-rendered PNG preview, base64 (a `data:image/png;base64,` prefix is allowed).
+    /// Omitted keeps the existing preview on update.
+    #[serde(default)]
+    pub preview: Option<String>,
+}
+
+/// JSON body extractor that answers an empty or whitespace-only body with a
+/// clear error instead of serde's "EOF while parsing" message. Otherwise behaves
+/// exactly like [`Json`].
+pub struct NonEmptyJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for NonEmptyJson<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (parts, body) = req.into_parts();
+        let bytes = Bytes::from_request(Request::from_parts(parts.clone(), body), state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Err(AppError::BadRequest("empty request body".into()).into_response());
+        }
+        let Json(value) = Json::<T>::from_request(Request::from_parts(parts, Body::from(bytes)), state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        Ok(Self(value))
+    }
 }
 
 // ──────────────────────────────────────────────
@@ -182,35 +429,137 @@ pub struct ActivatePersistentCollabResponse {
 // Handlers
 // ──────────────────────────────────────────────
 
+/// Normalize tags (trim, lowercase, dedupe, drop empties) and enforce the
+/// configured count and length limits on the normalized result.
+fn normalize_tags(tags: &[String], max_count: usize, max_len: usize) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || normalized.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > max_len {
+            return Err(AppError::BadRequest(format!(
+                "Tag '{tag}' exceeds the maximum length of {max_len} characters."
+            )));
+        }
+        normalized.push(tag);
+    }
+    if normalized.len() > max_count {
+        return Err(AppError::BadRequest(format!(
+            "Too many tags: {} given, at most {max_count} allowed.",
+            normalized.len()
+        )));
+    }
+    Ok(normalized)
+}
+
 pub async fn upload_drawing(
     State(state): State<AppState>,
-    Json(body): Json<UploadRequest>,
+    key: Option<Extension<KeyLabel>>,
+    headers: HeaderMap,
+    NonEmptyJson(mut body): NonEmptyJson<UploadRequest>,
 ) -> Result<(StatusCode, Json<UploadResponse>), AppError> {
-    let doc_type = body
-        .data
-        .get("type")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
+    let warnings = check_document(&state, &mut body.data)?;
 
-    if doc_type != "excalidraw" {
-        return Err(AppError::BadRequest(
-            "Invalid document: missing or wrong 'type' field. Expected 'excalidraw'.".into(),
-        ));
+    let tags = body
+        .tags
+        .as_deref()
+        .map(|tags| normalize_tags(tags, state.max_tags_per_drawing, state.max_tag_length))
+        .transpose()?;
+
+    let source_path = body.source_path.as_deref().map(|p| clean_source_path(&state, p));
+
+    let preview = body
+        .preview
+        .as_deref()
+        .map(|p| decode_preview(p, state.max_preview_bytes))
+        .transpose()?;
+
+    if body.max_views == Some(0) {
+        return Err(AppError::BadRequest("max_views must be at least 1".into()));
     }
 
-    if !body.data.get("elements").map_or(false, |v| v.is_array()) {
-        return Err(AppError::BadRequest(
-            "Invalid document: missing 'elements' array.".into(),
-        ));
+    let title = body.title.as_deref().map(str::trim).filter(|t| !t.is_empty());
+    if title.is_some_and(|t| t.chars().count() > MAX_TITLE_LENGTH) {
+        return Err(AppError::BadRequest(format!("title must be at most {MAX_TITLE_LENGTH} characters")));
+    }
+    if let Some(created_at) = body.created_at {
+        validate_created_at(created_at)?;
+    }
+
+    let mut data = body.data;
+    storage::strip_internal_fields(&mut data);
+    normalize_document(&state, &mut data);
+    if let Some(obj) = data.as_object_mut() {
+        if let Some(tags) = tags {
+            obj.insert("_tags".to_string(), serde_json::json!(tags));
+        }
+        if let Some(max_views) = body.max_views {
+            obj.insert("_max_views".to_string(), serde_json::json!(max_views));
+        }
+        if let Some(title) = title {
+            obj.insert("_title".to_string(), serde_json::json!(title));
+        }
+        if let Some(secs) = body.expires_in_secs {
+            let expires_at = expiry_after(chrono::Utc::now(), secs.min(state.max_ttl_secs));
+            obj.insert("_expires_at".to_string(), serde_json::json!(expires_at.to_rfc3339()));
+        }
+        if let Some(created_at) = body.created_at {
+            obj.insert("_created_at".to_string(), serde_json::json!(created_at.to_rfc3339()));
+        }
     }
 
+    // Identical new uploads (e.g. a retrying client) collapse onto one drawing,
+    // which takes the tags and title the upload sends. The per-hash lock closes the
+    // check-then-write race between concurrent requests. Password-protected,
+    // view-limited and expiring uploads are never deduplicated: those settings
+    // belong to one share.
+    let deduplicate = body.password.is_none() && body.max_views.is_none() && body.expires_in_secs.is_none();
+    let _dedup_guard = if state.dedup_uploads && body.id.is_none() && deduplicate {
+        let hash = storage::document_hash(&data);
+        let guard = state.upload_locks.lock(&hash).await;
+        let existing = state
+            .storage
+            .find_by_content_hash(&hash)
+            .await?
+            .into_iter()
+            .find(|d| !d.password_protected && !d.is_expired() && d.source_path == source_path);
+        if let Some(existing) = existing {
+            let _update_guard = state.upload_locks.lock(&format!("update:{}", existing.id)).await;
+            let mut stored = state.storage.load(&existing.id).await?;
+            let mut changed = false;
+            if let Some(obj) = stored.as_object_mut() {
+                for key in ["_tags", "_title"] {
+                    if let Some(value) = data.get(key).filter(|v| obj.get(key) != Some(*v)) {
+                        obj.insert(key.to_string(), value.clone());
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                state.storage.save(&existing.id, &stored, source_path.as_deref(), None).await?;
+            }
+            if let Some(webhooks) = &state.webhooks {
+                webhooks.notify(WebhookEvent::new("upload", &existing.id, source_path.as_deref()));
+            }
+            tracing::info!(id = %existing.id, "Duplicate upload matched existing drawing");
+            state.metrics.record_upload();
+            let url = format!("{}/d/{}", state.base_url.trim_end_matches('/'), existing.id);
+            return Ok((
+                StatusCode::OK,
+                Json(UploadResponse { id: existing.id, url, password_protected: false, warnings }),
+            ));
+        }
+        Some(guard)
+    } else {
+        None
+    };
+
     let mut is_update = false;
     let id = if let Some(req_id) = body.id {
         // Validate the ID format to prevent abuse
-        let valid = !req_id.is_empty()
-            && req_id.len() <= 64
-            && req_id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
-        if !valid {
+        if !is_valid_id(&req_id) {
             return Err(AppError::BadRequest(
                 "Invalid ID: must be 1-64 alphanumeric characters, hyphens, or underscores.".into(),
             ));
@@ -218,24 +567,66 @@ pub async fn upload_drawing(
         is_update = true;
         req_id
     } else {
-        let new_id = Uuid::new_v4()
-            .to_string()
-            .replace('-', "")
-            .chars()
-            .take(16)
-            .collect::<String>();
-
-        // Ensure uniqueness for new IDs
-        if state.storage.exists(&new_id).await? {
-            Uuid::new_v4()
-                .to_string()
-                .replace('-', "")
-                .chars()
-                .take(16)
-                .collect::<String>()
-        } else {
-            new_id
+        storage::generate_id(&state.storage).await?
+    };
+
+    // An `If-Match` update only goes through if the drawing is still the version
+    // the client last saw. The per-id lock keeps another update from landing between
+    // the check and the write. Without `If-Match` the last writer wins.
+    let _update_guard = if is_update {
+        let guard = state.upload_locks.lock(&format!("update:{id}")).await;
+        if let Some(value) = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok()) {
+            let current = match served_head(&state, &id).await {
+                Ok(head) => Some(format!("\"{}\"", head.hash)),
+                Err(AppError::NotFound) => None,
+                Err(e) => return Err(e),
+            };
+            if !current.as_deref().is_some_and(|etag| if_match(value, etag)) {
+                return Err(AppError::PreconditionFailed(format!(
+                    "drawing '{id}' has changed since it was last fetched"
+                )));
+            }
+        }
+        Some(guard)
+    } else {
+        None
+    };
+
+    // Remember which client created the drawing; updates keep the original value.
+    // Only new drawings count towards `MAX_DRAWINGS`.
+    let is_new = !state.storage.exists(&id).await?;
+    let drawing_slot = if is_new { Some(state.drawing_quota.add()?) } else { None };
+    if is_new {
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect::<String>());
+        if let (Some(user_agent), Some(obj)) = (user_agent, data.as_object_mut()) {
+            obj.insert("_user_agent".to_string(), serde_json::json!(user_agent));
+        }
+    }
+
+    // Slugs are assigned once and then kept, so links stay stable when the title changes.
+    // The lock keeps two uploads with the same title from claiming the same slug.
+    let _slug_guard = match title.and_then(slug::slugify) {
+        Some(base) if state.auto_slug => {
+            let existing_slug = if is_update {
+                state.storage.load(&id).await.ok().and_then(|d| d.get("_slug").cloned())
+            } else {
+                None
+            };
+            if existing_slug.is_none() {
+                let guard = state.upload_locks.lock("slug").await;
+                let slug = slug::unique_slug(&state.storage, &base, &id).await?;
+                if let Some(obj) = data.as_object_mut() {
+                    obj.insert("_slug".to_string(), serde_json::json!(slug));
+                }
+                Some(guard)
+            } else {
+                None
+            }
         }
+        _ => None,
     };
 
     // Handle password: hash if provided, preserve existing if not specified on update
@@ -256,570 +647,3907 @@ pub async fn upload_drawing(
         None => None,
     };
 
-    state.storage.save(&id, &body.data, body.source_path.as_deref(), password_hash.as_deref()).await?;
+    state.storage.save(&id, &data, source_path.as_deref(), password_hash.as_deref()).await?;
+    if let Some(slot) = drawing_slot {
+        slot.commit();
+    }
+    if let Some(png) = &preview {
+        state.storage.save_preview(&id, png).await?;
+    }
+
+    if let Some(webhooks) = &state.webhooks {
+        webhooks.notify(WebhookEvent::new("upload", &id, source_path.as_deref()));
+    }
 
     let url = format!("{}/d/{}", state.base_url.trim_end_matches('/'), id);
     let password_protected = password_hash.is_some();
 
+    state.metrics.record_upload();
+    let key = key.map(|Extension(KeyLabel(label))| label);
     if is_update {
-        tracing::info!(id = %id, source_path = ?body.source_path, password_protected, "Drawing updated");
+        tracing::info!(id = %id, source_path = ?source_path, password_protected, key = ?key, "Drawing updated");
     } else {
-        tracing::info!(id = %id, source_path = ?body.source_path, password_protected, "Drawing uploaded");
+        tracing::info!(id = %id, source_path = ?source_path, password_protected, key = ?key, "Drawing uploaded");
     }
 
     Ok((
         if is_update { StatusCode::OK } else { StatusCode::CREATED },
-        Json(UploadResponse { id, url, password_protected }),
+        Json(UploadResponse { id, url, password_protected, warnings }),
     ))
 }
 
+/// The checks an uploaded document has to pass, with `STRIP_FILES` applied.
+/// Returns the warnings worth passing on to the client.
+fn check_document(state: &AppState, data: &mut serde_json::Value) -> Result<Vec<String>, AppError> {
+    validate_excalidraw(data)?;
+    if state.reject_duplicate_element_ids {
+        reject_duplicate_element_ids(data)?;
+    }
+    let mut warnings = Vec::new();
+    if (state.warn_empty_drawings || state.reject_empty_drawings) && is_blank(data) {
+        if state.reject_empty_drawings {
+            return Err(AppError::BadRequest(
+                "Invalid document: the drawing has no elements, so the shared page would be blank.".into(),
+            ));
+        }
+        warnings.push("The drawing has no elements, so the shared page will be blank.".to_string());
+    }
+    if state.strip_files {
+        if strip_embedded_files(data) {
+            warnings.push("Embedded files were removed; images in the drawing will show as missing.".to_string());
+        }
+    } else {
+        if let Some(max) = state.max_embedded_files {
+            validate_embedded_file_count(data, max)?;
+        }
+        if let Some(max) = state.max_embedded_bytes {
+            validate_embedded_file_size(data, max)?;
+        }
+    }
+    if let Some(allowed) = &state.allowed_link_origins {
+        reject_external_links(data, allowed)?;
+    }
+    if let Some(max) = state.max_coordinate.filter(|_| !state.clamp_coordinates) {
+        reject_out_of_bounds_coordinates(data, max)?;
+    }
+    Ok(warnings)
+}
+
+/// Coordinate clamping and rounding, and the default `appState`, as configured.
+fn normalize_document(state: &AppState, data: &mut serde_json::Value) {
+    if let Some(max) = state.max_coordinate.filter(|_| state.clamp_coordinates) {
+        normalize::clamp_coordinates(data, max);
+    }
+    if let Some(decimals) = state.round_coordinates {
+        normalize::round_coordinates(data, decimals);
+    }
+    if let Some(defaults) = &state.default_app_state {
+        normalize::apply_default_app_state(data, defaults);
+    }
+}
+
 #[derive(Deserialize)]
 pub struct ViewQuery {
     #[serde(default)]
     pub key: Option<String>,
 }
 
-pub async fn get_drawing(
-    State(state): State<AppState>,
-    Path(id): Path<String>,
-    Query(query): Query<ViewQuery>,
-    headers: HeaderMap,
-) -> Result<Json<serde_json::Value>, AppError> {
-    let data = state.storage.load(&id).await?;
+/// `?theme=` for the rendered views; unset keeps the drawing's own theme.
+/// `?width=` (pixels) only applies to PNGs, which default to the drawing's size.
+#[derive(Deserialize, Default)]
+pub struct RenderQuery {
+    #[serde(default)]
+    pub theme: Option<render::Theme>,
+    #[serde(default)]
+    pub width: Option<u32>,
+}
+
+/// Verify the viewer-supplied key against a drawing's `_password_hash`, if any.
+fn check_drawing_password(data: &serde_json::Value, key: Option<&str>) -> Result<(), AppError> {
+    check_password_hash(data.get("_password_hash").and_then(|v| v.as_str()), key)
+}
+
+/// [`check_drawing_password`] against a password hash read from metadata.
+fn check_password_hash(hash: Option<&str>, key: Option<&str>) -> Result<(), AppError> {
+    let Some(hash) = hash else {
+        return Ok(());
+    };
+    match key {
+        None => Err(AppError::PasswordRequired),
+        Some(key) => {
+            let valid = password::verify_password(key, hash)
+                .map_err(|e| AppError::Internal(format!("Password verification error: {e}")))?;
+            if valid {
+                Ok(())
+            } else {
+                Err(AppError::InvalidPassword)
+            }
+        }
+    }
+}
+
+/// What a visitor may do with a drawing: the access checks shared by every
+/// view, decided from either its metadata or its loaded document.
+struct ViewAccess {
+    max_views: Option<u64>,
+    has_valid_api_key: bool,
+    cache_control: String,
+}
+
+/// Refuse expired drawings and wrong passwords (the API key bypasses them), and
+/// pick the `Cache-Control` the drawing's responses get.
+fn check_view(
+    state: &AppState,
+    headers: &HeaderMap,
+    key: Option<&str>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    password_hash: Option<&str>,
+    max_views: Option<u64>,
+) -> Result<ViewAccess, AppError> {
+    if expires_at.is_some_and(|t| t <= chrono::Utc::now()) {
+        return Err(AppError::Gone);
+    }
 
     // Check if the request carries a valid API key (admin bypass)
-    let has_valid_api_key = is_valid_api_key(&headers, &state.api_key);
+    let has_valid_api_key = is_valid_api_key(headers, &state.api_keys);
 
     // Check if drawing is password-protected
     // Admin (valid API key) bypasses the drawing password
-    let password_hash = data.get("_password_hash")
-        .and_then(|v| v.as_str());
-
-    if let Some(hash) = password_hash {
-        if !has_valid_api_key {
-            match &query.key {
-                None => return Err(AppError::PasswordRequired),
-                Some(key) => {
-                    let valid = password::verify_password(key, hash)
-                        .map_err(|e| AppError::Internal(format!("Password verification error: {e}")))?;
-                    if !valid {
-                        return Err(AppError::InvalidPassword);
-                    }
-                }
-            }
-        }
+    if !has_valid_api_key {
+        check_password_hash(password_hash, key)?;
     }
 
-    // Strip internal metadata fields from the response
-    let mut response_data = data;
+    // Followers can't count views, so they leave view-limited drawings to the primary
+    if state.follower && max_views.is_some() && !has_valid_api_key {
+        return Err(AppError::Forbidden("view-limited drawings are only served by the primary".into()));
+    }
 
-    // Extract persistent collab info before stripping
-    let persistent_collab = response_data.get("_persistent_collab")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false);
-    let persistent_version = response_data.get("_persistent_collab_version")
-        .and_then(|v| v.as_u64());
+    // Public drawings may be cached by CDNs and revalidated in the background;
+    // anything gated by a password or the API key, or with limited views, must never
+    // be stored by a shared cache.
+    let cache_control = if password_hash.is_some() || has_valid_api_key || max_views.is_some() {
+        "private, no-store".to_string()
+    } else {
+        format!(
+            "public, max-age={}, stale-while-revalidate={}",
+            state.cache_max_age_secs, state.cache_swr_secs
+        )
+    };
+    Ok(ViewAccess { max_views, has_valid_api_key, cache_control })
+}
 
-    if let Some(obj) = response_data.as_object_mut() {
-        obj.remove("_password_hash");
-        obj.remove("_source_path");
-        obj.remove("_persistent_collab");
-        obj.remove("_persistent_collab_version");
-        obj.remove("_persistent_collab_password_hash");
+/// [`check_view`] from a drawing's metadata, without loading it. Also returns
+/// the metadata, for `Last-Modified`.
+async fn check_view_meta(
+    state: &AppState,
+    id: &str,
+    key: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(ViewAccess, storage::DrawingMeta), AppError> {
+    let access = match state.storage.meta(id).await {
+        Err(AppError::NotFound) if state.storage.is_burned(id).await? => return Err(AppError::Gone),
+        result => result?,
+    };
+    let checked = check_view(
+        state,
+        headers,
+        key,
+        access.meta.expires_at,
+        access.password_hash.as_deref(),
+        access.max_views,
+    )?;
+    Ok((checked, access.meta))
+}
 
-        // Expose persistent collab info (without underscore prefix)
-        if persistent_collab {
-            obj.insert("persistent_collab".to_string(), serde_json::Value::Bool(true));
-            if let Some(v) = persistent_version {
-                obj.insert("persistent_collab_version".to_string(), serde_json::json!(v));
-            }
+/// A drawing loaded for a visitor, access checks passed.
+struct ViewedDrawing {
+    /// The document with internal fields stripped
+    data: serde_json::Value,
+    /// `_source_path` before it was stripped
+    source_path: Option<String>,
+    access: ViewAccess,
+}
+
+impl ViewedDrawing {
+    fn cache_control(&self) -> &str {
+        &self.access.cache_control
+    }
+
+    /// Count the view if `response` sends the drawing: a `304 Not Modified`
+    /// doesn't use one up. Burn after reading: views by visitors (not admins)
+    /// of a view-limited drawing are counted right away, and the drawing
+    /// deleted once its last allowed view has been served.
+    async fn count_sent(&self, state: &AppState, id: &str, response: Response) -> Result<Response, AppError> {
+        if response.status() != StatusCode::OK {
+            return Ok(response);
         }
+        state.metrics.record_view();
+        if self.access.has_valid_api_key || state.follower {
+            return Ok(response);
+        }
+        let Some(max_views) = self.access.max_views else {
+            state.view_counter.increment(id).await;
+            return Ok(response);
+        };
+        let _guard = state.view_locks.lock(id).await;
+        let views = match state.storage.record_view(id).await {
+            // Burned by a concurrent request while we waited
+            Err(AppError::NotFound) => return Err(AppError::Gone),
+            result => result?,
+        };
+        // Views are counted one at a time and the last allowed one burns the
+        // drawing, so later ones find it gone above
+        debug_assert!(views <= max_views, "{views} views of {id}, at most {max_views} allowed");
+        if views >= max_views {
+            state.storage.burn(id).await?;
+            state.drawing_quota.remove();
+            tracing::info!(id = %id, views, "Drawing burned after final view");
+        }
+        Ok(response)
     }
+}
 
-    Ok(Json(response_data))
+/// Load a drawing the way `/api/view/{id}` serves it: expired and burned drawings
+/// are gone and passwords are checked (the API key bypasses them). The view is
+/// counted by [`ViewedDrawing::count_sent`] once the response is built. With
+/// `access` already checked from metadata, the loaded document isn't checked again.
+async fn load_viewed(
+    state: &AppState,
+    id: &str,
+    key: Option<&str>,
+    headers: &HeaderMap,
+    access: Option<ViewAccess>,
+) -> Result<ViewedDrawing, AppError> {
+    let loaded = match &state.asset_base_url {
+        Some(base) => state.storage.load_with_asset_urls(id, base).await,
+        None => state.storage.load(id).await,
+    };
+    let data = match loaded {
+        Err(AppError::NotFound) if state.storage.is_burned(id).await? => return Err(AppError::Gone),
+        result => result?,
+    };
+    let access = match access {
+        Some(access) => access,
+        None => check_view(
+            state,
+            headers,
+            key,
+            storage::expires_at(&data),
+            data.get("_password_hash").and_then(|v| v.as_str()),
+            data.get("_max_views").and_then(|v| v.as_u64()),
+        )?,
+    };
+    Ok(ViewedDrawing {
+        source_path: data.get("_source_path").and_then(|v| v.as_str()).map(String::from),
+        data: storage::public_document(data),
+        access,
+    })
 }
 
-pub async fn delete_drawing(
+/// `GET /api/view/{id}`. A matching `If-None-Match` or `If-Modified-Since`
+/// gets `304 Not Modified`, which doesn't count as a view: only a sent body
+/// uses one up.
+pub async fn get_drawing(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<StatusCode, AppError> {
-    state.storage.delete(&id).await?;
-    tracing::info!(id = %id, "Drawing deleted");
-    Ok(StatusCode::NO_CONTENT)
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let key = query.key.as_deref();
+    let (access, meta) = check_view_meta(&state, &id, key, &headers).await?;
+    let viewed = load_viewed(&state, &id, key, &headers, Some(access)).await?;
+    let body = serde_json::to_vec(&viewed.data)?;
+    let response = json_with_etag(&headers, body, viewed.cache_control(), state.weak_etags)?;
+    let response = with_last_modified(&headers, response, meta.modified_at)?;
+    viewed.count_sent(&state, &id, response).await
 }
 
-pub async fn list_drawings(
+/// `HEAD` of `/api/view/{id}`: the status and headers a `GET` would get,
+/// `ETag` and `Content-Length` included, conditional requests answered alike,
+/// but without the body and without counting a view.
+pub async fn head_drawing(
     State(state): State<AppState>,
-) -> Result<Json<ListResponse>, AppError> {
-    let drawings = state.storage.list().await?;
-    Ok(Json(ListResponse { drawings }))
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (access, meta) = check_view_meta(&state, &id, query.key.as_deref(), &headers).await?;
+    let head = match served_head(&state, &id).await {
+        Err(AppError::NotFound) if state.storage.is_burned(&id).await? => return Err(AppError::Gone),
+        result => result?,
+    };
+    // A body of unknown length keeps axum from replacing the length with 0
+    let body = axum::body::Body::from_stream(futures::stream::empty::<Result<Bytes, std::convert::Infallible>>());
+    let response = (
+        [(header::CONTENT_TYPE, "application/json".to_string()), (header::CONTENT_LENGTH, head.size.to_string())],
+        body,
+    );
+    let response = with_etag(&headers, response.into_response(), &head.hash, &access.cache_control, state.weak_etags)?;
+    with_last_modified(&headers, response, meta.modified_at)
 }
 
-pub async fn list_drawings_public(
-    State(state): State<AppState>,
-) -> Result<Json<PublicListResponse>, AppError> {
-    let drawings = state.storage.list().await?;
-    let public_drawings: Vec<PublicDrawingMeta> = drawings
-        .into_iter()
-        .map(|d| PublicDrawingMeta {
-            id: d.id,
-            created_at: d.created_at,
-            source_path: d.source_path,
-            password_protected: d.password_protected,
-            persistent_collab: d.persistent_collab,
-        })
-        .collect();
-    Ok(Json(PublicListResponse { drawings: public_drawings }))
+/// Add `Last-Modified` to a response and answer a satisfied `If-Modified-Since`
+/// with `304 Not Modified`. `If-None-Match` wins when both are sent, as RFC 9110 says.
+fn with_last_modified(request: &HeaderMap, response: Response, modified_at: chrono::DateTime<chrono::Utc>) -> Result<Response, AppError> {
+    let unchanged = response.status() == StatusCode::OK
+        && !request.contains_key(header::IF_NONE_MATCH)
+        && request
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok())
+            .is_some_and(|since| modified_at.timestamp() <= since.timestamp());
+    let mut response = if unchanged {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        for name in [header::CACHE_CONTROL, header::ETAG] {
+            if let Some(value) = response.headers().get(&name) {
+                not_modified.headers_mut().insert(name, value.clone());
+            }
+        }
+        not_modified
+    } else {
+        response
+    };
+    let last_modified = modified_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    response.headers_mut().insert(
+        header::LAST_MODIFIED,
+        HeaderValue::from_str(&last_modified).map_err(|e| AppError::Internal(e.to_string()))?,
+    );
+    Ok(response)
 }
 
-pub async fn health() -> &'static str {
-    "ok"
+#[derive(Serialize, Deserialize)]
+pub struct SvgResponse {
+    pub svg: String,
 }
 
-// ──────────────────────────────────────────────
-// Lookup by source path (for frontmatter recovery)
-// ──────────────────────────────────────────────
+/// A drawing rendered to SVG, wrapped in JSON for clients that inline it (public).
+/// Access rules and view counting are the same as for `/api/view/{id}`.
+pub async fn get_drawing_svg_json(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    Query(render_query): Query<RenderQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let viewed = load_viewed(&state, &id, query.key.as_deref(), &headers, None).await?;
+    let svg = render::to_svg(&viewed.data, render_query.theme);
+    let body = serde_json::to_vec(&SvgResponse { svg })?;
+    let response = json_with_etag(&headers, body, viewed.cache_control(), state.weak_etags)?;
+    viewed.count_sent(&state, &id, response).await
+}
 
-#[derive(Deserialize)]
-pub struct LookupParams {
-    pub source_path: String,
+/// File name to save a drawing under: the base name of its source path without
+/// the `.md`/`.excalidraw` extensions, else its id. Like `drawing_path`, only
+/// keeps characters that are safe in a file name.
+fn download_filename(source_path: Option<&str>, id: &str) -> String {
+    let safe = |name: &str| -> String {
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '))
+            .collect();
+        name.trim_matches(|c| c == '.' || c == ' ').to_string()
+    };
+    let stem = source_path
+        .and_then(|p| p.rsplit(['/', '\\']).next())
+        .map(|name| name.strip_suffix(".md").unwrap_or(name))
+        .map(|name| name.strip_suffix(".excalidraw").unwrap_or(name))
+        .map(safe)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| safe(id));
+    format!("{stem}.excalidraw")
 }
 
-#[derive(Serialize)]
-pub struct LookupResponse {
-    pub id: String,
-    pub source_path: Option<String>,
-    pub created_at: chrono::DateTime<chrono::Utc>,
-    pub password_protected: bool,
-    pub persistent_collab: bool,
-    pub persistent_collab_version: u64,
+/// `Content-Disposition: attachment` for `filename`, with an RFC 6266
+/// `filename*` for names that aren't plain ASCII.
+fn attachment_disposition(filename: &str) -> Result<HeaderValue, AppError> {
+    let ascii: String = filename.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect();
+    let mut value = format!("attachment; filename=\"{ascii}\"");
+    if ascii != filename {
+        let encoded: String = filename
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+                _ => format!("%{b:02X}"),
+            })
+            .collect();
+        value.push_str(&format!("; filename*=UTF-8''{encoded}"));
+    }
+    HeaderValue::from_str(&value).map_err(|e| AppError::Internal(e.to_string()))
 }
 
-/// Look up a drawing by its source path (vault-relative file path).
-/// Used by the Obsidian plugin to recover published state when frontmatter
-/// is lost (e.g., overwritten by a third-party sync plugin).
-/// Requires API key authentication.
-pub async fn lookup_by_source_path(
+/// The drawing's JSON as a `.excalidraw` file download (public). Access rules
+/// and view counting are the same as for `/api/view/{id}`.
+pub async fn download_drawing(
     State(state): State<AppState>,
-    Query(params): Query<LookupParams>,
-) -> Result<Json<LookupResponse>, AppError> {
-    let meta = state.storage.find_by_source_path(&params.source_path).await?;
-
-    match meta {
-        Some(drawing_meta) => {
-            // Read persistent_collab_version from the drawing JSON if persistent collab is enabled
-            let persistent_collab_version = if drawing_meta.persistent_collab {
-                match state.storage.load(&drawing_meta.id).await {
-                    Ok(data) => data.get("_persistent_collab_version")
-                        .and_then(|v| v.as_u64())
-                        .unwrap_or(0),
-                    Err(_) => 0,
-                }
-            } else {
-                0
-            };
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let viewed = load_viewed(&state, &id, query.key.as_deref(), &headers, None).await?;
+    let body = serde_json::to_vec(&viewed.data)?;
+    let mut response = json_with_etag(&headers, body, viewed.cache_control(), state.weak_etags)?;
+    let filename = download_filename(viewed.source_path.as_deref(), &id);
+    response.headers_mut().insert(header::CONTENT_DISPOSITION, attachment_disposition(&filename)?);
+    viewed.count_sent(&state, &id, response).await
+}
 
-            tracing::info!(
-                source_path = %params.source_path,
-                id = %drawing_meta.id,
-                "Drawing found by source path lookup"
-            );
+/// A drawing as a standalone SVG file, for embedding and printing (public).
+/// Access rules and view counting are the same as for `/api/view/{id}`.
+pub async fn get_drawing_svg(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    Query(render_query): Query<RenderQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let viewed = load_viewed(&state, &id, query.key.as_deref(), &headers, None).await?;
+    let svg = render::to_svg(&viewed.data, render_query.theme);
+    let mut response = body_with_etag(&headers, svg.into(), "image/svg+xml", viewed.cache_control(), state.weak_etags)?;
 
-            Ok(Json(LookupResponse {
-                id: drawing_meta.id,
-                source_path: drawing_meta.source_path,
-                created_at: drawing_meta.created_at,
-                password_protected: drawing_meta.password_protected,
-                persistent_collab: drawing_meta.persistent_collab,
-                persistent_collab_version,
-            }))
-        }
+    let filename: String = id.chars().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')).collect();
+    response.headers_mut().insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("inline; filename=\"{filename}.svg\""))
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+    );
+    viewed.count_sent(&state, &id, response).await
+}
+
+/// A drawing rendered server-side to PNG, for link previews and hover cards (public).
+/// Access rules and view counting are the same as for `/api/view/{id}`. Renders
+/// are cached by the drawing's content hash, width and theme. At most
+/// `RENDER_CONCURRENCY` renders run at once; further requests wait their turn.
+pub async fn get_drawing_png(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    Query(render_query): Query<RenderQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let viewed = load_viewed(&state, &id, query.key.as_deref(), &headers, None).await?;
+    let width = render_query.width.map(render::clamp_width);
+    let key = format!(
+        "{}:{:?}:{:?}",
+        storage::content_hash(&serde_json::to_vec(&viewed.data)?),
+        width,
+        render_query.theme
+    );
+    let png = match state.render_cache.get(&key) {
+        Some(png) => png,
         None => {
-            Err(AppError::NotFound)
+            let data = viewed.data.clone();
+            let permit = state
+                .render_permits
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            let png: Bytes = tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                render::to_png(&data, width, render_query.theme)
+            })
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?
+                .into();
+            state.render_cache.put(&key, png.clone());
+            png
         }
-    }
+    };
+    let response = body_with_etag(&headers, png, "image/png", viewed.cache_control(), state.weak_etags)?;
+    viewed.count_sent(&state, &id, response).await
 }
 
-// ──────────────────────────────────────────────
-// Collab Handlers
-// ──────────────────────────────────────────────
-
-/// Start a new collab session for a drawing (auth required).
-pub async fn start_collab(
-    State(state): State<AppState>,
-    Json(body): Json<StartCollabRequest>,
-) -> Result<(StatusCode, Json<StartCollabResponse>), AppError> {
-    // Verify the drawing exists
-    let drawing_data = state.storage.load(&body.drawing_id).await?;
+/// Whether an `If-None-Match` value matches `etag`. Uses the weak comparison
+/// RFC 9110 prescribes for it: `W/"x"` and `"x"` match each other, so a tag a
+/// proxy weakened (e.g. after compressing the body) still revalidates.
+fn if_none_match(value: &str, etag: &str) -> bool {
+    etag_matches(value, etag, false)
+}
 
-    // Clamp timeout to a reasonable range (5 min – 24 hours)
-    const MIN_TIMEOUT: u64 = 300;
-    const MAX_TIMEOUT: u64 = 86400;
-    let timeout = body.timeout_secs.clamp(MIN_TIMEOUT, MAX_TIMEOUT);
+/// Whether an `If-Match` value matches `etag`. Uses the strong comparison
+/// RFC 9110 prescribes for it: a weak tag on either side never matches, as it
+/// doesn't promise the byte-identical version an update is conditioned on.
+fn if_match(value: &str, etag: &str) -> bool {
+    etag_matches(value, etag, true)
+}
 
-    // Hash the collab password if provided
-    let collab_password_hash = match &body.password {
-        Some(pw) if !pw.is_empty() => {
-            let hash = password::hash_password(pw)
-                .map_err(|e| AppError::Internal(format!("Failed to hash password: {e}")))?;
-            Some(hash)
+/// Whether a list of entity tags (or `*`) contains `etag`. With `strong`,
+/// weak tags match nothing; otherwise `W/` is ignored.
+fn etag_matches(value: &str, etag: &str, strong: bool) -> bool {
+    let opaque = |tag: &str| {
+        let tag = tag.trim();
+        match tag.strip_prefix("W/") {
+            Some(_) if strong => None,
+            Some(weak) => Some(weak.to_string()),
+            None => Some(tag.to_string()),
         }
-        _ => None,
     };
+    let Some(etag) = opaque(etag) else {
+        return false;
+    };
+    value.split(',').any(|tag| tag.trim() == "*" || opaque(tag).as_ref() == Some(&etag))
+}
 
-    let session_id = state
-        .session_manager
-        .create_session(&body.drawing_id, &drawing_data, timeout, collab_password_hash)
-        .await?;
+/// Build a JSON response with a content-hash `ETag`, answering a matching
+/// `If-None-Match` with `304 Not Modified`. With `weak` the tag is marked
+/// `W/`, claiming only semantic equivalence rather than byte equality.
+fn json_with_etag(headers: &HeaderMap, body: Vec<u8>, cache_control: &str, weak: bool) -> Result<Response, AppError> {
+    body_with_etag(headers, body.into(), "application/json", cache_control, weak)
+}
 
-    let base = state.base_url.trim_end_matches('/');
-    let ws_scheme = if base.starts_with("https") {
-        "wss"
+/// [`json_with_etag`] for any content type.
+fn body_with_etag(
+    headers: &HeaderMap,
+    body: Bytes,
+    content_type: &'static str,
+    cache_control: &str,
+    weak: bool,
+) -> Result<Response, AppError> {
+    let hash = storage::content_hash(&body);
+    let response = ([(header::CONTENT_TYPE, content_type)], body).into_response();
+    with_etag(headers, response, &hash, cache_control, weak)
+}
+
+/// Add `Cache-Control` and an `ETag` of `hash` to a response whose body hashes
+/// to it, answering a matching `If-None-Match` with `304 Not Modified`.
+fn with_etag(headers: &HeaderMap, response: Response, hash: &str, cache_control: &str, weak: bool) -> Result<Response, AppError> {
+    let etag = format!("{}\"{hash}\"", if weak { "W/" } else { "" });
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| if_none_match(v, &etag));
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
     } else {
-        "ws"
+        response
     };
-    let host = base
-        .trim_start_matches("https://")
-        .trim_start_matches("http://");
-    let ws_url = format!("{ws_scheme}://{host}/ws/collab/{session_id}");
 
-    tracing::info!(
-        drawing_id = %body.drawing_id,
-        session_id = %session_id,
-        "Collab session started"
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(cache_control).map_err(|e| AppError::Internal(e.to_string()))?,
+    );
+    response_headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).map_err(|e| AppError::Internal(e.to_string()))?,
     );
 
-    Ok((
-        StatusCode::CREATED,
-        Json(StartCollabResponse { session_id, ws_url }),
-    ))
+    Ok(response)
 }
 
-/// Stop a collab session, optionally saving changes (auth required).
-pub async fn stop_collab(
-    State(state): State<AppState>,
-    Json(body): Json<StopCollabRequest>,
-) -> Result<Json<StopCollabResponse>, AppError> {
-    let result = state
-        .session_manager
-        .end_session(&body.session_id, body.save)
-        .await?;
-
-    if let Some((drawing_id, data)) = result {
-        // Preserve the existing source_path and password_hash from the stored drawing
-        // (collab session data doesn't include _source_path or _password_hash)
-        let (source_path, password_hash) = match state.storage.load(&drawing_id).await {
-            Ok(existing) => (
-                existing.get("_source_path")
-                    .and_then(|v| v.as_str())
-                    .map(String::from),
-                existing.get("_password_hash")
-                    .and_then(|v| v.as_str())
-                    .map(String::from),
-            ),
-            Err(_) => (None, None),
-        };
-
-        state
-            .storage
-            .save(&drawing_id, &data, source_path.as_deref(), password_hash.as_deref())
-            .await?;
+/// One entry of the client-side search index.
+#[derive(Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub id: String,
+    pub title: String,
+    pub source_path: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
 
-        tracing::info!(
-            drawing_id = %drawing_id,
-            "Collab session changes saved to storage"
-        );
+/// Human-readable title: the uploaded title, else the source file name without
+/// its extension, else the id.
+fn drawing_title(meta: &DrawingMeta) -> String {
+    if let Some(title) = &meta.title {
+        return title.clone();
     }
-
-    Ok(Json(StopCollabResponse { saved: body.save }))
+    meta.source_path
+        .as_deref()
+        .and_then(|sp| sp.rsplit('/').next())
+        .map(|name| name.trim_end_matches(".md").trim_end_matches(".excalidraw"))
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&meta.id)
+        .to_string()
 }
 
-/// Get collab status for a drawing (public).
-/// The session_id is exposed so the frontend viewer can join via WebSocket.
-/// Security relies on session IDs being full 128-bit UUIDs (unguessable).
-pub async fn collab_status(
-    State(state): State<AppState>,
-    Path(drawing_id): Path<String>,
-) -> Json<CollabStatusResponse> {
-    let (active, session_id, participant_count, password_required, persistent) =
-        state.session_manager.get_session_status_extended(&drawing_id).await;
-
-    if active {
-        Json(CollabStatusResponse {
-            active: true,
-            session_id,
-            participant_count: Some(participant_count),
-            password_required: Some(password_required),
-            persistent,
-        })
-    } else {
-        Json(CollabStatusResponse {
-            active: false,
-            session_id: None,
-            participant_count: None,
-            password_required: None,
-            persistent,
-        })
+fn index_entry(d: DrawingMeta) -> IndexEntry {
+    IndexEntry {
+        title: drawing_title(&d),
+        id: d.id,
+        source_path: d.source_path,
+        created_at: d.created_at,
     }
 }
 
 #[derive(Deserialize)]
-pub struct VerifyCollabPasswordRequest {
-    pub session_id: String,
-    #[serde(default)]
-    pub password: Option<String>,
+pub struct SearchQuery {
+    pub text: String,
 }
 
-#[derive(Serialize)]
-pub struct VerifyCollabPasswordResponse {
-    pub valid: bool,
+#[derive(Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub drawings: Vec<IndexEntry>,
 }
 
-/// Verify a collab session password (public, used before WebSocket connection).
-pub async fn verify_collab_password(
+/// Full-text search over the text elements of drawings (public).
+/// Same visibility as the index: password-protected drawings only for API key holders.
+pub async fn search_drawings(
     State(state): State<AppState>,
-    Json(body): Json<VerifyCollabPasswordRequest>,
-) -> Result<Json<VerifyCollabPasswordResponse>, AppError> {
-    let valid = state
-        .session_manager
-        .verify_session_password(&body.session_id, body.password.as_deref())
-        .await?;
-
-    if !valid {
-        return Err(if body.password.is_some() {
-            AppError::InvalidPassword
-        } else {
-            AppError::PasswordRequired
-        });
+    Query(query): Query<SearchQuery>,
+    headers: HeaderMap,
+) -> Result<Json<SearchResponse>, AppError> {
+    let text = query.text.trim();
+    if text.is_empty() {
+        return Err(AppError::BadRequest("text query must not be empty".into()));
     }
-
-    Ok(Json(VerifyCollabPasswordResponse { valid: true }))
+    let has_valid_api_key = is_valid_api_key(&headers, &state.api_keys);
+    let drawings = state
+        .storage
+        .search_text(text)
+        .await?
+        .into_iter()
+        .filter(|d| !d.is_expired() && (has_valid_api_key || !d.password_protected))
+        .map(index_entry)
+        .collect();
+    Ok(Json(SearchResponse { drawings }))
 }
 
-/// List all active collab sessions (auth required, for admin).
-pub async fn list_collab_sessions(
+/// Compact index of all drawings for client-side search (public).
+/// Password-protected drawings are only included for API key holders.
+/// Clients revalidate with the `ETag`, so the index is only re-downloaded when the catalog changes.
+pub async fn drawing_index(
     State(state): State<AppState>,
-) -> Json<CollabSessionsResponse> {
-    let sessions = state.session_manager.list_sessions().await;
-    Json(CollabSessionsResponse { sessions })
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let has_valid_api_key = is_valid_api_key(&headers, &state.api_keys);
+    let index: Vec<IndexEntry> = state
+        .storage
+        .list()
+        .await?
+        .into_iter()
+        .filter(|d| !d.is_expired() && (has_valid_api_key || !d.password_protected))
+        .map(index_entry)
+        .collect();
+
+    let cache_control = if has_valid_api_key { "private, no-cache" } else { "public, no-cache" };
+    json_with_etag(&headers, serde_json::to_vec(&index)?, cache_control, state.weak_etags)
 }
 
-// ──────────────────────────────────────────────
-// Persistent Collab Handlers
-// ──────────────────────────────────────────────
+/// Image types served under their own MIME type by [`get_asset`]. SVG is left
+/// out since it can carry scripts.
+const ASSET_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+    "image/x-icon",
+];
 
-/// Enable persistent collab for a drawing (auth required).
-/// Creates a persistent session that auto-saves and survives server restarts.
-pub async fn enable_persistent_collab(
+/// Serve one of a drawing's extracted embedded files by content hash (public).
+/// Assets follow the drawing's access rules like previews, and only files the
+/// drawing references are served. The uploader picks the MIME type, so anything
+/// but a known raster image type is sent as a download, and the response is
+/// sandboxed against being rendered as a page.
+pub async fn get_asset(
     State(state): State<AppState>,
-    Json(body): Json<EnablePersistentCollabRequest>,
-) -> Result<(StatusCode, Json<EnablePersistentCollabResponse>), AppError> {
-    // 1. Verify drawing exists
-    let drawing_data = state.storage.load(&body.drawing_id).await?;
-
-    // 2. Hash password if provided
-    let password_hash = match &body.password {
-        Some(pw) if !pw.is_empty() => {
-            let hash = password::hash_password(pw)
-                .map_err(|e| AppError::Internal(format!("Failed to hash password: {e}")))?;
-            Some(hash)
+    Path((id, hash)): Path<(String, String)>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let data = state.storage.load_stored(&id).await?;
+    if storage::expires_at(&data).is_some_and(|t| t <= chrono::Utc::now()) {
+        return Err(AppError::Gone);
+    }
+    let has_valid_api_key = is_valid_api_key(&headers, &state.api_keys);
+    if !has_valid_api_key {
+        check_drawing_password(&data, query.key.as_deref())?;
+        if data.get("_max_views").is_some() {
+            return Err(AppError::NotFound);
         }
-        _ => None,
+    }
+    if !storage::references_asset(&data, &hash) {
+        return Err(AppError::NotFound);
+    }
+
+    let (mime, bytes) = state.storage.read_asset(&hash).await?;
+    let content_type = if ASSET_IMAGE_TYPES.contains(&mime.to_ascii_lowercase().as_str()) {
+        mime
+    } else {
+        "application/octet-stream".to_string()
     };
+    let cache_control = if has_valid_api_key || data.get("_password_hash").is_some() {
+        "private, no-store".to_string()
+    } else {
+        format!(
+            "public, max-age={}, stale-while-revalidate={}",
+            state.cache_max_age_secs, state.cache_swr_secs
+        )
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, cache_control),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+        ],
+        bytes,
+    )
+        .into_response())
+}
 
-    // 3. Update drawing JSON: set _persistent_collab = true, store password hash
-    let mut data = drawing_data.clone();
-    if let Some(obj) = data.as_object_mut() {
-        obj.insert("_persistent_collab".to_string(), serde_json::Value::Bool(true));
-        if let Some(ph) = &password_hash {
-            obj.insert(
-                "_persistent_collab_password_hash".to_string(),
-                serde_json::Value::String(ph.clone()),
-            );
-        } else {
-            obj.remove("_persistent_collab_password_hash");
-        }
-        if !obj.contains_key("_persistent_collab_version") {
-            obj.insert("_persistent_collab_version".to_string(), serde_json::json!(0));
+/// Serve the client-rendered PNG preview of a drawing (public), e.g. for link
+/// unfurls and galleries. Previews follow the drawing's access rules: gone once
+/// expired, behind its password, and not shown for burn-after-reading drawings.
+pub async fn get_preview(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let data = state.storage.load(&id).await?;
+    if storage::expires_at(&data).is_some_and(|t| t <= chrono::Utc::now()) {
+        return Err(AppError::Gone);
+    }
+    let has_valid_api_key = is_valid_api_key(&headers, &state.api_keys);
+    if !has_valid_api_key {
+        check_drawing_password(&data, query.key.as_deref())?;
+        if data.get("_max_views").is_some() {
+            return Err(AppError::NotFound);
         }
     }
 
-    // 4. Save updated drawing (preserve source_path and password_hash)
-    let source_path = data.get("_source_path").and_then(|v| v.as_str()).map(String::from);
-    let pw_hash = data.get("_password_hash").and_then(|v| v.as_str()).map(String::from);
-    state.storage.save(&body.drawing_id, &data, source_path.as_deref(), pw_hash.as_deref()).await?;
-
-    // 5. Register in session manager
-    state.session_manager.register_persistent_drawing(body.drawing_id.clone()).await;
-
-    // 6. Create persistent session (if not already active)
-    let session_id = match state.session_manager.get_session_for_drawing(&body.drawing_id).await {
-        Some(existing_id) => existing_id,
-        None => {
-            state.session_manager.create_persistent_session(
-                &body.drawing_id, &drawing_data, password_hash,
-            ).await?
-        }
+    let png = state.storage.read_preview(&id).await?;
+    let cache_control = if has_valid_api_key || data.get("_password_hash").is_some() {
+        "private, no-store".to_string()
+    } else {
+        format!(
+            "public, max-age={}, stale-while-revalidate={}",
+            state.cache_max_age_secs, state.cache_swr_secs
+        )
     };
+    Ok(([(header::CONTENT_TYPE, "image/png".to_string()), (header::CACHE_CONTROL, cache_control)], png).into_response())
+}
 
-    tracing::info!(drawing_id = %body.drawing_id, "Persistent collab enabled");
+/// Redirect a slug link to the drawing's viewer page (public).
+pub async fn view_by_slug(
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+) -> Result<Redirect, AppError> {
+    let drawing = state.storage.find_by_slug(&slug).await?.ok_or(AppError::NotFound)?;
+    Ok(Redirect::temporary(&format!("/d/{}", drawing.id)))
+}
 
-    Ok((StatusCode::OK, Json(EnablePersistentCollabResponse {
-        enabled: true,
-        drawing_id: body.drawing_id,
-        session_id,
-    })))
+#[derive(Serialize)]
+struct BulkLine {
+    id: String,
+    drawing: serde_json::Value,
 }
 
-/// Disable persistent collab for a drawing (auth required).
-/// Ends any active session (saving changes) and removes persistent collab metadata.
-pub async fn disable_persistent_collab(
+/// Stream every matching drawing as one NDJSON line `{"id", "drawing"}` (public).
+/// Accepts the list filters. Drawings are loaded one at a time as the stream is read.
+/// Password-protected and view-limited drawings are only included for API key holders.
+pub async fn bulk_view(
     State(state): State<AppState>,
-    Json(body): Json<DisablePersistentCollabRequest>,
-) -> Result<Json<DisablePersistentCollabResponse>, AppError> {
-    // 1. End active session if exists (save to disk)
-    if let Some(session_id) = state.session_manager.get_session_for_drawing(&body.drawing_id).await {
-        let result = state.session_manager.end_session(&session_id, true).await;
-        // Save session data to disk if end_session returned it
-        if let Ok(Some((drawing_id, data))) = result {
-            let (source_path, password_hash) = match state.storage.load(&drawing_id).await {
-                Ok(existing) => (
-                    existing.get("_source_path")
-                        .and_then(|v| v.as_str())
-                        .map(String::from),
-                    existing.get("_password_hash")
-                        .and_then(|v| v.as_str())
-                        .map(String::from),
-                ),
-                Err(_) => (None, None),
+    Query(filter): Query<DrawingFilter>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let has_valid_api_key = is_valid_api_key(&headers, &state.api_keys);
+    let drawings: Vec<DrawingMeta> = state
+        .storage
+        .list()
+        .await?
+        .into_iter()
+        .filter(|d| filter.matches(d) && !d.is_expired() && (has_valid_api_key || !d.password_protected))
+        .collect();
+
+    let lines = futures::stream::iter(drawings).filter_map(move |meta| {
+        let state = state.clone();
+        async move {
+            let loaded = match &state.asset_base_url {
+                Some(base) => state.storage.load_with_asset_urls(&meta.id, base).await,
+                None => state.storage.load(&meta.id).await,
             };
-            let _ = state.storage.save(
-                &drawing_id, &data, source_path.as_deref(), password_hash.as_deref(),
-            ).await;
+            let data = match loaded {
+                Ok(data) => data,
+                Err(AppError::NotFound) => return None,
+                Err(e) => {
+                    tracing::warn!(id = %meta.id, error = %e, "Skipping drawing in bulk view");
+                    return None;
+                }
+            };
+            // Serving a view-limited drawing here would bypass its view count
+            if !has_valid_api_key && data.get("_max_views").is_some() {
+                return None;
+            }
+            let line = BulkLine { id: meta.id, drawing: storage::public_document(data) };
+            let mut bytes = serde_json::to_vec(&line).ok()?;
+            bytes.push(b'\n');
+            Some(Ok::<_, std::convert::Infallible>(axum::body::Bytes::from(bytes)))
         }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson"),
+            (header::CACHE_CONTROL, "private, no-store"),
+        ],
+        axum::body::Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// Lightweight structural summary of a drawing (public).
+/// Lets clients decide whether to fetch a large document before downloading it.
+/// Password-protected drawings require the same `?key=` (or API key) as the full view.
+pub async fn get_drawing_summary(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Json<DrawingSummary>, AppError> {
+    let access = state.storage.meta(&id).await?;
+    if access.meta.is_expired() {
+        return Err(AppError::Gone);
+    }
+    if !is_valid_api_key(&headers, &state.api_keys) {
+        check_password_hash(access.password_hash.as_deref(), query.key.as_deref())?;
     }
 
-    // 2. Remove _persistent_collab from drawing JSON
-    let data = state.storage.load(&body.drawing_id).await?;
-    let mut updated = data;
-    if let Some(obj) = updated.as_object_mut() {
-        obj.insert("_persistent_collab".to_string(), serde_json::Value::Bool(false));
-        obj.remove("_persistent_collab_password_hash");
-        obj.remove("_persistent_collab_version");
+    Ok(Json(state.storage.summary(&id).await?))
+}
+
+/// Public metadata of one drawing, read without loading its elements, e.g. for
+/// hover previews. Password-protected drawings require the same `?key=` (or API
+/// key) as the full view.
+pub async fn get_drawing_meta_public(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Json<PublicDrawingMeta>, AppError> {
+    let access = state.storage.meta(&id).await?;
+    if access.meta.is_expired() {
+        return Err(AppError::Gone);
     }
-    let source_path = updated.get("_source_path").and_then(|v| v.as_str()).map(String::from);
-    let pw_hash = updated.get("_password_hash").and_then(|v| v.as_str()).map(String::from);
-    state.storage.save(&body.drawing_id, &updated, source_path.as_deref(), pw_hash.as_deref()).await?;
+    if !is_valid_api_key(&headers, &state.api_keys) {
+        check_password_hash(access.password_hash.as_deref(), query.key.as_deref())?;
+    }
+    Ok(Json(access.meta.into()))
+}
 
-    // 3. Unregister from session manager
-    state.session_manager.unregister_persistent_drawing(&body.drawing_id).await;
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    /// `prepare` issues a confirm token instead of deleting
+    #[serde(default)]
+    pub phase: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
 
-    tracing::info!(drawing_id = %body.drawing_id, "Persistent collab disabled");
+#[derive(Serialize)]
+pub struct PrepareDeleteResponse {
+    pub id: String,
+    pub token: String,
+    pub expires_in_secs: u64,
+}
 
-    Ok(Json(DisablePersistentCollabResponse {
-        disabled: true,
-        drawing_id: body.drawing_id,
-    }))
+/// Metadata of a single drawing (auth required). `views` includes views not yet flushed to disk.
+pub async fn get_drawing_meta(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DrawingMeta>, AppError> {
+    let mut meta = state.storage.get_meta(&id).await?;
+    meta.views += state.view_counter.pending(&id).await;
+    Ok(Json(meta))
 }
 
-/// Activate a persistent collab session on demand (public, no auth).
-/// Creates the session if it doesn't exist yet (lazy activation).
-/// Returns the session_id for WebSocket connection.
-pub async fn activate_persistent_collab(
+/// Most ids accepted by one `meta-batch` request.
+const MAX_META_BATCH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct MetaBatchRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct MetaBatchResponse {
+    /// Metadata of every requested drawing that exists
+    pub drawings: std::collections::BTreeMap<String, DrawingMeta>,
+    /// Requested ids with no drawing
+    pub missing: Vec<String>,
+}
+
+/// Metadata of several drawings in one call (auth required), as returned by
+/// `/api/drawings/{id}/meta` for each.
+pub async fn get_drawing_meta_batch(
     State(state): State<AppState>,
-    Path(drawing_id): Path<String>,
-) -> Result<Json<ActivatePersistentCollabResponse>, AppError> {
-    // 1. Check if session already exists
-    if let Some(session_id) = state.session_manager.get_session_for_drawing(&drawing_id).await {
-        let (active, _, _, pw_req, _) =
-            state.session_manager.get_session_status_extended(&drawing_id).await;
-        if active {
-            return Ok(Json(ActivatePersistentCollabResponse {
-                session_id,
-                password_required: pw_req,
-            }));
+    Json(body): Json<MetaBatchRequest>,
+) -> Result<Json<MetaBatchResponse>, AppError> {
+    if body.ids.len() > MAX_META_BATCH {
+        return Err(AppError::BadRequest(format!("At most {MAX_META_BATCH} ids per batch")));
+    }
+    let mut response = MetaBatchResponse { drawings: Default::default(), missing: Vec::new() };
+    for id in body.ids {
+        if response.drawings.contains_key(&id) || response.missing.contains(&id) {
+            continue;
+        }
+        match state.storage.get_meta(&id).await {
+            Ok(mut meta) => {
+                meta.views += state.view_counter.pending(&id).await;
+                response.drawings.insert(id, meta);
+            }
+            Err(AppError::NotFound) => response.missing.push(id),
+            Err(e) => return Err(e),
         }
     }
+    Ok(Json(response))
+}
 
-    // 2. Verify drawing has persistent collab enabled
-    let has_persistent = state.storage.get_persistent_collab_status(&drawing_id).await?;
-    if !has_persistent {
-        return Err(AppError::NotFound);
+#[derive(Serialize, Deserialize)]
+pub struct HashResponse {
+    pub hash: String,
+}
+
+/// Content hash of a drawing as served by `/api/view/{id}`, i.e. its `ETag`
+/// without the quotes (auth required). Doesn't count as a view. Unlike the
+/// index `content_hash` of `/meta` and `include_hash` lists, it covers the
+/// document exactly as served, collab fields and asset URLs included.
+pub async fn get_drawing_hash(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<HashResponse>, AppError> {
+    Ok(Json(HashResponse { hash: served_head(&state, &id).await?.hash }))
+}
+
+/// Size and content hash of the body `/api/view/{id}` would serve for a drawing.
+async fn served_head(state: &AppState, id: &str) -> Result<storage::DrawingHead, AppError> {
+    match &state.asset_base_url {
+        Some(base) => state.storage.head_with_asset_urls(id, base).await,
+        None => state.storage.head(id).await,
     }
+}
 
-    // 3. Load drawing and create persistent session
-    let drawing_data = state.storage.load(&drawing_id).await?;
-    let password_hash = drawing_data.get("_persistent_collab_password_hash")
-        .and_then(|v| v.as_str())
-        .map(String::from);
+/// Replace only the `appState` (zoom, scroll, theme, ...) of a stored drawing,
+/// leaving elements and files untouched (auth required).
+pub async fn update_app_state(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(app_state): Json<serde_json::Value>,
+) -> Result<StatusCode, AppError> {
+    if !app_state.is_object() {
+        return Err(AppError::BadRequest("appState must be a JSON object".into()));
+    }
 
-    // Handle race condition: create_persistent_session may fail with SessionAlreadyExists
-    // if two visitors arrive simultaneously
-    let session_id = match state.session_manager.create_persistent_session(
-        &drawing_id, &drawing_data, password_hash.clone(),
-    ).await {
-        Ok(id) => id,
-        Err(AppError::SessionAlreadyExists) => {
-            // Another request created the session concurrently — return the existing one
-            state.session_manager.get_session_for_drawing(&drawing_id).await
-                .ok_or(AppError::Internal("Race condition: session disappeared".to_string()))?
-        }
-        Err(e) => return Err(e),
+    // Without the lock a concurrent upload landing between the load and the
+    // save would be overwritten with the old elements
+    let _update_guard = state.upload_locks.lock(&format!("update:{id}")).await;
+    let mut data = state.storage.load(&id).await?;
+    let source_path = data.get("_source_path").and_then(|v| v.as_str()).map(String::from);
+    let password_hash = data.get("_password_hash").and_then(|v| v.as_str()).map(String::from);
+    if let Some(obj) = data.as_object_mut() {
+        obj.insert("appState".to_string(), app_state);
+    }
+    state
+        .storage
+        .save(&id, &data, source_path.as_deref(), password_hash.as_deref())
+        .await?;
+
+    tracing::debug!(id = %id, "Drawing appState updated");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ExtendQuery {
+    /// Seconds to add; defaults to the server's extension step
+    #[serde(default)]
+    pub secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExtendResponse {
+    pub id: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Push a TTL drawing's expiry forward (auth required). Extends from the current
+/// expiry, or from now if it has already passed, and never beyond the server's
+/// maximum TTL from now.
+pub async fn extend_drawing(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ExtendQuery>,
+) -> Result<Json<ExtendResponse>, AppError> {
+    // Taken by the expiry sweep too, so it can't delete a drawing being extended
+    let _update_guard = state.upload_locks.lock(&format!("update:{id}")).await;
+    let mut data = state.storage.load(&id).await?;
+    let Some(current) = storage::expires_at(&data) else {
+        return Err(AppError::BadRequest("drawing has no expiry to extend".into()));
     };
 
-    tracing::info!(
-        drawing_id = %drawing_id,
-        session_id = %session_id,
-        "Persistent collab session activated on demand"
-    );
+    let now = chrono::Utc::now();
+    let secs = query.secs.unwrap_or(state.ttl_extension_secs).min(state.max_ttl_secs);
+    let expires_at = expiry_after(current.max(now), secs).min(expiry_after(now, state.max_ttl_secs));
 
-    Ok(Json(ActivatePersistentCollabResponse {
-        session_id,
-        password_required: password_hash.is_some(),
-    }))
+    let source_path = data.get("_source_path").and_then(|v| v.as_str()).map(String::from);
+    let password_hash = data.get("_password_hash").and_then(|v| v.as_str()).map(String::from);
+    if let Some(obj) = data.as_object_mut() {
+        obj.insert("_expires_at".to_string(), serde_json::json!(expires_at.to_rfc3339()));
+    }
+    state
+        .storage
+        .save(&id, &data, source_path.as_deref(), password_hash.as_deref())
+        .await?;
+
+    tracing::info!(id = %id, expires_at = %expires_at, "Drawing expiry extended");
+    Ok(Json(ExtendResponse { id, expires_at }))
 }
 
-// ──────────────────────────────────────────────
-// ICE Config — returns STUN/TURN server configuration for WebRTC
-// ──────────────────────────────────────────────
+#[derive(Serialize)]
+pub struct VersionsResponse {
+    pub id: String,
+    /// Newest first; at most `HISTORY_DEPTH` are kept
+    pub versions: Vec<DrawingVersion>,
+}
 
-/// Returns WebRTC ICE server configuration.
-/// If STUN_URL is configured, includes a STUN server entry.
-/// If TURN_URL and TURN_SECRET are configured, generates HMAC-SHA1 time-limited
-/// credentials (RFC 5766 / coturn compatible) valid for 1 hour.
-pub async fn ice_config_handler(
+/// Earlier versions of a drawing (public). Password-protected drawings require
+/// the same `?key=` (or API key) as the full view.
+pub async fn drawing_history(
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, AppError> {
-    let mut ice_servers: Vec<serde_json::Value> = Vec::new();
+    Path(id): Path<String>,
+    Query(query): Query<ViewQuery>,
+    headers: HeaderMap,
+) -> Result<Json<VersionsResponse>, AppError> {
+    let meta = state.storage.get_meta(&id).await?;
+    if meta.is_expired() {
+        return Err(AppError::Gone);
+    }
+    if meta.password_protected && !is_valid_api_key(&headers, &state.api_keys) {
+        let data = state.storage.load(&id).await?;
+        check_drawing_password(&data, query.key.as_deref())?;
+    }
 
-    if let Some(stun_url) = &state.stun_url {
-        ice_servers.push(serde_json::json!({ "urls": stun_url }));
+    let versions = state.storage.history(&id).await?;
+    Ok(Json(VersionsResponse { id, versions }))
+}
+
+#[derive(Serialize)]
+pub struct RollbackResponse {
+    pub id: String,
+    /// The version whose content is now current
+    pub restored_version: u64,
+    /// Problems worth telling the user about that didn't stop the rollback
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Make an earlier version's content current again (auth required). The content
+/// it replaces is kept as a new version, so the versions in between stay
+/// available. Only the content is restored: password, source path, title and
+/// expiry stay as they are now. The content has to pass the checks an upload
+/// does, under the current settings, and counts against `MAX_TOTAL_MB` like one.
+pub async fn rollback_drawing(
+    State(state): State<AppState>,
+    Path((id, version)): Path<(String, u64)>,
+) -> Result<Json<RollbackResponse>, AppError> {
+    let _update_guard = state.upload_locks.lock(&format!("update:{id}")).await;
+    let current = state.storage.load(&id).await?;
+    let mut restored = state.storage.load_version(&id, version).await?;
+    // The version passed the checks of its day, which may have been looser
+    storage::strip_internal_fields(&mut restored);
+    let warnings = check_document(&state, &mut restored)?;
+    normalize_document(&state, &mut restored);
+    if let (Some(obj), Some(internal)) = (restored.as_object_mut(), current.as_object()) {
+        obj.extend(internal.iter().filter(|(key, _)| key.starts_with('_')).map(|(k, v)| (k.clone(), v.clone())));
     }
 
-    if let (Some(turn_url), Some(turn_secret)) = (&state.turn_url, &state.turn_secret) {
-        // Generate HMAC time-limited credentials (coturn compatible)
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            + 3600; // Valid for 1 hour
+    let source_path = current.get("_source_path").and_then(|v| v.as_str());
+    let password_hash = current.get("_password_hash").and_then(|v| v.as_str());
+    state.storage.save(&id, &restored, source_path, password_hash).await?;
 
-        let username = format!("{}:excalishare", timestamp);
+    tracing::info!(id = %id, version, "Drawing rolled back");
+    Ok(Json(RollbackResponse { id, restored_version: version, warnings }))
+}
 
-        // HMAC-SHA1 of username with the shared secret
-        type HmacSha1 = Hmac<Sha1>;
-        let mut mac = HmacSha1::new_from_slice(turn_secret.as_bytes())
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-        mac.update(username.as_bytes());
-        let credential = general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+/// Delete a drawing (auth required).
+/// `?phase=prepare` returns a single-use confirm token for the drawing; the delete
+/// itself then needs `?token=<token>` when confirmation is required (or a token is sent).
+pub async fn delete_drawing(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<Response, AppError> {
+    match query.phase.as_deref() {
+        Some("prepare") => {
+            if !state.storage.exists(&id).await? {
+                return Err(AppError::NotFound);
+            }
+            let token = state.confirm_tokens.issue(&id).await;
+            return Ok(Json(PrepareDeleteResponse {
+                id,
+                token,
+                expires_in_secs: CONFIRM_TOKEN_TTL_SECS,
+            })
+            .into_response());
+        }
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "Invalid phase '{other}'. Expected 'prepare'."
+            )));
+        }
+        None => {}
+    }
 
-        ice_servers.push(serde_json::json!({
-            "urls": turn_url,
-            "username": username,
-            "credential": credential,
-            "credentialType": "password"
-        }));
+    if let Some(token) = &query.token {
+        if !state.confirm_tokens.consume(&id, token).await {
+            return Err(AppError::BadRequest(
+                "Invalid or expired confirm token. Request a new one with ?phase=prepare.".into(),
+            ));
+        }
+    } else if state.require_delete_confirmation {
+        return Err(AppError::BadRequest(
+            "Deletion requires a confirm token. Request one with ?phase=prepare.".into(),
+        ));
     }
 
-    Ok(axum::Json(serde_json::json!({ "iceServers": ice_servers })))
+    let source_path = match &state.webhooks {
+        Some(_) => state.storage.load(&id).await.ok().and_then(|data| {
+            data.get("_source_path").and_then(|v| v.as_str()).map(String::from)
+        }),
+        None => None,
+    };
+
+    if state.soft_delete {
+        state.storage.trash(&id).await?;
+        tracing::info!(id = %id, "Drawing moved to trash");
+    } else {
+        state.storage.delete(&id).await?;
+        tracing::info!(id = %id, "Drawing deleted");
+    }
+    state.drawing_quota.remove();
+    state.metrics.record_delete();
+
+    if let Some(webhooks) = &state.webhooks {
+        webhooks.notify(WebhookEvent::new("delete", &id, source_path.as_deref()));
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Most ids accepted by one bulk delete.
+const MAX_BULK_DELETE: usize = 500;
+
+#[derive(Deserialize)]
+pub struct BulkDeleteRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkDeleteStatus {
+    Deleted,
+    NotFound,
+    Error,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BulkDeleteResult {
+    pub id: String,
+    pub status: BulkDeleteStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BulkDeleteResponse {
+    pub results: Vec<BulkDeleteResult>,
+}
+
+/// Delete several drawings at once (auth required), reporting each id's
+/// outcome; one failure doesn't stop the others. Refused while deletes need a
+/// confirm token, which is issued per drawing.
+pub async fn bulk_delete_drawings(
+    State(state): State<AppState>,
+    Json(body): Json<BulkDeleteRequest>,
+) -> Result<Json<BulkDeleteResponse>, AppError> {
+    if state.require_delete_confirmation {
+        return Err(AppError::BadRequest(
+            "Bulk deletion is unavailable while deletes require a confirm token.".into(),
+        ));
+    }
+    if body.ids.len() > MAX_BULK_DELETE {
+        return Err(AppError::BadRequest(format!("At most {MAX_BULK_DELETE} ids per bulk delete")));
+    }
+    let mut ids = body.ids;
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(id.clone()));
+
+    // Source paths for the webhooks, read before they're gone
+    let mut source_paths = std::collections::HashMap::new();
+    if state.webhooks.is_some() {
+        for id in &ids {
+            if let Ok(meta) = state.storage.get_meta(id).await {
+                source_paths.insert(id.clone(), meta.source_path);
+            }
+        }
+    }
+
+    let outcomes = if state.soft_delete {
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for id in &ids {
+            outcomes.push(state.storage.trash(id).await);
+        }
+        outcomes
+    } else {
+        state.storage.delete_many(&ids).await
+    };
+    let mut results = Vec::with_capacity(ids.len());
+    for (id, outcome) in ids.into_iter().zip(outcomes) {
+        let (status, error) = match outcome {
+            Ok(()) => {
+                state.drawing_quota.remove();
+                state.metrics.record_delete();
+                if let Some(webhooks) = &state.webhooks {
+                    let source_path = source_paths.get(&id).cloned().flatten();
+                    webhooks.notify(WebhookEvent::new("delete", &id, source_path.as_deref()));
+                }
+                (BulkDeleteStatus::Deleted, None)
+            }
+            Err(AppError::NotFound) => (BulkDeleteStatus::NotFound, None),
+            Err(e) => {
+                tracing::warn!(id = %id, error = %e, "Bulk delete failed for drawing");
+                (BulkDeleteStatus::Error, Some(e.to_string()))
+            }
+        };
+        results.push(BulkDeleteResult { id, status, error });
+    }
+    let deleted = results.iter().filter(|r| r.status == BulkDeleteStatus::Deleted).count();
+    tracing::info!(requested = results.len(), deleted, "Bulk delete");
+    Ok(Json(BulkDeleteResponse { results }))
+}
+
+#[derive(Deserialize)]
+pub struct DeletedQuery {
+    /// Only drawings deleted after this time
+    #[serde(default)]
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeletedResponse {
+    pub drawings: Vec<Tombstone>,
+}
+
+/// Drawings deleted or burned after `since`, oldest first (auth required).
+/// Lets sync clients mirror deletions and admins find what vanished.
+pub async fn list_deleted(
+    State(state): State<AppState>,
+    Query(query): Query<DeletedQuery>,
+) -> Result<Json<DeletedResponse>, AppError> {
+    let drawings = state.storage.deleted_since(query.since).await?;
+    Ok(Json(DeletedResponse { drawings }))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TrashResponse {
+    pub drawings: Vec<TrashedDrawing>,
+}
+
+/// Drawings in the trash, most recently deleted first (auth required).
+pub async fn list_trash(State(state): State<AppState>) -> Result<Json<TrashResponse>, AppError> {
+    let drawings = state.storage.list_trash().await?;
+    Ok(Json(TrashResponse { drawings }))
+}
+
+/// Bring a drawing back from the trash (auth required), with the content and
+/// metadata it had when deleted. `409` when its id has been taken since.
+pub async fn restore_drawing(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DrawingMeta>, AppError> {
+    let _update_guard = state.upload_locks.lock(&format!("update:{id}")).await;
+    let slot = state.drawing_quota.add()?;
+    let meta = state.storage.restore(&id).await?;
+    slot.commit();
+    tracing::info!(id = %id, "Drawing restored from trash");
+    if let Some(webhooks) = &state.webhooks {
+        webhooks.notify(WebhookEvent::new("restore", &id, meta.source_path.as_deref()));
+    }
+    Ok(Json(meta))
+}
+
+#[derive(Deserialize, Default)]
+pub struct ListOptions {
+    /// Include each drawing's index `content_hash`, so clients can tell which
+    /// changed. It is the hash `/meta` reports, not the `ETag` of the view
+    #[serde(default)]
+    pub include_hash: bool,
+}
+
+/// Page size of the list endpoints when `limit` isn't given.
+const DEFAULT_LIST_LIMIT: usize = 50;
+/// Largest page the list endpoints serve.
+const MAX_LIST_LIMIT: usize = 500;
+
+/// `?limit=&offset=&sort=created|modified&order=asc|desc` for the list endpoints.
+#[derive(Deserialize)]
+pub struct Pagination {
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default = "default_list_limit")]
+    pub limit: usize,
+    #[serde(default)]
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub order: Option<String>,
+}
+
+fn default_list_limit() -> usize {
+    DEFAULT_LIST_LIMIT
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self { offset: 0, limit: DEFAULT_LIST_LIMIT, sort: None, order: None }
+    }
+}
+
+impl Pagination {
+    fn page(&self) -> Result<storage::Page, AppError> {
+        let sort = storage::ListSort {
+            field: self.sort.as_deref().map(str::parse).transpose()?.unwrap_or_default(),
+            order: self.order.as_deref().map(str::parse).transpose()?.unwrap_or_default(),
+        };
+        Ok(storage::Page { offset: self.offset, limit: self.limit.clamp(1, MAX_LIST_LIMIT), sort })
+    }
+}
+
+/// Offset of the page after one that started at `offset` with `len` drawings.
+fn next_offset(offset: usize, len: usize, total: usize) -> Option<usize> {
+    let next = offset.saturating_add(len);
+    (len > 0 && next < total).then_some(next)
+}
+
+/// `io::Write` sink that only counts bytes, to size a response without buffering it.
+#[derive(Default)]
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Refuse a list response whose JSON would exceed `--max-list-response-bytes`.
+fn check_list_size(response: &impl Serialize, max_bytes: usize) -> Result<(), AppError> {
+    let mut counter = ByteCounter::default();
+    serde_json::to_writer(&mut counter, response)?;
+    if counter.0 > max_bytes {
+        return Err(AppError::ResponseTooLarge(format!(
+            "the list would be {} bytes, over the {max_bytes} byte limit; request fewer drawings with `limit` and `offset`",
+            counter.0
+        )));
+    }
+    Ok(())
+}
+
+/// List drawings (auth required). The `ETag` identifies the listed page by its
+/// drawings' ids and modification times, so a poller sending `If-None-Match`
+/// gets `304 Not Modified` until a drawing is added, changed or removed. It is
+/// weak: two bodies with the same tag may still differ in e.g. view counts.
+pub async fn list_drawings(
+    State(state): State<AppState>,
+    Query(filter): Query<DrawingFilter>,
+    Query(options): Query<ListOptions>,
+    Query(pagination): Query<Pagination>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (drawings, total) = state.storage.list_page(&filter, pagination.page()?).await?;
+    let etag = collection_etag(&drawings, total, options.include_hash);
+    let etag_headers = [(header::ETAG, etag.clone()), (header::CACHE_CONTROL, "private, no-cache".to_string())];
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| if_none_match(v, &etag));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, etag_headers).into_response());
+    }
+
+    let next_offset = next_offset(pagination.offset, drawings.len(), total);
+    let drawings = drawings
+        .into_iter()
+        .map(|mut d| {
+            if !options.include_hash {
+                d.content_hash = None;
+            }
+            d
+        })
+        .collect();
+    let response = ListResponse { drawings, total, next_offset };
+    check_list_size(&response, state.max_list_response_bytes)?;
+    Ok((etag_headers, Json(response)).into_response())
+}
+
+/// Weak tag of a listed page: its drawings' ids and modification times, the
+/// total and whether hashes are included.
+fn collection_etag(drawings: &[DrawingMeta], total: usize, include_hash: bool) -> String {
+    let mut listing = format!("{total} {include_hash}\n");
+    for d in drawings {
+        listing.push_str(&format!("{} {}\n", d.id, d.modified_at.to_rfc3339()));
+    }
+    format!("W/\"{}\"", storage::content_hash(listing.as_bytes()))
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub id_conflict: IdConflict,
+    /// Shorthand for `id_conflict`: `true` is `overwrite`, `false` is `skip`
+    #[serde(default)]
+    pub overwrite: Option<bool>,
+    /// Store every entry or none of them
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+/// Restore drawings from an export archive (auth required). The request body is
+/// the ZIP, or a multipart form carrying it as a file; `?id_conflict=skip|overwrite|rename`
+/// (or `?overwrite=true|false`) controls id collisions and `?atomic=true` makes
+/// the import all-or-nothing.
+pub async fn import_drawings(
+    State(state): State<AppState>,
+    Query(mut query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ArchiveImportSummary>, AppError> {
+    if let Some(overwrite) = query.overwrite {
+        query.id_conflict = if overwrite { IdConflict::Overwrite } else { IdConflict::Skip };
+    }
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let archive = if content_type.starts_with("multipart/form-data") {
+        import::multipart_file(content_type, &body)?
+    } else {
+        body.to_vec()
+    };
+    let summary = import::import_archive(&state.storage, archive, query.id_conflict, query.atomic, state.import_limits).await;
+    // Restores aren't capped, but the drawings they add count from now on
+    state.drawing_quota.recount(&state.storage).await?;
+    let summary = summary?;
+    tracing::info!(
+        imported = summary.imported.len(),
+        renamed = summary.renamed.len(),
+        skipped = summary.skipped.len(),
+        failed = summary.failed.len(),
+        policy = ?query.id_conflict,
+        atomic = query.atomic,
+        "Archive imported"
+    );
+    Ok(Json(summary))
+}
+
+/// Export drawings as a streamed ZIP archive with a `manifest.json` (write key
+/// required: drawings are exported as stored, password hashes included). With
+/// `--embed-metadata` each document also carries its current metadata under `_share`.
+/// Accepts the same `tag`, `source_path`, `from` and `to` filters as the list endpoint.
+pub async fn export_drawings(
+    State(state): State<AppState>,
+    Query(filter): Query<DrawingFilter>,
+) -> Result<Response, AppError> {
+    let drawings: Vec<DrawingMeta> = state
+        .storage
+        .list()
+        .await?
+        .into_iter()
+        .filter(|d| filter.matches(d))
+        .collect();
+
+    tracing::info!(count = drawings.len(), "Starting drawing export");
+
+    let filename = format!("excalidraw-export-{}.zip", chrono::Utc::now().format("%Y-%m-%d"));
+    let body = export::zip_stream(state.storage.clone(), drawings, state.embed_metadata);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// The subset of [`DrawingFilter`] open to the public list: only fields that
+/// are already part of its response.
+#[derive(Deserialize, Default)]
+pub struct PublicSearch {
+    #[serde(default)]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub source_prefix: Option<String>,
+}
+
+pub async fn list_drawings_public(
+    State(state): State<AppState>,
+    Query(search): Query<PublicSearch>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<PublicListResponse>, AppError> {
+    let filter = DrawingFilter {
+        q: search.q,
+        source_path: search.source_prefix,
+        exclude_expired: true,
+        ..Default::default()
+    };
+    let (drawings, total) = state.storage.list_page(&filter, pagination.page()?).await?;
+    let next_offset = next_offset(pagination.offset, drawings.len(), total);
+    let public_drawings: Vec<PublicDrawingMeta> = drawings.into_iter().map(PublicDrawingMeta::listed).collect();
+    let response = PublicListResponse { drawings: public_drawings, total, next_offset };
+    check_list_size(&response, state.max_list_response_bytes)?;
+    Ok(Json(response))
+}
+
+pub async fn health() -> &'static str {
+    "ok"
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReadyResponse {
+    /// `ready`, or `unavailable` when storage failed the check
+    pub status: String,
+    /// `fs` or `sqlite`
+    pub backend: String,
+    pub storage_ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drawing_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Readiness probe: writes, reads back and removes a scratch entry (followers
+/// only read) and counts the drawings, with `503` if storage fails either.
+/// `/api/health` stays the cheap liveness probe.
+pub async fn ready(State(state): State<AppState>) -> Response {
+    let check = async {
+        if !state.follower {
+            state.storage.probe_write().await?;
+        }
+        let page = storage::Page { offset: 0, limit: 1, sort: storage::ListSort::default() };
+        let (_, total) = state.storage.list_page(&DrawingFilter::default(), page).await?;
+        Ok::<_, AppError>(total)
+    };
+    let (status, body) = match check.await {
+        Ok(total) => (
+            StatusCode::OK,
+            ReadyResponse {
+                status: "ready".into(),
+                backend: state.storage.backend().into(),
+                storage_ok: true,
+                drawing_count: Some(total),
+                error: None,
+            },
+        ),
+        Err(e) => {
+            // The probe is public: the details go to the log only
+            tracing::warn!(error = %e, "Readiness check failed");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ReadyResponse {
+                    status: "unavailable".into(),
+                    backend: state.storage.backend().into(),
+                    storage_ok: false,
+                    drawing_count: None,
+                    error: Some("storage check failed".into()),
+                },
+            )
+        }
+    };
+    (status, [(header::CACHE_CONTROL, "no-store")], Json(body)).into_response()
+}
+
+/// Prometheus metrics (public, only routed with `--metrics`).
+pub async fn get_metrics(State(state): State<AppState>) -> Response {
+    let mut body = state.metrics.render();
+    if let Some(usage) = state.storage.cache_usage() {
+        crate::metrics::render_cache(&mut body, usage);
+    }
+    crate::metrics::render_quota(&mut body, state.byte_quota.usage());
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FolderCount {
+    pub path: String,
+    /// Number of drawings in this folder or any of its subfolders
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct FoldersResponse {
+    pub folders: Vec<FolderCount>,
+}
+
+/// Derive every directory prefix of the given source paths (at all levels),
+/// sorted, with the number of drawings under each.
+/// `Projects/ClientX/plan.excalidraw` contributes to `Projects` and `Projects/ClientX`.
+fn folder_counts<'a>(source_paths: impl Iterator<Item = &'a str>) -> Vec<FolderCount> {
+    let mut counts: std::collections::BTreeMap<String, usize> = std::collections::BTreeMap::new();
+    for path in source_paths {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        // The last segment is the file name, not a folder
+        for depth in 1..segments.len() {
+            *counts.entry(segments[..depth].join("/")).or_insert(0) += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(path, count)| FolderCount { path, count })
+        .collect()
+}
+
+/// List the distinct folders derived from drawings' `source_path` (auth required).
+pub async fn list_folders(
+    State(state): State<AppState>,
+) -> Result<Json<FoldersResponse>, AppError> {
+    let drawings = state.storage.list().await?;
+    let folders = folder_counts(drawings.iter().filter_map(|d| d.source_path.as_deref()));
+    Ok(Json(FoldersResponse { folders }))
+}
+
+/// Aggregate statistics across all drawings (auth required).
+/// Element-type counts are summed from per-drawing histograms cached in the sidecars.
+pub async fn stats(
+    State(state): State<AppState>,
+) -> Result<Json<StatsResponse>, AppError> {
+    let storage = state.storage.stats().await?;
+    let webhook_queue_depth = match &state.webhooks {
+        Some(webhooks) => Some(webhooks.queue_depth().await),
+        None => None,
+    };
+    Ok(Json(StatsResponse {
+        storage,
+        webhook_queue_depth,
+        cache: state.storage.cache_usage(),
+        quota: state.byte_quota.usage(),
+        read_only: state.write_breaker.is_read_only(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    pub samples: Vec<UsageSample>,
+}
+
+/// Recorded storage usage samples, optionally limited to `[from, to]` (auth required).
+pub async fn storage_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>, AppError> {
+    let samples = state.usage_history.range(query.from, query.to).await?;
+    Ok(Json(HistoryResponse { samples }))
+}
+
+#[derive(Serialize)]
+pub struct VerifyResponse {
+    /// No discrepancies were found
+    pub consistent: bool,
+    #[serde(flatten)]
+    pub report: VerifyReport,
+}
+
+/// The configuration the server is running with, secrets redacted (auth required).
+pub async fn get_config(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(state.effective_config.as_ref().clone())
+}
+
+/// Compare the storage index against the stored drawings and report drift
+/// (auth required). Read-only: nothing is repaired.
+pub async fn verify_storage(State(state): State<AppState>) -> Result<Json<VerifyResponse>, AppError> {
+    let report = state.storage.verify().await?;
+    if !report.is_consistent() {
+        tracing::warn!(
+            missing_from_index = report.missing_from_index.len(),
+            orphaned_index_entries = report.orphaned_index_entries.len(),
+            hash_mismatches = report.hash_mismatches.len(),
+            stale_index_entries = report.stale_index_entries.len(),
+            unreadable = report.unreadable.len(),
+            "Storage verification found discrepancies"
+        );
+    }
+    Ok(Json(VerifyResponse { consistent: report.is_consistent(), report }))
+}
+
+// ──────────────────────────────────────────────
+// Lookup by source path (for frontmatter recovery)
+// ──────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct LookupParams {
+    pub source_path: String,
+}
+
+#[derive(Serialize)]
+pub struct LookupResponse {
+    pub id: String,
+    pub source_path: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub password_protected: bool,
+    pub persistent_collab: bool,
+    pub persistent_collab_version: u64,
+}
+
+/// Look up a drawing by its source path (vault-relative file path).
+/// Used by the Obsidian plugin to recover published state when frontmatter
+/// is lost (e.g., overwritten by a third-party sync plugin).
+/// Requires API key authentication.
+pub async fn lookup_by_source_path(
+    State(state): State<AppState>,
+    Query(params): Query<LookupParams>,
+) -> Result<Json<LookupResponse>, AppError> {
+    let meta = state
+        .storage
+        .find_by_source_path(&clean_source_path(&state, &params.source_path))
+        .await?;
+
+    match meta {
+        Some(drawing_meta) => {
+            // Read persistent_collab_version from the drawing JSON if persistent collab is enabled
+            let persistent_collab_version = if drawing_meta.persistent_collab {
+                match state.storage.load(&drawing_meta.id).await {
+                    Ok(data) => data.get("_persistent_collab_version")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(0),
+                    Err(_) => 0,
+                }
+            } else {
+                0
+            };
+
+            tracing::info!(
+                source_path = %params.source_path,
+                id = %drawing_meta.id,
+                "Drawing found by source path lookup"
+            );
+
+            Ok(Json(LookupResponse {
+                id: drawing_meta.id,
+                source_path: drawing_meta.source_path,
+                created_at: drawing_meta.created_at,
+                password_protected: drawing_meta.password_protected,
+                persistent_collab: drawing_meta.persistent_collab,
+                persistent_collab_version,
+            }))
+        }
+        None => {
+            Err(AppError::NotFound)
+        }
+    }
+}
+
+// ──────────────────────────────────────────────
+// Collab Handlers
+// ──────────────────────────────────────────────
+
+/// Start a new collab session for a drawing (auth required).
+pub async fn start_collab(
+    State(state): State<AppState>,
+    Json(body): Json<StartCollabRequest>,
+) -> Result<(StatusCode, Json<StartCollabResponse>), AppError> {
+    // Verify the drawing exists
+    let drawing_data = state.storage.load(&body.drawing_id).await?;
+
+    // Clamp timeout to a reasonable range (5 min – 24 hours)
+    const MIN_TIMEOUT: u64 = 300;
+    const MAX_TIMEOUT: u64 = 86400;
+    let timeout = body.timeout_secs.clamp(MIN_TIMEOUT, MAX_TIMEOUT);
+
+    // Hash the collab password if provided
+    let collab_password_hash = match &body.password {
+        Some(pw) if !pw.is_empty() => {
+            let hash = password::hash_password(pw)
+                .map_err(|e| AppError::Internal(format!("Failed to hash password: {e}")))?;
+            Some(hash)
+        }
+        _ => None,
+    };
+
+    let session_id = state
+        .session_manager
+        .create_session(&body.drawing_id, &drawing_data, timeout, collab_password_hash)
+        .await?;
+
+    let base = state.base_url.trim_end_matches('/');
+    let ws_scheme = if base.starts_with("https") {
+        "wss"
+    } else {
+        "ws"
+    };
+    let host = base
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let ws_url = format!("{ws_scheme}://{host}/ws/collab/{session_id}");
+
+    tracing::info!(
+        drawing_id = %body.drawing_id,
+        session_id = %session_id,
+        "Collab session started"
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(StartCollabResponse { session_id, ws_url }),
+    ))
+}
+
+/// Stop a collab session, optionally saving changes (auth required).
+pub async fn stop_collab(
+    State(state): State<AppState>,
+    Json(body): Json<StopCollabRequest>,
+) -> Result<Json<StopCollabResponse>, AppError> {
+    let result = state
+        .session_manager
+        .end_session(&body.session_id, body.save)
+        .await?;
+
+    if let Some((drawing_id, data)) = result {
+        // Preserve the existing source_path and password_hash from the stored drawing
+        // (collab session data doesn't include _source_path or _password_hash)
+        let (source_path, password_hash) = match state.storage.load(&drawing_id).await {
+            Ok(existing) => (
+                existing.get("_source_path")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                existing.get("_password_hash")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+            ),
+            Err(_) => (None, None),
+        };
+
+        state
+            .storage
+            .save(&drawing_id, &data, source_path.as_deref(), password_hash.as_deref())
+            .await?;
+
+        tracing::info!(
+            drawing_id = %drawing_id,
+            "Collab session changes saved to storage"
+        );
+    }
+
+    Ok(Json(StopCollabResponse { saved: body.save }))
+}
+
+/// Get collab status for a drawing (public).
+/// The session_id is exposed so the frontend viewer can join via WebSocket.
+/// Security relies on session IDs being full 128-bit UUIDs (unguessable).
+pub async fn collab_status(
+    State(state): State<AppState>,
+    Path(drawing_id): Path<String>,
+) -> Json<CollabStatusResponse> {
+    let (active, session_id, participant_count, password_required, persistent) =
+        state.session_manager.get_session_status_extended(&drawing_id).await;
+
+    if active {
+        Json(CollabStatusResponse {
+            active: true,
+            session_id,
+            participant_count: Some(participant_count),
+            password_required: Some(password_required),
+            persistent,
+        })
+    } else {
+        Json(CollabStatusResponse {
+            active: false,
+            session_id: None,
+            participant_count: None,
+            password_required: None,
+            persistent,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VerifyCollabPasswordRequest {
+    pub session_id: String,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct VerifyCollabPasswordResponse {
+    pub valid: bool,
+}
+
+/// Verify a collab session password (public, used before WebSocket connection).
+pub async fn verify_collab_password(
+    State(state): State<AppState>,
+    Json(body): Json<VerifyCollabPasswordRequest>,
+) -> Result<Json<VerifyCollabPasswordResponse>, AppError> {
+    let valid = state
+        .session_manager
+        .verify_session_password(&body.session_id, body.password.as_deref())
+        .await?;
+
+    if !valid {
+        return Err(if body.password.is_some() {
+            AppError::InvalidPassword
+        } else {
+            AppError::PasswordRequired
+        });
+    }
+
+    Ok(Json(VerifyCollabPasswordResponse { valid: true }))
+}
+
+/// List all active collab sessions (auth required, for admin).
+pub async fn list_collab_sessions(
+    State(state): State<AppState>,
+) -> Json<CollabSessionsResponse> {
+    let sessions = state.session_manager.list_sessions().await;
+    Json(CollabSessionsResponse { sessions })
+}
+
+// ──────────────────────────────────────────────
+// Persistent Collab Handlers
+// ──────────────────────────────────────────────
+
+/// Enable persistent collab for a drawing (auth required).
+/// Creates a persistent session that auto-saves and survives server restarts.
+pub async fn enable_persistent_collab(
+    State(state): State<AppState>,
+    Json(body): Json<EnablePersistentCollabRequest>,
+) -> Result<(StatusCode, Json<EnablePersistentCollabResponse>), AppError> {
+    // 1. Verify drawing exists
+    let drawing_data = state.storage.load(&body.drawing_id).await?;
+
+    // 2. Hash password if provided
+    let password_hash = match &body.password {
+        Some(pw) if !pw.is_empty() => {
+            let hash = password::hash_password(pw)
+                .map_err(|e| AppError::Internal(format!("Failed to hash password: {e}")))?;
+            Some(hash)
+        }
+        _ => None,
+    };
+
+    // 3. Update drawing JSON: set _persistent_collab = true, store password hash
+    let mut data = drawing_data.clone();
+    if let Some(obj) = data.as_object_mut() {
+        obj.insert("_persistent_collab".to_string(), serde_json::Value::Bool(true));
+        if let Some(ph) = &password_hash {
+            obj.insert(
+                "_persistent_collab_password_hash".to_string(),
+                serde_json::Value::String(ph.clone()),
+            );
+        } else {
+            obj.remove("_persistent_collab_password_hash");
+        }
+        if !obj.contains_key("_persistent_collab_version") {
+            obj.insert("_persistent_collab_version".to_string(), serde_json::json!(0));
+        }
+    }
+
+    // 4. Save updated drawing (preserve source_path and password_hash)
+    let source_path = data.get("_source_path").and_then(|v| v.as_str()).map(String::from);
+    let pw_hash = data.get("_password_hash").and_then(|v| v.as_str()).map(String::from);
+    state.storage.save(&body.drawing_id, &data, source_path.as_deref(), pw_hash.as_deref()).await?;
+
+    // 5. Register in session manager
+    state.session_manager.register_persistent_drawing(body.drawing_id.clone()).await;
+
+    // 6. Create persistent session (if not already active)
+    let session_id = match state.session_manager.get_session_for_drawing(&body.drawing_id).await {
+        Some(existing_id) => existing_id,
+        None => {
+            state.session_manager.create_persistent_session(
+                &body.drawing_id, &drawing_data, password_hash,
+            ).await?
+        }
+    };
+
+    tracing::info!(drawing_id = %body.drawing_id, "Persistent collab enabled");
+
+    Ok((StatusCode::OK, Json(EnablePersistentCollabResponse {
+        enabled: true,
+        drawing_id: body.drawing_id,
+        session_id,
+    })))
+}
+
+/// Disable persistent collab for a drawing (auth required).
+/// Ends any active session (saving changes) and removes persistent collab metadata.
+pub async fn disable_persistent_collab(
+    State(state): State<AppState>,
+    Json(body): Json<DisablePersistentCollabRequest>,
+) -> Result<Json<DisablePersistentCollabResponse>, AppError> {
+    // 1. End active session if exists (save to disk)
+    if let Some(session_id) = state.session_manager.get_session_for_drawing(&body.drawing_id).await {
+        let result = state.session_manager.end_session(&session_id, true).await;
+        // Save session data to disk if end_session returned it
+        if let Ok(Some((drawing_id, data))) = result {
+            let (source_path, password_hash) = match state.storage.load(&drawing_id).await {
+                Ok(existing) => (
+                    existing.get("_source_path")
+                        .and_then(|v| v.as_str())
+                        .map(String::from),
+                    existing.get("_password_hash")
+                        .and_then(|v| v.as_str())
+                        .map(String::from),
+                ),
+                Err(_) => (None, None),
+            };
+            let _ = state.storage.save(
+                &drawing_id, &data, source_path.as_deref(), password_hash.as_deref(),
+            ).await;
+        }
+    }
+
+    // 2. Remove _persistent_collab from drawing JSON
+    let data = state.storage.load(&body.drawing_id).await?;
+    let mut updated = data;
+    if let Some(obj) = updated.as_object_mut() {
+        obj.insert("_persistent_collab".to_string(), serde_json::Value::Bool(false));
+        obj.remove("_persistent_collab_password_hash");
+        obj.remove("_persistent_collab_version");
+    }
+    let source_path = updated.get("_source_path").and_then(|v| v.as_str()).map(String::from);
+    let pw_hash = updated.get("_password_hash").and_then(|v| v.as_str()).map(String::from);
+    state.storage.save(&body.drawing_id, &updated, source_path.as_deref(), pw_hash.as_deref()).await?;
+
+    // 3. Unregister from session manager
+    state.session_manager.unregister_persistent_drawing(&body.drawing_id).await;
+
+    tracing::info!(drawing_id = %body.drawing_id, "Persistent collab disabled");
+
+    Ok(Json(DisablePersistentCollabResponse {
+        disabled: true,
+        drawing_id: body.drawing_id,
+    }))
+}
+
+/// Activate a persistent collab session on demand (public, no auth).
+/// Creates the session if it doesn't exist yet (lazy activation).
+/// Returns the session_id for WebSocket connection.
+pub async fn activate_persistent_collab(
+    State(state): State<AppState>,
+    Path(drawing_id): Path<String>,
+) -> Result<Json<ActivatePersistentCollabResponse>, AppError> {
+    // 1. Check if session already exists
+    if let Some(session_id) = state.session_manager.get_session_for_drawing(&drawing_id).await {
+        let (active, _, _, pw_req, _) =
+            state.session_manager.get_session_status_extended(&drawing_id).await;
+        if active {
+            return Ok(Json(ActivatePersistentCollabResponse {
+                session_id,
+                password_required: pw_req,
+            }));
+        }
+    }
+
+    // 2. Verify drawing has persistent collab enabled
+    let has_persistent = state.storage.get_persistent_collab_status(&drawing_id).await?;
+    if !has_persistent {
+        return Err(AppError::NotFound);
+    }
+
+    // 3. Load drawing and create persistent session
+    let drawing_data = state.storage.load(&drawing_id).await?;
+    let password_hash = drawing_data.get("_persistent_collab_password_hash")
+        .and_then(|v| v.as_str())
+        .map(String::from);
+
+    // Handle race condition: create_persistent_session may fail with SessionAlreadyExists
+    // if two visitors arrive simultaneously
+    let session_id = match state.session_manager.create_persistent_session(
+        &drawing_id, &drawing_data, password_hash.clone(),
+    ).await {
+        Ok(id) => id,
+        Err(AppError::SessionAlreadyExists) => {
+            // Another request created the session concurrently — return the existing one
+            state.session_manager.get_session_for_drawing(&drawing_id).await
+                .ok_or(AppError::Internal("Race condition: session disappeared".to_string()))?
+        }
+        Err(e) => return Err(e),
+    };
+
+    tracing::info!(
+        drawing_id = %drawing_id,
+        session_id = %session_id,
+        "Persistent collab session activated on demand"
+    );
+
+    Ok(Json(ActivatePersistentCollabResponse {
+        session_id,
+        password_required: password_hash.is_some(),
+    }))
+}
+
+// ──────────────────────────────────────────────
+// ICE Config — returns STUN/TURN server configuration for WebRTC
+// ──────────────────────────────────────────────
+
+/// Returns WebRTC ICE server configuration.
+/// If STUN_URL is configured, includes a STUN server entry.
+/// If TURN_URL and TURN_SECRET are configured, generates HMAC-SHA1 time-limited
+/// credentials (RFC 5766 / coturn compatible) valid for 1 hour.
+pub async fn ice_config_handler(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut ice_servers: Vec<serde_json::Value> = Vec::new();
+
+    if let Some(stun_url) = &state.stun_url {
+        ice_servers.push(serde_json::json!({ "urls": stun_url }));
+    }
+
+    if let (Some(turn_url), Some(turn_secret)) = (&state.turn_url, &state.turn_secret) {
+        // Generate HMAC time-limited credentials (coturn compatible)
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600; // Valid for 1 hour
+
+        let username = format!("{}:excalishare", timestamp);
+
+        // HMAC-SHA1 of username with the shared secret
+        type HmacSha1 = Hmac<Sha1>;
+        let mut mac = HmacSha1::new_from_slice(turn_secret.as_bytes())
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        mac.update(username.as_bytes());
+        let credential = general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+
+        ice_servers.push(serde_json::json!({
+            "urls": turn_url,
+            "username": username,
+            "credential": credential,
+            "credentialType": "password"
+        }));
+    }
+
+    Ok(axum::Json(serde_json::json!({ "iceServers": ice_servers })))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::storage::FileSystemStorage;
+    use serde_json::json;
+
+    /// State for handler tests, storing drawings in `dir`; the API key is `test-key`.
+    pub(crate) async fn test_state(dir: &std::path::Path) -> AppState {
+        AppState {
+            storage: FileSystemStorage::new(dir).await.unwrap().into(),
+            base_url: "http://localhost:8184".to_string(),
+            session_manager: SessionManager::new(),
+            api_keys: ApiKeys::single("test-key"),
+            stun_url: None,
+            turn_url: None,
+            turn_secret: None,
+            cache_max_age_secs: 60,
+            cache_swr_secs: 300,
+            max_tags_per_drawing: 3,
+            max_tag_length: 10,
+            require_delete_confirmation: false,
+            confirm_tokens: ConfirmTokens::new(),
+            webhooks: None,
+            dedup_uploads: false,
+            upload_locks: KeyedLocks::new(),
+            view_locks: KeyedLocks::new(),
+            asset_base_url: None,
+            auto_slug: false,
+            reject_duplicate_element_ids: false,
+            warn_empty_drawings: false,
+            reject_empty_drawings: false,
+            embed_metadata: false,
+            max_embedded_files: None,
+            max_embedded_bytes: None,
+            strip_files: false,
+            drawing_quota: DrawingQuota::default(),
+            byte_quota: ByteQuota::default(),
+            render_cache: RenderCache::new(1024 * 1024),
+            render_permits: Arc::new(tokio::sync::Semaphore::new(2)),
+            effective_config: Arc::new(serde_json::Value::Null),
+            metrics: Metrics::new(),
+            round_coordinates: None,
+            max_coordinate: None,
+            clamp_coordinates: false,
+            allowed_link_origins: None,
+            default_app_state: None,
+            max_ttl_secs: 30 * 24 * 3600,
+            ttl_extension_secs: 7 * 24 * 3600,
+            view_counter: ViewCounter::new(),
+            write_breaker: WriteBreaker::new(0, std::time::Duration::from_secs(30)),
+            max_preview_bytes: 1024,
+            normalize_source_paths: true,
+            usage_history: UsageHistory::new(dir.join(".usage-history")),
+            max_list_response_bytes: usize::MAX,
+            import_limits: import::ImportLimits { max_entries: 1000, max_entry_bytes: 1 << 20, max_total_bytes: 1 << 24 },
+            weak_etags: false,
+            follower: false,
+            soft_delete: false,
+        }
+    }
+
+    fn sample_drawing() -> serde_json::Value {
+        json!({ "type": "excalidraw", "version": 2, "elements": [] })
+    }
+
+    fn cache_control(response: &Response) -> &str {
+        response.headers()[header::CACHE_CONTROL].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_public_drawing_gets_swr_cache_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("public1", &sample_drawing(), None, None).await.unwrap();
+
+        let response = get_drawing(
+            State(state.clone()),
+            Path("public1".to_string()),
+            Query(ViewQuery { key: None }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            cache_control(&response),
+            "public, max-age=60, stale-while-revalidate=300"
+        );
+        let etag = response.headers()[header::ETAG].clone();
+
+        // Revalidating with the ETag yields 304
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let response = get_drawing(
+            State(state),
+            Path("public1".to_string()),
+            Query(ViewQuery { key: None }),
+            headers,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_protected_drawing_gets_no_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let hash = password::hash_password("secret").unwrap();
+        state
+            .storage
+            .save("private1", &sample_drawing(), None, Some(&hash))
+            .await
+            .unwrap();
+
+        let response = get_drawing(
+            State(state),
+            Path("private1".to_string()),
+            Query(ViewQuery { key: Some("secret".to_string()) }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(cache_control(&response), "private, no-store");
+    }
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_normalize_tags_dedupes_equivalent_tags() {
+        let normalized = normalize_tags(&tags(&[" Work ", "work", "WORK", "", "ideas"]), 3, 10).unwrap();
+        assert_eq!(normalized, vec!["work", "ideas"]);
+    }
+
+    #[test]
+    fn test_normalize_tags_rejects_too_many() {
+        let result = normalize_tags(&tags(&["a", "b", "c", "d"]), 3, 10);
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        // Duplicates don't count against the limit
+        assert!(normalize_tags(&tags(&["a", "b", "c", "A"]), 3, 10).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_tags_rejects_too_long() {
+        let result = normalize_tags(&tags(&["this-tag-is-too-long"]), 3, 10);
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    fn delete_query(phase: Option<&str>, token: Option<&str>) -> Query<DeleteQuery> {
+        Query(DeleteQuery {
+            phase: phase.map(String::from),
+            token: token.map(String::from),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_two_phase_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.require_delete_confirmation = true;
+        state.storage.save("doomed", &sample_drawing(), None, None).await.unwrap();
+
+        // No token, or a bogus one, is refused
+        let result = delete_drawing(State(state.clone()), Path("doomed".into()), delete_query(None, None)).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        let result = delete_drawing(State(state.clone()), Path("doomed".into()), delete_query(None, Some("bogus"))).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(state.storage.exists("doomed").await.unwrap());
+
+        // prepare → confirm succeeds
+        let token = state.confirm_tokens.issue("doomed").await;
+        let response = delete_drawing(State(state.clone()), Path("doomed".into()), delete_query(None, Some(&token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!state.storage.exists("doomed").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.soft_delete = true;
+        for id in ["doomed", "bulk"] {
+            state.storage.save(id, &sample_drawing(), None, None).await.unwrap();
+        }
+
+        let response = delete_drawing(State(state.clone()), Path("doomed".into()), delete_query(None, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let body = BulkDeleteRequest { ids: vec!["bulk".into(), "missing".into()] };
+        let Json(bulk) = bulk_delete_drawings(State(state.clone()), Json(body)).await.unwrap();
+        assert_eq!(bulk.results[0].status, BulkDeleteStatus::Deleted);
+        assert_eq!(bulk.results[1].status, BulkDeleteStatus::NotFound);
+        assert!(!state.storage.exists("doomed").await.unwrap());
+        let Json(trash) = list_trash(State(state.clone())).await.unwrap();
+        assert_eq!(trash.drawings.len(), 2);
+
+        let Json(restored) = restore_drawing(State(state.clone()), Path("doomed".into())).await.unwrap();
+        assert_eq!(restored.id, "doomed");
+        assert!(state.storage.exists("doomed").await.unwrap());
+        assert!(matches!(restore_drawing(State(state.clone()), Path("doomed".into())).await, Err(AppError::NotFound)));
+
+        // Without soft delete, deletes are for good
+        state.soft_delete = false;
+        delete_drawing(State(state.clone()), Path("doomed".into()), delete_query(None, None)).await.unwrap();
+        let Json(trash) = list_trash(State(state)).await.unwrap();
+        assert_eq!(trash.drawings.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), ["bulk"]);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_phase_issues_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("keep", &sample_drawing(), None, None).await.unwrap();
+
+        let response = delete_drawing(State(state.clone()), Path("keep".into()), delete_query(Some("prepare"), None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.storage.exists("keep").await.unwrap());
+
+        let result = delete_drawing(State(state), Path("missing".into()), delete_query(Some("prepare"), None)).await;
+        assert!(matches!(result, Err(AppError::NotFound)));
+    }
+
+    #[test]
+    fn test_folder_counts() {
+        let paths = [
+            "Projects/ClientX/plan.excalidraw",
+            "Projects/ClientX/flow.excalidraw",
+            "Projects/Internal/arch.excalidraw",
+            "Inbox/sketch.excalidraw",
+            "root.excalidraw",
+        ];
+        let folders = folder_counts(paths.into_iter());
+        let expected = [
+            ("Inbox", 1),
+            ("Projects", 3),
+            ("Projects/ClientX", 2),
+            ("Projects/Internal", 1),
+        ];
+        assert_eq!(
+            folders,
+            expected
+                .iter()
+                .map(|(path, count)| FolderCount { path: path.to_string(), count: *count })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn upload_request(data: serde_json::Value) -> UploadRequest {
+        UploadRequest {
+            data,
+            source_path: Some("retry.excalidraw".to_string()),
+            id: None,
+            password: None,
+            tags: None,
+            max_views: None,
+            title: None,
+            expires_in_secs: None,
+            created_at: None,
+            preview: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_keeps_supplied_created_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let original: chrono::DateTime<chrono::Utc> = "2021-03-04T05:06:07Z".parse().unwrap();
+
+        let request = UploadRequest { created_at: Some(original), ..upload_request(sample_drawing()) };
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        assert_eq!(state.storage.get_meta(&uploaded.id).await.unwrap().created_at, original);
+
+        // An update without created_at keeps it
+        let request = UploadRequest { id: Some(uploaded.id.clone()), ..upload_request(sample_drawing()) };
+        let _ = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        assert_eq!(state.storage.get_meta(&uploaded.id).await.unwrap().created_at, original);
+
+        let future = chrono::Utc::now() + chrono::Duration::days(365);
+        let request = UploadRequest { created_at: Some(future), ..upload_request(sample_drawing()) };
+        let result = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        // A raw `_created_at` in the document is ignored
+        let mut data = sample_drawing();
+        data["_created_at"] = serde_json::json!("3000-01-01T00:00:00Z");
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(data))).await.unwrap();
+        assert!(state.storage.get_meta(&uploaded.id).await.unwrap().created_at <= chrono::Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_follower_counts_no_views() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.storage.save("open", &sample_drawing(), None, None).await.unwrap();
+        let mut limited = sample_drawing();
+        limited["_max_views"] = json!(1);
+        state.storage.save("limited", &limited, None, None).await.unwrap();
+        state.follower = true;
+
+        let view = |id: &str, headers: HeaderMap| {
+            get_drawing(State(state.clone()), Path(id.to_string()), Query(ViewQuery { key: None }), headers)
+        };
+        view("open", HeaderMap::new()).await.unwrap();
+        assert_eq!(state.view_counter.pending("open").await, 0);
+        assert!(matches!(view("limited", HeaderMap::new()).await, Err(AppError::Forbidden(_))));
+        let mut admin = HeaderMap::new();
+        admin.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer test-key"));
+        view("limited", admin).await.unwrap();
+        assert_eq!(state.storage.get_meta("limited").await.unwrap().views, 0);
+    }
+
+    #[tokio::test]
+    async fn test_upload_drops_client_internal_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut data = sample_drawing();
+        for (key, value) in [
+            ("_slug", json!("taken")),
+            ("_user_agent", json!("spoofed")),
+            ("_expires_at", json!("3000-01-01T00:00:00Z")),
+            ("_max_views", json!(1)),
+            ("_persistent_collab", json!(true)),
+            ("_blob", json!("0".repeat(64))),
+            ("_share", json!({ "title": "forged" })),
+        ] {
+            data[key] = value;
+        }
+        let mut request = upload_request(data);
+        request.title = Some("Real".into());
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+
+        let stored = state.storage.load(&uploaded.id).await.unwrap();
+        let internal: Vec<_> = stored.as_object().unwrap().keys().filter(|k| k.starts_with('_')).cloned().collect();
+        // Only what the server set from the request itself
+        assert_eq!(internal, ["_source_path", "_title"]);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_uploads_and_views() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(sample_drawing()))).await.unwrap();
+        let _ = get_drawing(State(state.clone()), Path(uploaded.id), Query(ViewQuery { key: None }), HeaderMap::new()).await.unwrap();
+
+        let response = get_metrics(State(state)).await;
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = std::str::from_utf8(&body).unwrap();
+        assert!(text.contains("excalishare_uploads_total 1\n"));
+        assert!(text.contains("excalishare_views_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_public_meta_without_elements() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let request = UploadRequest { source_path: Some("Notes/a.excalidraw".into()), ..upload_request(sample_drawing()) };
+        let (_, Json(open)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        let request = UploadRequest { password: Some("pw".into()), ..upload_request(sample_drawing()) };
+        let (_, Json(locked)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        let meta = |id: &str, key: Option<&str>| {
+            get_drawing_meta_public(State(state.clone()), Path(id.to_string()), Query(ViewQuery { key: key.map(Into::into) }), HeaderMap::new())
+        };
+
+        let Json(public) = meta(&open.id, None).await.unwrap();
+        let stored = state.storage.get_meta(&open.id).await.unwrap();
+        assert_eq!(public.source_path.as_deref(), Some("Notes/a.excalidraw"));
+        assert_eq!(public.created_at, stored.created_at);
+        assert_eq!(public.size_bytes, Some(stored.size_bytes));
+        assert_eq!(public.content_hash, stored.content_hash);
+        assert!(!serde_json::to_value(&public).unwrap().as_object().unwrap().contains_key("elements"));
+
+        assert!(matches!(meta("nope", None).await, Err(AppError::NotFound)));
+        assert!(matches!(meta(&locked.id, None).await, Err(AppError::PasswordRequired)));
+        assert!(matches!(meta(&locked.id, Some("nope")).await, Err(AppError::InvalidPassword)));
+        assert!(meta(&locked.id, Some("pw")).await.unwrap().password_protected);
+
+        // The public list leaves out content hashes, and sizes of protected drawings
+        let Json(list) = list_drawings_public(State(state.clone()), Query(PublicSearch::default()), Query(Pagination::default()))
+            .await
+            .unwrap();
+        let listed = |id: &str| list.drawings.iter().find(|d| d.id == id).unwrap();
+        assert!(listed(&open.id).content_hash.is_none());
+        assert_eq!(listed(&open.id).size_bytes, Some(stored.size_bytes));
+        assert!(listed(&locked.id).size_bytes.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_restores_content_as_new_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.storage = FileSystemStorage::new(dir.path()).await.unwrap().with_history_depth(10).into();
+        let with_element = |id: &str| json!({ "type": "excalidraw", "elements": [{ "id": id, "type": "rectangle", "x": 0, "y": 0 }] });
+        for element in ["first", "second", "third"] {
+            let request = UploadRequest { id: Some("doc".into()), password: Some("pw".into()), ..upload_request(with_element(element)) };
+            let _ = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        }
+
+        let history = || drawing_history(State(state.clone()), Path("doc".to_string()), Query(ViewQuery { key: Some("pw".into()) }), HeaderMap::new());
+        let Json(listed) = history().await.unwrap();
+        assert_eq!(listed.versions.iter().map(|v| v.version).collect::<Vec<_>>(), [2, 1]);
+        let locked = drawing_history(State(state.clone()), Path("doc".to_string()), Query(ViewQuery { key: None }), HeaderMap::new()).await;
+        assert!(matches!(locked, Err(AppError::PasswordRequired)));
+
+        let Json(rolled_back) = rollback_drawing(State(state.clone()), Path(("doc".to_string(), 1))).await.unwrap();
+        assert_eq!(rolled_back.restored_version, 1);
+        let current = state.storage.load("doc").await.unwrap();
+        assert_eq!(current["elements"][0]["id"], "first");
+        assert!(current.get("_password_hash").is_some());
+        // The replaced content is a new version; nothing in between was dropped
+        let Json(listed) = history().await.unwrap();
+        assert_eq!(listed.versions.iter().map(|v| v.version).collect::<Vec<_>>(), [3, 2, 1]);
+        assert_eq!(state.storage.load_version("doc", 3).await.unwrap()["elements"][0]["id"], "third");
+
+        let missing = rollback_drawing(State(state.clone()), Path(("doc".to_string(), 9))).await;
+        assert!(matches!(missing, Err(AppError::NotFound)));
+
+        // A version that an upload couldn't store any more can't come back either
+        let twice = json!({ "type": "excalidraw", "elements": [
+            { "id": "same", "type": "rectangle", "x": 0, "y": 0 },
+            { "id": "same", "type": "ellipse", "x": 0, "y": 0 },
+        ] });
+        for data in [twice, with_element("fifth")] {
+            let request = UploadRequest { id: Some("doc".into()), ..upload_request(data) };
+            let _ = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        }
+        state.reject_duplicate_element_ids = true;
+        let rejected = rollback_drawing(State(state.clone()), Path(("doc".to_string(), 5))).await;
+        assert!(matches!(rejected, Err(AppError::BadRequest(_))));
+        assert_eq!(state.storage.load("doc").await.unwrap()["elements"][0]["id"], "fifth");
+    }
+
+    #[tokio::test]
+    async fn test_embedded_metadata_neither_stored_nor_served() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.embed_metadata = true;
+        let request = UploadRequest { title: Some("Plan".into()), ..upload_request(sample_drawing()) };
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        // Exports add it from the metadata of the day, so it can't go stale
+        assert!(state.storage.load(&uploaded.id).await.unwrap().get("_share").is_none());
+
+        // Nor do viewers see one left in a document stored by an older version
+        let mut legacy = sample_drawing();
+        legacy["_share"] = json!({ "title": "Old" });
+        state.storage.save("legacy", &legacy, None, None).await.unwrap();
+        let view = get_drawing(State(state), Path("legacy".into()), Query(ViewQuery { key: None }), HeaderMap::new()).await.unwrap();
+        let body = axum::body::to_bytes(view.into_body(), usize::MAX).await.unwrap();
+        let served: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(served.get("_share").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_identical_uploads_collapse() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.dedup_uploads = true;
+
+        let doc = json!({ "type": "excalidraw", "version": 2, "elements": [{ "id": "a", "type": "text", "x": 0, "y": 0 }] });
+        let (first, second) = tokio::join!(
+            upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(doc.clone()))),
+            upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(doc.clone()))),
+        );
+        let (_, Json(first)) = first.unwrap();
+        let (_, Json(second)) = second.unwrap();
+
+        assert_eq!(first.id, second.id);
+        assert_eq!(state.storage.list().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dedup_hit_takes_new_tags_and_title() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.dedup_uploads = true;
+
+        let doc = json!({ "type": "excalidraw", "version": 2, "elements": [{ "id": "a", "type": "text", "x": 0, "y": 0 }] });
+        let (_, Json(first)) =
+            upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(doc.clone())))
+                .await
+                .unwrap();
+
+        let mut retagged = upload_request(doc.clone());
+        retagged.tags = Some(vec!["project".to_string()]);
+        retagged.title = Some("Renamed".to_string());
+        let (_, Json(second)) =
+            upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(retagged)).await.unwrap();
+        assert_eq!(first.id, second.id);
+        let stored = state.storage.load(&first.id).await.unwrap();
+        assert_eq!(stored["_tags"], json!(["project"]));
+        assert_eq!(stored["_title"], "Renamed");
+
+        // A view limit belongs to one share, so it never collapses onto another
+        let mut limited = upload_request(doc);
+        limited.max_views = Some(1);
+        let (_, Json(third)) =
+            upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(limited)).await.unwrap();
+        assert_ne!(third.id, first.id);
+    }
+
+    async fn zip_entries(response: Response) -> Vec<String> {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let zip = async_zip::base::read::mem::ZipFileReader::new(bytes.to_vec()).await.unwrap();
+        let mut names: Vec<String> = zip
+            .file()
+            .entries()
+            .iter()
+            .map(|e| e.filename().as_str().unwrap().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_export_filtered_by_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut tagged = sample_drawing();
+        tagged["_tags"] = json!(["project"]);
+        state.storage.save("tagged1", &tagged, None, None).await.unwrap();
+        state.storage.save("tagged2", &tagged, None, None).await.unwrap();
+        state.storage.save("other", &sample_drawing(), None, None).await.unwrap();
+
+        let filter = DrawingFilter { tag: Some("Project".to_string()), ..Default::default() };
+        let response = export_drawings(State(state), Query(filter)).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/zip");
+        assert_eq!(
+            zip_entries(response).await,
+            vec!["manifest.json", "tagged1.json", "tagged2.json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_from_sqlite_carries_source_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.storage = crate::storage::SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap().into();
+        state.storage.save("first", &sample_drawing(), Some("Notes/First.excalidraw"), None).await.unwrap();
+        state.storage.save("second", &sample_drawing(), None, None).await.unwrap();
+
+        let response = export_drawings(State(state), Query(DrawingFilter::default())).await.unwrap();
+        let disposition = response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().to_string();
+        assert!(disposition.starts_with("attachment; filename=\"excalidraw-export-"), "{disposition}");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let zip = async_zip::base::read::mem::ZipFileReader::new(bytes.to_vec()).await.unwrap();
+        let index = zip.file().entries().iter().position(|e| e.filename().as_str().unwrap() == "manifest.json").unwrap();
+        let mut manifest = String::new();
+        zip.reader_with_entry(index).await.unwrap().read_to_string_checked(&mut manifest).await.unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        let drawings = manifest["drawings"].as_array().unwrap();
+        assert_eq!(drawings.len(), 2);
+        let first = drawings.iter().find(|d| d["id"] == "first").unwrap();
+        assert_eq!(first["source_path"], "Notes/First.excalidraw");
+    }
+
+    #[tokio::test]
+    async fn test_index_tracks_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state
+            .storage
+            .save("first", &sample_drawing(), Some("Notes/Roadmap.excalidraw.md"), None)
+            .await
+            .unwrap();
+        state.storage.save("locked", &sample_drawing(), None, Some("hash")).await.unwrap();
+
+        let response = drawing_index(State(state.clone()), HeaderMap::new()).await.unwrap();
+        let etag = response.headers()[header::ETAG].clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let index: Vec<IndexEntry> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index[0].id, "first");
+        assert_eq!(index[0].title, "Roadmap");
+
+        // Unchanged catalog revalidates
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let response = drawing_index(State(state.clone()), headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // An upload changes the hash
+        state.storage.save("second", &sample_drawing(), None, None).await.unwrap();
+        let response = drawing_index(State(state), HeaderMap::new()).await.unwrap();
+        assert_ne!(response.headers()[header::ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn test_max_views_burns_after_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut request = upload_request(sample_drawing());
+        request.max_views = Some(1);
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+
+        let view = || {
+            get_drawing(
+                State(state.clone()),
+                Path(uploaded.id.clone()),
+                Query(ViewQuery { key: None }),
+                HeaderMap::new(),
+            )
+        };
+        let response = view().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(cache_control(&response), "private, no-store");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let served: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(served.get("_max_views").is_none());
+
+        let err = view().await.unwrap_err();
+        assert!(matches!(err, AppError::Gone));
+        assert!(!state.storage.exists(&uploaded.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_renders_use_no_view() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut limited = sample_drawing();
+        limited["_max_views"] = json!(1);
+        state.storage.save("once", &limited, None, None).await.unwrap();
+        let svg_json = |headers: HeaderMap| {
+            get_drawing_svg_json(
+                State(state.clone()),
+                Path("once".to_string()),
+                Query(ViewQuery { key: None }),
+                Query(RenderQuery::default()),
+                headers,
+            )
+        };
+
+        // The ETag of the render, learned as an admin without spending the one view
+        let mut admin = HeaderMap::new();
+        admin.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer test-key"));
+        let etag = svg_json(admin).await.unwrap().headers()[header::ETAG].clone();
+        for _ in 0..2 {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, etag.clone());
+            assert_eq!(svg_json(headers).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+        }
+        assert_eq!(state.storage.get_meta("once").await.unwrap().views, 0);
+
+        assert_eq!(svg_json(HeaderMap::new()).await.unwrap().status(), StatusCode::OK);
+        assert!(matches!(svg_json(HeaderMap::new()).await, Err(AppError::Gone)));
+    }
+
+    #[tokio::test]
+    async fn test_update_app_state_keeps_elements() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("view1", &sample_drawing(), Some("View.excalidraw"), None).await.unwrap();
+        let before = state.storage.load("view1").await.unwrap();
+
+        let app_state = json!({"zoom": {"value": 2}, "theme": "dark"});
+        let status = update_app_state(State(state.clone()), Path("view1".to_string()), Json(app_state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let after = state.storage.load("view1").await.unwrap();
+        assert_eq!(after["appState"], app_state);
+        assert_eq!(after["elements"], before["elements"]);
+        assert_eq!(after["_source_path"], "View.excalidraw");
+    }
+
+    #[tokio::test]
+    async fn test_update_app_state_waits_for_concurrent_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("view1", &sample_drawing(), None, None).await.unwrap();
+
+        // An upload holding the lock lands new elements before appState is applied
+        let guard = state.upload_locks.lock("update:view1").await;
+        let task = tokio::spawn(update_app_state(
+            State(state.clone()),
+            Path("view1".to_string()),
+            Json(json!({"theme": "dark"})),
+        ));
+        tokio::task::yield_now().await;
+        let mut uploaded = sample_drawing();
+        uploaded["elements"] = json!([{"id": "new", "type": "rectangle"}]);
+        state.storage.save("view1", &uploaded, None, None).await.unwrap();
+        drop(guard);
+        assert_eq!(task.await.unwrap().unwrap(), StatusCode::NO_CONTENT);
+
+        let after = state.storage.load("view1").await.unwrap();
+        assert_eq!(after["elements"], uploaded["elements"]);
+        assert_eq!(after["appState"]["theme"], "dark");
+    }
+
+    #[tokio::test]
+    async fn test_update_app_state_rejects_non_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("view1", &sample_drawing(), None, None).await.unwrap();
+
+        let err = update_app_state(State(state), Path("view1".to_string()), Json(json!([1, 2])))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_asset_urls_point_at_asset_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.storage = FileSystemStorage::new(dir.path()).await.unwrap().with_asset_extraction(true).into();
+        state.asset_base_url = Some("https://cdn.example.com/".to_string());
+
+        let png = b"\x89PNG fake image bytes";
+        let data_url = format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(png));
+        let mut doc = sample_drawing();
+        doc["files"] = json!({ "f1": { "id": "f1", "mimeType": "image/png", "dataURL": data_url } });
+        state.storage.save("img1", &doc, None, None).await.unwrap();
+
+        let response = get_drawing(
+            State(state.clone()),
+            Path("img1".to_string()),
+            Query(ViewQuery { key: None }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let served: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let url = served["files"]["f1"]["dataURL"].as_str().unwrap();
+        let hash = url.strip_prefix("https://cdn.example.com/api/view/img1/assets/").unwrap();
+
+        let fetch = |id: &str, hash: &str, key: Option<&str>| {
+            get_asset(
+                State(state.clone()),
+                Path((id.to_string(), hash.to_string())),
+                Query(ViewQuery { key: key.map(String::from) }),
+                HeaderMap::new(),
+            )
+        };
+        let response = fetch("img1", hash, None).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(response.headers()[header::CONTENT_SECURITY_POLICY], "sandbox");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], png);
+
+        // Only through a drawing that references the asset
+        state.storage.save("other", &sample_drawing(), None, None).await.unwrap();
+        assert!(matches!(fetch("other", hash, None).await, Err(AppError::NotFound)));
+
+        // An uploaded HTML "image" is served as a sandboxed download
+        let html = format!("data:text/html;base64,{}", general_purpose::STANDARD.encode("<script>alert(1)</script>"));
+        let mut doc = sample_drawing();
+        doc["files"] = json!({ "f1": { "id": "f1", "mimeType": "text/html", "dataURL": html } });
+        state.storage.save("html1", &doc, None, None).await.unwrap();
+        let raw = state.storage.load_stored("html1").await.unwrap();
+        let html_hash = raw["files"]["f1"]["_asset"].as_str().unwrap().to_string();
+        let response = fetch("html1", &html_hash, None).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/octet-stream");
+
+        // Protected drawings keep files inline and gate their assets on the key
+        let password_hash = password::hash_password("secret").unwrap();
+        state.storage.save("img1", &doc, None, Some(&password_hash)).await.unwrap();
+        let served = state.storage.load_with_asset_urls("img1", "https://cdn.example.com").await.unwrap();
+        assert!(served["files"]["f1"]["dataURL"].as_str().unwrap().starts_with("data:"));
+        assert!(matches!(fetch("img1", &html_hash, None).await, Err(AppError::PasswordRequired)));
+        let response = fetch("img1", &html_hash, Some("secret")).await.unwrap();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "private, no-store");
+    }
+
+    async fn upload_titled(state: &AppState, title: &str) -> String {
+        let mut request = upload_request(sample_drawing());
+        request.source_path = None;
+        request.title = Some(title.to_string());
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        uploaded.id
+    }
+
+    #[tokio::test]
+    async fn test_auto_slug_suffixes_collisions_and_resolves() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.auto_slug = true;
+
+        let first = upload_titled(&state, "Q3 Roadmap").await;
+        let second = upload_titled(&state, "q3 roadmap!").await;
+        let third = upload_titled(&state, "Q3  Roadmap").await;
+
+        let slug_of = |id: String| {
+            let storage = state.storage.clone();
+            async move { storage.load(&id).await.unwrap()["_slug"].as_str().unwrap().to_string() }
+        };
+        assert_eq!(slug_of(first.clone()).await, "q3-roadmap");
+        assert_eq!(slug_of(second).await, "q3-roadmap-2");
+        assert_eq!(slug_of(third).await, "q3-roadmap-3");
+
+        let redirect = view_by_slug(State(state.clone()), Path("q3-roadmap".to_string()))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(redirect.headers()[header::LOCATION], format!("/d/{first}"));
+
+        let missing = view_by_slug(State(state), Path("nope".to_string())).await;
+        assert!(matches!(missing, Err(AppError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_view_streams_one_document_per_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut tagged = sample_drawing();
+        tagged["_tags"] = json!(["project"]);
+        state.storage.save("a", &tagged, Some("A.excalidraw"), None).await.unwrap();
+        state.storage.save("b", &tagged, None, None).await.unwrap();
+        state.storage.save("locked", &tagged, None, Some("hash")).await.unwrap();
+        state.storage.save("other", &sample_drawing(), None, None).await.unwrap();
+
+        let filter = DrawingFilter { tag: Some("project".to_string()), ..Default::default() };
+        let response = bulk_view(State(state), Query(filter), HeaderMap::new()).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let mut ids: Vec<String> = std::str::from_utf8(&bytes)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let line: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(line["drawing"]["type"], "excalidraw");
+                assert!(line["drawing"].get("_source_path").is_none());
+                line["id"].as_str().unwrap().to_string()
+            })
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_storage_history_filters_by_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let early = state.usage_history.sample(&state.storage).await.unwrap();
+        state.storage.save("a", &sample_drawing(), None, None).await.unwrap();
+        let late = state.usage_history.sample(&state.storage).await.unwrap();
+
+        let query = HistoryQuery { from: Some(late.timestamp), to: None };
+        let Json(history) = storage_history(State(state.clone()), Query(query)).await.unwrap();
+        assert_eq!(history.samples, vec![late]);
+
+        let query = HistoryQuery { from: None, to: Some(early.timestamp) };
+        let Json(history) = storage_history(State(state), Query(query)).await.unwrap();
+        assert_eq!(history.samples, vec![early]);
+    }
+
+    #[tokio::test]
+    async fn test_search_matches_text_elements() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let labelled = json!({
+            "type": "excalidraw",
+            "elements": [
+                {"id": "t1", "type": "text", "text": "Payment Gateway"},
+                {"id": "r1", "type": "rectangle"}
+            ]
+        });
+        state.storage.save("arch", &labelled, None, None).await.unwrap();
+        let unrelated = json!({"type": "excalidraw", "elements": [{"id": "t1", "type": "text", "text": "Grocery list"}]});
+        state.storage.save("misc", &unrelated, None, None).await.unwrap();
+
+        let search = |text: &str| {
+            search_drawings(
+                State(state.clone()),
+                Query(SearchQuery { text: text.to_string() }),
+                HeaderMap::new(),
+            )
+        };
+        let Json(found) = search("payment").await.unwrap();
+        let ids: Vec<&str> = found.drawings.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["arch"]);
+
+        let Json(found) = search("kubernetes").await.unwrap();
+        assert!(found.drawings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_agent_recorded_on_creation() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("obsidian-excalidraw-share/1.4.0"));
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, headers, NonEmptyJson(upload_request(sample_drawing())))
+            .await
+            .unwrap();
+
+        // A later update from another client keeps the creator
+        let mut update = upload_request(sample_drawing());
+        update.id = Some(uploaded.id.clone());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl/8.0"));
+        let _ = upload_drawing(State(state.clone()), None, headers, NonEmptyJson(update)).await.unwrap();
+
+        let Json(meta) = get_drawing_meta(State(state.clone()), Path(uploaded.id)).await.unwrap();
+        assert_eq!(meta.user_agent.as_deref(), Some("obsidian-excalidraw-share/1.4.0"));
+
+        let filter = DrawingFilter { user_agent: Some("Obsidian".to_string()), ..Default::default() };
+        let listed = list_body(list_drawings(State(state.clone()), Query(filter), Query(ListOptions::default()), Query(Pagination::default()), HeaderMap::new()).await.unwrap()).await;
+        assert_eq!(listed.drawings.len(), 1);
+        let filter = DrawingFilter { user_agent: Some("firefox".to_string()), ..Default::default() };
+        let listed = list_body(list_drawings(State(state), Query(filter), Query(ListOptions::default()), Query(Pagination::default()), HeaderMap::new()).await.unwrap()).await;
+        assert!(listed.drawings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hash_matches_etag_and_changes_on_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("hashed", &sample_drawing(), None, None).await.unwrap();
+
+        let view = || get_drawing(State(state.clone()), Path("hashed".to_string()), Query(ViewQuery { key: None }), HeaderMap::new());
+        let etag = view().await.unwrap().headers()[header::ETAG].clone();
+        let Json(first) = get_drawing_hash(State(state.clone()), Path("hashed".to_string())).await.unwrap();
+        assert_eq!(etag, format!("\"{}\"", first.hash).as_str());
+
+        let mut updated = sample_drawing();
+        updated["elements"] = serde_json::json!([{"id": "new", "type": "rectangle"}]);
+        state.storage.save("hashed", &updated, None, None).await.unwrap();
+        let Json(second) = get_drawing_hash(State(state.clone()), Path("hashed".to_string())).await.unwrap();
+        assert_ne!(second.hash, first.hash);
+        assert_eq!(view().await.unwrap().headers()[header::ETAG], format!("\"{}\"", second.hash).as_str());
+
+        let missing = get_drawing_hash(State(state), Path("missing".to_string())).await;
+        assert!(matches!(missing, Err(AppError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_if_match_guards_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("shared", &sample_drawing(), None, None).await.unwrap();
+        let view = || get_drawing(State(state.clone()), Path("shared".to_string()), Query(ViewQuery { key: None }), HeaderMap::new());
+        let etag = view().await.unwrap().headers()[header::ETAG].clone();
+
+        let update = |if_match: Option<HeaderValue>, element: &str| {
+            let mut headers = HeaderMap::new();
+            if let Some(tag) = if_match {
+                headers.insert(header::IF_MATCH, tag);
+            }
+            let mut data = sample_drawing();
+            data["elements"] = serde_json::json!([{"id": element, "type": "rectangle", "x": 0, "y": 0}]);
+            let request = UploadRequest { id: Some("shared".to_string()), ..upload_request(data) };
+            upload_drawing(State(state.clone()), None, headers, NonEmptyJson(request))
+        };
+
+        // The first client's update matches; the second, still holding the old tag, is refused
+        assert!(update(Some(etag.clone()), "first").await.is_ok());
+        let result = update(Some(etag.clone()), "second").await;
+        assert!(matches!(result, Err(AppError::PreconditionFailed(_))));
+        assert_eq!(state.storage.load("shared").await.unwrap()["elements"][0]["id"], "first");
+
+        // Without If-Match the last writer wins, and `*` matches any existing version
+        assert!(update(None, "third").await.is_ok());
+        assert!(update(Some(HeaderValue::from_static("*")), "fourth").await.is_ok());
+        // If-Match compares strongly, so a weak tag is refused even when it names the current version
+        let current = view().await.unwrap().headers()[header::ETAG].to_str().unwrap().to_string();
+        let weak = format!("W/{current}");
+        let result = update(Some(HeaderValue::from_str(&weak).unwrap()), "fifth").await;
+        assert!(matches!(result, Err(AppError::PreconditionFailed(_))));
+        assert!(update(Some(HeaderValue::from_str(&format!("{weak}, {current}")).unwrap()), "fifth").await.is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, HeaderValue::from_static("*"));
+        let request = UploadRequest { id: Some("missing".to_string()), ..upload_request(sample_drawing()) };
+        let result = upload_drawing(State(state.clone()), None, headers, NonEmptyJson(request)).await;
+        assert!(matches!(result, Err(AppError::PreconditionFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_max_drawings_refuses_creates_but_not_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.storage.save("existing", &sample_drawing(), None, None).await.unwrap();
+        state.drawing_quota = DrawingQuota::load(Some(2), &state.storage).await.unwrap();
+
+        let upload = |id: Option<&str>, element: &str| {
+            let mut data = sample_drawing();
+            data["elements"] = serde_json::json!([{"id": element, "type": "rectangle", "x": 0, "y": 0}]);
+            let request = UploadRequest { id: id.map(String::from), ..upload_request(data) };
+            upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request))
+        };
+
+        assert!(upload(None, "second").await.is_ok());
+        assert!(matches!(upload(None, "third").await, Err(AppError::QuotaExceeded(_))));
+        assert!(matches!(upload(Some("unknown"), "third").await, Err(AppError::QuotaExceeded(_))));
+        assert_eq!(state.storage.list().await.unwrap().len(), 2);
+
+        assert!(upload(Some("existing"), "updated").await.is_ok());
+        assert_eq!(state.storage.load("existing").await.unwrap()["elements"][0]["id"], "updated");
+        assert_eq!(state.drawing_quota.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_empty_drawings_warned_or_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        let empty = || upload_request(serde_json::json!({"type": "excalidraw", "elements": []}));
+
+        // Off by default
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(empty())).await.unwrap();
+        assert!(uploaded.warnings.is_empty());
+
+        state.warn_empty_drawings = true;
+        let (status, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(empty())).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(uploaded.warnings.len(), 1);
+        assert!(state.storage.exists(&uploaded.id).await.unwrap());
+        let mut drawn = sample_drawing();
+        drawn["elements"] = serde_json::json!([{"id": "r", "type": "rectangle", "x": 0, "y": 0}]);
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(drawn))).await.unwrap();
+        assert!(uploaded.warnings.is_empty());
+
+        state.reject_empty_drawings = true;
+        let result = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(empty())).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_download_filename() {
+        assert_eq!(download_filename(Some("Notes/Roadmap 2024.excalidraw.md"), "abc"), "Roadmap 2024.excalidraw");
+        assert_eq!(download_filename(Some("..\\..\\etc\\passwd"), "abc"), "passwd.excalidraw");
+        assert_eq!(download_filename(Some("a/\"quoted\";.excalidraw"), "abc"), "quoted.excalidraw");
+        assert_eq!(download_filename(Some("dir/../"), "abc"), "abc.excalidraw");
+        assert_eq!(download_filename(None, "abc-1"), "abc-1.excalidraw");
+
+        assert_eq!(attachment_disposition("plan.excalidraw").unwrap(), "attachment; filename=\"plan.excalidraw\"");
+        assert_eq!(
+            attachment_disposition("Plän.excalidraw").unwrap(),
+            "attachment; filename=\"Pl_n.excalidraw\"; filename*=UTF-8''Pl%C3%A4n.excalidraw"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_serves_view_json_as_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("dl", &sample_drawing(), Some("Vault/Sketch.excalidraw.md"), None).await.unwrap();
+
+        let response = download_drawing(State(state.clone()), Path("dl".to_string()), Query(ViewQuery { key: None }), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"Sketch.excalidraw\"");
+        let etag = response.headers()[header::ETAG].clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let view = get_drawing(State(state), Path("dl".to_string()), Query(ViewQuery { key: None }), HeaderMap::new()).await.unwrap();
+        assert_eq!(view.headers()[header::ETAG], etag);
+        assert_eq!(axum::body::to_bytes(view.into_body(), usize::MAX).await.unwrap(), body);
+        assert!(!std::str::from_utf8(&body).unwrap().contains("Vault/"));
+    }
+
+    #[tokio::test]
+    async fn test_svg_file_served_with_disposition() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut data = sample_drawing();
+        data["elements"] = serde_json::json!([
+            {"id": "r", "type": "rectangle", "x": 0, "y": 0, "width": 80, "height": 40},
+            {"id": "i", "type": "image", "x": 0, "y": 0, "width": 10, "height": 10}
+        ]);
+        state.storage.save("vector", &data, None, None).await.unwrap();
+
+        let response = get_drawing_svg(
+            State(state.clone()),
+            Path("vector".to_string()),
+            Query(ViewQuery { key: None }),
+            Query(RenderQuery::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/svg+xml");
+        assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "inline; filename=\"vector.svg\"");
+        assert!(response.headers().contains_key(header::ETAG));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let svg = std::str::from_utf8(&body).unwrap();
+        assert!(svg.starts_with("<svg") && svg.contains("<rect x=\"0\" y=\"0\" width=\"80\""));
+        assert!(svg.contains("<!-- image element not rendered -->"));
+    }
+
+    #[tokio::test]
+    async fn test_png_preview_rendered_and_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut data = sample_drawing();
+        data["elements"] = serde_json::json!([{"id": "r", "type": "rectangle", "x": 0, "y": 0, "width": 80, "height": 40}]);
+        state.storage.save("pic", &data, None, None).await.unwrap();
+
+        let render = |width: Option<u32>| {
+            get_drawing_png(
+                State(state.clone()),
+                Path("pic".to_string()),
+                Query(ViewQuery { key: None }),
+                Query(RenderQuery { theme: Some(render::Theme::Dark), width }),
+                HeaderMap::new(),
+            )
+        };
+        let response = render(Some(200)).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let etag = response.headers()[header::ETAG].clone();
+        let png = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&png[16..20], 200u32.to_be_bytes());
+        let cached_as = |width: u32| {
+            format!(
+                "{}:Some({width}):Some(Dark)",
+                storage::content_hash(&serde_json::to_vec(&storage::public_document(data.clone())).unwrap())
+            )
+        };
+        assert!(state.render_cache.get(&cached_as(200)).is_some());
+
+        // The cached render is served as is, and other sizes are separate entries
+        assert_eq!(render(Some(200)).await.unwrap().headers()[header::ETAG], etag);
+        assert_ne!(render(Some(100)).await.unwrap().headers()[header::ETAG], etag);
+
+        // Oversized widths share the entry of the largest one
+        render(Some(1_000_000)).await.unwrap();
+        assert!(state.render_cache.get(&cached_as(render::MAX_PNG_SIDE)).is_some());
+        assert!(state.render_cache.get(&cached_as(1_000_000)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_weak_and_strong_etags() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.storage.save("tagged", &sample_drawing(), None, None).await.unwrap();
+        let Json(HashResponse { hash }) = get_drawing_hash(State(state.clone()), Path("tagged".to_string())).await.unwrap();
+
+        for (weak, expected) in [(false, format!("\"{hash}\"")), (true, format!("W/\"{hash}\""))] {
+            state.weak_etags = weak;
+            let view = |if_none_match: Option<&str>| {
+                let mut headers = HeaderMap::new();
+                if let Some(tag) = if_none_match {
+                    headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(tag).unwrap());
+                }
+                get_drawing(State(state.clone()), Path("tagged".to_string()), Query(ViewQuery { key: None }), headers)
+            };
+
+            let response = view(None).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::ETAG], expected.as_str());
+
+            // Either form of the tag revalidates, as proxies may weaken it
+            for tag in [format!("\"{hash}\""), format!("W/\"{hash}\""), format!("\"other\", W/\"{hash}\"")] {
+                let response = view(Some(&tag)).await.unwrap();
+                assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "weak={weak} {tag}");
+                assert_eq!(response.headers()[header::ETAG], expected.as_str());
+            }
+            assert_eq!(view(Some("W/\"other\"")).await.unwrap().status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_upload_body_gets_clear_error() {
+        use tower::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let app = axum::Router::new()
+            .route("/api/upload", axum::routing::post(upload_drawing))
+            .with_state(test_state(dir.path()).await);
+        let upload = |body: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/upload")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        for body in ["", " \n\t "] {
+            let response = app.clone().oneshot(upload(body)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{body:?}");
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let error: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(error["error"], "empty request body");
+        }
+
+        let valid = serde_json::to_string(&sample_drawing()).unwrap();
+        let response = app.oneshot(upload(&valid)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_extend_pushes_expiry_and_clamps_to_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut request = upload_request(sample_drawing());
+        request.expires_in_secs = Some(60);
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request))
+            .await
+            .unwrap();
+        let before = state.storage.get_meta(&uploaded.id).await.unwrap().expires_at.unwrap();
+
+        let extend = |secs| {
+            extend_drawing(State(state.clone()), Path(uploaded.id.clone()), Query(ExtendQuery { secs }))
+        };
+        let Json(extended) = extend(Some(3600)).await.unwrap();
+        assert_eq!(extended.expires_at, before + chrono::Duration::seconds(3600));
+        let meta = state.storage.get_meta(&uploaded.id).await.unwrap();
+        assert_eq!(meta.expires_at, Some(extended.expires_at));
+
+        // Far beyond the cap: clamped to the maximum TTL from now
+        let Json(clamped) = extend(Some(365 * 24 * 3600)).await.unwrap();
+        let cap = chrono::Utc::now() + chrono::Duration::seconds(state.max_ttl_secs as i64);
+        assert!(clamped.expires_at <= cap);
+        assert!(clamped.expires_at > cap - chrono::Duration::seconds(5));
+    }
+
+    #[tokio::test]
+    async fn test_huge_ttls_are_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.max_ttl_secs = u64::MAX;
+        let mut request = upload_request(sample_drawing());
+        request.expires_in_secs = Some(u64::MAX);
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request))
+            .await
+            .unwrap();
+        let Json(extended) =
+            extend_drawing(State(state.clone()), Path(uploaded.id.clone()), Query(ExtendQuery { secs: Some(u64::MAX) }))
+                .await
+                .unwrap();
+        let century = chrono::Utc::now() + chrono::Duration::seconds(MAX_EXPIRY_SECS as i64);
+        assert!(extended.expires_at <= century);
+        assert_eq!(state.storage.get_meta(&uploaded.id).await.unwrap().expires_at, Some(extended.expires_at));
+    }
+
+    #[tokio::test]
+    async fn test_expired_drawing_is_gone_and_unextendable_without_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut drawing = sample_drawing();
+        drawing["_expires_at"] = serde_json::json!((chrono::Utc::now() - chrono::Duration::seconds(1)).to_rfc3339());
+        state.storage.save("expired", &drawing, None, None).await.unwrap();
+        let response = get_drawing(State(state.clone()), Path("expired".to_string()), Query(ViewQuery { key: None }), HeaderMap::new()).await;
+        assert!(matches!(response, Err(AppError::Gone)));
+        let summary = get_drawing_summary(State(state.clone()), Path("expired".to_string()), Query(ViewQuery { key: None }), HeaderMap::new()).await;
+        assert!(matches!(summary, Err(AppError::Gone)));
+
+        state.storage.save("forever", &sample_drawing(), None, None).await.unwrap();
+        let result = extend_drawing(State(state), Path("forever".to_string()), Query(ExtendQuery { secs: None })).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_svg_json_returns_well_formed_svg() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let drawing = serde_json::json!({
+            "type": "excalidraw",
+            "elements": [
+                {"id": "r", "type": "rectangle", "x": 0, "y": 0, "width": 40, "height": 20},
+                {"id": "t", "type": "text", "x": 5, "y": 5, "text": "Hi & bye"}
+            ]
+        });
+        state.storage.save("svg", &drawing, None, None).await.unwrap();
+
+        let response = get_drawing_svg_json(State(state), Path("svg".to_string()), Query(ViewQuery { key: None }), Query(RenderQuery::default()), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let SvgResponse { svg } = serde_json::from_slice(&bytes).unwrap();
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"40\" height=\"20\""));
+        assert!(svg.contains("Hi &amp; bye"));
+        // Every element that's opened is closed
+        let opened = svg.matches('<').count() - svg.matches("</").count();
+        assert_eq!(opened, svg.matches("/>").count() + svg.matches("</").count());
+    }
+
+    fn png(len: usize) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.resize(len, 0);
+        png
+    }
+
+    #[tokio::test]
+    async fn test_uploaded_preview_is_stored_and_served() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let mut request = upload_request(sample_drawing());
+        request.preview = Some(format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(png(100))));
+        let (_, Json(uploaded)) = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request))
+            .await
+            .unwrap();
+        assert!(dir.path().join(format!("{}.preview.png", uploaded.id)).exists());
+
+        let response = get_preview(State(state.clone()), Path(uploaded.id.clone()), Query(ViewQuery { key: None }), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes.to_vec(), png(100));
+
+        // Deleting the drawing removes its preview
+        state.storage.delete(&uploaded.id).await.unwrap();
+        assert!(!dir.path().join(format!("{}.preview.png", uploaded.id)).exists());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_or_non_image_preview_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        for preview in [
+            general_purpose::STANDARD.encode(png(2048)),
+            general_purpose::STANDARD.encode(b"GIF89a not a png"),
+            "not base64!".to_string(),
+        ] {
+            let mut request = upload_request(sample_drawing());
+            request.preview = Some(preview);
+            let result = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await;
+            assert!(matches!(result, Err(AppError::BadRequest(_))));
+        }
+        assert!(state.storage.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backslash_source_path_normalized_and_grouped() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        for path in ["Projects\\Work\\plan.excalidraw", "Projects/Work/notes.excalidraw"] {
+            let mut request = upload_request(sample_drawing());
+            request.source_path = Some(path.to_string());
+            let _ = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(request)).await.unwrap();
+        }
+
+        let Json(found) = lookup_by_source_path(
+            State(state.clone()),
+            Query(LookupParams { source_path: "Projects\\Work\\plan.excalidraw".to_string() }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(found.source_path.as_deref(), Some("Projects/Work/plan.excalidraw"));
+
+        let Json(folders) = list_folders(State(state)).await.unwrap();
+        assert_eq!(
+            folders.folders,
+            vec![
+                FolderCount { path: "Projects".to_string(), count: 2 },
+                FolderCount { path: "Projects/Work".to_string(), count: 2 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deleted_drawings_listed_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("first", &sample_drawing(), Some("a/first.excalidraw"), None).await.unwrap();
+        state.storage.save("second", &sample_drawing(), None, None).await.unwrap();
+        state.storage.save("kept", &sample_drawing(), None, None).await.unwrap();
+
+        state.storage.delete("first").await.unwrap();
+        let between = chrono::Utc::now();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        state.storage.delete("second").await.unwrap();
+
+        let Json(all) = list_deleted(State(state.clone()), Query(DeletedQuery { since: None })).await.unwrap();
+        let ids: Vec<&str> = all.drawings.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["first", "second"]);
+        assert_eq!(all.drawings[0].source_path.as_deref(), Some("a/first.excalidraw"));
+        assert!(!all.drawings[0].burned);
+
+        let Json(recent) = list_deleted(State(state.clone()), Query(DeletedQuery { since: Some(between) })).await.unwrap();
+        let ids: Vec<&str> = recent.drawings.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["second"]);
+
+        // A plain delete is not a burn: the drawing reads as missing, not gone
+        let response = get_drawing(State(state), Path("first".to_string()), Query(ViewQuery { key: None }), HeaderMap::new()).await;
+        assert!(matches!(response, Err(AppError::NotFound)));
+    }
+
+    async fn list_body(response: Response) -> ListResponse {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_etag_changes_only_with_the_collection() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("first", &sample_drawing(), None, None).await.unwrap();
+        let list = |headers| {
+            list_drawings(
+                State(state.clone()),
+                Query(DrawingFilter::default()),
+                Query(ListOptions::default()),
+                Query(Pagination::default()),
+                headers,
+            )
+        };
+
+        let response = list(HeaderMap::new()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[header::ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with("W/\""));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let response = list(headers.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+
+        let _ = upload_drawing(State(state.clone()), None, HeaderMap::new(), NonEmptyJson(upload_request(sample_drawing()))).await.unwrap();
+        let response = list(headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag);
+        assert_eq!(list_body(response).await.total, 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_includes_hashes_only_when_requested() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let drawing = sample_drawing();
+        state.storage.save("hashed", &drawing, None, None).await.unwrap();
+
+        let response = list_drawings(State(state.clone()), Query(DrawingFilter::default()), Query(ListOptions::default()), Query(Pagination::default()), HeaderMap::new())
+            .await
+            .unwrap();
+        let listed = list_body(response).await;
+        assert!(listed.drawings[0].content_hash.is_none());
+        assert!(serde_json::to_value(&listed.drawings[0]).unwrap().get("content_hash").is_none());
+
+        let options = ListOptions { include_hash: true };
+        let listed = list_body(list_drawings(State(state), Query(DrawingFilter::default()), Query(options), Query(Pagination::default()), HeaderMap::new()).await.unwrap()).await;
+        assert_eq!(listed.drawings[0].content_hash, Some(storage::document_hash(&drawing)));
+    }
+
+    #[tokio::test]
+    async fn test_list_endpoints_paginate() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        for id in ["a", "b", "c", "d", "e"] {
+            state.storage.save(id, &sample_drawing(), None, None).await.unwrap();
+        }
+        let list = |offset, limit| {
+            list_drawings(
+                State(state.clone()),
+                Query(DrawingFilter::default()),
+                Query(ListOptions::default()),
+                Query(Pagination { offset, limit, ..Default::default() }),
+                HeaderMap::new(),
+            )
+        };
+
+        let first = list_body(list(0, 2).await.unwrap()).await;
+        assert_eq!(first.drawings.len(), 2);
+        assert_eq!(first.total, 5);
+        assert_eq!(first.next_offset, Some(2));
+
+        let last = list_body(list(4, 2).await.unwrap()).await;
+        assert_eq!(last.drawings.len(), 1);
+        assert_eq!(last.next_offset, None);
+
+        let beyond = list_body(list(10, 2).await.unwrap()).await;
+        assert!(beyond.drawings.is_empty());
+        assert_eq!(beyond.total, 5);
+
+        let Json(public) = list_drawings_public(State(state), Query(PublicSearch::default()), Query(Pagination { offset: 3, ..Default::default() })).await.unwrap();
+        assert_eq!(public.drawings.len(), 2);
+        assert_eq!(public.total, 5);
+        assert_eq!(public.next_offset, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_endpoints_search_id_and_source_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        for (id, path) in [("AlphaOne", "Work/a.excalidraw"), ("alphaTwo", "Home/b.excalidraw"), ("beta", "Work/c.excalidraw")] {
+            state.storage.save(id, &sample_drawing(), Some(path), None).await.unwrap();
+        }
+
+        let Query(filter): Query<DrawingFilter> = Query::try_from_uri(&"/api/drawings?q=ALPHA&source_prefix=Work/".parse().unwrap()).unwrap();
+        let listed = list_body(list_drawings(State(state.clone()), Query(filter), Query(ListOptions::default()), Query(Pagination::default()), HeaderMap::new()).await.unwrap()).await;
+        assert_eq!(listed.total, 1);
+        assert_eq!(listed.drawings[0].id, "AlphaOne");
+
+        let search = PublicSearch { q: Some("alpha".to_string()), source_prefix: None };
+        let Json(public) = list_drawings_public(State(state), Query(search), Query(Pagination::default())).await.unwrap();
+        assert_eq!(public.total, 2);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_list_response_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(dir.path()).await;
+        state.max_list_response_bytes = 600;
+        for i in 0..10 {
+            state.storage.save(&format!("drawing-{i}"), &sample_drawing(), Some("Projects/plan.excalidraw"), None).await.unwrap();
+        }
+        let list = |limit| {
+            list_drawings(
+                State(state.clone()),
+                Query(DrawingFilter::default()),
+                Query(ListOptions::default()),
+                Query(Pagination { limit, ..Default::default() }),
+                HeaderMap::new(),
+            )
+        };
+
+        let Err(err) = list(DEFAULT_LIST_LIMIT).await else { panic!("oversized list was returned") };
+        assert!(matches!(err, AppError::ResponseTooLarge(_)));
+        assert_eq!(err.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let page = list_body(list(1).await.unwrap()).await;
+        assert_eq!(page.drawings.len(), 1);
+        assert_eq!(page.next_offset, Some(1));
+
+        let public = list_drawings_public(State(state.clone()), Query(PublicSearch::default()), Query(Pagination::default())).await;
+        assert!(matches!(public, Err(AppError::ResponseTooLarge(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ready_probes_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let state = test_state(&data_dir).await;
+        state.storage.save("one", &sample_drawing(), None, None).await.unwrap();
+        let probe = |state: AppState| async move {
+            let response = ready(State(state)).await;
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<ReadyResponse>(&body).unwrap())
+        };
+
+        let (status, body) = probe(state.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body.status.as_str(), body.backend.as_str()), ("ready", "fs"));
+        assert!(body.storage_ok);
+        assert_eq!(body.drawing_count, Some(1));
+        // Concurrent probes don't trip over each other, and leave nothing behind
+        let probes = futures::future::join_all((0..8).map(|_| probe(state.clone()))).await;
+        assert!(probes.iter().all(|(status, _)| *status == StatusCode::OK));
+        let leftovers = std::fs::read_dir(&data_dir).unwrap().filter_map(|e| e.unwrap().file_name().into_string().ok());
+        assert!(leftovers.filter(|name| name.starts_with(".write-probe")).count() == 0);
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+        let (status, body) = probe(state).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.status, "unavailable");
+        assert!(!body.storage_ok);
+        // No paths or OS error text in the public response
+        assert_eq!(body.error.as_deref(), Some("storage check failed"));
+    }
+
+    #[test]
+    fn test_list_sort_parsed_or_rejected() {
+        let query = |sort: &str, order: &str| Pagination {
+            sort: Some(sort.to_string()),
+            order: Some(order.to_string()),
+            ..Default::default()
+        };
+        let page = query("modified", "asc").page().unwrap();
+        assert_eq!(page.sort, storage::ListSort { field: storage::SortField::Modified, order: storage::SortOrder::Asc });
+        assert_eq!(Pagination::default().page().unwrap().sort, storage::ListSort::default());
+        assert!(matches!(query("views", "asc").page(), Err(AppError::BadRequest(_))));
+        assert!(matches!(query("created", "up").page(), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_list_limit_is_capped() {
+        assert_eq!(Pagination { limit: 1_000_000, ..Default::default() }.page().unwrap().limit, MAX_LIST_LIMIT);
+        assert_eq!(Pagination { limit: 0, ..Default::default() }.page().unwrap().limit, 1);
+        assert_eq!(Pagination::default().page().unwrap().limit, DEFAULT_LIST_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn test_meta_batch_returns_existing_and_reports_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("first", &sample_drawing(), Some("First.excalidraw"), None).await.unwrap();
+        state.storage.save("second", &sample_drawing(), None, None).await.unwrap();
+        state.view_counter.increment("second").await;
+
+        let ids = ["first", "nope", "second", "first"].map(String::from).to_vec();
+        let Json(batch) = get_drawing_meta_batch(State(state.clone()), Json(MetaBatchRequest { ids })).await.unwrap();
+        assert_eq!(batch.drawings.len(), 2);
+        assert_eq!(batch.drawings["first"].source_path.as_deref(), Some("First.excalidraw"));
+        assert_eq!(batch.drawings["second"].views, 1);
+        assert_eq!(batch.missing, ["nope"]);
+
+        let ids = vec!["x".to_string(); MAX_META_BATCH + 1];
+        let result = get_drawing_meta_batch(State(state), Json(MetaBatchRequest { ids })).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_reports_each_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        for id in ["one", "two"] {
+            state.storage.save(id, &sample_drawing(), None, None).await.unwrap();
+        }
+
+        let ids = ["one", "nope", "../two", "two", "one"].map(String::from).to_vec();
+        let Json(response) = bulk_delete_drawings(State(state.clone()), Json(BulkDeleteRequest { ids })).await.unwrap();
+        let statuses: Vec<_> = response.results.iter().map(|r| (r.id.as_str(), &r.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                ("one", &BulkDeleteStatus::Deleted),
+                ("nope", &BulkDeleteStatus::NotFound),
+                ("../two", &BulkDeleteStatus::Error),
+                ("two", &BulkDeleteStatus::Deleted),
+            ]
+        );
+        assert!(response.results[2].error.as_deref().is_some_and(|e| e.contains("invalid drawing id")));
+        assert!(state.storage.list().await.unwrap().is_empty());
+
+        let ids = vec!["x".to_string(); MAX_BULK_DELETE + 1];
+        let result = bulk_delete_drawings(State(state.clone()), Json(BulkDeleteRequest { ids })).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let mut state = state;
+        state.require_delete_confirmation = true;
+        let result = bulk_delete_drawings(State(state), Json(BulkDeleteRequest { ids: vec!["one".into()] })).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_view_last_modified_and_if_modified_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("dated", &sample_drawing(), None, None).await.unwrap();
+        let view = |headers: HeaderMap| {
+            get_drawing(State(state.clone()), Path("dated".to_string()), Query(ViewQuery { key: None }), headers)
+        };
+
+        let response = view(HeaderMap::new()).await.unwrap();
+        let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+        assert!(last_modified.to_str().unwrap().ends_with(" GMT"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+        let response = view(headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.headers().contains_key(header::ETAG));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, HeaderValue::from_static("Mon, 01 Jan 2001 00:00:00 GMT"));
+        assert_eq!(view(headers).await.unwrap().status(), StatusCode::OK);
+
+        // If-None-Match decides when both are sent
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"stale\""));
+        assert_eq!(view(headers).await.unwrap().status(), StatusCode::OK);
+
+        // A 304 doesn't use up a view of a view-limited drawing
+        let mut limited = sample_drawing();
+        limited["_max_views"] = json!(2);
+        state.storage.save("limited", &limited, None, None).await.unwrap();
+        let view_limited = |headers: HeaderMap| {
+            get_drawing(State(state.clone()), Path("limited".to_string()), Query(ViewQuery { key: None }), headers)
+        };
+        let last_modified = view_limited(HeaderMap::new()).await.unwrap().headers()[header::LAST_MODIFIED].clone();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified);
+        assert_eq!(view_limited(headers).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(view_limited(HeaderMap::new()).await.unwrap().status(), StatusCode::OK);
+        assert!(matches!(view_limited(HeaderMap::new()).await, Err(AppError::Gone)));
+    }
+
+    #[tokio::test]
+    async fn test_head_view_matches_get_and_counts_nothing() {
+        use tower::ServiceExt;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        state.storage.save("headed", &sample_drawing(), None, None).await.unwrap();
+        let mut limited = sample_drawing();
+        limited["_max_views"] = json!(1);
+        state.storage.save("limited", &limited, None, None).await.unwrap();
+        let app = axum::Router::new()
+            .route("/api/view/{id}", axum::routing::get(get_drawing).head(head_drawing))
+            .with_state(state.clone());
+        let head = |uri: &str, condition: Option<(header::HeaderName, HeaderValue)>| {
+            let mut request = axum::http::Request::head(uri);
+            if let Some((name, value)) = condition {
+                request = request.header(name, value);
+            }
+            app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+        };
+
+        let response = head("/api/view/headed", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CACHE_CONTROL].to_str().unwrap().starts_with("public"));
+        let etag = response.headers()[header::ETAG].clone();
+        let length = response.headers()[header::CONTENT_LENGTH].clone();
+        let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+        assert!(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+        assert_eq!(state.view_counter.pending("headed").await, 0);
+
+        // Conditional requests are answered as a `GET` would answer them
+        let response = head("/api/view/headed", Some((header::IF_NONE_MATCH, etag.clone()))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        let response = head("/api/view/headed", Some((header::IF_MODIFIED_SINCE, last_modified))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let stale = HeaderValue::from_static("\"stale\"");
+        assert_eq!(head("/api/view/headed", Some((header::IF_NONE_MATCH, stale))).await.unwrap().status(), StatusCode::OK);
+
+        // A view-limited drawing keeps its view, and stays out of shared caches
+        for _ in 0..2 {
+            let response = head("/api/view/limited", None).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::CACHE_CONTROL], "private, no-store");
+        }
+        assert_eq!(state.storage.get_meta("limited").await.unwrap().views, 0);
+        assert_eq!(head("/api/view/missing", None).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        let request = axum::http::Request::get("/api/view/headed").body(axum::body::Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(length.to_str().unwrap(), body.len().to_string());
+        assert_eq!(state.view_counter.pending("headed").await, 1);
+    }
 }
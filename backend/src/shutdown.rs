@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    serve::{Listener, ListenerExt},
+    Json, Router,
+};
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How long to wait for connections to close once their requests were cut off.
+const CUT_OFF_GRACE: Duration = Duration::from_secs(1);
+
+/// Requests being handled, and a switch that ends them all early.
+#[derive(Clone)]
+struct InFlight {
+    count: Arc<AtomicUsize>,
+    cut_off: watch::Sender<bool>,
+}
+
+/// Decrements the in-flight count when a request finishes or is dropped.
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Middleware counting in-flight requests. Once shutdown gives up waiting, the
+/// remaining ones are abandoned with a 503 so their connections can close.
+async fn in_flight_middleware(State(in_flight): State<InFlight>, request: Request, next: Next) -> Response {
+    in_flight.count.fetch_add(1, Ordering::AcqRel);
+    let _guard = InFlightGuard(in_flight.count.clone());
+    let mut cut_off = in_flight.cut_off.subscribe();
+
+    tokio::select! {
+        response = next.run(request) => response,
+        _ = cut_off.wait_for(|cut| *cut) => {
+            let body = Json(serde_json::json!({ "error": "Server is shutting down" }));
+            (StatusCode::SERVICE_UNAVAILABLE, body).into_response()
+        }
+    }
+}
+
+/// Serve `app` until `signal` resolves, then let in-flight requests finish.
+/// With a `timeout`, requests still running that long after the signal are cut
+/// off so a stuck client can't hold up a restart. Returns how many were dropped.
+pub async fn serve<L: Listener<Addr = SocketAddr>>(
+    listener: L,
+    app: Router,
+    signal: impl Future<Output = ()> + Send + 'static,
+    timeout: Option<Duration>,
+) -> std::io::Result<usize> {
+    let in_flight = InFlight { count: Arc::default(), cut_off: watch::channel(false).0 };
+    let app = app.layer(middleware::from_fn_with_state(in_flight.clone(), in_flight_middleware));
+
+    let (draining_tx, mut draining) = watch::channel(false);
+    let count = in_flight.count.clone();
+    // Tapping makes the peer address available as `ConnectInfo` for any listener
+    let listener = listener.tap_io(|_| {});
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            signal.await;
+            tracing::info!(in_flight = count.load(Ordering::Acquire), "Shutting down, draining connections");
+            let _ = draining_tx.send(true);
+        })
+        .into_future();
+    tokio::pin!(server);
+
+    let Some(timeout) = timeout else {
+        server.await?;
+        return Ok(0);
+    };
+    tokio::select! {
+        result = &mut server => return result.map(|_| 0),
+        _ = async {
+            let _ = draining.wait_for(|draining| *draining).await;
+            tokio::time::sleep(timeout).await;
+        } => {}
+    }
+
+    let dropped = in_flight.count.load(Ordering::Acquire);
+    tracing::warn!(dropped, timeout_secs = timeout.as_secs_f64(), "Shutdown timeout elapsed; dropping in-flight requests");
+    let _ = in_flight.cut_off.send(true);
+    if tokio::time::timeout(CUT_OFF_GRACE, &mut server).await.is_err() {
+        tracing::warn!("Connections still open after cutting off requests; exiting anyway");
+    }
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::time::Instant;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_stuck_request_cut_off_after_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                "done"
+            }),
+        );
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            async {
+                let _ = stop_rx.await;
+            },
+            Some(Duration::from_millis(200)),
+        ));
+
+        let request = tokio::spawn(reqwest::get(format!("http://{addr}/slow")));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let started = Instant::now();
+        stop_tx.send(()).unwrap();
+
+        let dropped = server.await.unwrap().unwrap();
+        assert_eq!(dropped, 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_finished_requests_need_no_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/fast", get(|| async { "ok" }));
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, async { let _ = stop_rx.await; }, Some(Duration::from_secs(30))));
+
+        let response = reqwest::get(format!("http://{addr}/fast")).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+        stop_tx.send(()).unwrap();
+        let started = Instant::now();
+        assert_eq!(server.await.unwrap().unwrap(), 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::error::AppError;
+use crate::storage::DrawingStorage;
+
+/// Longest slug generated from a title (before any collision suffix).
+const MAX_SLUG_LEN: usize = 64;
+
+/// Turn a title into a URL-safe slug: lowercase ASCII letters and digits,
+/// with every other run of characters collapsed into a single hyphen.
+/// Returns `None` when nothing usable remains (e.g. an all-emoji title).
+pub fn slugify(title: &str) -> Option<String> {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    (!slug.is_empty()).then(|| slug.to_string())
+}
+
+/// First of `base`, `base-2`, `base-3`, ... not taken by a drawing other than `id`.
+pub async fn unique_slug(storage: &impl DrawingStorage, base: &str, id: &str) -> Result<String, AppError> {
+    let mut candidate = base.to_string();
+    let mut suffix = 2;
+    loop {
+        match storage.find_by_slug(&candidate).await? {
+            Some(existing) if existing.id != id => {
+                candidate = format!("{base}-{suffix}");
+                suffix += 1;
+            }
+            _ => return Ok(candidate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Q3 Roadmap").as_deref(), Some("q3-roadmap"));
+        assert_eq!(slugify("  System -- Design: v2! ").as_deref(), Some("system-design-v2"));
+        assert_eq!(slugify("Café Übersicht").as_deref(), Some("caf-bersicht"));
+        assert_eq!(slugify("🎨✨"), None);
+        assert_eq!(slugify(&"a".repeat(100)).unwrap().len(), MAX_SLUG_LEN);
+    }
+}
@@ -0,0 +1,113 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::storage::{DrawingStorage, Storage};
+
+/// State for the SPA fallback used when a custom not-found page is configured.
+#[derive(Clone)]
+pub struct SpaState {
+    pub storage: Storage,
+    pub index_file: PathBuf,
+    pub not_found_page: PathBuf,
+}
+
+/// Whether a path is a client-side route of the frontend that should get `index.html`.
+/// Returns the drawing id for `/d/{id}` so its existence can be checked.
+enum SpaRoute<'a> {
+    Page,
+    Drawing(&'a str),
+    Unknown,
+}
+
+fn classify(path: &str) -> SpaRoute<'_> {
+    let path = path.trim_end_matches('/');
+    match path {
+        "" | "/admin" => SpaRoute::Page,
+        _ => match path.strip_prefix("/d/") {
+            Some(id) if !id.is_empty() && !id.contains('/') => SpaRoute::Drawing(id),
+            _ => SpaRoute::Unknown,
+        },
+    }
+}
+
+async fn html(path: &PathBuf, status: StatusCode) -> Response {
+    match fs::read(path).await {
+        Ok(bytes) => (status, [(header::CONTENT_TYPE, "text/html; charset=utf-8")], bytes).into_response(),
+        Err(e) => {
+            tracing::error!(path = %path.display(), error = %e, "Failed to read frontend page");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Fallback in API-only mode: no frontend, every unmatched path is a JSON 404.
+pub async fn api_only_fallback() -> Response {
+    (StatusCode::NOT_FOUND, axum::Json(serde_json::json!({ "error": "Not found" }))).into_response()
+}
+
+/// Fallback for paths that don't match a static file: known SPA routes get
+/// `index.html`, everything else (including `/d/{id}` for a missing drawing)
+/// gets the custom not-found page with a real 404 status.
+pub async fn spa_fallback(State(state): State<SpaState>, uri: Uri) -> Response {
+    let serve_index = match classify(uri.path()) {
+        SpaRoute::Page => true,
+        SpaRoute::Drawing(id) => state.storage.exists(id).await.unwrap_or(false),
+        SpaRoute::Unknown => false,
+    };
+
+    if serve_index {
+        html(&state.index_file, StatusCode::OK).await
+    } else {
+        html(&state.not_found_page, StatusCode::NOT_FOUND).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileSystemStorage;
+
+    async fn test_state(dir: &std::path::Path) -> SpaState {
+        let index_file = dir.join("index.html");
+        let not_found_page = dir.join("404.html");
+        std::fs::write(&index_file, "<html>app</html>").unwrap();
+        std::fs::write(&not_found_page, "<html>custom 404</html>").unwrap();
+        SpaState {
+            storage: FileSystemStorage::new(dir.join("drawings")).await.unwrap().into(),
+            index_file,
+            not_found_page,
+        }
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_missing_drawing_gets_custom_404() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path()).await;
+        let drawing = serde_json::json!({ "type": "excalidraw", "elements": [] });
+        state.storage.save("exists", &drawing, None, None).await.unwrap();
+
+        let response = spa_fallback(State(state.clone()), Uri::from_static("/d/missing")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body(response).await, "<html>custom 404</html>");
+
+        let response = spa_fallback(State(state.clone()), Uri::from_static("/d/exists")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, "<html>app</html>");
+
+        let response = spa_fallback(State(state.clone()), Uri::from_static("/admin")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = spa_fallback(State(state), Uri::from_static("/no/such/page")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
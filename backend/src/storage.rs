@@ -1,31 +1,852 @@
+use crate::cache::{CacheUsage, CachedStorage};
 use crate::error::AppError;
+use crate::locks::KeyedLocks;
+use crate::quota::ByteQuota;
+use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Utc};
+use rusqlite::{params, types::Value as SqlValue, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 
 /// Metadata about a stored drawing.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DrawingMeta {
     pub id: String,
     pub created_at: DateTime<Utc>,
+    /// Last content change; equal to `created_at` until the drawing is updated
+    pub modified_at: DateTime<Utc>,
     pub size_bytes: u64,
     pub source_path: Option<String>,
     pub password_protected: bool,
     pub persistent_collab: bool,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// URL-safe alias, viewable at `/s/{slug}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    /// `User-Agent` of the client that created the drawing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// When the drawing stops being served (TTL shares)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Persisted view count; views batched in memory are added on the next flush
+    #[serde(default)]
+    pub views: u64,
+    /// Hash of the drawing's content (see [`document_hash`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// A drawing's metadata with its password hash and view limit, from
+/// [`DrawingStorage::meta`].
+#[derive(Debug, Clone)]
+pub struct DrawingAccess {
+    pub meta: DrawingMeta,
+    pub password_hash: Option<String>,
+    /// `_max_views`; `None` also for metadata written before it was kept
+    pub max_views: Option<u64>,
+}
+
+/// Size and content hash of a drawing as `/api/view/{id}` serves it, from
+/// [`DrawingStorage::head`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawingHead {
+    /// Length of the served JSON body in bytes
+    pub size: u64,
+    /// [`content_hash`] of that body, the `ETag` value
+    pub hash: String,
+}
+
+impl DrawingHead {
+    /// The head of a stored drawing once its internal fields are stripped.
+    pub fn of(data: serde_json::Value) -> Result<Self, AppError> {
+        let body = serde_json::to_vec(&public_document(data))?;
+        Ok(Self { size: body.len() as u64, hash: content_hash(&body) })
+    }
+}
+
+/// What remains of a deleted drawing, so clients can mirror the deletion.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub id: String,
+    pub deleted_at: DateTime<Utc>,
+    pub source_path: Option<String>,
+    /// Removed by burn-after-reading rather than an explicit delete
+    #[serde(default)]
+    pub burned: bool,
+}
+
+/// A soft-deleted drawing, kept in the trash until restored or purged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedDrawing {
+    pub id: String,
+    pub deleted_at: DateTime<Utc>,
+    pub source_path: Option<String>,
+    pub size_bytes: u64,
+}
+
+/// An earlier version of a drawing, kept when an update replaced its content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrawingVersion {
+    pub version: u64,
+    /// When this content was saved, before it was replaced
+    pub saved_at: DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+impl DrawingMeta {
+    /// Whether the drawing's TTL has run out.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|t| t <= Utc::now())
+    }
 }
 
 /// Lightweight sidecar metadata stored alongside each drawing.
 /// Avoids reading the full drawing JSON just to list metadata.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct SidecarMeta {
     pub created_at: DateTime<Utc>,
+    /// `None` for sidecars written before modifications were tracked
+    #[serde(default)]
+    pub modified_at: Option<DateTime<Utc>>,
     #[serde(default)]
     pub source_path: Option<String>,
     #[serde(default)]
     pub password_protected: bool,
     #[serde(default)]
     pub persistent_collab: bool,
+    /// Count of live (non-deleted) elements per element type.
+    /// `None` for sidecars written before histograms were tracked.
+    #[serde(default)]
+    pub element_types: Option<BTreeMap<String, u64>>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Structural summary of the document, `None` for sidecars written before it was tracked.
+    #[serde(default)]
+    pub content: Option<ContentSummary>,
+    /// `document_hash` of the stored content
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Counted views: synchronously for drawings with a `max_views` limit,
+    /// batched through [`crate::counters::ViewCounter`] for all others
+    #[serde(default)]
+    pub views: u64,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub slug: Option<String>,
+    /// Text of the drawing's text elements, for search.
+    /// `None` for sidecars written before it was tracked.
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// `_password_hash` of the drawing, so access can be checked without loading
+    /// it. `None` for sidecars written before it was kept here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    /// `_max_views` of the drawing, `None` when unlimited (or written before it
+    /// was kept here)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_views: Option<u64>,
+}
+
+impl SidecarMeta {
+    /// Metadata for a drawing about to be stored. `data` is the document as
+    /// uploaded, `data_with_meta` the same with internal fields merged in.
+    /// Updates keep the `existing` creation time and view count.
+    fn for_document(
+        data: &serde_json::Value,
+        data_with_meta: &serde_json::Value,
+        source_path: Option<&str>,
+        existing: Option<&SidecarMeta>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            // A supplied `_created_at` (migrated history) wins over the stored one
+            created_at: created_at(data_with_meta).or(existing.map(|m| m.created_at)).unwrap_or(now),
+            modified_at: Some(now),
+            source_path: source_path.map(String::from),
+            password_protected: data_with_meta.get("_password_hash").is_some(),
+            persistent_collab: data_with_meta
+                .get("_persistent_collab")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            element_types: Some(element_type_histogram(data)),
+            tags: tags_from(data_with_meta),
+            content: Some(content_summary(data)),
+            content_hash: Some(document_hash(data)),
+            views: existing.map_or(0, |m| m.views),
+            title: text_field(data_with_meta, "_title"),
+            slug: text_field(data_with_meta, "_slug"),
+            text: Some(text_content(data)),
+            user_agent: text_field(data_with_meta, "_user_agent"),
+            expires_at: expires_at(data_with_meta),
+            password_hash: text_field(data_with_meta, "_password_hash"),
+            max_views: data_with_meta.get("_max_views").and_then(|v| v.as_u64()),
+        }
+    }
+
+    /// Refresh the content-derived fields after a persistent collab save.
+    fn update_persistent_content(&mut self, data: &serde_json::Value) {
+        self.modified_at = Some(Utc::now());
+        self.persistent_collab = true;
+        self.element_types = Some(element_type_histogram(data));
+        self.content = Some(content_summary(data));
+        self.content_hash = Some(document_hash(data));
+        self.text = Some(text_content(data));
+    }
+
+    /// Combine sidecar fields with per-file information into a `DrawingMeta`.
+    fn to_meta(&self, id: &str, size_bytes: u64) -> DrawingMeta {
+        DrawingMeta {
+            id: id.to_string(),
+            created_at: self.created_at,
+            modified_at: self.modified_at.unwrap_or(self.created_at),
+            size_bytes,
+            source_path: self.source_path.clone(),
+            password_protected: self.password_protected,
+            persistent_collab: self.persistent_collab,
+            tags: self.tags.clone(),
+            title: self.title.clone(),
+            slug: self.slug.clone(),
+            user_agent: self.user_agent.clone(),
+            expires_at: self.expires_at,
+            views: self.views,
+            content_hash: self.content_hash.clone(),
+        }
+    }
+}
+
+/// Remove every top-level `_` field from a client-supplied document. Those are
+/// internal fields, and only the server may set them.
+pub fn strip_internal_fields(data: &mut serde_json::Value) {
+    if let Some(obj) = data.as_object_mut() {
+        obj.retain(|key, _| !key.starts_with('_'));
+    }
+}
+
+/// The document as stored: `data` plus internal `_` fields. The source path and
+/// password hash are set (or the hash removed) from the arguments; other internal
+/// fields missing from `data` are carried over from the `existing` stored document.
+fn with_internal_fields(
+    data: &serde_json::Value,
+    source_path: Option<&str>,
+    password_hash: Option<&str>,
+    existing: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    let mut data_with_meta = data.clone();
+    if let Some(obj) = data_with_meta.as_object_mut() {
+        // Only the SQLite backend writes blob references
+        obj.remove(SQLITE_BLOB_FIELD);
+        if let Some(sp) = source_path {
+            obj.insert("_source_path".to_string(), serde_json::Value::String(sp.to_string()));
+        }
+        // Store or remove password hash
+        if let Some(ph) = password_hash {
+            obj.insert("_password_hash".to_string(), serde_json::Value::String(ph.to_string()));
+        } else {
+            obj.remove("_password_hash");
+        }
+
+        // Preserve persistent collab fields from existing drawing if not in new data
+        if let Some(existing_data) = existing {
+            if !obj.contains_key("_persistent_collab") {
+                if let Some(pc) = existing_data.get("_persistent_collab") {
+                    obj.insert("_persistent_collab".to_string(), pc.clone());
+                }
+            }
+            if !obj.contains_key("_persistent_collab_version") {
+                if let Some(pcv) = existing_data.get("_persistent_collab_version") {
+                    obj.insert("_persistent_collab_version".to_string(), pcv.clone());
+                }
+            }
+            if !obj.contains_key("_persistent_collab_password_hash") {
+                if let Some(pcph) = existing_data.get("_persistent_collab_password_hash") {
+                    obj.insert("_persistent_collab_password_hash".to_string(), pcph.clone());
+                }
+            }
+            if !obj.contains_key("_tags") {
+                if let Some(tags) = existing_data.get("_tags") {
+                    obj.insert("_tags".to_string(), tags.clone());
+                }
+            }
+            for key in ["_max_views", "_title", "_slug", "_user_agent", "_expires_at", "_created_at"] {
+                if !obj.contains_key(key) {
+                    if let Some(value) = existing_data.get(key) {
+                        obj.insert(key.to_string(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    data_with_meta
+}
+
+/// The document stored by a persistent collab save: `data` with the existing
+/// drawing's source path and passwords, the collab flag and the new version.
+fn persistent_document(data: &serde_json::Value, existing: &serde_json::Value, version: u64) -> serde_json::Value {
+    let mut data_with_meta = data.clone();
+    if let Some(obj) = data_with_meta.as_object_mut() {
+        obj.remove(SQLITE_BLOB_FIELD);
+        // Preserve _source_path from existing
+        if let Some(sp) = existing.get("_source_path") {
+            obj.insert("_source_path".to_string(), sp.clone());
+        }
+        // Preserve _password_hash from existing
+        if let Some(ph) = existing.get("_password_hash") {
+            obj.insert("_password_hash".to_string(), ph.clone());
+        }
+        // Preserve _persistent_collab_password_hash from existing
+        if let Some(pcph) = existing.get("_persistent_collab_password_hash") {
+            obj.insert("_persistent_collab_password_hash".to_string(), pcph.clone());
+        }
+        // Preserve embedded metadata from existing
+        if let Some(share) = existing.get("_share") {
+            obj.insert("_share".to_string(), share.clone());
+        }
+        // Ensure persistent collab flag stays true
+        obj.insert("_persistent_collab".to_string(), serde_json::Value::Bool(true));
+        // Set the version
+        obj.insert("_persistent_collab_version".to_string(), serde_json::json!(version));
+    }
+
+    data_with_meta
+}
+
+/// Read the `_expires_at` timestamp of a TTL drawing.
+pub fn expires_at(data: &serde_json::Value) -> Option<DateTime<Utc>> {
+    text_field(data, "_expires_at")
+        .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Whether one of a stored drawing's files was extracted to the asset `hash`.
+pub fn references_asset(data: &serde_json::Value, hash: &str) -> bool {
+    is_asset_hash(hash)
+        && data
+            .get("files")
+            .and_then(|v| v.as_object())
+            .is_some_and(|files| files.values().any(|f| f.get("_asset").and_then(|v| v.as_str()) == Some(hash)))
+}
+
+/// Whether `hash` has the form of the SHA-256 hex digests assets are stored under.
+fn is_asset_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Creation time carried over from another system in `_created_at` (RFC 3339).
+pub fn created_at(data: &serde_json::Value) -> Option<DateTime<Utc>> {
+    text_field(data, "_created_at")
+        .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Read an internal string field such as `_title` from a drawing document.
+fn text_field(data: &serde_json::Value, key: &str) -> Option<String> {
+    data.get(key).and_then(|v| v.as_str()).map(String::from)
+}
+
+/// Read the normalized tag list stored under `_tags` in a drawing document.
+fn tags_from(data: &serde_json::Value) -> Vec<String> {
+    data.get("_tags")
+        .and_then(|v| v.as_array())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|t| t.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Filters accepted by the list and export endpoints. Unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DrawingFilter {
+    /// Only drawings carrying this tag (case-insensitive)
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Only drawings whose `source_path` starts with this prefix
+    #[serde(default, alias = "source_prefix")]
+    pub source_path: Option<String>,
+    /// Only drawings whose id contains this (case-insensitive)
+    #[serde(default)]
+    pub q: Option<String>,
+    /// Only drawings created at or after this time
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// Only drawings created at or before this time
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    /// Only drawings whose creating client's `User-Agent` contains this (case-insensitive)
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Leave out drawings whose TTL has run out. Set by the public listing,
+    /// not a query parameter.
+    #[serde(skip)]
+    pub exclude_expired: bool,
+}
+
+impl DrawingFilter {
+    pub fn matches(&self, meta: &DrawingMeta) -> bool {
+        if self.exclude_expired && meta.is_expired() {
+            return false;
+        }
+        if let Some(tag) = &self.tag {
+            let tag = tag.trim().to_lowercase();
+            if !meta.tags.contains(&tag) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.source_path {
+            if !meta.source_path.as_deref().is_some_and(|sp| sp.starts_with(prefix.as_str())) {
+                return false;
+            }
+        }
+        if let Some(q) = &self.q {
+            if !meta.id.to_lowercase().contains(&q.to_lowercase()) {
+                return false;
+            }
+        }
+        if self.from.is_some_and(|from| meta.created_at < from) {
+            return false;
+        }
+        if self.to.is_some_and(|to| meta.created_at > to) {
+            return false;
+        }
+        if let Some(needle) = &self.user_agent {
+            let needle = needle.to_lowercase();
+            if !meta.user_agent.as_deref().is_some_and(|ua| ua.to_lowercase().contains(&needle)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Timestamp the drawing list is sorted by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortField {
+    #[default]
+    Created,
+    Modified,
+}
+
+impl std::str::FromStr for SortField {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s {
+            "created" => Ok(Self::Created),
+            "modified" => Ok(Self::Modified),
+            _ => Err(AppError::BadRequest(format!("invalid sort '{s}', expected 'created' or 'modified'"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s {
+            "asc" => Ok(Self::Asc),
+            "desc" => Ok(Self::Desc),
+            _ => Err(AppError::BadRequest(format!("invalid order '{s}', expected 'asc' or 'desc'"))),
+        }
+    }
+}
+
+/// Order of a listed page; newest created first by default. Drawings with
+/// equal timestamps are ordered by id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListSort {
+    pub field: SortField,
+    pub order: SortOrder,
+}
+
+impl ListSort {
+    pub fn compare(self, a: &DrawingMeta, b: &DrawingMeta) -> std::cmp::Ordering {
+        let (a_at, b_at) = match self.field {
+            SortField::Created => (a.created_at, b.created_at),
+            SortField::Modified => (a.modified_at, b.modified_at),
+        };
+        let by_time = match self.order {
+            SortOrder::Asc => a_at.cmp(&b_at),
+            SortOrder::Desc => b_at.cmp(&a_at),
+        };
+        by_time.then_with(|| a.id.cmp(&b.id))
+    }
+
+    /// The `ORDER BY` terms for the SQLite `drawings` table.
+    fn sqlite_order(self) -> String {
+        let column = match self.field {
+            SortField::Created => "created_at",
+            // Rows written before modification times were tracked lack one. The
+            // column's `2024-01-02 03:04:05.678+00:00` text sorts in time order
+            // at full precision, so the RFC 3339 time from `meta` is rewritten to
+            // match rather than compared by julianday, which keeps milliseconds only
+            SortField::Modified => {
+                "replace(replace(COALESCE(json_extract(meta, '$.modified_at'), created_at), 'T', ' '), 'Z', '+00:00')"
+            }
+        };
+        let direction = match self.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        format!("{column} {direction}, id")
+    }
+}
+
+/// A window into the sorted drawing list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+    pub sort: ListSort,
+}
+
+/// Sort an already filtered listing and cut `page` out of it; returns it with
+/// the total count.
+pub(crate) fn page_of(mut matching: Vec<DrawingMeta>, page: Page) -> (Vec<DrawingMeta>, usize) {
+    matching.sort_by(|a, b| page.sort.compare(a, b));
+    let total = matching.len();
+    (matching.into_iter().skip(page.offset).take(page.limit).collect(), total)
+}
+
+/// Aggregate statistics across all stored drawings.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageStats {
+    pub drawing_count: usize,
+    pub total_bytes: u64,
+    pub element_types: BTreeMap<String, u64>,
+}
+
+/// Drift between a backend's metadata index and the drawings it describes,
+/// found by [`DrawingStorage::verify`]. Nothing is repaired.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct VerifyReport {
+    /// Drawings checked
+    pub checked: usize,
+    /// Drawings without (readable) index metadata
+    pub missing_from_index: Vec<String>,
+    /// Index metadata left behind for drawings that no longer exist
+    pub orphaned_index_entries: Vec<String>,
+    /// Drawings whose indexed `content_hash` doesn't match their content
+    pub hash_mismatches: Vec<HashMismatch>,
+    /// Drawings indexed without a `content_hash`, so their content couldn't be
+    /// checked. Not a discrepancy: metadata from before hashes were kept has none.
+    pub unhashed: Vec<String>,
+    /// Drawings a derived index (cached listing, lookup columns) disagrees about
+    pub stale_index_entries: Vec<String>,
+    /// Drawings whose stored document can't be parsed
+    pub unreadable: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HashMismatch {
+    pub id: String,
+    pub indexed: String,
+    pub actual: String,
+}
+
+impl VerifyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_from_index.is_empty()
+            && self.orphaned_index_entries.is_empty()
+            && self.hash_mismatches.is_empty()
+            && self.stale_index_entries.is_empty()
+            && self.unreadable.is_empty()
+    }
+
+    /// Compare the hash indexed for a drawing with that of its content. With
+    /// no hash indexed there is nothing to compare against.
+    fn check_hash(&mut self, id: &str, indexed: Option<String>, data: &serde_json::Value) {
+        let Some(indexed) = indexed else {
+            self.unhashed.push(id.to_string());
+            return;
+        };
+        let actual = document_hash(data);
+        if indexed != actual {
+            self.hash_mismatches.push(HashMismatch { id: id.to_string(), indexed, actual });
+        }
+    }
+}
+
+/// Cheap structural summary of a drawing document, computed at save time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentSummary {
+    /// Top-level keys of the document, excluding internal `_`-prefixed fields.
+    pub keys: Vec<String>,
+    /// Number of live (non-deleted) elements.
+    pub element_count: usize,
+    pub has_files: bool,
+    pub file_count: usize,
+    /// Total length of the embedded `files` data URLs in bytes.
+    pub embedded_bytes: u64,
+}
+
+/// Summary of a stored drawing, as served by `GET /api/view/{id}/summary`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DrawingSummary {
+    pub id: String,
+    pub size_bytes: u64,
+    pub password_protected: bool,
+    #[serde(flatten)]
+    pub content: ContentSummary,
+}
+
+/// Compute the structural summary of a drawing document.
+pub fn content_summary(data: &serde_json::Value) -> ContentSummary {
+    let keys = data
+        .as_object()
+        .map(|obj| {
+            obj.keys()
+                .filter(|k| !k.starts_with('_'))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let element_count = element_type_histogram(data).values().sum::<u64>() as usize;
+    let files = data.get("files").and_then(|v| v.as_object());
+    let file_count = files.map_or(0, |f| f.len());
+    let embedded_bytes = files
+        .map(|f| {
+            f.values()
+                .filter_map(|file| file.get("dataURL").and_then(|v| v.as_str()))
+                .map(|url| url.len() as u64)
+                .sum()
+        })
+        .unwrap_or(0);
+
+    ContentSummary {
+        keys,
+        element_count,
+        has_files: file_count > 0,
+        file_count,
+        embedded_bytes,
+    }
+}
+
+/// Count the live elements of a drawing by their `type` field.
+/// Elements marked `isDeleted` are skipped, matching what the viewer renders.
+pub fn element_type_histogram(data: &serde_json::Value) -> BTreeMap<String, u64> {
+    let mut histogram = BTreeMap::new();
+    let elements = data
+        .get("elements")
+        .and_then(|v| v.as_array())
+        .map(|a| a.as_slice())
+        .unwrap_or(&[]);
+    for element in elements {
+        if element.get("isDeleted").and_then(|v| v.as_bool()).unwrap_or(false) {
+            continue;
+        }
+        let element_type = element
+            .get("type")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown");
+        *histogram.entry(element_type.to_string()).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// Upper bound on the text kept per drawing for search.
+const MAX_TEXT_CONTENT_BYTES: usize = 64 * 1024;
+
+/// Text of all live text elements, one per line, for full-text search.
+pub fn text_content(data: &serde_json::Value) -> String {
+    let elements = data
+        .get("elements")
+        .and_then(|v| v.as_array())
+        .map(|a| a.as_slice())
+        .unwrap_or(&[]);
+    let mut text = String::new();
+    for element in elements {
+        if element.get("isDeleted").and_then(|v| v.as_bool()).unwrap_or(false)
+            || element.get("type").and_then(|v| v.as_str()) != Some("text")
+        {
+            continue;
+        }
+        let Some(content) = element.get("text").and_then(|v| v.as_str()) else { continue };
+        if text.len() + content.len() + 1 > MAX_TEXT_CONTENT_BYTES {
+            break;
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(content);
+    }
+    text
+}
+
+/// Hex-encoded SHA-256 of a serialized drawing, used for ETags and change detection.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Hash of a drawing's content, ignoring internal `_`-prefixed fields.
+/// Two uploads of the same document produce the same hash.
+pub fn document_hash(data: &serde_json::Value) -> String {
+    let content = match data.as_object() {
+        Some(obj) => serde_json::Value::Object(
+            obj.iter()
+                .filter(|(k, _)| !k.starts_with('_'))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        ),
+        None => data.clone(),
+    };
+    // serde_json maps are ordered by key, so serialization is canonical
+    content_hash(&serde_json::to_vec(&content).unwrap_or_default())
+}
+
+/// Strip internal `_` metadata fields from a stored drawing before serving it,
+/// exposing the persistent collab flag and version without the underscore.
+pub fn public_document(mut data: serde_json::Value) -> serde_json::Value {
+    // Extract persistent collab info before stripping
+    let persistent_collab = data.get("_persistent_collab")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let persistent_version = data.get("_persistent_collab_version")
+        .and_then(|v| v.as_u64());
+
+    if let Some(obj) = data.as_object_mut() {
+        obj.remove("_password_hash");
+        obj.remove("_source_path");
+        obj.remove("_persistent_collab");
+        obj.remove("_persistent_collab_version");
+        obj.remove("_persistent_collab_password_hash");
+        obj.remove("_tags");
+        obj.remove("_max_views");
+        obj.remove("_title");
+        obj.remove("_slug");
+        obj.remove("_user_agent");
+        obj.remove("_expires_at");
+        obj.remove("_created_at");
+        obj.remove("_share");
+
+        // Expose persistent collab info (without underscore prefix)
+        if persistent_collab {
+            obj.insert("persistent_collab".to_string(), serde_json::Value::Bool(true));
+            if let Some(v) = persistent_version {
+                obj.insert("persistent_collab_version".to_string(), serde_json::json!(v));
+            }
+        }
+    }
+    data
+}
+
+/// Reject ids with anything but alphanumerics, hyphens and underscores, which
+/// the filesystem backend would otherwise strip (`../x` quietly becoming `x`).
+pub fn validate_id(id: &str) -> Result<(), AppError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        tracing::warn!(id = %id, "Rejected invalid drawing id");
+        return Err(AppError::BadRequest("invalid drawing id".into()));
+    }
+    Ok(())
+}
+
+/// Delete every drawing whose TTL has run out and return the ones this call
+/// removed. Expired drawings are already refused when viewed; this frees their
+/// space. Each drawing is re-checked under its `update:{id}` lock, so one
+/// extended (or replaced) since the listing is kept.
+pub async fn delete_expired(storage: &impl DrawingStorage, locks: &KeyedLocks) -> Result<Vec<DrawingMeta>, AppError> {
+    let mut deleted = Vec::new();
+    for drawing in storage.list().await?.into_iter().filter(DrawingMeta::is_expired) {
+        let _guard = locks.lock(&format!("update:{}", drawing.id)).await;
+        let drawing = match storage.get_meta(&drawing.id).await {
+            Ok(meta) if meta.is_expired() => meta,
+            // Extended, or deleted by someone else in the meantime
+            Ok(_) | Err(AppError::NotFound) => continue,
+            Err(e) => {
+                tracing::warn!(id = %drawing.id, error = %e, "Failed to re-check expired drawing");
+                continue;
+            }
+        };
+        match storage.delete(&drawing.id).await {
+            Ok(()) => deleted.push(drawing),
+            Err(AppError::NotFound) => {}
+            Err(e) => tracing::warn!(id = %drawing.id, error = %e, "Failed to delete expired drawing"),
+        }
+    }
+    Ok(deleted)
+}
+
+/// Generate a fresh 16-character drawing id that isn't in use yet.
+pub async fn generate_id(storage: &impl DrawingStorage) -> Result<String, AppError> {
+    let new_id = || {
+        uuid::Uuid::new_v4()
+            .to_string()
+            .replace('-', "")
+            .chars()
+            .take(16)
+            .collect::<String>()
+    };
+    let id = new_id();
+    // Ensure uniqueness for new IDs
+    if storage.exists(&id).await? {
+        Ok(new_id())
+    } else {
+        Ok(id)
+    }
+}
+
+/// Write a file atomically: write a uniquely named temp file next to it, flush
+/// it to disk, then rename it into place. A crash at any point leaves either
+/// the old file or the new one, never a truncated one. Temp names end in
+/// `.tmp`, so directory scans for `.json` never pick them up, and are removed
+/// when the write fails (or, after a crash, by [`remove_stale_temp_files`]).
+async fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), AppError> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{file_name}.{}.tmp", uuid::Uuid::new_v4().simple()));
+    let written = async {
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(bytes).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp_path, path).await
+    };
+    if let Err(e) = written.await {
+        let _ = fs::remove_file(&tmp_path).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Delete temp files of [`write_atomic`] calls a crash interrupted.
+async fn remove_stale_temp_files(dir: &Path) {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return;
+    };
+    let mut removed = 0u32;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        if name.starts_with('.') && name.ends_with(".tmp") && fs::remove_file(entry.path()).await.is_ok() {
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        tracing::info!(count = removed, dir = %dir.display(), "Removed temp files left by interrupted writes");
+    }
 }
 
 /// Trait abstracting drawing storage – implement this for different backends
@@ -35,9 +856,32 @@ pub trait DrawingStorage: Send + Sync + 'static {
     async fn save(&self, id: &str, data: &serde_json::Value, source_path: Option<&str>, password_hash: Option<&str>) -> Result<DrawingMeta, AppError>;
     async fn load(&self, id: &str) -> Result<serde_json::Value, AppError>;
     async fn delete(&self, id: &str) -> Result<(), AppError>;
+
+    /// Remove a drawing as if it had never been stored, to undo a write that
+    /// is being rolled back. Unlike [`DrawingStorage::delete`] it leaves no
+    /// tombstone, so sync clients are never told about it.
+    async fn discard(&self, id: &str) -> Result<(), AppError>;
+
+    /// Delete several drawings, returning each one's outcome in the order of
+    /// `ids`. One failure doesn't stop the rest.
+    async fn delete_many(&self, ids: &[String]) -> Vec<Result<(), AppError>> {
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            results.push(self.delete(id).await);
+        }
+        results
+    }
+
     async fn list(&self) -> Result<Vec<DrawingMeta>, AppError>;
     async fn exists(&self, id: &str) -> Result<bool, AppError>;
 
+    /// One page of the drawings matching `filter`, in `page.sort` order, along with
+    /// the number of matching drawings in total.
+    async fn list_page(&self, filter: &DrawingFilter, page: Page) -> Result<(Vec<DrawingMeta>, usize), AppError> {
+        let matching = self.list().await?.into_iter().filter(|d| filter.matches(d)).collect();
+        Ok(page_of(matching, page))
+    }
+
     /// Check if a drawing has persistent collab enabled.
     async fn get_persistent_collab_status(&self, id: &str) -> Result<bool, AppError>;
 
@@ -58,13 +902,109 @@ pub trait DrawingStorage: Send + Sync + 'static {
     /// Scans sidecar metadata files for a matching source_path.
     /// Returns the DrawingMeta if found, or None if no drawing matches.
     async fn find_by_source_path(&self, source_path: &str) -> Result<Option<DrawingMeta>, AppError>;
+
+    /// Drawings whose text elements contain `query` (case-insensitive).
+    async fn search_text(&self, query: &str) -> Result<Vec<DrawingMeta>, AppError>;
+
+    /// Metadata of a single drawing.
+    async fn get_meta(&self, id: &str) -> Result<DrawingMeta, AppError>;
+
+    /// Metadata of a single drawing with its password hash, for access checks
+    /// that don't need the content.
+    async fn meta(&self, id: &str) -> Result<DrawingAccess, AppError>;
+
+    /// Size and hash of the body a `GET` of the drawing serves, for `HEAD`.
+    async fn head(&self, id: &str) -> Result<DrawingHead, AppError> {
+        DrawingHead::of(self.load(id).await?)
+    }
+
+    /// Find a drawing by its slug.
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<DrawingMeta>, AppError>;
+
+    /// Aggregate drawing count, total size and element-type counts across all drawings.
+    async fn stats(&self) -> Result<StorageStats, AppError>;
+
+    /// Structural summary of a single drawing, served from metadata where possible.
+    async fn summary(&self, id: &str) -> Result<DrawingSummary, AppError>;
+
+    /// Find drawings whose content matches a `document_hash`.
+    async fn find_by_content_hash(&self, hash: &str) -> Result<Vec<DrawingMeta>, AppError>;
+
+    /// Count one view of a drawing and return the new total.
+    async fn record_view(&self, id: &str) -> Result<u64, AppError>;
+
+    /// Add `delta` views to a drawing's persisted count; returns the new total.
+    /// Concurrent calls, and saves in between, never lose each other's counts.
+    async fn add_views(&self, id: &str, delta: u64) -> Result<u64, AppError>;
+
+    /// Delete a drawing that has used up its views, remembering that it existed.
+    async fn burn(&self, id: &str) -> Result<(), AppError>;
+
+    /// Whether a drawing was removed by `burn`.
+    async fn is_burned(&self, id: &str) -> Result<bool, AppError>;
+
+    /// Tombstones of drawings deleted (or burned) after `since`, oldest first.
+    async fn deleted_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Tombstone>, AppError>;
+
+    /// Compare the metadata index against the stored drawings (read-only).
+    async fn verify(&self) -> Result<VerifyReport, AppError>;
+
+    /// Earlier versions of a drawing, newest first. Empty when none were kept.
+    async fn history(&self, id: &str) -> Result<Vec<DrawingVersion>, AppError>;
+
+    /// The stored document of an earlier version of a drawing.
+    async fn load_version(&self, id: &str, version: u64) -> Result<serde_json::Value, AppError>;
+
+    /// Delete a drawing but keep its content and metadata in the trash, from
+    /// where [`DrawingStorage::restore`] can bring it back. Until then it reads
+    /// as deleted, tombstone included. Its preview and earlier versions are
+    /// dropped as with [`DrawingStorage::delete`]; its bytes stay counted
+    /// against the byte quota until it is purged.
+    async fn trash(&self, id: &str) -> Result<(), AppError>;
+
+    /// Drawings in the trash, most recently deleted first.
+    async fn list_trash(&self) -> Result<Vec<TrashedDrawing>, AppError>;
+
+    /// Move a drawing back out of the trash, the most recent deletion when the
+    /// id was trashed more than once. Fails with `Conflict` when a new drawing
+    /// has taken its id in the meantime.
+    async fn restore(&self, id: &str) -> Result<DrawingMeta, AppError>;
+
+    /// Permanently delete trashed drawings deleted before `before`, returning their ids.
+    async fn purge_trash(&self, before: DateTime<Utc>) -> Result<Vec<String>, AppError>;
+}
+
+/// How `remove_drawing` gets rid of a drawing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Removal {
+    Delete,
+    /// Deleted after its last view; the tombstone says so
+    Burn,
+    /// Moved into the trash, from where it can be restored
+    Trash,
+    /// Removed without a tombstone, as if never stored
+    Discard,
 }
 
 /// Filesystem-backed storage. Each drawing is a JSON file named `<id>.json`
 /// with a lightweight sidecar `<id>.meta.json` for fast listing.
+///
+/// With asset extraction enabled, embedded `files` data URLs are stored once
+/// under `assets/<sha256>` and the drawing only keeps an `_asset` reference.
+///
+/// With a history depth, content replaced by an update is kept as
+/// `history/<id>/v<N>.json`, listed in `history/<id>/index.json`.
 #[derive(Clone)]
 pub struct FileSystemStorage {
     base_path: PathBuf,
+    extract_assets: bool,
+    history_depth: usize,
+    byte_quota: ByteQuota,
+    /// Opened by a follower: nothing is cleaned up, migrated or backfilled
+    read_only: bool,
+    /// Per-id locks around sidecar updates that keep fields of the previous
+    /// sidecar, so concurrent view counts and saves don't overwrite each other
+    sidecar_locks: KeyedLocks,
 }
 
 impl FileSystemStorage {
@@ -72,39 +1012,381 @@ impl FileSystemStorage {
         let base_path = base_path.as_ref().to_path_buf();
         fs::create_dir_all(&base_path).await?;
 
-        let storage = Self { base_path };
+        let storage = Self {
+            base_path,
+            extract_assets: false,
+            history_depth: 0,
+            byte_quota: ByteQuota::default(),
+            read_only: false,
+            sidecar_locks: KeyedLocks::new(),
+        };
 
+        remove_stale_temp_files(&storage.base_path).await;
+        remove_stale_temp_files(&storage.assets_dir()).await;
         // Migrate: generate sidecar files for any existing drawings that lack them
         storage.migrate_sidecars().await;
 
         Ok(storage)
     }
 
-    fn drawing_path(&self, id: &str) -> PathBuf {
-        // Sanitize id to prevent path traversal
-        let safe_id: String = id
-            .chars()
-            .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
-            .collect();
-        self.base_path.join(format!("{safe_id}.json"))
+    /// Open a data directory another instance writes to (follower mode). Unlike
+    /// [`new`](Self::new), this leaves temp files and sidecars alone, as they
+    /// belong to the primary's writes, and never backfills sidecars on reads.
+    pub async fn open_read_only(base_path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let base_path = base_path.as_ref().to_path_buf();
+        if !fs::try_exists(&base_path).await? {
+            return Err(AppError::Internal(format!("Data directory {} does not exist", base_path.display())));
+        }
+        Ok(Self {
+            base_path,
+            extract_assets: false,
+            history_depth: 0,
+            byte_quota: ByteQuota::default(),
+            read_only: true,
+            sidecar_locks: KeyedLocks::new(),
+        })
     }
 
-    fn meta_path(&self, id: &str) -> PathBuf {
-        let safe_id: String = id
+    /// Store embedded `files` in content-addressed asset storage instead of inline.
+    /// Drawings with extracted assets are always reassembled on load, whatever this setting.
+    pub fn with_asset_extraction(mut self, enabled: bool) -> Self {
+        self.extract_assets = enabled;
+        self
+    }
+
+    /// Keep up to `depth` earlier versions of each drawing; 0 keeps none.
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Count written drawing bytes against `quota`, refusing writes past its cap.
+    pub fn with_byte_quota(mut self, quota: ByteQuota) -> Self {
+        self.byte_quota = quota;
+        self
+    }
+
+    /// Write, read back and remove a scratch file, to check that storage accepts
+    /// writes. Each probe gets its own name, so concurrent probes don't collide;
+    /// one left behind by a crash is cleaned up with the other temp files.
+    pub async fn probe_write(&self) -> Result<(), AppError> {
+        let path = self.base_path.join(format!(".write-probe-{}.tmp", uuid::Uuid::new_v4().simple()));
+        let written = Utc::now().to_rfc3339();
+        write_atomic(&path, written.as_bytes()).await?;
+        let read = fs::read(&path).await?;
+        fs::remove_file(&path).await?;
+        if read != written.as_bytes() {
+            return Err(AppError::Internal("Write probe read back different content".into()));
+        }
+        Ok(())
+    }
+
+    fn assets_dir(&self) -> PathBuf {
+        self.base_path.join("assets")
+    }
+
+    /// Replace each embedded file's `dataURL` with an `_asset` reference and
+    /// return the `(hash, data URL)` pairs for [`Self::write_assets`]. Nothing is
+    /// written yet, so the quota can be checked against the final document first.
+    ///
+    /// References the document arrives with are dropped, extracting or not: only
+    /// this writes them, so any other names an asset of some other drawing.
+    fn extract_files(data: &mut serde_json::Value, extract: bool) -> Vec<(String, String)> {
+        let Some(files) = data.get_mut("files").and_then(|v| v.as_object_mut()) else {
+            return Vec::new();
+        };
+        let mut assets = Vec::new();
+        for file in files.values_mut() {
+            let Some(obj) = file.as_object_mut() else { continue };
+            obj.remove("_asset");
+            if !extract {
+                continue;
+            }
+            let Some(serde_json::Value::String(data_url)) = obj.remove("dataURL") else { continue };
+
+            let hash = content_hash(data_url.as_bytes());
+            obj.insert("_asset".to_string(), serde_json::Value::String(hash.clone()));
+            assets.push((hash, data_url));
+        }
+        assets
+    }
+
+    /// Store extracted files in `assets/<hash>`. Identical images shared across
+    /// drawings are written only once.
+    async fn write_assets(&self, assets: &[(String, String)]) -> Result<(), AppError> {
+        if assets.is_empty() {
+            return Ok(());
+        }
+        let assets_dir = self.assets_dir();
+        fs::create_dir_all(&assets_dir).await?;
+        for (hash, data_url) in assets {
+            let asset_path = assets_dir.join(hash);
+            if !asset_path.exists() {
+                write_atomic(&asset_path, data_url.as_bytes()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace `_asset` references with the stored data URLs.
+    async fn inline_files(&self, data: &mut serde_json::Value) -> Result<(), AppError> {
+        self.resolve_files(data, None).await
+    }
+
+    /// Replace `_asset` references with the stored data URLs, or with
+    /// `<asset_prefix>/<hash>` links when an asset URL prefix is given.
+    async fn resolve_files(&self, data: &mut serde_json::Value, asset_prefix: Option<&str>) -> Result<(), AppError> {
+        let Some(files) = data.get_mut("files").and_then(|v| v.as_object_mut()) else {
+            return Ok(());
+        };
+        for file in files.values_mut() {
+            let Some(obj) = file.as_object_mut() else { continue };
+            let Some(hash) = obj.get("_asset").and_then(|v| v.as_str()) else { continue };
+
+            // Anything but a full hash (an empty one, say) is not a reference we wrote
+            if !is_asset_hash(hash) {
+                continue;
+            }
+            let data_url = match asset_prefix {
+                Some(prefix) => format!("{prefix}/{hash}"),
+                None => fs::read_to_string(self.assets_dir().join(hash)).await?,
+            };
+            obj.remove("_asset");
+            obj.insert("dataURL".to_string(), serde_json::Value::String(data_url));
+        }
+        Ok(())
+    }
+
+    /// Load a drawing as stored, with extracted files left as `_asset` references.
+    pub async fn load_stored(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        let path = self.drawing_path(id);
+        if !path.exists() {
+            return Err(AppError::NotFound);
+        }
+        let bytes = fs::read(&path).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Load a drawing with extracted files pointing at its asset endpoint,
+    /// `<asset_base>/api/view/<id>/assets/<hash>`, instead of inlined. Files
+    /// stored inline stay inline, and so do all files of drawings behind a
+    /// password or view limit, since image requests can't carry the key.
+    pub async fn load_with_asset_urls(&self, id: &str, asset_base: &str) -> Result<serde_json::Value, AppError> {
+        let mut data = self.load_stored(id).await?;
+        if data.get("_password_hash").is_some() || data.get("_max_views").is_some() {
+            self.inline_files(&mut data).await?;
+        } else {
+            let prefix = format!("{}/api/view/{id}/assets", asset_base.trim_end_matches('/'));
+            self.resolve_files(&mut data, Some(&prefix)).await?;
+        }
+        Ok(data)
+    }
+
+    /// Read an extracted asset, decoded from its data URL into (MIME type, bytes).
+    pub async fn read_asset(&self, hash: &str) -> Result<(String, Vec<u8>), AppError> {
+        if !is_asset_hash(hash) {
+            return Err(AppError::NotFound);
+        }
+        let data_url = match fs::read_to_string(self.assets_dir().join(hash)).await {
+            Ok(data_url) => data_url,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let (mime, encoded) = data_url
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+            .ok_or_else(|| AppError::Internal(format!("Asset {hash} is not a base64 data URL")))?;
+        let bytes = general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| AppError::Internal(format!("Asset {hash} has invalid base64: {e}")))?;
+        Ok((mime.to_string(), bytes))
+    }
+
+    /// Store a client-rendered PNG preview of a drawing.
+    pub async fn save_preview(&self, id: &str, png: &[u8]) -> Result<(), AppError> {
+        write_atomic(&self.preview_path(id), png).await
+    }
+
+    /// Read a drawing's uploaded PNG preview.
+    pub async fn read_preview(&self, id: &str) -> Result<Vec<u8>, AppError> {
+        match fs::read(self.preview_path(id)).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(AppError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn drawing_path(&self, id: &str) -> PathBuf {
+        // Sanitize id to prevent path traversal, for callers that skipped validate_id
+        let safe_id: String = id
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        self.base_path.join(format!("{safe_id}.json"))
+    }
+
+    fn meta_path(&self, id: &str) -> PathBuf {
+        let safe_id: String = id
             .chars()
             .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
             .collect();
         self.base_path.join(format!("{safe_id}.meta.json"))
     }
 
+    fn history_dir(&self, id: &str) -> PathBuf {
+        let safe_id: String = id
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        self.base_path.join("history").join(safe_id)
+    }
+
+    async fn read_history_index(&self, id: &str) -> Vec<DrawingVersion> {
+        match fs::read(self.history_dir(id).join("index.json")).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Keep `bytes`, the stored document an update is about to replace, as the
+    /// next version of `id`, and drop the versions beyond the history depth.
+    async fn record_version(&self, id: &str, bytes: &[u8], saved_at: DateTime<Utc>) -> Result<(), AppError> {
+        let dir = self.history_dir(id);
+        fs::create_dir_all(&dir).await?;
+        let mut versions = self.read_history_index(id).await;
+        let version = versions.first().map_or(1, |v| v.version + 1);
+        write_atomic(&dir.join(format!("v{version}.json")), bytes).await?;
+
+        versions.insert(0, DrawingVersion { version, saved_at, size_bytes: bytes.len() as u64 });
+        let keep = self.history_depth.min(versions.len());
+        for pruned in versions.drain(keep..) {
+            let _ = fs::remove_file(dir.join(format!("v{}.json", pruned.version))).await;
+        }
+        write_atomic(&dir.join("index.json"), &serde_json::to_vec(&versions)?).await
+    }
+
+    /// Bytes of the versions [`Self::record_version`] will prune when it adds one.
+    fn pruned_bytes(&self, versions: &[DrawingVersion]) -> u64 {
+        versions.iter().skip(self.history_depth.saturating_sub(1)).map(|v| v.size_bytes).sum()
+    }
+
+    /// Remove a drawing and everything stored alongside it, leaving a tombstone
+    /// unless discarded. Trashing moves the drawing and its sidecar into the
+    /// trash instead of deleting them.
+    async fn remove_drawing(&self, id: &str, removal: Removal) -> Result<(), AppError> {
+        let path = self.drawing_path(id);
+        if !path.exists() {
+            return Err(AppError::NotFound);
+        }
+        let deleted_at = Utc::now();
+        let source_path = self.read_sidecar(id).await.and_then(|m| m.source_path);
+        if removal != Removal::Discard {
+            let tombstone = Tombstone {
+                id: id.to_string(),
+                deleted_at,
+                source_path: source_path.clone(),
+                burned: removal == Removal::Burn,
+            };
+            write_atomic(&self.tombstone_path(id), &serde_json::to_vec(&tombstone)?).await?;
+        }
+        let size = fs::metadata(&path).await.map_or(0, |m| m.len());
+        let meta_path = self.meta_path(id);
+        if removal == Removal::Trash {
+            let trashed = TrashedDrawing {
+                id: id.to_string(),
+                deleted_at,
+                source_path,
+                size_bytes: size,
+            };
+            let record = serde_json::to_vec(&trashed)?;
+            let (trash_path, trash_meta_path, record_path) = self.trash_paths(&trashed);
+            fs::create_dir_all(self.trash_dir()).await?;
+            // The record goes in last, so a crash never lists a drawing that isn't there
+            fs::rename(&path, &trash_path).await?;
+            let _ = fs::rename(&meta_path, &trash_meta_path).await;
+            if let Err(e) = write_atomic(&record_path, &record).await {
+                let _ = fs::rename(&trash_meta_path, &meta_path).await;
+                let _ = fs::rename(&trash_path, &path).await;
+                return Err(e);
+            }
+            // Its bytes stay counted against the quota until it is purged
+        } else {
+            fs::remove_file(&path).await?;
+            // Also remove sidecar metadata
+            let _ = fs::remove_file(&meta_path).await; // Ignore error if sidecar doesn't exist
+            self.byte_quota.adjust(size, 0);
+        }
+
+        let _ = fs::remove_file(self.preview_path(id)).await;
+        let history_bytes: u64 = self.read_history_index(id).await.iter().map(|v| v.size_bytes).sum();
+        if fs::remove_dir_all(self.history_dir(id)).await.is_ok() {
+            self.byte_quota.adjust(history_bytes, 0);
+        }
+
+        Ok(())
+    }
+
+    async fn read_tombstone(&self, id: &str) -> Option<Tombstone> {
+        let bytes = fs::read(self.tombstone_path(id)).await.ok()?;
+        serde_json::from_slice(&bytes).ok().or_else(|| {
+            // Tombstones written before deletions were tracked only hold the burn time
+            let deleted_at = DateTime::parse_from_rfc3339(std::str::from_utf8(&bytes).ok()?.trim()).ok()?;
+            Some(Tombstone {
+                id: id.to_string(),
+                deleted_at: deleted_at.with_timezone(&Utc),
+                source_path: None,
+                burned: true,
+            })
+        })
+    }
+
+    fn preview_path(&self, id: &str) -> PathBuf {
+        self.drawing_path(id).with_extension("preview.png")
+    }
+
+    fn trash_dir(&self) -> PathBuf {
+        self.base_path.join(".trash")
+    }
+
+    /// Where a trashed drawing, its sidecar and its deletion record are kept.
+    /// Names carry the deletion time, so trashing a reused id again keeps the
+    /// earlier entry.
+    fn trash_paths(&self, trashed: &TrashedDrawing) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = self.trash_dir();
+        let stem = format!("{}.{}", trashed.id, trashed.deleted_at.timestamp_micros());
+        (
+            dir.join(format!("{stem}.json")),
+            dir.join(format!("{stem}.meta.json")),
+            dir.join(format!("{stem}.deleted")),
+        )
+    }
+
+    async fn read_trash_record(&self, path: &Path) -> Option<TrashedDrawing> {
+        serde_json::from_slice(&fs::read(path).await.ok()?).ok()
+    }
+
+    /// Record left behind by `delete` and `burn`, so deletions can be mirrored
+    /// and a burned drawing reads as gone rather than missing.
+    fn tombstone_path(&self, id: &str) -> PathBuf {
+        self.drawing_path(id).with_extension("gone")
+    }
+
     /// Write the sidecar metadata file for a drawing.
     async fn write_sidecar(&self, id: &str, meta: &SidecarMeta) -> Result<(), AppError> {
         let path = self.meta_path(id);
         let json_bytes = serde_json::to_vec(meta)?;
-        fs::write(&path, &json_bytes).await?;
+        write_atomic(&path, &json_bytes).await?;
         Ok(())
     }
 
+    /// Read a drawing from disk with its assets inlined.
+    async fn read_drawing(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        let bytes = fs::read(self.drawing_path(id)).await?;
+        let mut data: serde_json::Value = serde_json::from_slice(&bytes)?;
+        self.inline_files(&mut data).await?;
+        Ok(data)
+    }
+
     /// Read the sidecar metadata file for a drawing.
     async fn read_sidecar(&self, id: &str) -> Option<SidecarMeta> {
         let path = self.meta_path(id);
@@ -162,373 +1444,2547 @@ impl FileSystemStorage {
                             let pc = json.get("_persistent_collab")
                                 .and_then(|v| v.as_bool())
                                 .unwrap_or(false);
-                            (sp, pw, pc)
+                            let meta = (
+                                sp,
+                                pw,
+                                pc,
+                                tags_from(&json),
+                                text_field(&json, "_title"),
+                                text_field(&json, "_slug"),
+                                text_field(&json, "_user_agent"),
+                                expires_at(&json),
+                            );
+                            let content = (element_type_histogram(&json), content_summary(&json), document_hash(&json), text_content(&json));
+                            (meta, content)
                         });
 
-                    let (source_path, password_protected, persistent_collab) = parsed.unwrap_or((None, false, false));
+                    let ((source_path, password_protected, persistent_collab, tags, title, slug, user_agent, expires_at), (element_types, content, hash, text)) =
+                        parsed.unwrap_or_default();
+
+                    // Use file system creation and modification times as best-effort, or fall back to now
+                    let file_metadata = entry.metadata().await.ok();
+                    let created_at = file_metadata
+                        .as_ref()
+                        .and_then(|m| m.created().ok())
+                        .map(DateTime::from)
+                        .unwrap_or_else(Utc::now);
+                    let modified_at = file_metadata.and_then(|m| m.modified().ok()).map(DateTime::from);
+
+                    let sidecar = SidecarMeta {
+                        created_at,
+                        modified_at,
+                        source_path,
+                        password_protected,
+                        persistent_collab,
+                        element_types: Some(element_types),
+                        tags,
+                        content: Some(content),
+                        content_hash: Some(hash),
+                        views: 0,
+                        title,
+                        slug,
+                        text: Some(text),
+                        user_agent,
+                        expires_at,
+                        password_hash: None,
+                        max_views: None,
+                    };
+
+                    if let Err(e) = self.write_sidecar(&id, &sidecar).await {
+                        tracing::warn!(id = %id, error = %e, "Failed to migrate sidecar metadata");
+                    } else {
+                        migrated += 1;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(id = %id, error = %e, "Failed to read drawing for sidecar migration");
+                }
+            }
+        }
+
+        if migrated > 0 {
+            tracing::info!(count = migrated, "Migrated sidecar metadata files for existing drawings");
+        }
+    }
+}
+
+impl DrawingStorage for FileSystemStorage {
+    async fn save(&self, id: &str, data: &serde_json::Value, source_path: Option<&str>, password_hash: Option<&str>) -> Result<DrawingMeta, AppError> {
+        let path = self.drawing_path(id);
+
+        // Read existing drawing to preserve persistent collab fields
+        let existing_bytes = if path.exists() { fs::read(&path).await.ok() } else { None };
+        let existing = existing_bytes
+            .as_deref()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(bytes).ok());
+        let existing_sidecar = self.read_sidecar(id).await;
+
+        let mut data_with_meta = with_internal_fields(data, source_path, password_hash, existing.as_ref());
+        let assets = Self::extract_files(&mut data_with_meta, self.extract_assets);
+
+        let json_bytes = serde_json::to_vec(&data_with_meta)?;
+        let size_bytes = json_bytes.len() as u64;
+        let old_size = existing_bytes.as_ref().map_or(0, |bytes| bytes.len() as u64);
+        // Keep the content being replaced, unless only internal fields change.
+        // Its bytes then stay counted as history, less the versions pruned.
+        let keep_version = self.history_depth > 0
+            && existing.as_ref().is_some_and(|old| document_hash(old) != document_hash(data));
+        let freed = if keep_version { self.pruned_bytes(&self.read_history_index(id).await) } else { old_size };
+        self.byte_quota.reserve(freed, size_bytes)?;
+
+        let mut recorded = false;
+        let written = async {
+            self.write_assets(&assets).await?;
+            if let Some(bytes) = existing_bytes.as_deref().filter(|_| keep_version) {
+                let saved_at = existing_sidecar.as_ref().map_or_else(Utc::now, |m| m.modified_at.unwrap_or(m.created_at));
+                self.record_version(id, bytes, saved_at).await?;
+                recorded = true;
+            }
+            // Readers (load, list) see either the old or the new file, never a partial one
+            write_atomic(&path, &json_bytes).await
+        };
+        if let Err(e) = written.await {
+            // A version recorded before the failure is kept, alongside the old content
+            self.byte_quota.adjust(size_bytes, if recorded { old_size } else { freed });
+            return Err(e);
+        }
+
+        // Write/update sidecar metadata (tiny file, fast), keeping created_at and
+        // views of existing drawings. Re-read under the lock, for views counted meanwhile.
+        let sidecar_guard = self.sidecar_locks.lock(id).await;
+        let current_sidecar = self.read_sidecar(id).await;
+        let sidecar = SidecarMeta::for_document(data, &data_with_meta, source_path, current_sidecar.as_ref());
+        self.write_sidecar(id, &sidecar).await?;
+        drop(sidecar_guard);
+        // Re-publishing a burned id brings it back
+        let _ = fs::remove_file(self.tombstone_path(id)).await;
+
+        Ok(sidecar.to_meta(id, size_bytes))
+    }
+
+    async fn load(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        let path = self.drawing_path(id);
+        if !path.exists() {
+            return Err(AppError::NotFound);
+        }
+        self.read_drawing(id).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Delete).await
+    }
+
+    async fn discard(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Discard).await
+    }
+
+    /// List all drawings using lightweight sidecar metadata files.
+    /// Never reads the full drawing JSON — only the tiny .meta.json files.
+    async fn list(&self) -> Result<Vec<DrawingMeta>, AppError> {
+        let mut entries = fs::read_dir(&self.base_path).await?;
+        let mut drawings = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            // Only process .json files (not .meta.json)
+            if !filename.ends_with(".json") || filename.ends_with(".meta.json") {
+                continue;
+            }
+
+            let file_metadata = entry.metadata().await?;
+            let id = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_string();
+
+            if id.is_empty() {
+                continue;
+            }
+
+            // Read the lightweight sidecar (typically < 200 bytes)
+            let sidecar = self.read_sidecar(&id).await.unwrap_or_else(|| {
+                // Sidecar missing — use filesystem metadata as fallback
+                let created_at = file_metadata
+                    .created()
+                    .map(DateTime::from)
+                    .unwrap_or_else(|_| Utc::now());
+                SidecarMeta {
+                    created_at,
+                    ..Default::default()
+                }
+            });
+
+            drawings.push(sidecar.to_meta(&id, file_metadata.len()));
+        }
+
+        // Newest first; the id breaks ties so equal timestamps still list in a stable order
+        drawings.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(drawings)
+    }
+
+    async fn exists(&self, id: &str) -> Result<bool, AppError> {
+        Ok(self.drawing_path(id).exists())
+    }
+
+    async fn get_persistent_collab_status(&self, id: &str) -> Result<bool, AppError> {
+        // First check if the drawing exists
+        let path = self.drawing_path(id);
+        if !path.exists() {
+            return Err(AppError::NotFound);
+        }
+
+        // Read from sidecar for fast access
+        if let Some(sidecar) = self.read_sidecar(id).await {
+            return Ok(sidecar.persistent_collab);
+        }
+
+        // Fallback: read from the drawing JSON itself
+        let bytes = fs::read(&path).await?;
+        let data: serde_json::Value = serde_json::from_slice(&bytes)?;
+        Ok(data.get("_persistent_collab")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false))
+    }
+
+    async fn save_persistent(
+        &self,
+        id: &str,
+        data: &serde_json::Value,
+        version: u64,
+    ) -> Result<(), AppError> {
+        let path = self.drawing_path(id);
+        if !path.exists() {
+            return Err(AppError::NotFound);
+        }
+
+        // Load existing drawing to preserve internal fields
+        let existing_bytes = fs::read(&path).await?;
+        let existing: serde_json::Value = serde_json::from_slice(&existing_bytes)?;
+
+        let mut data_with_meta = persistent_document(data, &existing, version);
+        let assets = Self::extract_files(&mut data_with_meta, self.extract_assets);
+
+        let json_bytes = serde_json::to_vec(&data_with_meta)?;
+        let (old_size, size_bytes) = (existing_bytes.len() as u64, json_bytes.len() as u64);
+        self.byte_quota.reserve(old_size, size_bytes)?;
+
+        let written = async {
+            self.write_assets(&assets).await?;
+            write_atomic(&path, &json_bytes).await
+        };
+        if let Err(e) = written.await {
+            self.byte_quota.adjust(size_bytes, old_size);
+            return Err(e);
+        }
+
+        tracing::debug!(id = %id, version = version, "Persistent collab save completed");
+
+        // Update sidecar metadata, keeping everything except the collab flag and content stats
+        let _sidecar_guard = self.sidecar_locks.lock(id).await;
+        let mut sidecar = self.read_sidecar(id).await.unwrap_or_else(|| SidecarMeta {
+            created_at: Utc::now(),
+            ..Default::default()
+        });
+        sidecar.update_persistent_content(data);
+        self.write_sidecar(id, &sidecar).await?;
+
+        Ok(())
+    }
+
+    async fn list_persistent_collab_drawings(&self) -> Result<Vec<String>, AppError> {
+        let mut entries = fs::read_dir(&self.base_path).await?;
+        let mut ids = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            // Only process .meta.json sidecar files
+            if !filename.ends_with(".meta.json") {
+                continue;
+            }
+
+            // Extract the drawing ID from the sidecar filename
+            let id = filename.strip_suffix(".meta.json").unwrap_or("").to_string();
+            if id.is_empty() {
+                continue;
+            }
+
+            // Read the sidecar and check persistent_collab flag
+            if let Some(sidecar) = self.read_sidecar(&id).await {
+                if sidecar.persistent_collab {
+                    ids.push(id);
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn find_by_source_path(&self, source_path: &str) -> Result<Option<DrawingMeta>, AppError> {
+        let mut entries = fs::read_dir(&self.base_path).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            // Only process .meta.json sidecar files
+            if !filename.ends_with(".meta.json") {
+                continue;
+            }
+
+            let id = filename.strip_suffix(".meta.json").unwrap_or("").to_string();
+            if id.is_empty() {
+                continue;
+            }
+
+            if let Some(sidecar) = self.read_sidecar(&id).await {
+                if sidecar.source_path.as_deref() == Some(source_path) {
+                    // Found a match — verify the drawing file still exists
+                    let drawing_path = self.drawing_path(&id);
+                    if !drawing_path.exists() {
+                        continue;
+                    }
+
+                    let file_metadata = fs::metadata(&drawing_path).await?;
+
+                    return Ok(Some(sidecar.to_meta(&id, file_metadata.len())));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Substring match over the text stored in sidecars. Sidecars written before
+    /// text was tracked are backfilled from the drawing JSON on first use.
+    async fn search_text(&self, query: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        let query = query.to_lowercase();
+        let mut matches = Vec::new();
+        for meta in self.list().await? {
+            let Some(mut sidecar) = self.read_sidecar(&meta.id).await else { continue };
+            let text = match sidecar.text.clone() {
+                Some(text) => text,
+                None => {
+                    let text = match self.load(&meta.id).await {
+                        Ok(data) => text_content(&data),
+                        Err(_) => continue,
+                    };
+                    sidecar.text = Some(text.clone());
+                    if !self.read_only {
+                        if let Err(e) = self.write_sidecar(&meta.id, &sidecar).await {
+                            tracing::warn!(id = %meta.id, error = %e, "Failed to backfill search text");
+                        }
+                    }
+                    text
+                }
+            };
+            if text.to_lowercase().contains(&query) {
+                matches.push(meta);
+            }
+        }
+        Ok(matches)
+    }
+
+    async fn get_meta(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        let file_metadata = match fs::metadata(self.drawing_path(id)).await {
+            Ok(m) => m,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let sidecar = self.read_sidecar(id).await.unwrap_or_else(|| SidecarMeta {
+            created_at: file_metadata.created().map(DateTime::from).unwrap_or_else(|_| Utc::now()),
+            ..Default::default()
+        });
+        Ok(sidecar.to_meta(id, file_metadata.len()))
+    }
+
+    async fn meta(&self, id: &str) -> Result<DrawingAccess, AppError> {
+        let meta = self.get_meta(id).await?;
+        let sidecar = self.read_sidecar(id).await.unwrap_or_default();
+        let password_hash = match sidecar.password_hash {
+            Some(hash) => Some(hash),
+            None if meta.password_protected => text_field(&self.load_stored(id).await?, "_password_hash"),
+            None => None,
+        };
+        Ok(DrawingAccess { meta, password_hash, max_views: sidecar.max_views })
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<DrawingMeta>, AppError> {
+        Ok(self.list().await?.into_iter().find(|d| d.slug.as_deref() == Some(slug)))
+    }
+
+    /// Aggregate stats from sidecar metadata. Sidecars written before element
+    /// histograms existed are backfilled from the drawing JSON on first use.
+    async fn stats(&self) -> Result<StorageStats, AppError> {
+        let mut entries = fs::read_dir(&self.base_path).await?;
+        let mut stats = StorageStats::default();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            // Only process .json files (not .meta.json)
+            if !filename.ends_with(".json") || filename.ends_with(".meta.json") {
+                continue;
+            }
+
+            let id = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_string();
+            if id.is_empty() {
+                continue;
+            }
+
+            stats.drawing_count += 1;
+            stats.total_bytes += entry.metadata().await?.len();
+
+            let sidecar = self.read_sidecar(&id).await;
+            let histogram = match sidecar.as_ref().and_then(|m| m.element_types.clone()) {
+                Some(histogram) => histogram,
+                None => {
+                    let histogram = match self.load(&id).await {
+                        Ok(data) => element_type_histogram(&data),
+                        Err(e) => {
+                            tracing::warn!(id = %id, error = %e, "Failed to read drawing for element stats");
+                            continue;
+                        }
+                    };
+                    if let Some(mut meta) = sidecar.filter(|_| !self.read_only) {
+                        meta.element_types = Some(histogram.clone());
+                        if let Err(e) = self.write_sidecar(&id, &meta).await {
+                            tracing::warn!(id = %id, error = %e, "Failed to backfill element stats");
+                        }
+                    }
+                    histogram
+                }
+            };
+
+            for (element_type, count) in histogram {
+                *stats.element_types.entry(element_type).or_insert(0) += count;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    async fn find_by_content_hash(&self, hash: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        let mut entries = fs::read_dir(&self.base_path).await?;
+        let mut matches = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            // Only process .meta.json sidecar files
+            let Some(id) = filename.strip_suffix(".meta.json") else {
+                continue;
+            };
+            if id.is_empty() {
+                continue;
+            }
+
+            if let Some(sidecar) = self.read_sidecar(id).await {
+                if sidecar.content_hash.as_deref() == Some(hash) {
+                    let drawing_path = self.drawing_path(id);
+                    if let Ok(file_metadata) = fs::metadata(&drawing_path).await {
+                        matches.push(sidecar.to_meta(id, file_metadata.len()));
+                    }
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+
+    async fn summary(&self, id: &str) -> Result<DrawingSummary, AppError> {
+        let path = self.drawing_path(id);
+        if !path.exists() {
+            return Err(AppError::NotFound);
+        }
+        let size_bytes = fs::metadata(&path).await?.len();
+
+        let sidecar = self.read_sidecar(id).await;
+        let content = match sidecar.as_ref().and_then(|m| m.content.clone()) {
+            Some(content) => content,
+            None => {
+                let content = content_summary(&self.load(id).await?);
+                if let Some(mut meta) = sidecar.clone().filter(|_| !self.read_only) {
+                    meta.content = Some(content.clone());
+                    if let Err(e) = self.write_sidecar(id, &meta).await {
+                        tracing::warn!(id = %id, error = %e, "Failed to backfill content summary");
+                    }
+                }
+                content
+            }
+        };
+
+        Ok(DrawingSummary {
+            id: id.to_string(),
+            size_bytes,
+            password_protected: sidecar.is_some_and(|m| m.password_protected),
+            content,
+        })
+    }
+
+    async fn record_view(&self, id: &str) -> Result<u64, AppError> {
+        self.add_views(id, 1).await
+    }
+
+    async fn add_views(&self, id: &str, delta: u64) -> Result<u64, AppError> {
+        if !self.drawing_path(id).exists() {
+            return Err(AppError::NotFound);
+        }
+        let _sidecar_guard = self.sidecar_locks.lock(id).await;
+        let mut sidecar = self.read_sidecar(id).await.unwrap_or_else(|| SidecarMeta {
+            created_at: Utc::now(),
+            ..Default::default()
+        });
+        sidecar.views += delta;
+        self.write_sidecar(id, &sidecar).await?;
+        Ok(sidecar.views)
+    }
+
+    async fn burn(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Burn).await
+    }
+
+    async fn is_burned(&self, id: &str) -> Result<bool, AppError> {
+        Ok(self.read_tombstone(id).await.is_some_and(|t| t.burned))
+    }
+
+    async fn deleted_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Tombstone>, AppError> {
+        let mut tombstones = Vec::new();
+        let mut entries = fs::read_dir(&self.base_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(id) = name.to_str().and_then(|n| n.strip_suffix(".gone")) else {
+                continue;
+            };
+            if let Some(tombstone) = self.read_tombstone(id).await {
+                if since.is_none_or(|since| tombstone.deleted_at > since) {
+                    tombstones.push(tombstone);
+                }
+            }
+        }
+        tombstones.sort_by(|a, b| a.deleted_at.cmp(&b.deleted_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(tombstones)
+    }
+
+    /// The index here is the `.meta.json` sidecars.
+    async fn verify(&self) -> Result<VerifyReport, AppError> {
+        let (mut drawings, mut sidecars) = (BTreeSet::new(), BTreeSet::new());
+        let mut entries = fs::read_dir(&self.base_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if let Some(id) = name.strip_suffix(".meta.json") {
+                sidecars.insert(id.to_string());
+            } else if let Some(id) = name.strip_suffix(".json").filter(|id| !id.is_empty()) {
+                drawings.insert(id.to_string());
+            }
+        }
+
+        let mut report = VerifyReport {
+            checked: drawings.len(),
+            orphaned_index_entries: sidecars.difference(&drawings).cloned().collect(),
+            ..Default::default()
+        };
+        for id in &drawings {
+            let Some(sidecar) = self.read_sidecar(id).await else {
+                report.missing_from_index.push(id.clone());
+                continue;
+            };
+            match self.read_drawing(id).await {
+                Ok(data) => report.check_hash(id, sidecar.content_hash, &data),
+                Err(_) => report.unreadable.push(id.clone()),
+            }
+        }
+        Ok(report)
+    }
+
+    async fn history(&self, id: &str) -> Result<Vec<DrawingVersion>, AppError> {
+        Ok(self.read_history_index(id).await)
+    }
+
+    async fn load_version(&self, id: &str, version: u64) -> Result<serde_json::Value, AppError> {
+        let bytes = match fs::read(self.history_dir(id).join(format!("v{version}.json"))).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let mut data: serde_json::Value = serde_json::from_slice(&bytes)?;
+        self.inline_files(&mut data).await?;
+        Ok(data)
+    }
+
+    async fn trash(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Trash).await
+    }
+
+    async fn list_trash(&self) -> Result<Vec<TrashedDrawing>, AppError> {
+        let mut trashed = Vec::new();
+        let mut entries = match fs::read_dir(self.trash_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(trashed),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().extension().is_some_and(|ext| ext == "deleted") {
+                trashed.extend(self.read_trash_record(&entry.path()).await);
+            }
+        }
+        trashed.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(trashed)
+    }
+
+    /// Brings back the id's most recent deletion. Its bytes never left the quota.
+    async fn restore(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        let record = self.list_trash().await?.into_iter().find(|t| t.id == id).ok_or(AppError::NotFound)?;
+        let (trash_path, trash_meta_path, record_path) = self.trash_paths(&record);
+        let path = self.drawing_path(id);
+        // Unlike a rename, linking never replaces a drawing that took the id meanwhile
+        match fs::hard_link(&trash_path, &path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(AppError::Conflict(format!("A drawing with id '{id}' exists; delete it before restoring")));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // A record whose drawing is gone can't be restored
+                let _ = fs::remove_file(&record_path).await;
+                return Err(AppError::NotFound);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let _ = fs::remove_file(&trash_path).await;
+        let _ = fs::rename(&trash_meta_path, self.meta_path(id)).await;
+        let _ = fs::remove_file(&record_path).await;
+        let _ = fs::remove_file(self.tombstone_path(id)).await;
+        self.get_meta(id).await
+    }
+
+    async fn purge_trash(&self, before: DateTime<Utc>) -> Result<Vec<String>, AppError> {
+        let mut purged = Vec::new();
+        for trashed in self.list_trash().await? {
+            if trashed.deleted_at >= before {
+                continue;
+            }
+            let (trash_path, trash_meta_path, record_path) = self.trash_paths(&trashed);
+            let _ = fs::remove_file(&trash_path).await;
+            let _ = fs::remove_file(&trash_meta_path).await;
+            fs::remove_file(&record_path).await?;
+            self.byte_quota.adjust(trashed.size_bytes, 0);
+            purged.push(trashed.id);
+        }
+        Ok(purged)
+    }
+}
+
+/// Schema of the SQLite backend. Drawings keep the sidecar metadata as JSON in
+/// `meta`; the columns next to it exist for indexed lookups.
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS drawings (
+        id TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        source_path TEXT,
+        size_bytes INTEGER NOT NULL,
+        meta TEXT NOT NULL
+    );
+    DROP INDEX IF EXISTS drawings_created_at;
+    CREATE INDEX IF NOT EXISTS drawings_created_at_id ON drawings (created_at DESC, id);
+    CREATE INDEX IF NOT EXISTS drawings_source_path ON drawings (source_path);
+    CREATE TABLE IF NOT EXISTS tombstones (
+        id TEXT PRIMARY KEY,
+        deleted_at TEXT NOT NULL,
+        source_path TEXT,
+        burned INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS previews (
+        id TEXT PRIMARY KEY,
+        png BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        id TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        saved_at TEXT NOT NULL,
+        size_bytes INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (id, version)
+    );
+    CREATE TABLE IF NOT EXISTS blobs (
+        hash TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        refs INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS trash (
+        id TEXT NOT NULL,
+        deleted_at TEXT NOT NULL,
+        data TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        source_path TEXT,
+        size_bytes INTEGER NOT NULL,
+        meta TEXT NOT NULL,
+        PRIMARY KEY (id, deleted_at)
+    );
+    CREATE TABLE IF NOT EXISTS write_probe (
+        id INTEGER PRIMARY KEY,
+        at TEXT NOT NULL
+    );
+";
+
+/// SQLite-backed storage: all drawings live in a single database file, one row
+/// per drawing. Embedded files are always stored inline. Earlier versions kept
+/// by updates are rows in `history`. With content dedup, a drawing's row only
+/// keeps its internal fields and the hash of a reference-counted `blobs` row
+/// shared by every drawing with that content.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    conn: Arc<Mutex<rusqlite::Connection>>,
+    history_depth: usize,
+    dedup_content: bool,
+    byte_quota: ByteQuota,
+}
+
+/// A stored drawing's metadata as read from the `drawings` table.
+type SqliteRow = (String, u64, SidecarMeta);
+
+/// Condition leaving out drawings whose TTL has run out.
+const SQLITE_NOT_EXPIRED: &str = "(json_extract(meta, '$.expires_at') IS NULL
+    OR julianday(json_extract(meta, '$.expires_at')) > julianday('now'))";
+
+impl SqliteStorage {
+    /// Open (or create) the database at `path` and make sure the schema exists.
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).await?;
+        }
+        let conn = tokio::task::spawn_blocking(move || -> Result<_, AppError> {
+            let conn = rusqlite::Connection::open(&path)?;
+            conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+            conn.execute_batch(SQLITE_SCHEMA)?;
+            Ok(conn)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("SQLite task failed: {e}")))??;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            history_depth: 0,
+            dedup_content: false,
+            byte_quota: ByteQuota::default(),
+        })
+    }
+
+    /// Keep up to `depth` earlier versions of each drawing; 0 keeps none.
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Store identical content once, shared between the drawings that have it.
+    /// Rows written without it stay readable either way.
+    pub fn with_content_dedup(mut self, enabled: bool) -> Self {
+        self.dedup_content = enabled;
+        self
+    }
+
+    /// Count written drawing bytes against `quota`, refusing writes past its cap.
+    pub fn with_byte_quota(mut self, quota: ByteQuota) -> Self {
+        self.byte_quota = quota;
+        self
+    }
+
+    /// Run `f` against the connection on the blocking thread pool.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut rusqlite::Connection) -> Result<T, AppError> + Send + 'static,
+    ) -> Result<T, AppError> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("SQLite task failed: {e}")))?
+    }
+
+    /// Rows matching `filter`, a `WHERE` clause (or empty), newest first and by id
+    /// among equal timestamps; when `page` is given, only those in it and in its order.
+    async fn rows(&self, filter: impl Into<String>, mut params: Vec<SqlValue>, page: Option<Page>) -> Result<Vec<SqliteRow>, AppError> {
+        let filter = filter.into();
+        let order = page.map(|page| page.sort).unwrap_or_default().sqlite_order();
+        self.with_conn(move |conn| {
+            let mut sql = format!("SELECT id, size_bytes, meta FROM drawings {filter} ORDER BY {order}");
+            if let Some(page) = page {
+                sql.push_str(&format!(" LIMIT ?{} OFFSET ?{}", params.len() + 1, params.len() + 2));
+                params.push(SqlValue::Integer(page.limit.try_into().unwrap_or(i64::MAX)));
+                params.push(SqlValue::Integer(page.offset.try_into().unwrap_or(i64::MAX)));
+            }
+            let mut stmt = conn.prepare_cached(&sql)?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(params), |r| {
+                Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)? as u64, r.get::<_, String>(2)?))
+            })?;
+            rows.map(|row| {
+                let (id, size_bytes, meta) = row?;
+                Ok((id, size_bytes, serde_json::from_str(&meta)?))
+            })
+            .collect()
+        })
+        .await
+    }
+
+    async fn sidecar(&self, id: &str) -> Result<(u64, SidecarMeta), AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| read_sqlite_sidecar(conn, &id)).await
+    }
+
+    /// Store a client-rendered PNG preview of a drawing.
+    pub async fn save_preview(&self, id: &str, png: &[u8]) -> Result<(), AppError> {
+        let (id, png) = (id.to_string(), png.to_vec());
+        self.with_conn(move |conn| {
+            conn.execute("INSERT OR REPLACE INTO previews (id, png) VALUES (?1, ?2)", params![id, png])?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read a drawing's uploaded PNG preview.
+    pub async fn read_preview(&self, id: &str) -> Result<Vec<u8>, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            conn.query_row("SELECT png FROM previews WHERE id = ?1", [&id], |r| r.get(0))
+                .optional()?
+                .ok_or(AppError::NotFound)
+        })
+        .await
+    }
+
+    /// Write, read back and remove a scratch row, to check that the database
+    /// accepts writes.
+    pub async fn probe_write(&self) -> Result<(), AppError> {
+        self.with_conn(|conn| {
+            let written = Utc::now();
+            let id = conn.query_row("INSERT INTO write_probe (at) VALUES (?1) RETURNING id", [written], |r| r.get::<_, i64>(0))?;
+            let read: DateTime<Utc> = conn.query_row("SELECT at FROM write_probe WHERE id = ?1", [id], |r| r.get(0))?;
+            conn.execute("DELETE FROM write_probe WHERE id = ?1", [id])?;
+            if read != written {
+                return Err(AppError::Internal("Write probe read back a different value".into()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Remove a drawing with its preview and history, leaving a tombstone unless
+    /// discarded. Trashing copies its row into `trash` first, content inlined so
+    /// the trash holds no blob references.
+    async fn remove_drawing(&self, id: &str, removal: Removal) -> Result<(), AppError> {
+        let id = id.to_string();
+        let quota = self.byte_quota.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let (source_path, size): (Option<String>, i64) = tx
+                .query_row("SELECT source_path, size_bytes FROM drawings WHERE id = ?1", [&id], |r| Ok((r.get(0)?, r.get(1)?)))
+                .optional()?
+                .ok_or(AppError::NotFound)?;
+            let deleted_at = Utc::now();
+            if removal != Removal::Discard {
+                tx.execute(
+                    "INSERT OR REPLACE INTO tombstones (id, deleted_at, source_path, burned) VALUES (?1, ?2, ?3, ?4)",
+                    params![id, deleted_at, source_path, removal == Removal::Burn],
+                )?;
+            }
+            if removal == Removal::Trash {
+                let data = serde_json::to_string(&read_sqlite_data(&tx, &id)?)?;
+                tx.execute(
+                    "INSERT INTO trash (id, deleted_at, data, created_at, source_path, size_bytes, meta)
+                     SELECT id, ?2, ?3, created_at, source_path, size_bytes, meta FROM drawings WHERE id = ?1",
+                    params![id, deleted_at, data],
+                )?;
+            }
+            release_sqlite_blob(&tx, &id)?;
+            tx.execute("DELETE FROM drawings WHERE id = ?1", [&id])?;
+            tx.execute("DELETE FROM previews WHERE id = ?1", [&id])?;
+            let history_bytes: i64 = tx.query_row("SELECT COALESCE(SUM(size_bytes), 0) FROM history WHERE id = ?1", [&id], |r| r.get(0))?;
+            tx.execute("DELETE FROM history WHERE id = ?1", [&id])?;
+            tx.commit()?;
+            // Trashed bytes stay counted against the quota until purged
+            quota.adjust(history_bytes as u64, 0);
+            if removal != Removal::Trash {
+                quota.adjust(size as u64, 0);
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Internal field of a deduplicated row naming the `blobs` row with its content.
+const SQLITE_BLOB_FIELD: &str = "_blob";
+
+fn read_sqlite_data(conn: &rusqlite::Connection, id: &str) -> Result<serde_json::Value, AppError> {
+    let data: String = conn
+        .query_row("SELECT data FROM drawings WHERE id = ?1", [id], |r| r.get(0))
+        .optional()?
+        .ok_or(AppError::NotFound)?;
+    resolve_sqlite_blob(conn, serde_json::from_str(&data)?)
+}
+
+/// The content blob a `drawings.data` row points at. Only rows written by
+/// [`store_sqlite_blob`] count: those hold nothing but internal fields, so a
+/// `_blob` key sitting next to inline content is not a reference.
+fn sqlite_blob_ref(data: &serde_json::Value) -> Option<&str> {
+    let obj = data.as_object()?;
+    if !obj.keys().all(|k| k.starts_with('_')) {
+        return None;
+    }
+    obj.get(SQLITE_BLOB_FIELD)?.as_str()
+}
+
+/// Merge a deduplicated row's internal fields back into its shared content.
+/// Rows stored inline are returned as they are.
+fn resolve_sqlite_blob(conn: &rusqlite::Connection, mut data: serde_json::Value) -> Result<serde_json::Value, AppError> {
+    let Some(hash) = sqlite_blob_ref(&data).map(str::to_string) else {
+        return Ok(data);
+    };
+    if let Some(obj) = data.as_object_mut() {
+        obj.remove(SQLITE_BLOB_FIELD);
+    }
+    let content: String = conn
+        .query_row("SELECT data FROM blobs WHERE hash = ?1", [&hash], |r| r.get(0))
+        .optional()?
+        .ok_or_else(|| AppError::Internal(format!("Content blob {hash} is missing")))?;
+    let mut content: serde_json::Value = serde_json::from_str(&content)?;
+    if let (Some(content), Some(internal)) = (content.as_object_mut(), data.as_object_mut()) {
+        content.append(internal);
+    }
+    Ok(content)
+}
+
+/// Store a document's content in `blobs`, or take another reference to the
+/// identical content already there, and return what goes in `drawings.data`.
+fn store_sqlite_blob(conn: &rusqlite::Connection, data: &serde_json::Value) -> Result<String, AppError> {
+    let Some(obj) = data.as_object() else {
+        return Ok(serde_json::to_string(data)?);
+    };
+    let (mut internal, content): (serde_json::Map<_, _>, serde_json::Map<_, _>) =
+        obj.clone().into_iter().filter(|(k, _)| k != SQLITE_BLOB_FIELD).partition(|(k, _)| k.starts_with('_'));
+    let content = serde_json::to_string(&content)?;
+    // Same as document_hash, as the internal fields are the ones left out
+    let hash = content_hash(content.as_bytes());
+    conn.execute(
+        "INSERT INTO blobs (hash, data, refs) VALUES (?1, ?2, 1) ON CONFLICT (hash) DO UPDATE SET refs = refs + 1",
+        params![hash, content],
+    )?;
+    internal.insert(SQLITE_BLOB_FIELD.to_string(), serde_json::Value::String(hash));
+    Ok(serde_json::to_string(&internal)?)
+}
+
+/// Drop a drawing's reference to its content blob, if it has one, deleting
+/// the blob once no drawing is left pointing at it.
+fn release_sqlite_blob(conn: &rusqlite::Connection, id: &str) -> Result<(), AppError> {
+    let data: Option<String> = conn
+        .query_row(
+            "SELECT data FROM drawings WHERE id = ?1 AND json_extract(data, '$._blob') IS NOT NULL",
+            [id],
+            |r| r.get(0),
+        )
+        .optional()?;
+    let data: Option<serde_json::Value> = data.map(|data| serde_json::from_str(&data)).transpose()?;
+    if let Some(hash) = data.as_ref().and_then(sqlite_blob_ref) {
+        conn.execute("UPDATE blobs SET refs = refs - 1 WHERE hash = ?1", [&hash])?;
+        conn.execute("DELETE FROM blobs WHERE hash = ?1 AND refs <= 0", [&hash])?;
+    }
+    Ok(())
+}
+
+fn read_sqlite_sidecar(conn: &rusqlite::Connection, id: &str) -> Result<(u64, SidecarMeta), AppError> {
+    let (size_bytes, meta): (i64, String) = conn
+        .query_row("SELECT size_bytes, meta FROM drawings WHERE id = ?1", [id], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .optional()?
+        .ok_or(AppError::NotFound)?;
+    Ok((size_bytes as u64, serde_json::from_str(&meta)?))
+}
+
+impl DrawingStorage for SqliteStorage {
+    async fn save(&self, id: &str, data: &serde_json::Value, source_path: Option<&str>, password_hash: Option<&str>) -> Result<DrawingMeta, AppError> {
+        let (id, data) = (id.to_string(), data.clone());
+        let (source_path, password_hash) = (source_path.map(String::from), password_hash.map(String::from));
+        let (history_depth, dedup_content) = (self.history_depth, self.dedup_content);
+        let quota = self.byte_quota.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let existing = read_sqlite_data(&tx, &id).ok();
+            let (old_size, existing_sidecar) = match read_sqlite_sidecar(&tx, &id) {
+                Ok((size, meta)) => (size, Some(meta)),
+                Err(_) => (0, None),
+            };
+
+            // Keep the content being replaced, unless only internal fields change.
+            // Its bytes then stay counted as history, less the versions pruned.
+            let mut freed = old_size;
+            if let (Some(old), Some(old_meta)) = (&existing, &existing_sidecar) {
+                if history_depth > 0 && document_hash(old) != document_hash(&data) {
+                    let old_json = serde_json::to_string(old)?;
+                    let version: i64 = tx.query_row(
+                        "SELECT COALESCE(MAX(version), 0) + 1 FROM history WHERE id = ?1",
+                        [&id],
+                        |r| r.get(0),
+                    )?;
+                    tx.execute(
+                        "INSERT INTO history (id, version, saved_at, size_bytes, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![id, version, old_meta.modified_at.unwrap_or(old_meta.created_at), old_json.len() as i64, old_json],
+                    )?;
+                    let mut prune = tx.prepare("DELETE FROM history WHERE id = ?1 AND version <= ?2 RETURNING size_bytes")?;
+                    let pruned = prune.query_map(params![id, version - history_depth as i64], |r| r.get::<_, i64>(0))?;
+                    freed = pruned.sum::<Result<i64, _>>()? as u64;
+                }
+            }
+
+            let data_with_meta = with_internal_fields(&data, source_path.as_deref(), password_hash.as_deref(), existing.as_ref());
+            let json = serde_json::to_string(&data_with_meta)?;
+            let sidecar = SidecarMeta::for_document(&data, &data_with_meta, source_path.as_deref(), existing_sidecar.as_ref());
+            // Take the new reference first, so re-saving the same content keeps its blob
+            let stored = if dedup_content { store_sqlite_blob(&tx, &data_with_meta)? } else { json.clone() };
+            release_sqlite_blob(&tx, &id)?;
+
+            tx.execute(
+                "INSERT INTO drawings (id, data, created_at, source_path, size_bytes, meta)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data, created_at = excluded.created_at,
+                     source_path = excluded.source_path, size_bytes = excluded.size_bytes, meta = excluded.meta",
+                params![id, stored, sidecar.created_at, sidecar.source_path, json.len() as i64, serde_json::to_string(&sidecar)?],
+            )?;
+            // Re-publishing a burned id brings it back
+            tx.execute("DELETE FROM tombstones WHERE id = ?1", [&id])?;
+            // Refusing here rolls the transaction back
+            let size_bytes = json.len() as u64;
+            quota.reserve(freed, size_bytes)?;
+            if let Err(e) = tx.commit() {
+                quota.adjust(size_bytes, freed);
+                return Err(e.into());
+            }
+            Ok(sidecar.to_meta(&id, size_bytes))
+        })
+        .await
+    }
+
+    async fn load(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| read_sqlite_data(conn, &id)).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Delete).await
+    }
+
+    async fn discard(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Discard).await
+    }
+
+    /// One transaction, deleting with `WHERE id IN (...)`.
+    async fn delete_many(&self, ids: &[String]) -> Vec<Result<(), AppError>> {
+        if ids.is_empty() {
+            return Vec::new();
+        }
+        let requested = ids.to_vec();
+        let quota = self.byte_quota.clone();
+        let deleted = self
+            .with_conn(move |conn| {
+                let tx = conn.transaction()?;
+                let placeholders = vec!["?"; requested.len()].join(", ");
+                let found: Vec<(String, Option<String>, i64)> = {
+                    let mut stmt =
+                        tx.prepare(&format!("SELECT id, source_path, size_bytes FROM drawings WHERE id IN ({placeholders})"))?;
+                    let rows = stmt.query_map(rusqlite::params_from_iter(&requested), |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+                    rows.collect::<Result<_, _>>()?
+                };
+                let deleted_at = Utc::now();
+                for (id, source_path, _) in &found {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO tombstones (id, deleted_at, source_path, burned) VALUES (?1, ?2, ?3, 0)",
+                        params![id, deleted_at, source_path],
+                    )?;
+                    release_sqlite_blob(&tx, id)?;
+                }
+                let history_bytes: i64 = tx.query_row(
+                    &format!("SELECT COALESCE(SUM(size_bytes), 0) FROM history WHERE id IN ({placeholders})"),
+                    rusqlite::params_from_iter(&requested),
+                    |r| r.get(0),
+                )?;
+                for table in ["drawings", "previews", "history"] {
+                    tx.execute(&format!("DELETE FROM {table} WHERE id IN ({placeholders})"), rusqlite::params_from_iter(&requested))?;
+                }
+                tx.commit()?;
+                quota.adjust(found.iter().map(|(_, _, size)| *size as u64).sum::<u64>() + history_bytes as u64, 0);
+                Ok(found.into_iter().map(|(id, _, _)| id).collect::<BTreeSet<_>>())
+            })
+            .await;
+        match deleted {
+            Ok(deleted) => ids.iter().map(|id| if deleted.contains(id) { Ok(()) } else { Err(AppError::NotFound) }).collect(),
+            Err(e) => {
+                let message = e.to_string();
+                ids.iter().map(|_| Err(AppError::Internal(message.clone()))).collect()
+            }
+        }
+    }
+
+    /// One query over the `created_at` index; drawing data is never read.
+    async fn list(&self) -> Result<Vec<DrawingMeta>, AppError> {
+        let rows = self.rows("", Vec::new(), None).await?;
+        Ok(rows.into_iter().map(|(id, size, sidecar)| sidecar.to_meta(&id, size)).collect())
+    }
+
+    async fn exists(&self, id: &str) -> Result<bool, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            Ok(conn.query_row("SELECT EXISTS (SELECT 1 FROM drawings WHERE id = ?1)", [&id], |r| r.get(0))?)
+        })
+        .await
+    }
+
+    /// Expiry, id and source path filters run in the database, which also does
+    /// the paging; any other filter is matched over the full listing.
+    async fn list_page(&self, filter: &DrawingFilter, page: Page) -> Result<(Vec<DrawingMeta>, usize), AppError> {
+        if filter.tag.is_some() || filter.from.is_some() || filter.to.is_some() || filter.user_agent.is_some() {
+            let matching = self.list().await?.into_iter().filter(|d| filter.matches(d)).collect();
+            return Ok(page_of(matching, page));
+        }
+
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        if filter.exclude_expired {
+            conditions.push(SQLITE_NOT_EXPIRED.to_string());
+        }
+        if let Some(q) = &filter.q {
+            params.push(SqlValue::Text(q.to_lowercase()));
+            conditions.push(format!("instr(lower(id), ?{}) > 0", params.len()));
+        }
+        if let Some(prefix) = &filter.source_path {
+            // A range over the source_path index: everything from the prefix up
+            // to the prefix followed by the highest code point
+            params.push(SqlValue::Text(prefix.clone()));
+            params.push(SqlValue::Text(format!("{prefix}{}", char::MAX)));
+            conditions.push(format!("source_path >= ?{} AND source_path < ?{}", params.len() - 1, params.len()));
+        }
+        let clause = if conditions.is_empty() { String::new() } else { format!("WHERE {}", conditions.join(" AND ")) };
+
+        let (count_clause, count_params) = (clause.clone(), params.clone());
+        let total: i64 = self
+            .with_conn(move |conn| {
+                let sql = format!("SELECT COUNT(*) FROM drawings {count_clause}");
+                Ok(conn.query_row(&sql, rusqlite::params_from_iter(count_params), |r| r.get(0))?)
+            })
+            .await?;
+        let rows = self.rows(clause, params, Some(page)).await?;
+        let drawings = rows.into_iter().map(|(id, size, sidecar)| sidecar.to_meta(&id, size)).collect();
+        Ok((drawings, total as usize))
+    }
+
+    async fn get_persistent_collab_status(&self, id: &str) -> Result<bool, AppError> {
+        Ok(self.sidecar(id).await?.1.persistent_collab)
+    }
+
+    async fn save_persistent(
+        &self,
+        id: &str,
+        data: &serde_json::Value,
+        version: u64,
+    ) -> Result<(), AppError> {
+        let (id, data) = (id.to_string(), data.clone());
+        let dedup_content = self.dedup_content;
+        let quota = self.byte_quota.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let existing = read_sqlite_data(&tx, &id)?;
+            let (old_size, mut sidecar) = read_sqlite_sidecar(&tx, &id)?;
+
+            let document = persistent_document(&data, &existing, version);
+            let json = serde_json::to_string(&document)?;
+            let stored = if dedup_content { store_sqlite_blob(&tx, &document)? } else { json.clone() };
+            release_sqlite_blob(&tx, &id)?;
+            sidecar.update_persistent_content(&data);
+            tx.execute(
+                "UPDATE drawings SET data = ?2, size_bytes = ?3, meta = ?4 WHERE id = ?1",
+                params![id, stored, json.len() as i64, serde_json::to_string(&sidecar)?],
+            )?;
+            let size_bytes = json.len() as u64;
+            quota.reserve(old_size, size_bytes)?;
+            if let Err(e) = tx.commit() {
+                quota.adjust(size_bytes, old_size);
+                return Err(e.into());
+            }
+            tracing::debug!(id = %id, version = version, "Persistent collab save completed");
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_persistent_collab_drawings(&self) -> Result<Vec<String>, AppError> {
+        let rows = self.rows("WHERE json_extract(meta, '$.persistent_collab')", Vec::new(), None).await?;
+        Ok(rows.into_iter().map(|(id, _, _)| id).collect())
+    }
+
+    async fn find_by_source_path(&self, source_path: &str) -> Result<Option<DrawingMeta>, AppError> {
+        let rows = self.rows("WHERE source_path = ?1", vec![SqlValue::Text(source_path.to_string())], None).await?;
+        Ok(rows.into_iter().next().map(|(id, size, sidecar)| sidecar.to_meta(&id, size)))
+    }
+
+    async fn search_text(&self, query: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        let query = query.to_lowercase();
+        let rows = self.rows("", Vec::new(), None).await?;
+        Ok(rows
+            .into_iter()
+            .filter(|(_, _, sidecar)| sidecar.text.as_deref().is_some_and(|t| t.to_lowercase().contains(&query)))
+            .map(|(id, size, sidecar)| sidecar.to_meta(&id, size))
+            .collect())
+    }
+
+    async fn get_meta(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        let (size_bytes, sidecar) = self.sidecar(id).await?;
+        Ok(sidecar.to_meta(id, size_bytes))
+    }
+
+    async fn meta(&self, id: &str) -> Result<DrawingAccess, AppError> {
+        let (size_bytes, sidecar) = self.sidecar(id).await?;
+        let password_hash = match sidecar.password_hash.clone() {
+            Some(hash) => Some(hash),
+            None if sidecar.password_protected => text_field(&self.load(id).await?, "_password_hash"),
+            None => None,
+        };
+        Ok(DrawingAccess { meta: sidecar.to_meta(id, size_bytes), password_hash, max_views: sidecar.max_views })
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<DrawingMeta>, AppError> {
+        let rows = self.rows("WHERE json_extract(meta, '$.slug') = ?1", vec![SqlValue::Text(slug.to_string())], None).await?;
+        Ok(rows.into_iter().next().map(|(id, size, sidecar)| sidecar.to_meta(&id, size)))
+    }
+
+    async fn stats(&self) -> Result<StorageStats, AppError> {
+        let mut stats = StorageStats::default();
+        for (_, size_bytes, sidecar) in self.rows("", Vec::new(), None).await? {
+            stats.drawing_count += 1;
+            stats.total_bytes += size_bytes;
+            for (element_type, count) in sidecar.element_types.unwrap_or_default() {
+                *stats.element_types.entry(element_type).or_insert(0) += count;
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn summary(&self, id: &str) -> Result<DrawingSummary, AppError> {
+        let (size_bytes, sidecar) = self.sidecar(id).await?;
+        let content = match sidecar.content {
+            Some(content) => content,
+            None => content_summary(&self.load(id).await?),
+        };
+        Ok(DrawingSummary {
+            id: id.to_string(),
+            size_bytes,
+            password_protected: sidecar.password_protected,
+            content,
+        })
+    }
+
+    async fn find_by_content_hash(&self, hash: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        let rows = self.rows("WHERE json_extract(meta, '$.content_hash') = ?1", vec![SqlValue::Text(hash.to_string())], None).await?;
+        Ok(rows.into_iter().map(|(id, size, sidecar)| sidecar.to_meta(&id, size)).collect())
+    }
+
+    async fn record_view(&self, id: &str) -> Result<u64, AppError> {
+        self.add_views(id, 1).await
+    }
+
+    async fn add_views(&self, id: &str, delta: u64) -> Result<u64, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let (_, mut sidecar) = read_sqlite_sidecar(&tx, &id)?;
+            sidecar.views += delta;
+            tx.execute("UPDATE drawings SET meta = ?2 WHERE id = ?1", params![id, serde_json::to_string(&sidecar)?])?;
+            tx.commit()?;
+            Ok(sidecar.views)
+        })
+        .await
+    }
+
+    async fn burn(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Burn).await
+    }
+
+    async fn is_burned(&self, id: &str) -> Result<bool, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let burned = conn
+                .query_row("SELECT burned FROM tombstones WHERE id = ?1", [&id], |r| r.get(0))
+                .optional()?;
+            Ok(burned.unwrap_or(false))
+        })
+        .await
+    }
+
+    async fn deleted_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Tombstone>, AppError> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, deleted_at, source_path, burned FROM tombstones
+                 WHERE ?1 IS NULL OR deleted_at > ?1 ORDER BY deleted_at, id",
+            )?;
+            let tombstones = stmt.query_map([since], |r| {
+                Ok(Tombstone {
+                    id: r.get(0)?,
+                    deleted_at: r.get(1)?,
+                    source_path: r.get(2)?,
+                    burned: r.get(3)?,
+                })
+            })?;
+            Ok(tombstones.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    /// The index here is the `meta` JSON and the `source_path` lookup column
+    /// derived from it.
+    async fn verify(&self) -> Result<VerifyReport, AppError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, data, source_path, meta FROM drawings ORDER BY id")?;
+            let mut rows = stmt.query([])?;
+            let mut report = VerifyReport::default();
+            while let Some(row) = rows.next()? {
+                let (id, data, source_path): (String, String, Option<String>) = (row.get(0)?, row.get(1)?, row.get(2)?);
+                report.checked += 1;
+                let Ok(sidecar) = serde_json::from_str::<SidecarMeta>(&row.get::<_, String>(3)?) else {
+                    report.missing_from_index.push(id);
+                    continue;
+                };
+                match serde_json::from_str(&data).map_err(AppError::from).and_then(|data| resolve_sqlite_blob(conn, data)) {
+                    Ok(data) => report.check_hash(&id, sidecar.content_hash, &data),
+                    Err(_) => report.unreadable.push(id.clone()),
+                }
+                if source_path != sidecar.source_path {
+                    report.stale_index_entries.push(id);
+                }
+            }
+            Ok(report)
+        })
+        .await
+    }
+
+    async fn history(&self, id: &str) -> Result<Vec<DrawingVersion>, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT version, saved_at, size_bytes FROM history WHERE id = ?1 ORDER BY version DESC",
+            )?;
+            let rows = stmt.query_map([&id], |r| {
+                Ok(DrawingVersion { version: r.get::<_, i64>(0)? as u64, saved_at: r.get(1)?, size_bytes: r.get::<_, i64>(2)? as u64 })
+            })?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    async fn load_version(&self, id: &str, version: u64) -> Result<serde_json::Value, AppError> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let data: String = conn
+                .query_row("SELECT data FROM history WHERE id = ?1 AND version = ?2", params![id, version as i64], |r| r.get(0))
+                .optional()?
+                .ok_or(AppError::NotFound)?;
+            Ok(serde_json::from_str(&data)?)
+        })
+        .await
+    }
+
+    async fn trash(&self, id: &str) -> Result<(), AppError> {
+        self.remove_drawing(id, Removal::Trash).await
+    }
+
+    async fn list_trash(&self) -> Result<Vec<TrashedDrawing>, AppError> {
+        self.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare_cached("SELECT id, deleted_at, source_path, size_bytes FROM trash ORDER BY deleted_at DESC, id")?;
+            let trashed = stmt.query_map([], |r| {
+                Ok(TrashedDrawing {
+                    id: r.get(0)?,
+                    deleted_at: r.get(1)?,
+                    source_path: r.get(2)?,
+                    size_bytes: r.get::<_, i64>(3)? as u64,
+                })
+            })?;
+            Ok(trashed.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    /// Brings back the id's most recent deletion. Content is re-shared through
+    /// `blobs` when dedup is enabled now.
+    async fn restore(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        let id = id.to_string();
+        let dedup_content = self.dedup_content;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let sql = "SELECT rowid, data, size_bytes, meta FROM trash WHERE id = ?1 ORDER BY deleted_at DESC LIMIT 1";
+            let (rowid, data, size_bytes, meta): (i64, String, i64, String) = tx
+                .query_row(sql, [&id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+                .optional()?
+                .ok_or(AppError::NotFound)?;
+            let taken = tx.query_row("SELECT 1 FROM drawings WHERE id = ?1", [&id], |_| Ok(())).optional()?.is_some();
+            if taken {
+                return Err(AppError::Conflict(format!("A drawing with id '{id}' exists; delete it before restoring")));
+            }
+            let stored = if dedup_content { store_sqlite_blob(&tx, &serde_json::from_str(&data)?)? } else { data };
+            tx.execute(
+                "INSERT INTO drawings (id, data, created_at, source_path, size_bytes, meta)
+                 SELECT id, ?2, created_at, source_path, size_bytes, meta FROM trash WHERE rowid = ?1",
+                params![rowid, stored],
+            )?;
+            tx.execute("DELETE FROM trash WHERE rowid = ?1", [rowid])?;
+            tx.execute("DELETE FROM tombstones WHERE id = ?1", [&id])?;
+            // Its bytes never left the quota
+            tx.commit()?;
+            let sidecar: SidecarMeta = serde_json::from_str(&meta)?;
+            Ok(sidecar.to_meta(&id, size_bytes as u64))
+        })
+        .await
+    }
+
+    async fn purge_trash(&self, before: DateTime<Utc>) -> Result<Vec<String>, AppError> {
+        let quota = self.byte_quota.clone();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached("DELETE FROM trash WHERE deleted_at < ?1 RETURNING id, size_bytes")?;
+            let purged = stmt.query_map([before], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?;
+            let mut ids = Vec::new();
+            for row in purged {
+                let (id, size) = row?;
+                quota.adjust(size as u64, 0);
+                ids.push(id);
+            }
+            Ok(ids)
+        })
+        .await
+    }
+}
+
+/// The storage backend chosen at startup (`--storage-backend`).
+#[derive(Clone)]
+pub enum Storage {
+    Fs(CachedStorage<FileSystemStorage>),
+    Sqlite(CachedStorage<SqliteStorage>),
+}
+
+impl From<CachedStorage<FileSystemStorage>> for Storage {
+    fn from(storage: CachedStorage<FileSystemStorage>) -> Self {
+        Self::Fs(storage)
+    }
+}
+
+impl From<CachedStorage<SqliteStorage>> for Storage {
+    fn from(storage: CachedStorage<SqliteStorage>) -> Self {
+        Self::Sqlite(storage)
+    }
+}
+
+impl From<FileSystemStorage> for Storage {
+    fn from(storage: FileSystemStorage) -> Self {
+        CachedStorage::new(storage).into()
+    }
+}
+
+impl From<SqliteStorage> for Storage {
+    fn from(storage: SqliteStorage) -> Self {
+        CachedStorage::new(storage).into()
+    }
+}
+
+/// Forward a call to whichever backend is in use.
+macro_rules! delegate {
+    ($self:ident, $s:ident => $call:expr) => {
+        match $self {
+            Storage::Fs($s) => $call,
+            Storage::Sqlite($s) => $call,
+        }
+    };
+}
+
+impl Storage {
+    /// See [`FileSystemStorage::load_with_asset_urls`]. SQLite never extracts
+    /// assets, so its drawings load as stored.
+    pub async fn load_with_asset_urls(&self, id: &str, asset_base: &str) -> Result<serde_json::Value, AppError> {
+        validate_id(id)?;
+        match self {
+            Self::Fs(s) => s.inner().load_with_asset_urls(id, asset_base).await,
+            Self::Sqlite(s) => s.load(id).await,
+        }
+    }
+
+    /// [`DrawingStorage::head`] of the document [`Storage::load_with_asset_urls`] loads.
+    pub async fn head_with_asset_urls(&self, id: &str, asset_base: &str) -> Result<DrawingHead, AppError> {
+        validate_id(id)?;
+        match self {
+            Self::Fs(s) => DrawingHead::of(s.inner().load_with_asset_urls(id, asset_base).await?),
+            Self::Sqlite(s) => s.head(id).await,
+        }
+    }
+
+    /// See [`FileSystemStorage::load_stored`]. SQLite stores files inline.
+    pub async fn load_stored(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        validate_id(id)?;
+        match self {
+            Self::Fs(s) => s.inner().load_stored(id).await,
+            Self::Sqlite(s) => s.load(id).await,
+        }
+    }
+
+    /// See [`FileSystemStorage::read_asset`].
+    pub async fn read_asset(&self, hash: &str) -> Result<(String, Vec<u8>), AppError> {
+        match self {
+            Self::Fs(s) => s.inner().read_asset(hash).await,
+            Self::Sqlite(_) => Err(AppError::NotFound),
+        }
+    }
+
+    /// Memory used by the cache, `None` when caching is disabled.
+    pub fn cache_usage(&self) -> Option<CacheUsage> {
+        delegate!(self, s => s.cache_usage())
+    }
+
+    pub async fn save_preview(&self, id: &str, png: &[u8]) -> Result<(), AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.inner().save_preview(id, png).await)
+    }
+
+    pub async fn read_preview(&self, id: &str) -> Result<Vec<u8>, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.inner().read_preview(id).await)
+    }
+
+    pub async fn probe_write(&self) -> Result<(), AppError> {
+        delegate!(self, s => s.inner().probe_write().await)
+    }
+
+    /// Name of the backend, as given to `--storage-backend`.
+    pub fn backend(&self) -> &'static str {
+        match self {
+            Self::Fs(_) => "fs",
+            Self::Sqlite(_) => "sqlite",
+        }
+    }
+}
+
+impl DrawingStorage for Storage {
+    async fn save(&self, id: &str, data: &serde_json::Value, source_path: Option<&str>, password_hash: Option<&str>) -> Result<DrawingMeta, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.save(id, data, source_path, password_hash).await)
+    }
+
+    async fn load(&self, id: &str) -> Result<serde_json::Value, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.load(id).await)
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.delete(id).await)
+    }
+
+    async fn discard(&self, id: &str) -> Result<(), AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.discard(id).await)
+    }
+
+    async fn delete_many(&self, ids: &[String]) -> Vec<Result<(), AppError>> {
+        let checks: Vec<_> = ids.iter().map(|id| validate_id(id)).collect();
+        let valid: Vec<String> = ids.iter().zip(&checks).filter(|(_, c)| c.is_ok()).map(|(id, _)| id.clone()).collect();
+        let mut deleted = delegate!(self, s => s.delete_many(&valid).await).into_iter();
+        checks
+            .into_iter()
+            .map(|check| check.and_then(|()| deleted.next().unwrap_or(Err(AppError::NotFound))))
+            .collect()
+    }
+
+    async fn list(&self) -> Result<Vec<DrawingMeta>, AppError> {
+        delegate!(self, s => s.list().await)
+    }
+
+    async fn exists(&self, id: &str) -> Result<bool, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.exists(id).await)
+    }
+
+    async fn list_page(&self, filter: &DrawingFilter, page: Page) -> Result<(Vec<DrawingMeta>, usize), AppError> {
+        delegate!(self, s => s.list_page(filter, page).await)
+    }
+
+    async fn get_persistent_collab_status(&self, id: &str) -> Result<bool, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.get_persistent_collab_status(id).await)
+    }
+
+    async fn save_persistent(&self, id: &str, data: &serde_json::Value, version: u64) -> Result<(), AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.save_persistent(id, data, version).await)
+    }
+
+    async fn list_persistent_collab_drawings(&self) -> Result<Vec<String>, AppError> {
+        delegate!(self, s => s.list_persistent_collab_drawings().await)
+    }
+
+    async fn find_by_source_path(&self, source_path: &str) -> Result<Option<DrawingMeta>, AppError> {
+        delegate!(self, s => s.find_by_source_path(source_path).await)
+    }
+
+    async fn search_text(&self, query: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        delegate!(self, s => s.search_text(query).await)
+    }
 
-                    // Use file system creation time as best-effort, or fall back to now
-                    let created_at = entry.metadata().await
-                        .ok()
-                        .and_then(|m| m.created().ok())
-                        .map(DateTime::from)
-                        .unwrap_or_else(Utc::now);
+    async fn get_meta(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.get_meta(id).await)
+    }
 
-                    let sidecar = SidecarMeta {
-                        created_at,
-                        source_path,
-                        password_protected,
-                        persistent_collab,
-                    };
+    async fn meta(&self, id: &str) -> Result<DrawingAccess, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.meta(id).await)
+    }
 
-                    if let Err(e) = self.write_sidecar(&id, &sidecar).await {
-                        tracing::warn!(id = %id, error = %e, "Failed to migrate sidecar metadata");
-                    } else {
-                        migrated += 1;
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!(id = %id, error = %e, "Failed to read drawing for sidecar migration");
-                }
-            }
-        }
+    async fn head(&self, id: &str) -> Result<DrawingHead, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.head(id).await)
+    }
 
-        if migrated > 0 {
-            tracing::info!(count = migrated, "Migrated sidecar metadata files for existing drawings");
-        }
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<DrawingMeta>, AppError> {
+        delegate!(self, s => s.find_by_slug(slug).await)
     }
-}
 
-impl DrawingStorage for FileSystemStorage {
-    async fn save(&self, id: &str, data: &serde_json::Value, source_path: Option<&str>, password_hash: Option<&str>) -> Result<DrawingMeta, AppError> {
-        let path = self.drawing_path(id);
+    async fn stats(&self) -> Result<StorageStats, AppError> {
+        delegate!(self, s => s.stats().await)
+    }
 
-        // Read existing drawing to preserve persistent collab fields
-        let existing = if path.exists() {
-            fs::read(&path).await
-                .ok()
-                .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
-        } else {
-            None
-        };
+    async fn summary(&self, id: &str) -> Result<DrawingSummary, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.summary(id).await)
+    }
 
-        let mut data_with_meta = data.clone();
-        if let Some(obj) = data_with_meta.as_object_mut() {
-            if let Some(sp) = source_path {
-                obj.insert("_source_path".to_string(), serde_json::Value::String(sp.to_string()));
-            }
-            // Store or remove password hash
-            if let Some(ph) = password_hash {
-                obj.insert("_password_hash".to_string(), serde_json::Value::String(ph.to_string()));
-            } else {
-                obj.remove("_password_hash");
-            }
+    async fn find_by_content_hash(&self, hash: &str) -> Result<Vec<DrawingMeta>, AppError> {
+        delegate!(self, s => s.find_by_content_hash(hash).await)
+    }
 
-            // Preserve persistent collab fields from existing drawing if not in new data
-            if let Some(ref existing_data) = existing {
-                if !obj.contains_key("_persistent_collab") {
-                    if let Some(pc) = existing_data.get("_persistent_collab") {
-                        obj.insert("_persistent_collab".to_string(), pc.clone());
-                    }
-                }
-                if !obj.contains_key("_persistent_collab_version") {
-                    if let Some(pcv) = existing_data.get("_persistent_collab_version") {
-                        obj.insert("_persistent_collab_version".to_string(), pcv.clone());
-                    }
-                }
-                if !obj.contains_key("_persistent_collab_password_hash") {
-                    if let Some(pcph) = existing_data.get("_persistent_collab_password_hash") {
-                        obj.insert("_persistent_collab_password_hash".to_string(), pcph.clone());
-                    }
-                }
-            }
-        }
+    async fn record_view(&self, id: &str) -> Result<u64, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.record_view(id).await)
+    }
 
-        let json_bytes = serde_json::to_vec(&data_with_meta)?;
-        let size_bytes = json_bytes.len() as u64;
+    async fn add_views(&self, id: &str, delta: u64) -> Result<u64, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.add_views(id, delta).await)
+    }
 
-        fs::write(&path, &json_bytes).await?;
+    async fn burn(&self, id: &str) -> Result<(), AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.burn(id).await)
+    }
 
-        // Determine created_at: preserve from existing sidecar, or use now for new drawings
-        let existing_sidecar = self.read_sidecar(id).await;
-        let created_at = existing_sidecar
-            .as_ref()
-            .map(|m| m.created_at)
-            .unwrap_or_else(Utc::now);
+    async fn is_burned(&self, id: &str) -> Result<bool, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.is_burned(id).await)
+    }
 
-        let password_protected = password_hash.is_some();
+    async fn deleted_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Tombstone>, AppError> {
+        delegate!(self, s => s.deleted_since(since).await)
+    }
 
-        // Determine persistent_collab from the saved data
-        let persistent_collab = data_with_meta
-            .get("_persistent_collab")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
+    async fn verify(&self) -> Result<VerifyReport, AppError> {
+        delegate!(self, s => s.verify().await)
+    }
 
-        // Write/update sidecar metadata (tiny file, fast)
-        let sidecar = SidecarMeta {
-            created_at,
-            source_path: source_path.map(String::from),
-            password_protected,
-            persistent_collab,
-        };
-        self.write_sidecar(id, &sidecar).await?;
+    async fn history(&self, id: &str) -> Result<Vec<DrawingVersion>, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.history(id).await)
+    }
 
-        Ok(DrawingMeta {
-            id: id.to_string(),
-            created_at,
-            size_bytes,
-            source_path: source_path.map(String::from),
-            password_protected,
-            persistent_collab,
-        })
+    async fn load_version(&self, id: &str, version: u64) -> Result<serde_json::Value, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.load_version(id, version).await)
     }
 
-    async fn load(&self, id: &str) -> Result<serde_json::Value, AppError> {
-        let path = self.drawing_path(id);
-        if !path.exists() {
-            return Err(AppError::NotFound);
-        }
-        let bytes = fs::read(&path).await?;
-        let data: serde_json::Value = serde_json::from_slice(&bytes)?;
-        Ok(data)
+    async fn trash(&self, id: &str) -> Result<(), AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.trash(id).await)
     }
 
-    async fn delete(&self, id: &str) -> Result<(), AppError> {
-        let path = self.drawing_path(id);
-        if !path.exists() {
-            return Err(AppError::NotFound);
-        }
-        fs::remove_file(&path).await?;
+    async fn list_trash(&self) -> Result<Vec<TrashedDrawing>, AppError> {
+        delegate!(self, s => s.list_trash().await)
+    }
 
-        // Also remove sidecar metadata
-        let meta_path = self.meta_path(id);
-        let _ = fs::remove_file(&meta_path).await; // Ignore error if sidecar doesn't exist
+    async fn restore(&self, id: &str) -> Result<DrawingMeta, AppError> {
+        validate_id(id)?;
+        delegate!(self, s => s.restore(id).await)
+    }
 
-        Ok(())
+    async fn purge_trash(&self, before: DateTime<Utc>) -> Result<Vec<String>, AppError> {
+        delegate!(self, s => s.purge_trash(before).await)
     }
+}
 
-    /// List all drawings using lightweight sidecar metadata files.
-    /// Never reads the full drawing JSON — only the tiny .meta.json files.
-    async fn list(&self) -> Result<Vec<DrawingMeta>, AppError> {
-        let mut entries = fs::read_dir(&self.base_path).await?;
-        let mut drawings = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    fn drawing(types: &[&str]) -> serde_json::Value {
+        let elements: Vec<serde_json::Value> = types
+            .iter()
+            .enumerate()
+            .map(|(i, t)| json!({ "id": format!("el{i}"), "type": t, "x": 0, "y": 0 }))
+            .collect();
+        json!({ "type": "excalidraw", "version": 2, "elements": elements })
+    }
 
-            // Only process .json files (not .meta.json)
-            if !filename.ends_with(".json") || filename.ends_with(".meta.json") {
-                continue;
-            }
+    #[test]
+    fn test_element_type_histogram_skips_deleted() {
+        let data = json!({
+            "type": "excalidraw",
+            "elements": [
+                { "id": "a", "type": "rectangle" },
+                { "id": "b", "type": "rectangle", "isDeleted": true },
+                { "id": "c", "type": "text" },
+            ]
+        });
+        let histogram = element_type_histogram(&data);
+        assert_eq!(histogram.get("rectangle"), Some(&1));
+        assert_eq!(histogram.get("text"), Some(&1));
+        assert_eq!(histogram.len(), 2);
+    }
 
-            let file_metadata = entry.metadata().await?;
-            let id = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_string();
+    #[tokio::test]
+    async fn test_stats_aggregates_element_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+
+        storage
+            .save("one", &drawing(&["rectangle", "arrow", "text"]), None, None)
+            .await
+            .unwrap();
+        storage
+            .save("two", &drawing(&["rectangle", "rectangle", "ellipse"]), None, None)
+            .await
+            .unwrap();
+
+        let stats = storage.stats().await.unwrap();
+        assert_eq!(stats.drawing_count, 2);
+        assert_eq!(stats.element_types.get("rectangle"), Some(&3));
+        assert_eq!(stats.element_types.get("arrow"), Some(&1));
+        assert_eq!(stats.element_types.get("text"), Some(&1));
+        assert_eq!(stats.element_types.get("ellipse"), Some(&1));
+        assert!(stats.total_bytes > 0);
+    }
 
-            if id.is_empty() {
-                continue;
-            }
+    #[tokio::test]
+    async fn test_summary_reports_elements_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+
+        let mut with_files = drawing(&["rectangle", "image"]);
+        with_files["files"] = json!({
+            "f1": { "id": "f1", "mimeType": "image/png", "dataURL": "data:image/png;base64,AAAA" }
+        });
+        storage.save("files", &with_files, Some("a.excalidraw"), None).await.unwrap();
+        storage.save("plain", &drawing(&["text"]), None, None).await.unwrap();
+
+        let summary = storage.summary("files").await.unwrap();
+        assert_eq!(summary.content.element_count, 2);
+        assert!(summary.content.has_files);
+        assert_eq!(summary.content.file_count, 1);
+        assert_eq!(summary.content.embedded_bytes, "data:image/png;base64,AAAA".len() as u64);
+        assert!(!summary.content.keys.iter().any(|k| k.starts_with('_')));
+
+        let summary = storage.summary("plain").await.unwrap();
+        assert_eq!(summary.content.element_count, 1);
+        assert!(!summary.content.has_files);
+
+        assert!(matches!(storage.summary("missing").await, Err(AppError::NotFound)));
+    }
 
-            // Read the lightweight sidecar (typically < 200 bytes)
-            let sidecar = self.read_sidecar(&id).await;
+    #[tokio::test]
+    async fn test_shared_embedded_file_is_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path())
+            .await
+            .unwrap()
+            .with_asset_extraction(true);
+
+        let data_url = format!("data:image/png;base64,{}", "A".repeat(4096));
+        let mut doc = drawing(&["image"]);
+        doc["files"] = json!({ "f1": { "id": "f1", "mimeType": "image/png", "dataURL": data_url } });
+
+        storage.save("first", &doc, None, None).await.unwrap();
+        storage.save("second", &doc, None, None).await.unwrap();
+
+        let assets: Vec<_> = std::fs::read_dir(dir.path().join("assets")).unwrap().collect();
+        assert_eq!(assets.len(), 1);
+
+        // The stored drawing holds only a reference
+        let raw: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("first.json")).unwrap()).unwrap();
+        assert!(raw["files"]["f1"].get("dataURL").is_none());
+        assert!(raw["files"]["f1"].get("_asset").is_some());
+
+        // Loading reassembles the full document
+        let loaded = storage.load("second").await.unwrap();
+        assert_eq!(loaded["files"]["f1"]["dataURL"], json!(data_url));
+        assert!(loaded["files"]["f1"].get("_asset").is_none());
+        assert_eq!(storage.list().await.unwrap().len(), 2);
+
+        // A reference sent with a document, e.g. to another drawing's image, is dropped
+        let hash = raw["files"]["f1"]["_asset"].clone();
+        let mut forged = drawing(&["image"]);
+        forged["files"] = json!({ "f1": { "id": "f1", "mimeType": "image/png", "_asset": hash }, "f2": { "_asset": "" } });
+        storage.save("forged", &forged, None, None).await.unwrap();
+        let loaded = storage.load("forged").await.unwrap();
+        assert_eq!(loaded["files"], json!({ "f1": { "id": "f1", "mimeType": "image/png" }, "f2": {} }));
+        // An empty one stored by an older version isn't resolved either
+        std::fs::write(dir.path().join("forged.json"), serde_json::to_vec(&forged).unwrap()).unwrap();
+        let loaded = storage.load("forged").await.unwrap();
+        assert_eq!(loaded["files"]["f2"], json!({ "_asset": "" }));
+
+        // A save refused by the byte quota writes no assets either
+        let dir = tempfile::tempdir().unwrap();
+        let capped = FileSystemStorage::new(dir.path())
+            .await
+            .unwrap()
+            .with_asset_extraction(true)
+            .with_byte_quota(ByteQuota::new(Some(16)));
+        assert!(matches!(capped.save("first", &doc, None, None).await, Err(AppError::QuotaExceeded(_))));
+        assert!(!dir.path().join("assets").exists());
+    }
 
-            let (created_at, source_path, password_protected, persistent_collab) = match sidecar {
-                Some(meta) => (meta.created_at, meta.source_path, meta.password_protected, meta.persistent_collab),
-                None => {
-                    // Sidecar missing — use filesystem metadata as fallback
-                    let created_at = file_metadata
-                        .created()
-                        .map(DateTime::from)
-                        .unwrap_or_else(|_| Utc::now());
-                    (created_at, None, false, false)
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_readers_never_see_partial_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+
+        // Two large versions of different sizes
+        let version = |label: &str, count: usize| {
+            let mut doc = drawing(&vec!["rectangle"; count]);
+            doc["label"] = json!(label);
+            doc
+        };
+        let (old, new) = (version("old", 2000), version("new", 4000));
+        let old_meta = storage.save("big", &old, None, None).await.unwrap();
+        let new_size = serde_json::to_vec(&new).unwrap().len() as u64;
+        let sizes = [old_meta.size_bytes, new_size];
+
+        let writer = {
+            let storage = storage.clone();
+            let (old, new) = (old.clone(), new.clone());
+            tokio::spawn(async move {
+                for i in 0..30 {
+                    let doc = if i % 2 == 0 { &new } else { &old };
+                    storage.save("big", doc, None, None).await.unwrap();
                 }
-            };
+            })
+        };
 
-            drawings.push(DrawingMeta {
-                id,
-                created_at,
-                size_bytes: file_metadata.len(),
-                source_path,
-                password_protected,
-                persistent_collab,
-            });
+        while !writer.is_finished() {
+            let listed = storage.list().await.unwrap();
+            assert_eq!(listed.len(), 1, "temp files must not be listed");
+            assert!(sizes.contains(&listed[0].size_bytes), "half-written size {}", listed[0].size_bytes);
+
+            let loaded = storage.load("big").await.unwrap();
+            assert!(loaded == old || loaded == new, "partial content observed");
+            tokio::task::yield_now().await;
         }
+        writer.await.unwrap();
+    }
 
-        drawings.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        Ok(drawings)
+    #[tokio::test]
+    async fn test_update_keeps_created_at_and_tracks_modified_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+
+        let first = storage.save("first", &drawing(&["rectangle"]), None, None).await.unwrap();
+        assert_eq!(first.created_at, first.modified_at, "new uploads are created and modified at once");
+        storage.save("second", &drawing(&["text"]), None, None).await.unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let updated = storage.save("first", &drawing(&["ellipse"]), None, None).await.unwrap();
+        assert_eq!(updated.created_at, first.created_at);
+        assert!(updated.modified_at > first.modified_at);
+
+        // The gallery order stays by creation, so the update doesn't jump to the top
+        let listed = storage.list().await.unwrap();
+        assert_eq!(listed[0].id, "second");
+        assert_eq!(listed[1].id, "first");
+        assert_eq!(listed[1].modified_at, updated.modified_at);
     }
 
-    async fn exists(&self, id: &str) -> Result<bool, AppError> {
-        Ok(self.drawing_path(id).exists())
+    #[tokio::test]
+    async fn test_identical_timestamps_list_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        let sqlite = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        let stamp: DateTime<Utc> = "2024-05-01T12:00:00Z".parse().unwrap();
+
+        for id in ["charlie", "alpha", "bravo"] {
+            storage.save(id, &drawing(&["rectangle"]), None, None).await.unwrap();
+            let mut sidecar = storage.read_sidecar(id).await.unwrap();
+            sidecar.created_at = stamp;
+            storage.write_sidecar(id, &sidecar).await.unwrap();
+
+            sqlite.save(id, &drawing(&["rectangle"]), None, None).await.unwrap();
+        }
+        sqlite
+            .with_conn(move |conn| Ok(conn.execute("UPDATE drawings SET created_at = ?1", [stamp])?))
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            let ids: Vec<String> = storage.list().await.unwrap().into_iter().map(|d| d.id).collect();
+            assert_eq!(ids, ["alpha", "bravo", "charlie"]);
+            let ids: Vec<String> = sqlite.list().await.unwrap().into_iter().map(|d| d.id).collect();
+            assert_eq!(ids, ["alpha", "bravo", "charlie"]);
+        }
     }
 
-    async fn get_persistent_collab_status(&self, id: &str) -> Result<bool, AppError> {
-        // First check if the drawing exists
-        let path = self.drawing_path(id);
-        if !path.exists() {
-            return Err(AppError::NotFound);
+    #[tokio::test]
+    async fn test_sqlite_save_load_and_list_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+
+        let meta = storage
+            .save("older", &drawing(&["rectangle"]), Some("Notes/older.excalidraw"), None)
+            .await
+            .unwrap();
+        assert_eq!(meta.source_path.as_deref(), Some("Notes/older.excalidraw"));
+        storage.save("newer", &drawing(&["text", "arrow"]), None, None).await.unwrap();
+
+        let loaded = storage.load("older").await.unwrap();
+        assert_eq!(loaded["elements"][0]["type"], "rectangle");
+        assert_eq!(loaded["_source_path"], "Notes/older.excalidraw");
+
+        let ids: Vec<String> = storage.list().await.unwrap().into_iter().map(|d| d.id).collect();
+        assert_eq!(ids, ["newer", "older"]);
+        let found = storage.find_by_source_path("Notes/older.excalidraw").await.unwrap().unwrap();
+        assert_eq!(found.id, "older");
+        assert_eq!(storage.stats().await.unwrap().element_types.get("arrow"), Some(&1));
+
+        // The write probe cleans up its scratch row
+        storage.probe_write().await.unwrap();
+        let sql = "SELECT COUNT(*) FROM write_probe";
+        let probes: i64 = storage.with_conn(move |conn| Ok(conn.query_row(sql, [], |r| r.get(0))?)).await.unwrap();
+        assert_eq!(probes, 0);
+
+        // Updating keeps created_at, so the order is unchanged
+        storage.save("older", &drawing(&["ellipse"]), Some("Notes/older.excalidraw"), None).await.unwrap();
+        let listed = storage.list().await.unwrap();
+        assert_eq!(listed[1].id, "older");
+        assert_eq!(listed[1].created_at, meta.created_at);
+        assert!(listed[1].modified_at >= meta.modified_at);
+
+        // Everything survives reopening the database
+        drop(storage);
+        let reopened = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        assert_eq!(reopened.load("older").await.unwrap()["elements"][0]["type"], "ellipse");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_missing_drawings_match_filesystem_semantics() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+
+        assert!(!storage.exists("nope").await.unwrap());
+        assert!(matches!(storage.load("nope").await, Err(AppError::NotFound)));
+        assert!(matches!(storage.delete("nope").await, Err(AppError::NotFound)));
+        assert!(matches!(storage.get_meta("nope").await, Err(AppError::NotFound)));
+
+        storage.save("doomed", &drawing(&["rectangle"]), Some("a.excalidraw"), None).await.unwrap();
+        assert!(storage.exists("doomed").await.unwrap());
+        storage.delete("doomed").await.unwrap();
+        assert!(!storage.exists("doomed").await.unwrap());
+        assert!(matches!(storage.load("doomed").await, Err(AppError::NotFound)));
+        assert!(matches!(storage.delete("doomed").await, Err(AppError::NotFound)));
+
+        let tombstones = storage.deleted_since(None).await.unwrap();
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!(tombstones[0].source_path.as_deref(), Some("a.excalidraw"));
+        assert!(!storage.is_burned("doomed").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_list_page_skips_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        for id in ["one", "two", "three"] {
+            storage.save(id, &drawing(&["rectangle"]), None, None).await.unwrap();
         }
+        let mut expired = drawing(&["rectangle"]);
+        expired["_expires_at"] = json!((Utc::now() - chrono::Duration::hours(1)).to_rfc3339());
+        storage.save("expired", &expired, None, None).await.unwrap();
+
+        let page = Page { offset: 0, limit: 2, sort: ListSort::default() };
+        let (drawings, total) = storage.list_page(&DrawingFilter::default(), page).await.unwrap();
+        assert_eq!((drawings.len(), total), (2, 4));
+
+        let live = DrawingFilter { exclude_expired: true, ..Default::default() };
+        let (drawings, total) = storage.list_page(&live, Page { offset: 2, limit: 2, sort: ListSort::default() }).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(drawings.len(), 1);
+        assert!(drawings.iter().all(|d| d.id != "expired"));
+    }
 
-        // Read from sidecar for fast access
-        if let Some(sidecar) = self.read_sidecar(id).await {
-            return Ok(sidecar.persistent_collab);
+    async fn check_delete_many(storage: Storage) {
+        for id in ["a", "b"] {
+            storage.save(id, &drawing(&["rectangle"]), Some(&format!("{id}.excalidraw")), None).await.unwrap();
         }
+        let ids = ["a", "missing", "../b", "b"].map(String::from);
+        let results = storage.delete_many(&ids).await;
+        assert!(matches!(
+            results.as_slice(),
+            [Ok(()), Err(AppError::NotFound), Err(AppError::BadRequest(_)), Ok(())]
+        ));
+        assert!(storage.list().await.unwrap().is_empty());
+        let tombstones = storage.deleted_since(None).await.unwrap();
+        assert_eq!(tombstones.len(), 2);
+        assert!(tombstones.iter().all(|t| t.source_path == Some(format!("{}.excalidraw", t.id)) && !t.burned));
+    }
 
-        // Fallback: read from the drawing JSON itself
-        let bytes = fs::read(&path).await?;
-        let data: serde_json::Value = serde_json::from_slice(&bytes)?;
-        Ok(data.get("_persistent_collab")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false))
+    #[tokio::test]
+    async fn test_delete_many() {
+        let dir = tempfile::tempdir().unwrap();
+        check_delete_many(FileSystemStorage::new(dir.path()).await.unwrap().into()).await;
+        let sqlite = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap().with_content_dedup(true);
+        check_delete_many(sqlite.into()).await;
     }
 
-    async fn save_persistent(
-        &self,
-        id: &str,
-        data: &serde_json::Value,
-        version: u64,
-    ) -> Result<(), AppError> {
-        let path = self.drawing_path(id);
-        if !path.exists() {
-            return Err(AppError::NotFound);
-        }
+    async fn check_trash_and_restore(storage: Storage, quota: ByteQuota) {
+        storage.save("doc", &drawing(&["rectangle"]), Some("a.excalidraw"), None).await.unwrap();
+        storage.save("twin", &drawing(&["rectangle"]), None, None).await.unwrap();
+        let before = storage.get_meta("doc").await.unwrap();
+        let used = quota.usage().used_bytes;
+
+        storage.trash("doc").await.unwrap();
+        assert!(!storage.exists("doc").await.unwrap());
+        assert!(matches!(storage.load("doc").await, Err(AppError::NotFound)));
+        assert_eq!(storage.list().await.unwrap().len(), 1);
+        assert_eq!(storage.deleted_since(None).await.unwrap()[0].id, "doc");
+        // Trashed bytes count until purged
+        assert_eq!(quota.usage().used_bytes, used);
+        let trashed = storage.list_trash().await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!((trashed[0].source_path.as_deref(), trashed[0].size_bytes), (Some("a.excalidraw"), before.size_bytes));
+        // Content shared with a drawing deleted meanwhile survives
+        storage.delete("twin").await.unwrap();
+
+        let restored = storage.restore("doc").await.unwrap();
+        assert_eq!((restored.created_at, restored.source_path), (before.created_at, before.source_path));
+        assert_eq!(storage.load("doc").await.unwrap()["elements"][0]["type"], "rectangle");
+        assert!(storage.list_trash().await.unwrap().is_empty());
+        assert_eq!(storage.deleted_since(None).await.unwrap().len(), 1);
+        assert_eq!(quota.usage().used_bytes, before.size_bytes);
+        assert!(matches!(storage.restore("doc").await, Err(AppError::NotFound)));
+
+        // A new drawing under a trashed id blocks its restore
+        storage.trash("doc").await.unwrap();
+        storage.save("doc", &drawing(&["ellipse"]), None, None).await.unwrap();
+        assert!(matches!(storage.restore("doc").await, Err(AppError::Conflict(_))));
+        assert_eq!(storage.load("doc").await.unwrap()["elements"][0]["type"], "ellipse");
+
+        // Trashing the id again keeps both entries; restore brings back the latest
+        storage.trash("doc").await.unwrap();
+        assert_eq!(storage.list_trash().await.unwrap().len(), 2);
+        storage.restore("doc").await.unwrap();
+        assert_eq!(storage.load("doc").await.unwrap()["elements"][0]["type"], "ellipse");
+        let ellipse = storage.get_meta("doc").await.unwrap().size_bytes;
+        assert_eq!(quota.usage().used_bytes, before.size_bytes + ellipse);
+
+        assert!(storage.purge_trash(Utc::now() - chrono::Duration::hours(1)).await.unwrap().is_empty());
+        assert_eq!(storage.purge_trash(Utc::now()).await.unwrap(), ["doc"]);
+        assert!(storage.list_trash().await.unwrap().is_empty());
+        assert_eq!(quota.usage().used_bytes, ellipse);
+        assert_eq!(storage.load("doc").await.unwrap()["elements"][0]["type"], "ellipse");
+    }
 
-        // Load existing drawing to preserve internal fields
-        let existing_bytes = fs::read(&path).await?;
-        let existing: serde_json::Value = serde_json::from_slice(&existing_bytes)?;
+    #[tokio::test]
+    async fn test_trash_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let quota = ByteQuota::new(None);
+        let fs = FileSystemStorage::new(dir.path()).await.unwrap().with_byte_quota(quota.clone());
+        check_trash_and_restore(fs.into(), quota).await;
+        let quota = ByteQuota::new(None);
+        let sqlite = SqliteStorage::new(dir.path().join("drawings.sqlite"))
+            .await
+            .unwrap()
+            .with_content_dedup(true)
+            .with_byte_quota(quota.clone());
+        check_trash_and_restore(sqlite.into(), quota).await;
+    }
 
-        let mut data_with_meta = data.clone();
-        if let Some(obj) = data_with_meta.as_object_mut() {
-            // Preserve _source_path from existing
-            if let Some(sp) = existing.get("_source_path") {
-                obj.insert("_source_path".to_string(), sp.clone());
-            }
-            // Preserve _password_hash from existing
-            if let Some(ph) = existing.get("_password_hash") {
-                obj.insert("_password_hash".to_string(), ph.clone());
+    #[tokio::test]
+    async fn test_trash_record_without_drawing_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        storage.save("doc", &drawing(&["rectangle"]), None, None).await.unwrap();
+        storage.trash("doc").await.unwrap();
+        // As if the trashed file went missing after its record was written
+        let trashed = storage.list_trash().await.unwrap().remove(0);
+        std::fs::remove_file(storage.trash_paths(&trashed).0).unwrap();
+
+        assert!(matches!(storage.restore("doc").await, Err(AppError::NotFound)));
+        assert!(storage.list_trash().await.unwrap().is_empty());
+    }
+
+    /// Set `id`'s modification time, which saves always set to now.
+    async fn set_modified_at(storage: &Storage, id: &str, at: DateTime<Utc>) {
+        match storage {
+            Storage::Fs(fs) => {
+                let mut sidecar = fs.inner().read_sidecar(id).await.unwrap();
+                sidecar.modified_at = Some(at);
+                fs.inner().write_sidecar(id, &sidecar).await.unwrap();
             }
-            // Preserve _persistent_collab_password_hash from existing
-            if let Some(pcph) = existing.get("_persistent_collab_password_hash") {
-                obj.insert("_persistent_collab_password_hash".to_string(), pcph.clone());
+            Storage::Sqlite(sqlite) => {
+                let (id, at) = (id.to_string(), serde_json::to_value(at).unwrap());
+                let sql = "UPDATE drawings SET meta = json_set(meta, '$.modified_at', ?2) WHERE id = ?1";
+                sqlite.inner().with_conn(move |conn| Ok(conn.execute(sql, params![id, at.as_str()])?)).await.unwrap();
             }
-            // Ensure persistent collab flag stays true
-            obj.insert("_persistent_collab".to_string(), serde_json::Value::Bool(true));
-            // Set the version
-            obj.insert("_persistent_collab_version".to_string(), serde_json::json!(version));
         }
+    }
 
-        let json_bytes = serde_json::to_vec(&data_with_meta)?;
+    async fn check_list_sort(storage: Storage) {
+        // Apart by less than a millisecond, and printed with differing precision
+        let base: DateTime<Utc> = "2024-05-01T12:00:00Z".parse().unwrap();
+        let times = [base, base + chrono::Duration::milliseconds(123), base + chrono::Duration::microseconds(123_456)];
+        for (id, at) in ["a", "b", "c"].into_iter().zip(times) {
+            let mut doc = drawing(&["rectangle"]);
+            doc["_created_at"] = json!(at);
+            storage.save(id, &doc, None, None).await.unwrap();
+        }
+        for (id, at) in ["b", "c", "a"].into_iter().zip(times) {
+            set_modified_at(&storage, id, at).await;
+        }
+        let ids = |field, order| {
+            let storage = storage.clone();
+            async move {
+                let page = Page { offset: 0, limit: 2, sort: ListSort { field, order } };
+                let (drawings, _) = storage.list_page(&DrawingFilter::default(), page).await.unwrap();
+                drawings.into_iter().map(|d| d.id).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(ids(SortField::Created, SortOrder::Desc).await, ["c", "b"]);
+        assert_eq!(ids(SortField::Created, SortOrder::Asc).await, ["a", "b"]);
+        assert_eq!(ids(SortField::Modified, SortOrder::Desc).await, ["a", "c"]);
+        assert_eq!(ids(SortField::Modified, SortOrder::Asc).await, ["b", "c"]);
+    }
 
-        // Atomic write: write to tmp file first, then rename
-        let tmp_path = self.base_path.join(format!(
-            "{}.json.tmp",
-            id.chars()
-                .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
-                .collect::<String>()
-        ));
-        fs::write(&tmp_path, &json_bytes).await?;
-        fs::rename(&tmp_path, &path).await?;
+    #[tokio::test]
+    async fn test_list_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        check_list_sort(FileSystemStorage::new(dir.path()).await.unwrap().into()).await;
+        check_list_sort(SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap().into()).await;
+    }
 
-        tracing::debug!(id = %id, version = version, "Persistent collab save completed");
+    async fn check_byte_quota(storage: Storage, quota: ByteQuota) {
+        let size = storage.save("a", &drawing(&["rectangle"]), None, None).await.unwrap().size_bytes;
+        assert_eq!(quota.usage().used_bytes, size);
+        // Room for twice that: a second drawing fits, a third doesn't
+        storage.save("b", &drawing(&["rectangle"]), None, None).await.unwrap();
+        let third = storage.save("c", &drawing(&["rectangle"]), None, None).await;
+        assert!(matches!(third, Err(AppError::QuotaExceeded(_))));
+        assert!(!storage.exists("c").await.unwrap());
+        // Updates only count their growth, so re-saving still fits
+        storage.save("a", &drawing(&["rectangle"]), None, None).await.unwrap();
+        assert_eq!(quota.usage().used_bytes, 2 * size);
+
+        storage.delete("b").await.unwrap();
+        assert_eq!(quota.usage().used_bytes, size);
+        storage.save("c", &drawing(&["rectangle"]), None, None).await.unwrap();
+        storage.delete_many(&["a".to_string(), "c".to_string()]).await;
+        assert_eq!(quota.usage().used_bytes, 0);
+    }
 
-        // Update sidecar metadata
-        let existing_sidecar = self.read_sidecar(id).await;
-        let created_at = existing_sidecar
-            .as_ref()
-            .map(|m| m.created_at)
-            .unwrap_or_else(Utc::now);
-        let source_path = existing_sidecar
-            .as_ref()
-            .and_then(|m| m.source_path.clone());
-        let password_protected = existing_sidecar
-            .as_ref()
-            .map(|m| m.password_protected)
-            .unwrap_or(false);
-
-        let sidecar = SidecarMeta {
-            created_at,
-            source_path,
-            password_protected,
-            persistent_collab: true,
-        };
-        self.write_sidecar(id, &sidecar).await?;
+    #[tokio::test]
+    async fn test_byte_quota_enforced_on_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let size = serde_json::to_vec(&drawing(&["rectangle"])).unwrap().len() as u64;
+        // Room for two drawings, whatever few bytes storing them adds
+        let max = 2 * size + size / 2;
+        let quota = ByteQuota::new(Some(max));
+        let fs = FileSystemStorage::new(dir.path().join("fs")).await.unwrap().with_byte_quota(quota.clone());
+        check_byte_quota(fs.into(), quota).await;
+        let quota = ByteQuota::new(Some(max));
+        let sqlite = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap().with_byte_quota(quota.clone());
+        check_byte_quota(sqlite.into(), quota).await;
+    }
 
-        Ok(())
+    async fn check_history_bytes(storage: Storage, quota: ByteQuota) {
+        let mut saves = Vec::new();
+        for kind in ["rectangle", "ellipse", "text"] {
+            saves.push(storage.save("a", &drawing(&[kind]), None, None).await.unwrap().size_bytes);
+        }
+        // The current content and the one version kept; the first was pruned
+        let kept: u64 = storage.history("a").await.unwrap().iter().map(|v| v.size_bytes).sum();
+        assert_eq!(kept, saves[1]);
+        assert_eq!(quota.usage().used_bytes, saves[2] + saves[1]);
+        let counted = ByteQuota::new(None);
+        counted.recount(&storage).await.unwrap();
+        assert_eq!(counted.usage(), quota.usage());
+
+        storage.delete("a").await.unwrap();
+        assert_eq!(quota.usage().used_bytes, 0);
     }
 
-    async fn list_persistent_collab_drawings(&self) -> Result<Vec<String>, AppError> {
-        let mut entries = fs::read_dir(&self.base_path).await?;
-        let mut ids = Vec::new();
+    #[tokio::test]
+    async fn test_history_counts_against_byte_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let quota = ByteQuota::new(None);
+        let fs = FileSystemStorage::new(dir.path().join("fs")).await.unwrap().with_history_depth(1).with_byte_quota(quota.clone());
+        check_history_bytes(fs.into(), quota).await;
+        let quota = ByteQuota::new(None);
+        let sqlite = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        check_history_bytes(sqlite.with_history_depth(1).with_byte_quota(quota.clone()).into(), quota).await;
+    }
 
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    #[tokio::test]
+    async fn test_invalid_ids_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage: Storage = FileSystemStorage::new(dir.path()).await.unwrap().into();
+        storage.save("etcetc", &drawing(&["rectangle"]), None, None).await.unwrap();
+        for id in ["../../etc", "etc/etc", "", "etc.json"] {
+            assert!(matches!(storage.load(id).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.exists(id).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.delete(id).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.save(id, &drawing(&[]), None, None).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.get_meta(id).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.history(id).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.load_version(id, 1).await, Err(AppError::BadRequest(_))), "{id:?}");
+            assert!(matches!(storage.record_view(id).await, Err(AppError::BadRequest(_))), "{id:?}");
+        }
+        assert!(storage.exists("etcetc").await.unwrap());
+        assert!(matches!(storage.load("missing").await, Err(AppError::NotFound)));
+    }
 
-            // Only process .meta.json sidecar files
-            if !filename.ends_with(".meta.json") {
-                continue;
-            }
+    #[tokio::test]
+    async fn test_sqlite_content_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap().with_content_dedup(true);
+        async fn blobs(storage: &SqliteStorage) -> Result<(i64, i64), AppError> {
+            let sql = "SELECT COUNT(*), COALESCE(SUM(refs), 0) FROM blobs";
+            storage.with_conn(move |conn| Ok(conn.query_row(sql, [], |r| Ok((r.get(0)?, r.get(1)?)))?)).await
+        }
+        let same = drawing(&["rectangle", "ellipse"]);
+        let a = storage.save("a", &same, Some("a.excalidraw"), None).await.unwrap();
+        let b = storage.save("b", &same, Some("b.excalidraw"), Some("hash")).await.unwrap();
+        assert_eq!(a.content_hash, b.content_hash);
+        assert_eq!(blobs(&storage).await.unwrap(), (1, 2));
+
+        // Each keeps its own internal fields around the shared content
+        let loaded = storage.load("b").await.unwrap();
+        assert_eq!(loaded["_source_path"], "b.excalidraw");
+        assert_eq!(loaded["_password_hash"], "hash");
+        assert_eq!(loaded["elements"], same["elements"]);
+        assert_eq!(storage.load("a").await.unwrap()["_source_path"], "a.excalidraw");
+
+        // Re-saving unchanged content keeps the reference count
+        storage.save("a", &same, Some("a.excalidraw"), None).await.unwrap();
+        assert_eq!(blobs(&storage).await.unwrap(), (1, 2));
+        storage.save("a", &drawing(&["text"]), Some("a.excalidraw"), None).await.unwrap();
+        assert_eq!(blobs(&storage).await.unwrap(), (2, 2));
+
+        storage.delete("a").await.unwrap();
+        assert_eq!(blobs(&storage).await.unwrap(), (1, 1));
+        assert_eq!(storage.load("b").await.unwrap()["elements"], same["elements"]);
+        assert!(storage.verify().await.unwrap().unreadable.is_empty());
+
+        // A client-sent `_blob` is dropped on save, and one next to inline
+        // content (rows saved before that) is neither resolved nor released
+        let hash: String = storage
+            .with_conn(|conn| Ok(conn.query_row("SELECT hash FROM blobs", [], |r| r.get(0))?))
+            .await
+            .unwrap();
+        let mut forged = drawing(&["text"]);
+        forged["_blob"] = json!(hash);
+        storage.save("forged", &forged, None, None).await.unwrap();
+        assert!(storage.load("forged").await.unwrap().get("_blob").is_none());
+        assert_eq!(blobs(&storage).await.unwrap(), (2, 2));
+        storage.delete("forged").await.unwrap();
+        let row = serde_json::to_string(&forged).unwrap();
+        storage
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO drawings (id, data, created_at, size_bytes, meta) VALUES ('forged', ?1, ?2, 0, '{}')",
+                    params![row, Utc::now()],
+                )?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(storage.load("forged").await.unwrap()["elements"], forged["elements"]);
+        storage.delete("forged").await.unwrap();
+        assert_eq!(blobs(&storage).await.unwrap(), (1, 1));
+
+        storage.delete("b").await.unwrap();
+        assert_eq!(blobs(&storage).await.unwrap(), (0, 0));
+    }
 
-            // Extract the drawing ID from the sidecar filename
-            let id = filename.strip_suffix(".meta.json").unwrap_or("").to_string();
-            if id.is_empty() {
-                continue;
+    #[tokio::test]
+    async fn test_delete_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        let mut expired = drawing(&["rectangle"]);
+        expired["_expires_at"] = json!((Utc::now() - chrono::Duration::hours(1)).to_rfc3339());
+        let mut live = drawing(&["rectangle"]);
+        live["_expires_at"] = json!((Utc::now() + chrono::Duration::hours(1)).to_rfc3339());
+        storage.save("expired", &expired, None, None).await.unwrap();
+        storage.save("live", &live, None, None).await.unwrap();
+        storage.save("forever", &drawing(&["rectangle"]), None, None).await.unwrap();
+
+        let locks = KeyedLocks::new();
+        let deleted = delete_expired(&storage, &locks).await.unwrap();
+        assert_eq!(deleted.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), ["expired"]);
+        let mut left: Vec<_> = storage.list().await.unwrap().into_iter().map(|d| d.id).collect();
+        left.sort();
+        assert_eq!(left, ["forever", "live"]);
+        assert!(delete_expired(&storage, &locks).await.unwrap().is_empty());
+
+        // Extended while the sweep waits for its lock: kept, and not reported
+        storage.save("late", &expired, None, None).await.unwrap();
+        let guard = locks.lock("update:late").await;
+        let extend = async {
+            while !locks.is_contended("update:late").await {
+                tokio::task::yield_now().await;
             }
+            storage.save("late", &live, None, None).await.unwrap();
+            drop(guard);
+        };
+        let (swept, ()) = tokio::join!(delete_expired(&storage, &locks), extend);
+        assert!(swept.unwrap().is_empty());
+        assert!(storage.exists("late").await.unwrap());
+    }
 
-            // Read the sidecar and check persistent_collab flag
-            if let Some(sidecar) = self.read_sidecar(&id).await {
-                if sidecar.persistent_collab {
-                    ids.push(id);
-                }
-            }
-        }
+    fn temp_files(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|e| e.unwrap().file_name().into_string().ok())
+            .filter(|name| name.ends_with(".tmp"))
+            .collect()
+    }
 
-        Ok(ids)
+    #[tokio::test]
+    async fn test_failed_atomic_write_leaves_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.json");
+        write_atomic(&target, b"first").await.unwrap();
+        write_atomic(&target, b"second").await.unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"second");
+        assert!(temp_files(dir.path()).is_empty());
+
+        // Renaming over a non-empty directory fails after the temp file was written
+        let blocked = dir.path().join("blocked.json");
+        std::fs::create_dir(&blocked).unwrap();
+        std::fs::write(blocked.join("inner"), b"x").unwrap();
+        assert!(write_atomic(&blocked, b"data").await.is_err());
+        assert!(temp_files(dir.path()).is_empty());
     }
 
-    async fn find_by_source_path(&self, source_path: &str) -> Result<Option<DrawingMeta>, AppError> {
-        let mut entries = fs::read_dir(&self.base_path).await?;
+    #[tokio::test]
+    async fn test_interrupted_update_keeps_previous_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        storage.save("doc", &drawing(&["rectangle"]), None, None).await.unwrap();
+
+        // A server killed mid-update leaves only a partial temp file behind
+        std::fs::write(dir.path().join(".doc.json.0123abcd.tmp"), b"{\"type\":\"excali").unwrap();
+        assert_eq!(storage.load("doc").await.unwrap()["elements"][0]["type"], "rectangle");
+        assert_eq!(storage.list().await.unwrap().len(), 1);
+
+        // and the next start cleans it up
+        let reopened = FileSystemStorage::new(dir.path()).await.unwrap();
+        assert!(temp_files(dir.path()).is_empty());
+        assert_eq!(reopened.load("doc").await.unwrap()["elements"][0]["type"], "rectangle");
+    }
 
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    #[tokio::test]
+    async fn test_read_only_open_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        storage.save("doc", &drawing(&["rectangle"]), None, None).await.unwrap();
+        // A sidecar from before summaries were tracked, and a write in flight
+        let sidecar_path = dir.path().join("doc.meta.json");
+        let mut sidecar: serde_json::Value = serde_json::from_slice(&std::fs::read(&sidecar_path).unwrap()).unwrap();
+        sidecar.as_object_mut().unwrap().remove("content");
+        std::fs::write(&sidecar_path, serde_json::to_vec(&sidecar).unwrap()).unwrap();
+        std::fs::write(dir.path().join(".doc.json.0123abcd.tmp"), b"{").unwrap();
+        std::fs::write(dir.path().join("bare.json"), serde_json::to_vec(&drawing(&["text"])).unwrap()).unwrap();
+
+        let follower = FileSystemStorage::open_read_only(dir.path()).await.unwrap();
+        assert_eq!(follower.summary("doc").await.unwrap().content.element_count, 1);
+        assert_eq!(temp_files(dir.path()), [".doc.json.0123abcd.tmp"]);
+        assert!(!dir.path().join("bare.meta.json").exists());
+        let after: serde_json::Value = serde_json::from_slice(&std::fs::read(&sidecar_path).unwrap()).unwrap();
+        assert!(after.get("content").is_none());
+
+        assert!(FileSystemStorage::open_read_only(dir.path().join("missing")).await.is_err());
+    }
 
-            // Only process .meta.json sidecar files
-            if !filename.ends_with(".meta.json") {
-                continue;
-            }
+    #[tokio::test]
+    async fn test_verify_reports_filesystem_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = CachedStorage::new(FileSystemStorage::new(dir.path()).await.unwrap()).with_cache(1024 * 1024, 0);
+        for id in ["intact", "unindexed", "tampered", "removed"] {
+            storage.save(id, &drawing(&["rectangle"]), None, None).await.unwrap();
+        }
+        assert!(storage.verify().await.unwrap().is_consistent());
+
+        storage.list().await.unwrap();
+        std::fs::remove_file(dir.path().join("unindexed.meta.json")).unwrap();
+        std::fs::write(dir.path().join("tampered.json"), serde_json::to_vec(&drawing(&["ellipse"])).unwrap()).unwrap();
+        std::fs::remove_file(dir.path().join("removed.json")).unwrap();
+
+        let report = storage.verify().await.unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.missing_from_index, ["unindexed"]);
+        assert_eq!(report.orphaned_index_entries, ["removed"]);
+        assert_eq!(report.hash_mismatches.len(), 1);
+        assert_eq!(report.hash_mismatches[0].id, "tampered");
+        assert_eq!(report.hash_mismatches[0].actual, document_hash(&drawing(&["ellipse"])));
+        // The cached listing still has the removed drawing and the old metadata
+        assert_eq!(report.stale_index_entries, ["removed", "unindexed"]);
+
+        // Nothing was repaired
+        assert!(!dir.path().join("unindexed.meta.json").exists());
+        assert!(dir.path().join("removed.meta.json").exists());
+    }
 
-            let id = filename.strip_suffix(".meta.json").unwrap_or("").to_string();
-            if id.is_empty() {
-                continue;
-            }
+    #[tokio::test]
+    async fn test_verify_reports_sqlite_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        for id in ["intact", "tampered", "moved"] {
+            storage.save(id, &drawing(&["rectangle"]), Some("a.excalidraw"), None).await.unwrap();
+        }
+        assert!(storage.verify().await.unwrap().is_consistent());
+
+        storage
+            .with_conn(|conn| {
+                conn.execute("UPDATE drawings SET data = '{\"type\":\"excalidraw\",\"elements\":[]}' WHERE id = 'tampered'", [])?;
+                conn.execute("UPDATE drawings SET source_path = 'b.excalidraw' WHERE id = 'moved'", [])?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let report = storage.verify().await.unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.hash_mismatches.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ["tampered"]);
+        assert_eq!(report.stale_index_entries, ["moved"]);
+        assert!(report.missing_from_index.is_empty() && report.unreadable.is_empty());
+    }
 
-            if let Some(sidecar) = self.read_sidecar(&id).await {
-                if sidecar.source_path.as_deref() == Some(source_path) {
-                    // Found a match — verify the drawing file still exists
-                    let drawing_path = self.drawing_path(&id);
-                    if !drawing_path.exists() {
-                        continue;
-                    }
+    #[tokio::test]
+    async fn test_concurrent_view_counts_add_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let fs: Storage = FileSystemStorage::new(dir.path().join("fs")).await.unwrap().into();
+        let sqlite: Storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap().into();
+        for storage in [fs, sqlite] {
+            storage.save("counted", &drawing(&["rectangle"]), None, None).await.unwrap();
+            let counts = (0..20).map(|_| storage.add_views("counted", 1));
+            let updated = drawing(&["ellipse"]);
+            let saves = (0..5).map(|_| storage.save("counted", &updated, None, None));
+            let (counted, saved) = tokio::join!(futures::future::join_all(counts), futures::future::join_all(saves));
+            assert!(counted.iter().all(Result::is_ok) && saved.iter().all(Result::is_ok));
+            assert_eq!(storage.get_meta("counted").await.unwrap().views, 20);
+        }
+    }
 
-                    let file_metadata = fs::metadata(&drawing_path).await?;
+    #[tokio::test]
+    async fn test_verify_counts_missing_hash_as_unknown() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        storage.save("old", &drawing(&["rectangle"]), None, None).await.unwrap();
+        // A sidecar from before content hashes were indexed
+        let meta_path = dir.path().join("old.meta.json");
+        let mut sidecar: serde_json::Value = serde_json::from_slice(&std::fs::read(&meta_path).unwrap()).unwrap();
+        sidecar.as_object_mut().unwrap().remove("content_hash");
+        std::fs::write(&meta_path, serde_json::to_vec(&sidecar).unwrap()).unwrap();
+
+        let report = storage.verify().await.unwrap();
+        assert!(report.hash_mismatches.is_empty());
+        assert_eq!(report.unhashed, ["old"]);
+        assert!(report.is_consistent());
+    }
 
-                    return Ok(Some(DrawingMeta {
-                        id,
-                        created_at: sidecar.created_at,
-                        size_bytes: file_metadata.len(),
-                        source_path: sidecar.source_path,
-                        password_protected: sidecar.password_protected,
-                        persistent_collab: sidecar.persistent_collab,
-                    }));
-                }
-            }
+    #[tokio::test]
+    async fn test_sqlite_list_page_searches_id_and_source_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        for (id, path) in [("AlphaOne", "Work/a.excalidraw"), ("alphaTwo", "Home/b.excalidraw"), ("beta", "Work/c.excalidraw")] {
+            storage.save(id, &drawing(&["rectangle"]), Some(path), None).await.unwrap();
         }
+        let page = Page { offset: 0, limit: 10, sort: ListSort::default() };
+
+        let filter = DrawingFilter { q: Some("ALPHA".to_string()), ..Default::default() };
+        let (drawings, total) = storage.list_page(&filter, page).await.unwrap();
+        assert_eq!(total, 2);
+        assert!(drawings.iter().all(|d| d.id.to_lowercase().starts_with("alpha")));
+
+        let filter = DrawingFilter { source_path: Some("Work/".to_string()), ..Default::default() };
+        let (_, total) = storage.list_page(&filter, page).await.unwrap();
+        assert_eq!(total, 2);
+        // Prefixes are case-sensitive, like on the filesystem backend
+        let filter = DrawingFilter { source_path: Some("work/".to_string()), ..Default::default() };
+        assert_eq!(storage.list_page(&filter, page).await.unwrap().1, 0);
+
+        let filter = DrawingFilter { q: Some("alpha".to_string()), source_path: Some("Work/".to_string()), ..Default::default() };
+        let (drawings, _) = storage.list_page(&filter, page).await.unwrap();
+        assert_eq!(drawings.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), ["AlphaOne"]);
+    }
 
-        Ok(None)
+    /// Three updates with a depth of 2: the oldest version is pruned, the
+    /// others load back, and saves that don't change the content add nothing.
+    async fn check_history(storage: &impl DrawingStorage) {
+        for types in [&["rectangle"][..], &["ellipse"], &["text"], &["arrow"]] {
+            storage.save("doc", &drawing(types), Some("a.excalidraw"), None).await.unwrap();
+        }
+        let mut data = storage.load("doc").await.unwrap();
+        data["_expires_at"] = json!("2999-01-01T00:00:00Z");
+        storage.save("doc", &data, Some("a.excalidraw"), None).await.unwrap();
+
+        let versions = storage.history("doc").await.unwrap();
+        assert_eq!(versions.iter().map(|v| v.version).collect::<Vec<_>>(), [3, 2]);
+        assert!(versions.iter().all(|v| v.size_bytes > 0));
+        let third = storage.load_version("doc", 3).await.unwrap();
+        assert_eq!(third["elements"][0]["type"], "text");
+        assert!(matches!(storage.load_version("doc", 1).await, Err(AppError::NotFound)));
+
+        storage.delete("doc").await.unwrap();
+        assert!(storage.history("doc").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_replaced_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        check_history(&FileSystemStorage::new(dir.path()).await.unwrap().with_history_depth(2)).await;
+        let sqlite = SqliteStorage::new(dir.path().join("drawings.sqlite")).await.unwrap();
+        check_history(&sqlite.with_history_depth(2)).await;
+    }
+
+    #[tokio::test]
+    async fn test_no_history_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileSystemStorage::new(dir.path()).await.unwrap();
+        storage.save("doc", &drawing(&["rectangle"]), None, None).await.unwrap();
+        storage.save("doc", &drawing(&["ellipse"]), None, None).await.unwrap();
+        assert!(storage.history("doc").await.unwrap().is_empty());
+        assert!(!dir.path().join("history").exists());
     }
 }
@@ -0,0 +1,121 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::time::{Duration, Instant};
+
+/// Requests slower than this are logged as warnings. `None` disables the log.
+#[derive(Clone, Copy)]
+pub struct SlowRequestThreshold(pub Option<Duration>);
+
+impl SlowRequestThreshold {
+    pub fn from_millis(ms: Option<u64>) -> Self {
+        Self(ms.filter(|&ms| ms > 0).map(Duration::from_millis))
+    }
+}
+
+/// Drawing id addressed by a request path, if any.
+fn drawing_id(path: &str) -> Option<&str> {
+    let rest = path
+        .strip_prefix("/api/drawings/")
+        .or_else(|| path.strip_prefix("/api/view/"))
+        .or_else(|| path.strip_prefix("/d/"))?;
+    rest.split('/').next().filter(|id| !id.is_empty())
+}
+
+/// Middleware that times every request and warns about those exceeding the
+/// threshold, so pathological drawings show up without enabling full tracing.
+pub async fn request_timing_middleware(
+    State(threshold): State<SlowRequestThreshold>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(threshold) = threshold.0 else {
+        return next.run(request).await;
+    };
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started.elapsed();
+
+    if elapsed >= threshold {
+        tracing::warn!(
+            %method,
+            path = %path,
+            drawing_id = %drawing_id(&path).unwrap_or("-"),
+            status = response.status().as_u16(),
+            duration_ms = elapsed.as_millis() as u64,
+            "Slow request"
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    /// Log sink shared with a test subscriber.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn logs_for(path: &str) -> String {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route(
+                "/api/view/{id}",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(60)).await;
+                    "slow"
+                }),
+            )
+            .route("/api/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                SlowRequestThreshold::from_millis(Some(30)),
+                request_timing_middleware,
+            ));
+        app.oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let bytes = logs.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_is_logged() {
+        let logs = logs_for("/api/view/abc123").await;
+        assert!(logs.contains("Slow request"), "{logs}");
+        assert!(logs.contains("drawing_id=abc123"), "{logs}");
+    }
+
+    #[tokio::test]
+    async fn test_fast_request_is_not_logged() {
+        let logs = logs_for("/api/health").await;
+        assert!(!logs.contains("Slow request"), "{logs}");
+    }
+}
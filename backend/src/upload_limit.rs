@@ -0,0 +1,114 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+
+use crate::auth::KeyId;
+use crate::error::AppError;
+
+/// Caps the number of in-flight uploads per API key, so one misbehaving
+/// contributor can't saturate the write path for everyone else.
+#[derive(Clone)]
+pub struct UploadLimiter {
+    max_per_key: usize,
+    in_flight: Arc<Mutex<HashMap<KeyId, Arc<Semaphore>>>>,
+}
+
+impl UploadLimiter {
+    pub fn new(max_per_key: usize) -> Self {
+        Self {
+            max_per_key,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    async fn semaphore(&self, key: &KeyId) -> Arc<Semaphore> {
+        self.in_flight
+            .lock()
+            .await
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_key)))
+            .clone()
+    }
+}
+
+/// Middleware that rejects an upload with 429 when its key already has
+/// `max_per_key` uploads in flight. Must run after [`crate::auth::api_key_middleware`].
+pub async fn upload_limit_middleware(
+    State(limiter): State<UploadLimiter>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(key) = request.extensions().get::<KeyId>().cloned() else {
+        return Ok(next.run(request).await);
+    };
+
+    let semaphore = limiter.semaphore(&key).await;
+    let Ok(_permit) = semaphore.try_acquire_owned() else {
+        tracing::warn!(key_id = %key.0, limit = limiter.max_per_key, "Concurrent upload limit reached");
+        return Err(AppError::TooManyRequests(format!(
+            "at most {} concurrent uploads per API key",
+            limiter.max_per_key
+        )));
+    };
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::post, Router};
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    fn request(path: &str, key: &str) -> Request {
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(KeyId(key.to_string()));
+        request
+    }
+
+    #[tokio::test]
+    async fn test_limit_is_per_key() {
+        let release = Arc::new(Notify::new());
+        let started = Arc::new(Notify::new());
+        let (release_h, started_h) = (release.clone(), started.clone());
+        let app = Router::new()
+            .route(
+                "/slow",
+                post(move || async move {
+                    started_h.notify_one();
+                    release_h.notified().await;
+                    "done"
+                }),
+            )
+            .route("/fast", post(|| async { "done" }))
+            .layer(middleware::from_fn_with_state(
+                UploadLimiter::new(1),
+                upload_limit_middleware,
+            ));
+
+        let held = tokio::spawn(app.clone().oneshot(request("/slow", "alice")));
+        started.notified().await;
+
+        let over = app.clone().oneshot(request("/fast", "alice")).await.unwrap();
+        assert_eq!(over.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let other = app.clone().oneshot(request("/fast", "bob")).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+
+        release.notify_one();
+        assert_eq!(held.await.unwrap().unwrap().status(), StatusCode::OK);
+
+        // The permit is returned once the in-flight upload finishes
+        let after = app.oneshot(request("/fast", "alice")).await.unwrap();
+        assert_eq!(after.status(), StatusCode::OK);
+    }
+}
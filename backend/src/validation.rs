@@ -0,0 +1,361 @@
+use crate::error::AppError;
+
+/// Check that a document looks like an Excalidraw drawing before it is stored:
+/// the `type`, a numeric `version` and an object `appState` when present, and an
+/// `elements` array whose entries carry at least an `id`, `type`, `x` and `y`.
+/// Unknown fields are allowed, as Excalidraw keeps adding them.
+pub fn validate_excalidraw(data: &serde_json::Value) -> Result<(), AppError> {
+    let doc_type = data.get("type").and_then(|v| v.as_str()).unwrap_or("");
+    if doc_type != "excalidraw" {
+        return Err(AppError::BadRequest(
+            "Invalid document: missing or wrong 'type' field. Expected 'excalidraw'.".into(),
+        ));
+    }
+
+    if data.get("version").is_some_and(|v| !v.is_number()) {
+        return Err(AppError::BadRequest(
+            "Invalid document: 'version' must be a number.".into(),
+        ));
+    }
+
+    if data.get("appState").is_some_and(|v| !v.is_object()) {
+        return Err(AppError::BadRequest(
+            "Invalid document: 'appState' must be an object.".into(),
+        ));
+    }
+
+    let Some(elements) = data.get("elements").and_then(|v| v.as_array()) else {
+        return Err(AppError::BadRequest(
+            "Invalid document: missing 'elements' array.".into(),
+        ));
+    };
+    for (index, element) in elements.iter().enumerate() {
+        validate_element(element).map_err(|problem| {
+            AppError::BadRequest(format!("Invalid document: element {index} {problem}."))
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The first problem with an element, phrased to follow "element N".
+fn validate_element(element: &serde_json::Value) -> Result<(), String> {
+    let Some(element) = element.as_object() else {
+        return Err("is not an object".into());
+    };
+    for field in ["id", "type"] {
+        match element.get(field) {
+            Some(v) if v.as_str().is_some_and(|s| !s.is_empty()) => {}
+            Some(_) => return Err(format!("has an invalid '{field}' (expected a non-empty string)")),
+            None => return Err(format!("is missing '{field}'")),
+        }
+    }
+    for field in ["x", "y"] {
+        match element.get(field) {
+            Some(v) if v.is_number() => {}
+            Some(_) => return Err(format!("has an invalid '{field}' (expected a number)")),
+            None => return Err(format!("is missing '{field}'")),
+        }
+    }
+    Ok(())
+}
+
+/// Whether a document would render as an empty canvas: no elements, or only deleted ones.
+pub fn is_blank(data: &serde_json::Value) -> bool {
+    data.get("elements")
+        .and_then(|v| v.as_array())
+        .is_none_or(|elements| {
+            elements.iter().all(|e| e.get("isDeleted").and_then(|v| v.as_bool()).unwrap_or(false))
+        })
+}
+
+/// Reject documents where two elements share an `id`; Excalidraw renders them incorrectly.
+pub fn reject_duplicate_element_ids(data: &serde_json::Value) -> Result<(), AppError> {
+    let Some(elements) = data.get("elements").and_then(|v| v.as_array()) else {
+        return Ok(());
+    };
+    let mut seen = std::collections::HashSet::new();
+    for id in elements.iter().filter_map(|e| e.get("id").and_then(|v| v.as_str())) {
+        if !seen.insert(id) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid document: duplicate element id '{id}'."
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reject documents whose `files` map holds more than `max` embedded files.
+pub fn validate_embedded_file_count(data: &serde_json::Value, max: usize) -> Result<(), AppError> {
+    let count = data.get("files").and_then(|v| v.as_object()).map_or(0, |f| f.len());
+    if count > max {
+        return Err(AppError::BadRequest(format!(
+            "Invalid document: {count} embedded files exceeds the limit of {max}."
+        )));
+    }
+    Ok(())
+}
+
+/// Reject documents whose embedded files' `dataURL`s add up to more than
+/// `max_bytes`, naming the largest file as the one to shrink or drop.
+pub fn validate_embedded_file_size(data: &serde_json::Value, max_bytes: usize) -> Result<(), AppError> {
+    let Some(files) = data.get("files").and_then(|v| v.as_object()) else {
+        return Ok(());
+    };
+    let size = |file: &serde_json::Value| file.get("dataURL").and_then(|v| v.as_str()).map_or(0, str::len);
+    let total: usize = files.values().map(size).sum();
+    if total <= max_bytes {
+        return Ok(());
+    }
+    let (id, largest) = files.iter().map(|(id, file)| (id.as_str(), size(file))).max_by_key(|(_, size)| *size).unwrap_or_default();
+    Err(AppError::BadRequest(format!(
+        "Invalid document: embedded files take {total} bytes, over the limit of {max_bytes}; the largest is file '{id}' ({largest} bytes)."
+    )))
+}
+
+/// Remove the `files` map, keeping the image elements that reference it (the
+/// viewer shows them as missing images). Returns whether any file was removed.
+pub fn strip_embedded_files(data: &mut serde_json::Value) -> bool {
+    data.as_object_mut()
+        .and_then(|obj| obj.remove("files"))
+        .is_some_and(|files| files.as_object().is_some_and(|f| !f.is_empty()))
+}
+
+/// Reject documents with an element coordinate (`x`, `y`, `width`, `height` or a
+/// `points` component) beyond `max` in magnitude; such canvases break renderers.
+pub fn reject_out_of_bounds_coordinates(data: &serde_json::Value, max: f64) -> Result<(), AppError> {
+    let Some(elements) = data.get("elements").and_then(|v| v.as_array()) else {
+        return Ok(());
+    };
+    for element in elements {
+        let fields = crate::normalize::COORDINATE_FIELDS.iter().filter_map(|f| element.get(*f));
+        let points = element
+            .get("points")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|p| p.as_array())
+            .flatten();
+        if fields.chain(points).filter_map(|v| v.as_f64()).any(|n| n.abs() > max) {
+            let id = element.get("id").and_then(|v| v.as_str()).unwrap_or("?");
+            return Err(AppError::BadRequest(format!(
+                "Invalid document: element '{id}' has a coordinate beyond ±{max}."
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Element fields that may point at a resource outside the drawing.
+const LINK_FIELDS: &[&str] = &["link", "url", "src"];
+
+/// `scheme://host[:port]` of an absolute URL, lowercased. Protocol-relative URLs
+/// (`//host/...`) count as `https`. `None` for relative URLs and `data:` URLs.
+fn url_origin(url: &str) -> Option<String> {
+    let url = url.trim();
+    let (scheme, rest) = match url.strip_prefix("//") {
+        Some(rest) => ("https", rest),
+        None => {
+            let (scheme, rest) = url.split_once(':')?;
+            let is_scheme = !scheme.is_empty()
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+            if !is_scheme || scheme.eq_ignore_ascii_case("data") {
+                return None;
+            }
+            (scheme, rest.trim_start_matches('/'))
+        }
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+    Some(format!("{scheme}://{host}").to_lowercase())
+}
+
+/// Reject documents whose elements link to or embed absolute URLs outside
+/// `allowed_origins` (e.g. `https://example.com`), so shared drawings can't pull
+/// in resources that track viewers. Relative and `data:` URLs are always fine.
+pub fn reject_external_links(data: &serde_json::Value, allowed_origins: &[String]) -> Result<(), AppError> {
+    let Some(elements) = data.get("elements").and_then(|v| v.as_array()) else {
+        return Ok(());
+    };
+    let values = elements
+        .iter()
+        .flat_map(|e| LINK_FIELDS.iter().filter_map(|field| e.get(*field).and_then(|v| v.as_str())));
+    for value in values {
+        if let Some(origin) = url_origin(value) {
+            if !allowed_origins.iter().any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(&origin)) {
+                return Err(AppError::BadRequest(format!(
+                    "Invalid document: external link to '{origin}' is not allowed."
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How far in the future a supplied `created_at` may lie, for clock skew.
+const CREATED_AT_MAX_SKEW_SECS: i64 = 24 * 3600;
+
+/// Reject a client-supplied creation time that lies in the future (beyond
+/// clock skew) or before the Unix epoch.
+pub fn validate_created_at(created_at: chrono::DateTime<chrono::Utc>) -> Result<(), AppError> {
+    let latest = chrono::Utc::now() + chrono::Duration::seconds(CREATED_AT_MAX_SKEW_SECS);
+    if created_at > latest || created_at.timestamp() < 0 {
+        return Err(AppError::BadRequest(format!(
+            "Invalid created_at {}: must not be in the future or before 1970",
+            created_at.to_rfc3339()
+        )));
+    }
+    Ok(())
+}
+
+/// Drawing ids chosen by clients: 1-64 alphanumeric characters, hyphens, or underscores.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn element(id: &str) -> serde_json::Value {
+        json!({"id": id, "type": "rectangle", "x": 0, "y": 10.5, "customField": true})
+    }
+
+    #[test]
+    fn test_valid_document_accepted() {
+        let data = json!({
+            "type": "excalidraw",
+            "version": 2,
+            "elements": [element("a"), element("b")],
+            "appState": {"viewBackgroundColor": "#ffffff"},
+            "unknownTopLevel": [],
+        });
+        assert!(validate_excalidraw(&data).is_ok());
+        assert!(validate_excalidraw(&json!({"type": "excalidraw", "elements": []})).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_documents_name_the_problem() {
+        let mut no_x = element("c");
+        no_x.as_object_mut().unwrap().remove("x");
+        let mut bad_id = element("d");
+        bad_id["id"] = json!(7);
+        let cases = [
+            (json!({"type": "excalidraw", "version": "2", "elements": []}), "'version' must be a number"),
+            (json!({"type": "excalidraw", "elements": [], "appState": []}), "'appState' must be an object"),
+            (json!({"type": "excalidraw", "elements": [element("a"), no_x]}), "element 1 is missing 'x'"),
+            (json!({"type": "excalidraw", "elements": [bad_id]}), "element 0 has an invalid 'id'"),
+            (json!({"type": "excalidraw", "elements": [element("a"), element("b"), "text"]}), "element 2 is not an object"),
+        ];
+        for (data, expected) in cases {
+            let Err(AppError::BadRequest(message)) = validate_excalidraw(&data) else {
+                panic!("{data} should be rejected");
+            };
+            assert!(message.contains(expected), "{message}");
+        }
+    }
+
+    #[test]
+    fn test_blank_documents() {
+        let mut deleted = element("a");
+        deleted["isDeleted"] = json!(true);
+        assert!(is_blank(&json!({"type": "excalidraw", "elements": []})));
+        assert!(is_blank(&json!({"type": "excalidraw", "elements": [deleted.clone()]})));
+        assert!(!is_blank(&json!({"type": "excalidraw", "elements": [deleted, element("b")]})));
+    }
+
+    #[test]
+    fn test_duplicate_element_ids_rejected() {
+        let data = json!({"type": "excalidraw", "elements": [{"id": "a"}, {"id": "b"}, {"id": "a"}]});
+        let err = reject_duplicate_element_ids(&data).unwrap_err();
+        assert!(err.to_string().contains("'a'"));
+    }
+
+    #[test]
+    fn test_unique_element_ids_accepted() {
+        let data = json!({"type": "excalidraw", "elements": [{"id": "a"}, {"id": "b"}]});
+        assert!(reject_duplicate_element_ids(&data).is_ok());
+    }
+
+    fn with_files(count: usize) -> serde_json::Value {
+        let files: serde_json::Map<_, _> = (0..count)
+            .map(|i| (format!("file-{i}"), json!({"mimeType": "image/png", "dataURL": "data:image/png;base64,AAAA"})))
+            .collect();
+        json!({"type": "excalidraw", "elements": [], "files": files})
+    }
+
+    #[test]
+    fn test_embedded_file_count_at_limit_accepted() {
+        assert!(validate_embedded_file_count(&with_files(3), 3).is_ok());
+        assert!(validate_embedded_file_count(&json!({"type": "excalidraw", "elements": []}), 0).is_ok());
+    }
+
+    #[test]
+    fn test_embedded_file_count_over_limit_rejected() {
+        let err = validate_embedded_file_count(&with_files(4), 3).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)));
+        assert!(err.to_string().contains("limit of 3"));
+    }
+
+    #[test]
+    fn test_embedded_file_size_names_largest_file() {
+        let mut data = with_files(2);
+        data["files"]["file-1"]["dataURL"] = json!(format!("data:image/png;base64,{}", "A".repeat(100)));
+        assert!(validate_embedded_file_size(&data, 200).is_ok());
+        let err = validate_embedded_file_size(&data, 100).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)));
+        assert!(err.to_string().contains("file 'file-1'"), "{err}");
+        assert!(validate_embedded_file_size(&json!({"type": "excalidraw", "elements": []}), 0).is_ok());
+    }
+
+    #[test]
+    fn test_strip_embedded_files_keeps_elements() {
+        let mut data = with_files(2);
+        data["elements"] = json!([{"id": "img", "type": "image", "fileId": "file-0"}]);
+        assert!(strip_embedded_files(&mut data));
+        assert!(data.get("files").is_none());
+        assert_eq!(data["elements"][0]["fileId"], "file-0");
+        assert!(!strip_embedded_files(&mut data));
+    }
+
+    #[test]
+    fn test_external_link_rejected() {
+        let allowed = vec!["https://drawings.example.com".to_string()];
+        for link in ["https://tracker.example.net/pixel.gif", "//cdn.example.org/x.js", "HTTP://Other.example"] {
+            let data = json!({"type": "excalidraw", "elements": [{"id": "a", "type": "embeddable", "link": link}]});
+            let err = reject_external_links(&data, &allowed).unwrap_err();
+            assert!(matches!(err, AppError::BadRequest(_)), "{link}");
+        }
+    }
+
+    #[test]
+    fn test_local_and_allowlisted_links_accepted() {
+        let allowed = vec!["https://drawings.example.com/".to_string()];
+        let data = json!({"type": "excalidraw", "elements": [
+            {"id": "a", "link": "https://Drawings.example.com/d/abc"},
+            {"id": "b", "link": "/d/other"},
+            {"id": "c", "url": "#section"},
+            {"id": "d", "src": "data:image/png;base64,AAAA"},
+            {"id": "e", "link": null},
+            {"id": "f"}
+        ]});
+        assert!(reject_external_links(&data, &allowed).is_ok());
+    }
+
+    #[test]
+    fn test_out_of_bounds_coordinate_rejected() {
+        let data = json!({"type": "excalidraw", "elements": [{"id": "far", "x": 1e18, "y": 0}]});
+        let err = reject_out_of_bounds_coordinates(&data, 1e6).unwrap_err();
+        assert!(err.to_string().contains("'far'"));
+        let data = json!({"type": "excalidraw", "elements": [{"id": "line", "x": 0, "y": 0, "points": [[0, 0], [0, -2e6]]}]});
+        assert!(reject_out_of_bounds_coordinates(&data, 1e6).is_err());
+    }
+
+    #[test]
+    fn test_in_bounds_coordinates_accepted() {
+        let data = json!({"type": "excalidraw", "elements": [
+            {"id": "a", "x": -500.5, "y": 999999, "width": 100, "height": 50, "points": [[0, 0], [10, 10]]}
+        ]});
+        assert!(reject_out_of_bounds_coordinates(&data, 1e6).is_ok());
+    }
+}
@@ -0,0 +1,324 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+
+/// Delay before the first retry of a failed delivery. Doubles on every attempt.
+const RETRY_BASE_SECS: i64 = 10;
+/// Upper bound for the retry backoff.
+const RETRY_MAX_BACKOFF_SECS: i64 = 3600;
+/// Timeout for a single delivery attempt.
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+/// Header carrying `sha256=<hex HMAC of the body>` when a secret is configured.
+pub const SIGNATURE_HEADER: &str = "X-Excalishare-Signature";
+
+/// JSON payload POSTed to the webhook URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub event: String,
+    pub id: String,
+    pub source_path: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl WebhookEvent {
+    pub fn new(event: &str, id: &str, source_path: Option<&str>) -> Self {
+        Self {
+            event: event.to_string(),
+            id: id.to_string(),
+            source_path: source_path.map(String::from),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// A delivery that failed and is waiting to be retried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedDelivery {
+    event: WebhookEvent,
+    attempts: u32,
+    next_attempt_at: DateTime<Utc>,
+}
+
+/// Sends webhook notifications, persisting failed deliveries to a queue file
+/// so they are retried with exponential backoff and survive restarts.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+    queue_path: PathBuf,
+    queue: Arc<Mutex<Vec<QueuedDelivery>>>,
+    /// Deliveries older than this are dropped instead of retried.
+    max_age: chrono::Duration,
+    retry_base_secs: i64,
+    /// Key for signing payloads, so receivers can check they came from us.
+    secret: Option<Arc<str>>,
+}
+
+impl WebhookNotifier {
+    /// Create a notifier, loading any deliveries left in the queue file by a previous run.
+    pub async fn new(url: String, queue_path: PathBuf, max_age_secs: u64) -> Result<Self, AppError> {
+        let queue: Vec<QueuedDelivery> = match fs::read(&queue_path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Discarding unreadable webhook queue file");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        if !queue.is_empty() {
+            tracing::info!(count = queue.len(), "Loaded pending webhook deliveries");
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+            .build()
+            .map_err(|e| AppError::Internal(format!("Failed to build webhook client: {e}")))?;
+
+        Ok(Self {
+            url,
+            client,
+            queue_path,
+            queue: Arc::new(Mutex::new(queue)),
+            max_age: i64::try_from(max_age_secs)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .unwrap_or(chrono::Duration::MAX),
+            retry_base_secs: RETRY_BASE_SECS,
+            secret: None,
+        })
+    }
+
+    /// Sign every payload with this secret (see [`SIGNATURE_HEADER`]).
+    pub fn with_secret(mut self, secret: Option<String>) -> Self {
+        self.secret = secret.map(Arc::from);
+        self
+    }
+
+    /// Number of deliveries waiting to be retried.
+    pub async fn queue_depth(&self) -> usize {
+        self.queue.lock().await.len()
+    }
+
+    /// Fire-and-forget notification: delivers on a spawned task so it never
+    /// blocks the API response.
+    pub fn notify(&self, event: WebhookEvent) {
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            notifier.deliver_or_enqueue(event).await;
+        });
+    }
+
+    /// Attempt a delivery, enqueueing it for retry on failure.
+    pub async fn deliver_or_enqueue(&self, event: WebhookEvent) {
+        if let Err(e) = self.deliver(&event).await {
+            tracing::warn!(event = %event.event, id = %event.id, error = %e, "Webhook delivery failed, queued for retry");
+            let mut queue = self.queue.lock().await;
+            queue.push(QueuedDelivery {
+                event,
+                attempts: 1,
+                next_attempt_at: Utc::now() + self.backoff(1),
+            });
+            self.persist(&queue).await;
+        }
+    }
+
+    /// Retry every queued delivery that is due. Deliveries past the max age are dropped.
+    pub async fn retry_due(&self) {
+        let now = Utc::now();
+        let due: Vec<QueuedDelivery> = {
+            let mut queue = self.queue.lock().await;
+            let before = queue.len();
+            queue.retain(|d| {
+                let expired = now - d.event.timestamp > self.max_age;
+                if expired {
+                    tracing::error!(event = %d.event.event, id = %d.event.id, attempts = d.attempts, "Dropping webhook delivery past max age");
+                }
+                !expired
+            });
+            let (due, pending): (Vec<_>, Vec<_>) =
+                queue.drain(..).partition(|d| d.next_attempt_at <= now);
+            *queue = pending;
+            if due.is_empty() && queue.len() != before {
+                self.persist(&queue).await;
+            }
+            due
+        };
+        if due.is_empty() {
+            return;
+        }
+
+        let mut failed = Vec::new();
+        for mut delivery in due {
+            match self.deliver(&delivery.event).await {
+                Ok(()) => {
+                    tracing::info!(event = %delivery.event.event, id = %delivery.event.id, attempts = delivery.attempts + 1, "Webhook redelivered");
+                }
+                Err(e) => {
+                    delivery.attempts += 1;
+                    delivery.next_attempt_at = Utc::now() + self.backoff(delivery.attempts);
+                    tracing::warn!(event = %delivery.event.event, id = %delivery.event.id, attempts = delivery.attempts, error = %e, "Webhook retry failed");
+                    failed.push(delivery);
+                }
+            }
+        }
+
+        let mut queue = self.queue.lock().await;
+        queue.extend(failed);
+        self.persist(&queue).await;
+    }
+
+    async fn deliver(&self, event: &WebhookEvent) -> Result<(), reqwest::Error> {
+        let body = serde_json::to_vec(event).expect("webhook event serializes");
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+        request.body(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    fn backoff(&self, attempts: u32) -> chrono::Duration {
+        let secs = self
+            .retry_base_secs
+            .saturating_mul(1i64 << attempts.saturating_sub(1).min(20))
+            .min(RETRY_MAX_BACKOFF_SECS);
+        chrono::Duration::seconds(secs)
+    }
+
+    /// Write the queue to disk (atomic via temp file + rename).
+    async fn persist(&self, queue: &[QueuedDelivery]) {
+        let result = async {
+            let bytes = serde_json::to_vec(queue)?;
+            let tmp_path = self.queue_path.with_extension("tmp");
+            fs::write(&tmp_path, &bytes).await?;
+            fs::rename(&tmp_path, &self.queue_path).await?;
+            Ok::<(), AppError>(())
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::error!(error = %e, "Failed to persist webhook queue");
+        }
+    }
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `body` under `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    format!("sha256={}", digest.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::post, Router};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Mock webhook receiver that fails until `healthy` is set.
+    async fn mock_server(healthy: Arc<AtomicBool>, hits: Arc<AtomicUsize>) -> String {
+        let app = Router::new().route(
+            "/hook",
+            post(move || {
+                let healthy = healthy.clone();
+                let hits = hits.clone();
+                async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    if healthy.load(Ordering::SeqCst) {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}/hook")
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_queued_and_redelivered() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join(".webhook-queue");
+        let healthy = Arc::new(AtomicBool::new(false));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = mock_server(healthy.clone(), hits.clone()).await;
+
+        let mut notifier = WebhookNotifier::new(url.clone(), queue_path.clone(), 3600).await.unwrap();
+        notifier.retry_base_secs = 0;
+
+        notifier
+            .deliver_or_enqueue(WebhookEvent::new("upload", "abc", None))
+            .await;
+        assert_eq!(notifier.queue_depth().await, 1);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // The queue survives a restart
+        let mut restarted = WebhookNotifier::new(url, queue_path, 3600).await.unwrap();
+        restarted.retry_base_secs = 0;
+        assert_eq!(restarted.queue_depth().await, 1);
+
+        // Still failing: stays queued
+        restarted.retry_due().await;
+        assert_eq!(restarted.queue_depth().await, 1);
+
+        // Endpoint recovers: delivered and dequeued
+        healthy.store(true, Ordering::SeqCst);
+        restarted.retry_due().await;
+        assert_eq!(restarted.queue_depth().await, 0);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_payload_signed_with_secret() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: axum::http::HeaderMap, body: axum::body::Bytes| {
+                let signature = headers.get(SIGNATURE_HEADER).map(|v| v.to_str().unwrap().to_string());
+                let _ = tx.send((signature, body));
+                async { StatusCode::OK }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join(".webhook-queue");
+        let signed = WebhookNotifier::new(url.clone(), queue_path.clone(), 3600)
+            .await
+            .unwrap()
+            .with_secret(Some("hunter2".into()));
+        signed.deliver_or_enqueue(WebhookEvent::new("upload", "abc", Some("a.excalidraw"))).await;
+        let (signature, body) = rx.recv().await.unwrap();
+        assert_eq!(signature.unwrap(), sign("hunter2", &body));
+        let event: WebhookEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!((event.event.as_str(), event.id.as_str()), ("upload", "abc"));
+
+        let unsigned = WebhookNotifier::new(url, queue_path, 3600).await.unwrap();
+        unsigned.deliver_or_enqueue(WebhookEvent::new("delete", "abc", None)).await;
+        assert_eq!(rx.recv().await.unwrap().0, None);
+    }
+
+    #[test]
+    fn test_sign_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -7,7 +7,6 @@ use axum::{
 };
 use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
-use subtle::ConstantTimeEq;
 use tokio::time::{interval, Duration};
 use uuid::Uuid;
 
@@ -16,13 +15,14 @@ use uuid::Uuid;
 /// Sending a ping every 30s ensures the connection stays alive.
 const WS_PING_INTERVAL_SECS: u64 = 30;
 
+use crate::auth::{ApiKeys, KeyScope};
 use crate::collab::{ClientMessage, ServerMessage, SessionManager};
 use crate::error::AppError;
 
 #[derive(Clone)]
 pub struct WsState {
     pub session_manager: SessionManager,
-    pub api_key: String,
+    pub api_keys: ApiKeys,
 }
 
 #[derive(Deserialize)]
@@ -82,12 +82,8 @@ pub async fn ws_collab_handler(
     Query(query): Query<WsQuery>,
     State(ws_state): State<WsState>,
 ) -> Result<impl IntoResponse, AppError> {
-    // Check if the request carries a valid API key (admin bypass)
-    let has_valid_api_key = query.api_key.as_ref().map_or(false, |key| {
-        let key_bytes = key.as_bytes();
-        let expected_bytes = ws_state.api_key.as_bytes();
-        key_bytes.len() == expected_bytes.len() && key_bytes.ct_eq(expected_bytes).into()
-    });
+    // Check if the request carries a valid write API key (admin bypass)
+    let has_valid_api_key = query.api_key.as_ref().is_some_and(|key| ws_state.api_keys.authorizes(key, KeyScope::Write));
 
     // Verify password before upgrading to WebSocket (admin bypasses)
     if !has_valid_api_key {
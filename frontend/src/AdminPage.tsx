@@ -2,6 +2,7 @@ import { useEffect, useState, useCallback } from 'react'
 import { Link } from 'react-router-dom'
 import AboutModal from './AboutModal'
 import type { CollabSessionInfo } from './types'
+import { fetchAllDrawings } from './utils/listDrawings'
 
 interface Drawing {
   id: string
@@ -25,7 +26,7 @@ function AdminPage() {
   const fetchDrawings = () => {
     if (!apiKey) return
 
-    fetch('/api/drawings', {
+    fetchAllDrawings('/api/drawings', {
       headers: { 'Authorization': `Bearer ${apiKey}` }
     })
       .then((res) => {
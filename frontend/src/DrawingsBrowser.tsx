@@ -1,6 +1,7 @@
 import { useEffect, useState, useRef } from 'react'
 import { Link, useNavigate } from 'react-router-dom'
 import { useMediaQuery } from './hooks/useMediaQuery'
+import { fetchAllDrawings } from './utils/listDrawings'
 
 const spinKeyframes = `
   @keyframes spin {
@@ -400,7 +401,7 @@ function DrawingsBrowser({ mode = 'standalone', theme, onClose, currentDrawingId
       return
     }
 
-    fetch('/api/public/drawings')
+    fetchAllDrawings('/api/public/drawings')
       .then((res) => {
         if (!res.ok) throw new Error('Failed to load drawings')
         return res.json()
@@ -424,7 +425,7 @@ function DrawingsBrowser({ mode = 'standalone', theme, onClose, currentDrawingId
     setRefreshing(true)
     setError(null)
 
-    fetch('/api/public/drawings')
+    fetchAllDrawings('/api/public/drawings')
       .then((res) => {
         if (!res.ok) throw new Error('Failed to load drawings')
         return res.json()
@@ -900,7 +901,7 @@ function DrawingsBrowser({ mode = 'standalone', theme, onClose, currentDrawingId
                   onClick={() => {
                     setError(null)
                     setLoading(true)
-                    fetch('/api/public/drawings')
+                    fetchAllDrawings('/api/public/drawings')
                       .then((res) => {
                         if (!res.ok) throw new Error('Failed to load drawings')
                         return res.json()
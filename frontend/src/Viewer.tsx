@@ -15,6 +15,7 @@ import ScreenShareOverlay from './ScreenShareOverlay'
 import AboutModal from './AboutModal'
 import PasswordDialog from './PasswordDialog'
 import DrawingsBrowser from './DrawingsBrowser'
+import { fetchAllDrawings } from './utils/listDrawings'
 
 const spinKeyframes = `
   @keyframes spin {
@@ -110,7 +111,7 @@ function Viewer() {
   // Preload drawings list on mount
   useEffect(() => {
     setLoadingDrawings(true)
-    fetch('/api/public/drawings')
+    fetchAllDrawings('/api/public/drawings')
       .then(res => res.json())
       .then(data => {
         const drawings = data.drawings || []
@@ -539,7 +540,7 @@ function Viewer() {
         setMode(prev => prev === 'present' ? 'view' : 'present')
         if (willBePresent && drawingsList.length === 0 && !loadingDrawings) {
           setLoadingDrawings(true)
-          fetch('/api/public/drawings')
+          fetchAllDrawings('/api/public/drawings')
             .then(res => res.json())
             .then(data => {
               const drawings = data.drawings || []
@@ -554,7 +555,7 @@ function Viewer() {
         if (drawingsList.length === 0) {
           if (!loadingDrawings) {
             setLoadingDrawings(true)
-            fetch('/api/public/drawings')
+            fetchAllDrawings('/api/public/drawings')
               .then(res => res.json())
               .then(data => {
                 const drawings = data.drawings || []
@@ -575,7 +576,7 @@ function Viewer() {
         if (drawingsList.length === 0) {
           if (!loadingDrawings) {
             setLoadingDrawings(true)
-            fetch('/api/public/drawings')
+            fetchAllDrawings('/api/public/drawings')
               .then(res => res.json())
               .then(data => {
                 const drawings = data.drawings || []
@@ -607,7 +608,7 @@ function Viewer() {
     if (loadingDrawingsRef.current) return
     loadingDrawingsRef.current = true
     setLoadingDrawings(true)
-    fetch('/api/public/drawings')
+    fetchAllDrawings('/api/public/drawings')
       .then(res => res.json())
       .then(data => {
         const drawings = data.drawings || []
@@ -810,7 +811,7 @@ function Viewer() {
           setMode('present')
           if (drawingsList.length === 0 && !loadingDrawings) {
             setLoadingDrawings(true)
-            fetch('/api/public/drawings')
+            fetchAllDrawings('/api/public/drawings')
               .then(res => res.json())
               .then(data => { setDrawingsList(data.drawings || []); setLoadingDrawings(false) })
               .catch(() => setLoadingDrawings(false))
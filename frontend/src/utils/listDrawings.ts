@@ -0,0 +1,24 @@
+/** Largest page the list endpoints serve (`MAX_LIST_LIMIT` in the backend) */
+const PAGE_SIZE = 500
+
+/**
+ * Fetch every page of a paginated list endpoint (`/api/drawings`,
+ * `/api/public/drawings`) and combine them into one response shaped like a
+ * single page holding all drawings. Error responses are returned as-is.
+ */
+export async function fetchAllDrawings(url: string, init?: RequestInit): Promise<Response> {
+  const drawings: unknown[] = []
+  let offset: number | null = 0
+  while (offset !== null) {
+    const separator = url.includes('?') ? '&' : '?'
+    const res = await fetch(`${url}${separator}limit=${PAGE_SIZE}&offset=${offset}`, init)
+    if (!res.ok) return res
+    const page = await res.json()
+    drawings.push(...(page.drawings || []))
+    const next: number | null = page.next_offset ?? null
+    offset = next !== null && next > offset ? next : null
+  }
+  return new Response(JSON.stringify({ drawings, total: drawings.length, next_offset: null }), {
+    headers: { 'Content-Type': 'application/json' },
+  })
+}